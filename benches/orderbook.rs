@@ -0,0 +1,135 @@
+use crabrolls::prelude::*;
+use std::error::Error;
+use std::time::{Duration, Instant};
+
+const BASE: Address = Address::repeat_byte(0xba);
+const QUOTE: Address = Address::repeat_byte(0x90);
+const ESCROW: Address = Address::repeat_byte(0xee);
+const TRADERS: u8 = 100;
+
+struct Noop;
+
+impl Application for Noop {
+	type Error = Box<dyn Error>;
+
+	async fn advance(
+		&self,
+		_env: &impl Environment,
+		_metadata: Metadata,
+		_payload: &[u8],
+		_deposit: Option<Deposit>,
+	) -> Result<FinishStatus, Box<dyn Error>> {
+		Ok(FinishStatus::Accept)
+	}
+}
+
+async fn setup() -> (Tester<Noop>, OrderBook) {
+	let tester = Tester::new(Noop, MockupOptions::default());
+	for trader in 1..=TRADERS {
+		for token in [BASE, QUOTE] {
+			tester
+				.deposit(Deposit::ERC20 {
+					sender: Address::repeat_byte(trader),
+					token,
+					amount: uint!("1_000_000_000_000"),
+				})
+				.await;
+		}
+	}
+	(tester, OrderBook::new(BASE, QUOTE, ESCROW).price_scale(Uint::one()))
+}
+
+fn trader(i: usize) -> Address {
+	Address::repeat_byte((i % TRADERS as usize) as u8 + 1)
+}
+
+fn report(name: &str, orders: usize, elapsed: Duration) {
+	println!(
+		"{:<32} {:>6} orders {:>10.2?} {:>10.2?}/order",
+		name,
+		orders,
+		elapsed,
+		elapsed / orders as u32
+	);
+}
+
+/// Resting orders spread over 100 price levels on each side, without crossing.
+async fn rest(orders: usize) {
+	let (tester, book) = setup().await;
+	let env = tester.env();
+	let start = Instant::now();
+	for i in 0..orders {
+		let (side, price) = match i % 2 {
+			0 => (Side::Buy, 1_000 - (i % 100) as u64),
+			_ => (Side::Sell, 1_001 + (i % 100) as u64),
+		};
+		book.place(env.as_ref(), trader(i), side, Uint::from(price), Uint::from(10))
+			.await
+			.unwrap();
+	}
+	report("rest", orders, start.elapsed());
+}
+
+/// A single taker sweeping every resting ask.
+async fn sweep(orders: usize) {
+	let (tester, book) = setup().await;
+	let env = tester.env();
+	for i in 0..orders {
+		book.place(
+			env.as_ref(),
+			trader(i),
+			Side::Sell,
+			Uint::from(1_000 + (i % 100) as u64),
+			Uint::from(10),
+		)
+		.await
+		.unwrap();
+	}
+	let start = Instant::now();
+	let placement = book
+		.place(
+			env.as_ref(),
+			trader(0),
+			Side::Buy,
+			Uint::from(2_000),
+			Uint::from(10 * orders as u64),
+		)
+		.await
+		.unwrap();
+	assert_eq!(placement.fills.len(), orders);
+	report("sweep", orders, start.elapsed());
+}
+
+/// Alternating crossing orders around the mid price, half of them filling resting liquidity.
+async fn mixed(orders: usize) {
+	let (tester, book) = setup().await;
+	let env = tester.env();
+	let start = Instant::now();
+	for i in 0..orders {
+		let offset = (i * 7919 % 21) as u64;
+		let (side, price) = match i % 2 {
+			0 => (Side::Buy, 990 + offset),
+			_ => (Side::Sell, 1_000 - 10 + offset),
+		};
+		book.place(
+			env.as_ref(),
+			trader(i),
+			side,
+			Uint::from(price),
+			Uint::from(5 + i as u64 % 10),
+		)
+		.await
+		.unwrap();
+	}
+	report("mixed", orders, start.elapsed());
+}
+
+fn main() {
+	async_std::task::block_on(async {
+		for orders in [1_000, 10_000] {
+			rest(orders).await;
+			sweep(orders).await;
+			mixed(orders).await;
+		}
+	});
+}
@@ -0,0 +1,113 @@
+use crabrolls::prelude::*;
+use std::error::Error;
+use std::time::{Duration, Instant};
+
+const TREASURY: Address = Address::repeat_byte(0xee);
+const TOKENS: [Address; 4] = [
+	Address::repeat_byte(0xf0),
+	Address::repeat_byte(0xf1),
+	Address::repeat_byte(0xf2),
+	Address::repeat_byte(0xf3),
+];
+
+/// Credits one unit of ether and of a token to each of the `n` accounts of the payload, from the treasury.
+struct Airdrop;
+
+impl Application for Airdrop {
+	type Error = Box<dyn Error>;
+
+	async fn advance(
+		&self,
+		env: &impl Environment,
+		_metadata: Metadata,
+		payload: &[u8],
+		_deposit: Option<Deposit>,
+	) -> Result<FinishStatus, Box<dyn Error>> {
+		let accounts = u64::from_be_bytes(payload.try_into()?);
+		for i in 0..accounts {
+			env.ether_transfer(TREASURY, account(i), Uint::one()).await?;
+			env.erc20_transfer(TREASURY, account(i), token(i), Uint::one()).await?;
+		}
+		Ok(FinishStatus::Accept)
+	}
+}
+
+fn account(i: u64) -> Address {
+	let mut bytes = [0u8; 20];
+	bytes[..8].copy_from_slice(&i.to_be_bytes());
+	bytes[12..].copy_from_slice(&(i * 7919).to_be_bytes());
+	Address::from(bytes)
+}
+
+fn token(i: u64) -> Address {
+	TOKENS[i as usize % TOKENS.len()]
+}
+
+fn report(name: &str, operations: u64, elapsed: Duration) {
+	println!(
+		"{:<24} {:>7} ops {:>10.2?} {:>10.2?}/op",
+		name,
+		operations,
+		elapsed,
+		elapsed / operations as u32
+	);
+}
+
+async fn wallets(accounts: u64) {
+	let tester = Tester::new(Airdrop, MockupOptions::default());
+	tester
+		.deposit(Deposit::Ether {
+			sender: TREASURY,
+			amount: Uint::from(accounts),
+		})
+		.await;
+	for token in TOKENS {
+		tester
+			.deposit(Deposit::ERC20 {
+				sender: TREASURY,
+				token,
+				amount: Uint::from(accounts),
+			})
+			.await;
+	}
+
+	let start = Instant::now();
+	assert!(tester.advance(TREASURY, accounts.to_be_bytes()).await.is_accepted());
+	report("credits (one input)", accounts * 2, start.elapsed());
+
+	let start = Instant::now();
+	for i in 0..accounts {
+		// Same token class on both ends, so every account keeps a single ERC20 balance
+		let (source, destination) = (account(i), account((i + TOKENS.len() as u64) % accounts));
+		tester.ether_transfer(source, destination, Uint::one()).await.unwrap();
+		tester
+			.erc20_transfer(source, destination, token(i), Uint::one())
+			.await
+			.unwrap();
+	}
+	report("transfers", accounts * 2, start.elapsed());
+
+	let start = Instant::now();
+	let mut total = Uint::zero();
+	for i in 0..accounts {
+		total += tester.ether_balance(account(i)).await;
+		total += tester.erc20_balance(account(i), token(i)).await;
+	}
+	assert_eq!(total, Uint::from(accounts * 2));
+	report("balance lookups", accounts * 2, start.elapsed());
+}
+
+fn main() {
+	println!(
+		"wallet hasher: {}",
+		match cfg!(feature = "fast-hash") {
+			true => "fxhash",
+			false => "siphash",
+		}
+	);
+	async_std::task::block_on(async {
+		for accounts in [10_000, 100_000] {
+			wallets(accounts).await;
+		}
+	});
+}
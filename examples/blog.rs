@@ -12,6 +12,7 @@ struct Post {
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(tag = "kind", content = "payload")]
+#[allow(clippy::enum_variant_names)]
 enum Input {
 	AddPost {
 		title: String,
@@ -96,6 +97,8 @@ impl JsonApp {
 }
 
 impl Application for JsonApp {
+	type Error = Box<dyn Error>;
+
 	async fn advance(
 		&self,
 		env: &impl Environment,
@@ -151,7 +154,7 @@ async fn main() {
 #[cfg(test)]
 mod tests {
 	use super::*;
-	use ethabi::Address;
+	use crabrolls::prelude::Address;
 	use serde_json::json;
 
 	async fn extract_posts(output: &Output) -> Vec<Post> {
@@ -183,10 +186,9 @@ mod tests {
 				"title": "First Post",
 				"content": "This is the first post."
 			}
-		})
-		.to_string();
+		});
 
-		let result = tester.advance(Address::default(), add_payload.into_bytes()).await;
+		let result = tester.advance_json(Address::default(), &add_payload).await;
 
 		assert!(result.is_accepted(), "Expected Accept status");
 		assert!(result.get_error().is_none(), "Expected no error");
@@ -221,10 +223,9 @@ mod tests {
 				"title": "First Post",
 				"content": "This is the first post."
 			}
-		})
-		.to_string();
+		});
 
-		tester.advance(Address::default(), add_payload.into_bytes()).await;
+		tester.advance_json(Address::default(), &add_payload).await;
 
 		let update_payload = json!({
 			"kind": "UpdatePost",
@@ -233,10 +234,9 @@ mod tests {
 				"title": "Updated First Post",
 				"content": null
 			}
-		})
-		.to_string();
+		});
 
-		let result = tester.advance(Address::default(), update_payload.into_bytes()).await;
+		let result = tester.advance_json(Address::default(), &update_payload).await;
 
 		assert!(result.is_accepted(), "Expected Accept status");
 		assert!(result.get_error().is_none(), "Expected no error");
@@ -271,20 +271,18 @@ mod tests {
 				"title": "First Post",
 				"content": "This is the first post."
 			}
-		})
-		.to_string();
+		});
 
-		tester.advance(Address::default(), add_payload.into_bytes()).await;
+		tester.advance_json(Address::default(), &add_payload).await;
 
 		let delete_payload = json!({
 			"kind": "DeletePost",
 			"payload": {
 				"id": 1
 			}
-		})
-		.to_string();
+		});
 
-		let result = tester.advance(Address::default(), delete_payload.into_bytes()).await;
+		let result = tester.advance_json(Address::default(), &delete_payload).await;
 
 		assert!(result.is_accepted(), "Expected Accept status");
 		assert!(result.get_error().is_none(), "Expected no error");
@@ -314,10 +312,9 @@ mod tests {
 				"title": "Updated First Post",
 				"content": null
 			}
-		})
-		.to_string();
+		});
 
-		let result = tester.advance(Address::default(), update_payload.into_bytes()).await;
+		let result = tester.advance_json(Address::default(), &update_payload).await;
 
 		assert!(result.is_rejected(), "Expected Reject status");
 		assert!(result.get_error().is_some(), "Expected an error");
@@ -10,6 +10,8 @@ impl EchoApp {
 }
 
 impl Application for EchoApp {
+	type Error = Box<dyn Error>;
+
 	async fn advance(
 		&self,
 		env: &impl Environment,
@@ -50,7 +52,7 @@ async fn main() {
 mod tests {
 	use super::EchoApp;
 	use crabrolls::prelude::*;
-	use ethabi::Address;
+	use crabrolls::prelude::Address;
 
 	#[async_std::test]
 	async fn test_echo() {
@@ -84,6 +86,7 @@ mod tests {
 				},
 				Output::Voucher {
 					destination: address,
+					value: Uint::zero(),
 					payload: payload.to_vec()
 				}
 			],
@@ -1,5 +1,5 @@
 use crabrolls::prelude::*;
-use ethabi::{Address, Uint};
+use crabrolls::prelude::{Address, Uint};
 use serde::{Deserialize, Serialize};
 use std::error::Error;
 
@@ -35,6 +35,12 @@ enum AdvanceWithdraw {
 
 pub struct WalletAbstractionApp;
 
+impl Default for WalletAbstractionApp {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
 impl WalletAbstractionApp {
 	pub fn new() -> Self {
 		Self
@@ -42,6 +48,8 @@ impl WalletAbstractionApp {
 }
 
 impl Application for WalletAbstractionApp {
+	type Error = Box<dyn Error>;
+
 	async fn advance(
 		&self,
 		env: &impl Environment,
@@ -84,6 +92,12 @@ impl Application for WalletAbstractionApp {
 					println!("Current balance of ERC1155 token ID {}: {}", id, balance);
 				}
 			}
+			Some(Deposit::Custom { portal, sender, .. }) => {
+				println!("Received custom deposit from {} through portal {}", sender, portal);
+			}
+			Some(deposit) => {
+				println!("Received unsupported deposit: {:?}", deposit);
+			}
 			None => {
 				println!("Received no deposit, triggering withdrawal request");
 
@@ -185,22 +199,22 @@ impl Application for WalletAbstractionApp {
 					units::wei::to_ether(balance)
 				);
 
-				env.send_report(balance.to_string()).await?;
+				env.send_report_str(balance).await?;
 			}
 			InspectBalance::ERC20 { address, token } => {
 				let balance = env.erc20_balance(address, token).await;
 				println!("Balance of ERC20 token {} for address {}: {}", token, address, balance);
 
-				env.send_report(balance.to_string()).await?;
+				env.send_report_str(balance).await?;
 			}
 			InspectBalance::ERC721 { token, id } => {
 				let owner = env.erc721_owner_of(token, id).await;
 
-				if owner.is_none() {
-					println!("ERC721 token ID {} does not exist", id);
-					env.send_report("0").await?;
+				if let Some(owner) = owner {
+					println!("Owner of ERC721 token ID {}: {}", id, owner);
 				} else {
-					println!("Owner of ERC721 token ID {}: {}", id, owner.unwrap());
+					println!("ERC721 token ID {} does not exist", id);
+					env.send_report_str(0).await?;
 				}
 
 				env.send_report(owner.to_owned().unwrap_or(Address::zero())).await?;
@@ -212,7 +226,7 @@ impl Application for WalletAbstractionApp {
 					id, address, balance
 				);
 
-				env.send_report(balance.to_string()).await?;
+				env.send_report_str(balance).await?;
 			}
 		}
 		Ok(FinishStatus::Accept)
@@ -254,10 +268,9 @@ mod tests {
 		let withdraw_payload = json!({
 			"kind": "ether",
 			"metadata": {}
-		})
-		.to_string();
+		});
 
-		let advance_result = tester.advance(address, withdraw_payload).await;
+		let advance_result = tester.advance_json(address, &withdraw_payload).await;
 
 		assert!(advance_result.is_accepted(), "Expected Accept status");
 		assert_eq!(tester.ether_balance(address).await, Uint::zero());
@@ -289,10 +302,9 @@ mod tests {
 			"metadata": {
 				"token": token_address
 			}
-		})
-		.to_string();
+		});
 
-		let advance_result = tester.advance(address, withdraw_payload).await;
+		let advance_result = tester.advance_json(address, &withdraw_payload).await;
 
 		assert!(advance_result.is_accepted(), "Expected Accept status");
 		assert_eq!(tester.erc20_balance(address, token_address).await, Uint::zero());
@@ -324,10 +336,9 @@ mod tests {
 				"token": token_address,
 				"id": token_id
 			}
-		})
-		.to_string();
+		});
 
-		let advance_result = tester.advance(address, withdraw_payload).await;
+		let advance_result = tester.advance_json(address, &withdraw_payload).await;
 
 		assert!(advance_result.is_accepted(), "Expected Accept status");
 		assert_eq!(tester.erc721_owner_of(token_address, token_id).await, None);
@@ -362,10 +373,9 @@ mod tests {
 				"ids": [token_id],
 				"data": null
 			}
-		})
-		.to_string();
+		});
 
-		let advance_result = tester.advance(address, withdraw_payload).await;
+		let advance_result = tester.advance_json(address, &withdraw_payload).await;
 
 		assert!(advance_result.is_accepted(), "Expected Accept status");
 		assert_eq!(
@@ -395,10 +405,9 @@ mod tests {
 			"metadata": {
 				"address": address
 			}
-		})
-		.to_string();
+		});
 
-		let inspect_result = tester.inspect(inspect_payload).await;
+		let inspect_result = tester.inspect_json(&inspect_payload).await;
 
 		assert!(inspect_result.is_accepted(), "Expected Accept status");
 		assert_eq!(
@@ -432,10 +441,9 @@ mod tests {
 				"address": address,
 				"token": token_address
 			}
-		})
-		.to_string();
+		});
 
-		let inspect_result = tester.inspect(inspect_payload).await;
+		let inspect_result = tester.inspect_json(&inspect_payload).await;
 
 		assert!(inspect_result.is_accepted(), "Expected Accept status");
 		assert_eq!(
@@ -469,10 +477,9 @@ mod tests {
 				"token": token_address,
 				"id": token_id
 			}
-		})
-		.to_string();
+		});
 
-		let inspect_result = tester.inspect(inspect_payload).await;
+		let inspect_result = tester.inspect_json(&inspect_payload).await;
 
 		assert!(inspect_result.is_accepted(), "Expected Accept status");
 		assert_eq!(
@@ -508,10 +515,9 @@ mod tests {
 				"token": token_address,
 				"id": token_id
 			}
-		})
-		.to_string();
+		});
 
-		let inspect_result = tester.inspect(inspect_payload).await;
+		let inspect_result = tester.inspect_json(&inspect_payload).await;
 
 		assert!(inspect_result.is_accepted(), "Expected Accept status");
 		assert_eq!(
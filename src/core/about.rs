@@ -0,0 +1,85 @@
+use super::context::Selector;
+use crate::types::machine::PortalHandlerConfig;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+
+pub const ABOUT_ROUTE: &[u8] = b"/_crabrolls/about";
+pub const PROTOCOL: &str = "rollup-http";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct BuildInfo {
+	pub name: String,
+	pub version: String,
+	pub git_sha: Option<String>,
+	pub build_timestamp: Option<u64>,
+	pub features: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct About {
+	pub version: String,
+	pub protocol: String,
+	pub portal_config: String,
+	pub routes: Vec<String>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub build: Option<BuildInfo>,
+}
+
+impl About {
+	pub fn new<'a>(portal_config: &PortalHandlerConfig, selectors: impl IntoIterator<Item = &'a Selector>) -> Self {
+		Self {
+			version: env!("CARGO_PKG_VERSION").to_string(),
+			protocol: PROTOCOL.to_string(),
+			portal_config: format!("{:?}", portal_config),
+			routes: selectors
+				.into_iter()
+				.map(|selector| format!("{:?}", selector))
+				.collect(),
+			build: None,
+		}
+	}
+
+	pub fn build(mut self, build: Option<BuildInfo>) -> Self {
+		self.build = build;
+		self
+	}
+
+	pub fn to_bytes(&self) -> Result<Vec<u8>, Box<dyn Error>> {
+		Ok(serde_json::to_vec(&serde_json::json!({ "about": self }))?)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::types::primitives::*;
+
+	#[test]
+	fn test_about() {
+		let selectors = [Selector::senders([Address::zero()]), Selector::Any];
+		let about = About::new(&PortalHandlerConfig::default(), &selectors);
+
+		assert_eq!(about.version, env!("CARGO_PKG_VERSION"));
+		assert_eq!(about.portal_config, "Handle { advance: true }");
+		assert_eq!(about.routes.len(), 2);
+		assert_eq!(about.routes[1], "Any");
+
+		let value: serde_json::Value = serde_json::from_slice(&about.to_bytes().unwrap()).unwrap();
+		assert_eq!(value["about"]["protocol"], PROTOCOL);
+		assert!(value["about"].get("build").is_none());
+	}
+
+	#[test]
+	fn test_build_info_macro() {
+		let build = crate::build_info!("tls", "alloy");
+		assert_eq!(build.name, "crabrolls");
+		assert_eq!(build.version, env!("CARGO_PKG_VERSION"));
+		assert_eq!(build.git_sha.as_deref(), option_env!("GIT_SHA"));
+		assert_eq!(build.features.contains(&"alloy".to_string()), cfg!(feature = "alloy"));
+		assert_eq!(build.features.contains(&"tls".to_string()), cfg!(feature = "tls"));
+
+		let about = About::new(&PortalHandlerConfig::default(), []).build(Some(build));
+		let value: serde_json::Value = serde_json::from_slice(&about.to_bytes().unwrap()).unwrap();
+		assert_eq!(value["about"]["build"]["name"], "crabrolls");
+	}
+}
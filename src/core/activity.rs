@@ -0,0 +1,129 @@
+use crate::types::machine::Metadata;
+use crate::types::primitives::*;
+use std::collections::{BTreeMap, HashMap};
+
+pub const DEFAULT_ACTIVITY_WINDOW: u64 = 3600;
+const MAX_ACTIVITY_WINDOWS: usize = 24;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum InputKind {
+	Advance,
+	Deposit,
+	Inspect,
+}
+
+#[derive(Debug, Clone)]
+pub struct ActivityCounters {
+	window: u64,
+	by_sender: HashMap<Address, u64>,
+	by_kind: HashMap<InputKind, u64>,
+	by_window: BTreeMap<u64, u64>,
+}
+
+impl Default for ActivityCounters {
+	fn default() -> Self {
+		Self::new(DEFAULT_ACTIVITY_WINDOW)
+	}
+}
+
+impl ActivityCounters {
+	pub fn new(window: u64) -> Self {
+		Self {
+			window: window.max(1),
+			by_sender: HashMap::new(),
+			by_kind: HashMap::new(),
+			by_window: BTreeMap::new(),
+		}
+	}
+
+	pub fn record_advance(&mut self, metadata: &Metadata, kind: InputKind) {
+		*self.by_sender.entry(metadata.sender).or_default() += 1;
+		*self.by_kind.entry(kind).or_default() += 1;
+		*self.by_window.entry(self.window_start(metadata.timestamp)).or_default() += 1;
+
+		// Only the most recent windows are kept, so the counters don't grow with the rollup age.
+		while self.by_window.len() > MAX_ACTIVITY_WINDOWS {
+			self.by_window.pop_first();
+		}
+	}
+
+	pub fn record_inspect(&mut self) {
+		*self.by_kind.entry(InputKind::Inspect).or_default() += 1;
+	}
+
+	pub fn window(&self) -> u64 {
+		self.window
+	}
+
+	pub fn sender_count(&self, sender: Address) -> u64 {
+		self.by_sender.get(&sender).copied().unwrap_or(0)
+	}
+
+	pub fn kind_count(&self, kind: InputKind) -> u64 {
+		self.by_kind.get(&kind).copied().unwrap_or(0)
+	}
+
+	/// Counts the advance inputs in the window containing `timestamp`.
+	pub fn window_count(&self, timestamp: u64) -> u64 {
+		self.by_window.get(&self.window_start(timestamp)).copied().unwrap_or(0)
+	}
+
+	pub fn senders(&self) -> Vec<(Address, u64)> {
+		let mut senders: Vec<(Address, u64)> = self.by_sender.iter().map(|(sender, count)| (*sender, *count)).collect();
+		senders.sort();
+		senders
+	}
+
+	fn window_start(&self, timestamp: u64) -> u64 {
+		timestamp - timestamp % self.window
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn metadata(sender: Address, timestamp: u64) -> Metadata {
+		Metadata {
+			input_index: 0,
+			sender,
+			block_number: 0,
+			timestamp,
+			app_contract: None,
+			chain_id: None,
+			prev_randao: None,
+			annotations: Default::default(),
+		}
+	}
+
+	#[test]
+	fn test_activity_counters() {
+		let alice = Address::repeat_byte(1);
+		let bob = Address::repeat_byte(2);
+		let mut activity = ActivityCounters::new(60);
+
+		activity.record_advance(&metadata(alice, 0), InputKind::Advance);
+		activity.record_advance(&metadata(alice, 59), InputKind::Deposit);
+		activity.record_advance(&metadata(bob, 60), InputKind::Advance);
+		activity.record_inspect();
+
+		assert_eq!(activity.sender_count(alice), 2);
+		assert_eq!(activity.sender_count(bob), 1);
+		assert_eq!(activity.kind_count(InputKind::Advance), 2);
+		assert_eq!(activity.kind_count(InputKind::Deposit), 1);
+		assert_eq!(activity.kind_count(InputKind::Inspect), 1);
+		assert_eq!(activity.window_count(30), 2);
+		assert_eq!(activity.window_count(119), 1);
+		assert_eq!(activity.senders(), vec![(alice, 2), (bob, 1)]);
+	}
+
+	#[test]
+	fn test_activity_windows_are_bounded() {
+		let mut activity = ActivityCounters::new(1);
+		for timestamp in 0..100 {
+			activity.record_advance(&metadata(Address::zero(), timestamp), InputKind::Advance);
+		}
+		assert_eq!(activity.window_count(0), 0);
+		assert_eq!(activity.window_count(99), 1);
+	}
+}
@@ -1,19 +1,80 @@
 use super::environment::Environment;
+use crate::types::error::CrabrollsError;
 use crate::types::machine::{Deposit, FinishStatus, Metadata};
 use std::{error::Error, future::Future};
 
 pub trait Application {
+	/// Error of the app, e.g. its own error enum, or `Box<dyn Error>` for any error, taking the `CrabrollsError`s of
+	/// the environment. The `Supervisor` and the `Tester` box it to report it, and `ResultUtils::get_error_as` gets it
+	/// back from the results of the `Tester`.
+	type Error: Into<Box<dyn Error>> + From<CrabrollsError>;
+
+	/// Report sent by the default `inspect`, e.g. a static description of the app. None sends no report.
+	const INSPECT_REPORT: Option<&'static [u8]> = None;
+
+	/// Applies an advance input, only called for plain inputs once `validate` accepted them.
 	fn advance(
 		&self,
 		env: &impl Environment,
 		metadata: Metadata,
 		payload: &[u8],
 		deposit: Option<Deposit>,
-	) -> impl Future<Output = Result<FinishStatus, Box<dyn Error>>>;
+	) -> impl Future<Output = Result<FinishStatus, Self::Error>>;
 
+	/// Accepts, sending the `INSPECT_REPORT` if any, unless overridden, for apps with nothing else to inspect.
 	fn inspect(
 		&self,
 		env: &impl Environment,
-		payload: &[u8],
-	) -> impl Future<Output = Result<FinishStatus, Box<dyn Error>>>;
+		_payload: &[u8],
+	) -> impl Future<Output = Result<FinishStatus, Self::Error>> {
+		async move {
+			if let Some(report) = Self::INSPECT_REPORT {
+				env.send_report(report).await?;
+			}
+			Ok(FinishStatus::Accept)
+		}
+	}
+
+	/// Checks a plain advance input before it's applied, rejecting it cheaply without calling `advance`. It has no
+	/// access to the environment, so invalid inputs can't emit outputs or change the wallets. Deposits aren't
+	/// validated, since their assets must be credited anyway. Accepts every input unless overridden.
+	fn validate(&self, _metadata: &Metadata, _payload: &[u8]) -> Result<(), Self::Error> {
+		Ok(())
+	}
+
+	/// Called by the `Supervisor` before the first input, e.g. to warm caches. An error stops it without processing
+	/// any input.
+	fn on_start(&self, _env: &impl Environment) -> impl Future<Output = Result<(), Self::Error>> {
+		async { Ok(()) }
+	}
+
+	/// Called by the `Supervisor` when its loop terminates, also after an error, e.g. to persist the state or send a
+	/// final report, which goes with the last input.
+	fn on_shutdown(&self, _env: &impl Environment) -> impl Future<Output = Result<(), Self::Error>> {
+		async { Ok(()) }
+	}
+
+	/// Called with the errors returned by `validate`, `advance`, `inspect` and the middlewares before the input is
+	/// rejected, e.g. to count failures or send a custom report.
+	fn on_error(&self, _env: &impl Environment, _error: &(dyn Error + 'static)) -> impl Future<Output = ()> {
+		async {}
+	}
+}
+
+/// Runs the two phases of an advance input: `validate` for plain inputs, then `advance` if it passed.
+pub async fn validate_and_advance(
+	app: &impl Application,
+	env: &impl Environment,
+	metadata: Metadata,
+	payload: &[u8],
+	deposit: Option<Deposit>,
+) -> Result<FinishStatus, Box<dyn Error>> {
+	if deposit.is_none() {
+		if let Err(e) = app.validate(&metadata, payload) {
+			let e = e.into();
+			debug!("Advance input failed validation: {}", e);
+			return Err(e);
+		}
+	}
+	app.advance(env, metadata, payload, deposit).await.map_err(Into::into)
 }
@@ -0,0 +1,361 @@
+use super::application::{validate_and_advance, Application};
+use super::environment::Environment;
+use crate::types::error::CrabrollsError;
+use crate::types::machine::{Deposit, FinishStatus, Metadata};
+use crate::types::primitives::*;
+use crate::types::report::ErrorReport;
+use crate::utils::parsers::deserializers::{deserialize_string_of_bytes, serialize_bytes_as_string};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::error::Error;
+use std::sync::{Arc, RwLock};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Operation {
+	EtherWithdraw {
+		wallet: Address,
+		amount: Uint,
+	},
+	#[cfg(feature = "erc20")]
+	Erc20Withdraw {
+		wallet: Address,
+		token: Address,
+		amount: Uint,
+	},
+	Voucher {
+		destination: Address,
+		#[serde(
+			serialize_with = "serialize_bytes_as_string",
+			deserialize_with = "deserialize_string_of_bytes"
+		)]
+		payload: Vec<u8>,
+	},
+}
+
+impl Operation {
+	pub(crate) async fn execute(&self, env: &impl Environment) -> Result<(), CrabrollsError> {
+		match self.clone() {
+			Operation::EtherWithdraw { wallet, amount } => env.ether_withdraw(wallet, amount).await,
+			#[cfg(feature = "erc20")]
+			Operation::Erc20Withdraw { wallet, token, amount } => env.erc20_withdraw(wallet, token, amount).await,
+			Operation::Voucher { destination, payload } => env.send_voucher(destination, payload).await.map(|_| ()),
+		}
+	}
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct PendingApproval {
+	pub id: u64,
+	pub operation: Operation,
+	pub approvals: BTreeSet<Address>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ApprovalInput {
+	Propose(Operation),
+	Approve(u64),
+	Cancel(u64),
+}
+
+#[derive(Deserialize)]
+struct Envelope {
+	approvals: ApprovalInput,
+}
+
+#[derive(Default)]
+struct State {
+	next_id: u64,
+	pending: BTreeMap<u64, PendingApproval>,
+}
+
+/// N-of-M admin approvals collected across inputs before an operation (e.g. a treasury withdrawal) is executed.
+#[derive(Clone)]
+pub struct Approvals {
+	admins: HashSet<Address>,
+	threshold: usize,
+	state: Arc<RwLock<State>>,
+}
+
+impl Approvals {
+	pub fn new(admins: impl IntoIterator<Item = Address>, threshold: usize) -> Result<Self, Box<dyn Error>> {
+		let admins: HashSet<Address> = admins.into_iter().collect();
+		if threshold == 0 || threshold > admins.len() {
+			return Err(format!("threshold must be between 1 and {}", admins.len()).into());
+		}
+		Ok(Self {
+			admins,
+			threshold,
+			state: Arc::default(),
+		})
+	}
+
+	pub fn is_admin(&self, address: Address) -> bool {
+		self.admins.contains(&address)
+	}
+
+	pub fn pending(&self) -> Vec<PendingApproval> {
+		let state = self.state.read().expect("Approvals lock poisoned");
+		state.pending.values().cloned().collect()
+	}
+
+	pub fn get(&self, id: u64) -> Option<PendingApproval> {
+		self.state
+			.read()
+			.expect("Approvals lock poisoned")
+			.pending
+			.get(&id)
+			.cloned()
+	}
+
+	/// Registers the operation with the proposer approval, executing it right away if that meets the threshold.
+	pub async fn propose(
+		&self,
+		env: &impl Environment,
+		admin: Address,
+		operation: Operation,
+	) -> Result<u64, Box<dyn Error>> {
+		self.ensure_admin(admin)?;
+		let id = {
+			let mut state = self.state.write().expect("Approvals lock poisoned");
+			let id = state.next_id;
+			state.next_id += 1;
+			state.pending.insert(
+				id,
+				PendingApproval {
+					id,
+					operation,
+					approvals: BTreeSet::new(),
+				},
+			);
+			id
+		};
+		debug!("Operation {} proposed by {:?}", id, admin);
+		self.approve(env, admin, id).await?;
+		Ok(id)
+	}
+
+	/// Adds the admin approval, returning `true` if the operation reached the threshold and was executed.
+	pub async fn approve(&self, env: &impl Environment, admin: Address, id: u64) -> Result<bool, Box<dyn Error>> {
+		self.ensure_admin(admin)?;
+		let ready = {
+			let mut state = self.state.write().expect("Approvals lock poisoned");
+			let pending = state
+				.pending
+				.get_mut(&id)
+				.ok_or_else(|| ErrorReport::not_found(format!("operation {} is not pending", id)))?;
+			if !pending.approvals.insert(admin) {
+				return Err(ErrorReport::invalid_payload(format!(
+					"operation {} was already approved by {:?}",
+					id, admin
+				))
+				.into());
+			}
+			match pending.approvals.len() >= self.threshold {
+				true => state.pending.remove(&id),
+				false => None,
+			}
+		};
+
+		match ready {
+			Some(pending) => {
+				debug!("Operation {} reached {} approvals, executing it", id, self.threshold);
+				if let Err(e) = pending.operation.execute(env).await {
+					let mut pending = pending;
+					pending.approvals.remove(&admin);
+					self.state
+						.write()
+						.expect("Approvals lock poisoned")
+						.pending
+						.insert(id, pending);
+					return Err(e.into());
+				}
+				Ok(true)
+			}
+			None => Ok(false),
+		}
+	}
+
+	pub fn cancel(&self, admin: Address, id: u64) -> Result<(), Box<dyn Error>> {
+		self.ensure_admin(admin)?;
+		match self.state.write().expect("Approvals lock poisoned").pending.remove(&id) {
+			Some(_) => Ok(()),
+			None => Err(ErrorReport::not_found(format!("operation {} is not pending", id)).into()),
+		}
+	}
+
+	/// Applies a `{"approvals": ...}` input, returning `None` for any other payload.
+	pub async fn handle_input(
+		&self,
+		env: &impl Environment,
+		sender: Address,
+		payload: &[u8],
+	) -> Option<Result<(), Box<dyn Error>>> {
+		let envelope = serde_json::from_slice::<Envelope>(payload).ok()?;
+		Some(match envelope.approvals {
+			ApprovalInput::Propose(operation) => self.propose(env, sender, operation).await.map(|_| ()),
+			ApprovalInput::Approve(id) => self.approve(env, sender, id).await.map(|_| ()),
+			ApprovalInput::Cancel(id) => self.cancel(sender, id),
+		})
+	}
+
+	fn ensure_admin(&self, address: Address) -> Result<(), Box<dyn Error>> {
+		match self.is_admin(address) {
+			true => Ok(()),
+			false => Err(ErrorReport::unauthorized(format!("{:?} is not an approver", address)).into()),
+		}
+	}
+}
+
+/// Handles the approval inputs, reports the pending operations on the `{"approvals":"pending"}` inspect and passes
+/// every other input to the app.
+pub struct ApprovalGate<A: Application> {
+	app: A,
+	approvals: Approvals,
+}
+
+impl<A: Application> ApprovalGate<A> {
+	pub fn new(app: A, approvals: Approvals) -> Self {
+		Self { app, approvals }
+	}
+
+	pub fn approvals(&self) -> &Approvals {
+		&self.approvals
+	}
+}
+
+impl<A: Application> Application for ApprovalGate<A> {
+	type Error = Box<dyn Error>;
+
+	async fn advance(
+		&self,
+		env: &impl Environment,
+		metadata: Metadata,
+		payload: &[u8],
+		deposit: Option<Deposit>,
+	) -> Result<FinishStatus, Box<dyn Error>> {
+		if deposit.is_none() {
+			if let Some(result) = self.approvals.handle_input(env, metadata.sender, payload).await {
+				result?;
+				return Ok(FinishStatus::Accept);
+			}
+		}
+		validate_and_advance(&self.app, env, metadata, payload, deposit).await
+	}
+
+	async fn inspect(&self, env: &impl Environment, payload: &[u8]) -> Result<FinishStatus, Box<dyn Error>> {
+		if serde_json::from_slice::<serde_json::Value>(payload).ok()
+			== Some(serde_json::json!({ "approvals": "pending" }))
+		{
+			env.send_report_json(&self.approvals.pending()).await?;
+			return Ok(FinishStatus::Accept);
+		}
+		self.app.inspect(env, payload).await.map_err(Into::into)
+	}
+
+	async fn on_start(&self, env: &impl Environment) -> Result<(), Box<dyn Error>> {
+		self.app.on_start(env).await.map_err(Into::into)
+	}
+
+	async fn on_shutdown(&self, env: &impl Environment) -> Result<(), Box<dyn Error>> {
+		self.app.on_shutdown(env).await.map_err(Into::into)
+	}
+
+	async fn on_error(&self, env: &impl Environment, error: &(dyn Error + 'static)) {
+		self.app.on_error(env, error).await
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::core::testing::{MockupOptions, Tester};
+	use crate::types::testing::ResultUtils;
+
+	struct Treasury;
+
+	impl Application for Treasury {
+		type Error = Box<dyn Error>;
+
+		async fn advance(
+			&self,
+			_env: &impl Environment,
+			_metadata: Metadata,
+			_payload: &[u8],
+			_deposit: Option<Deposit>,
+		) -> Result<FinishStatus, Box<dyn Error>> {
+			Ok(FinishStatus::Accept)
+		}
+	}
+
+	#[test]
+	fn test_invalid_threshold() {
+		assert!(Approvals::new([Address::repeat_byte(1)], 0).is_err());
+		assert!(Approvals::new([Address::repeat_byte(1)], 2).is_err());
+	}
+
+	#[async_std::test]
+	async fn test_two_of_three_voucher() {
+		let admins = [
+			Address::repeat_byte(1),
+			Address::repeat_byte(2),
+			Address::repeat_byte(3),
+		];
+		let approvals = Approvals::new(admins, 2).unwrap();
+		let tester = Tester::new(ApprovalGate::new(Treasury, approvals), MockupOptions::default());
+		let propose = format!(
+			r#"{{"approvals":{{"propose":{{"voucher":{{"destination":"{:?}","payload":"0xdeadbeef"}}}}}}}}"#,
+			Address::repeat_byte(9)
+		);
+
+		let result = tester.advance(Address::repeat_byte(7), propose.clone()).await;
+		assert!(result.is_rejected());
+
+		let result = tester.advance(admins[0], propose).await;
+		assert!(result.is_accepted());
+		assert!(result.vouchers().is_empty());
+
+		let result = tester.inspect(r#"{"approvals":"pending"}"#).await;
+		let pending = result.reports_as::<serde_json::Value>().unwrap();
+		assert_eq!(pending[0][0]["id"], 0);
+		assert_eq!(pending[0][0]["approvals"].as_array().unwrap().len(), 1);
+
+		let result = tester.advance(admins[0], r#"{"approvals":{"approve":0}}"#).await;
+		assert!(result.is_rejected());
+
+		let result = tester.advance(admins[2], r#"{"approvals":{"approve":0}}"#).await;
+		assert!(result.is_accepted());
+		assert_eq!(
+			result.vouchers(),
+			vec![(Address::repeat_byte(9), vec![0xde, 0xad, 0xbe, 0xef])]
+		);
+
+		let result = tester.advance(admins[1], r#"{"approvals":{"approve":0}}"#).await;
+		assert!(result.is_rejected());
+	}
+
+	#[async_std::test]
+	async fn test_cancel() {
+		let admins = [Address::repeat_byte(1), Address::repeat_byte(2)];
+		let approvals = Approvals::new(admins, 2).unwrap();
+		let tester = Tester::new(ApprovalGate::new(Treasury, approvals.clone()), MockupOptions::default());
+
+		let result = tester
+			.advance(
+				admins[1],
+				r#"{"approvals":{"propose":{"ether_withdraw":{"wallet":"0x0101010101010101010101010101010101010101","amount":"0x1"}}}}"#,
+			)
+			.await;
+		assert!(result.is_accepted());
+		assert_eq!(approvals.pending().len(), 1);
+
+		let result = tester.advance(admins[0], r#"{"approvals":{"approve":0}}"#).await;
+		assert!(result.is_rejected()); // The wallet has no ether, the operation stays pending
+		assert_eq!(approvals.get(0).unwrap().approvals.len(), 1);
+
+		let result = tester.advance(admins[0], r#"{"approvals":{"cancel":0}}"#).await;
+		assert!(result.is_accepted());
+		assert!(approvals.pending().is_empty());
+	}
+}
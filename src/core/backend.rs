@@ -0,0 +1,68 @@
+use std::path::Path;
+
+pub const MACHINE_ROLLUP_URL: &str = "http://127.0.0.1:5004";
+pub const HOST_ROLLUP_URL: &str = "http://127.0.0.1:8080/host-runner";
+pub const ROLLUP_DEVICES: &[&str] = &["/dev/cmio", "/dev/rollup"];
+pub const ROLLUP_URL_VAR: &str = "ROLLUP_HTTP_SERVER_URL";
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Backend {
+	#[default]
+	Explicit, // Rollup URL of the options, the rollup HTTP server of the Cartesi machine when unset
+	Auto,    // Detect whether the app runs inside a Cartesi machine or on a developer host
+	Machine, // Rollup HTTP server of the Cartesi machine
+	Host,    // Host runner of `cartesi run --no-backend`
+}
+
+impl Backend {
+	/// Inside a Cartesi machine, the rollup device is present and the init script exports the rollup server URL.
+	pub fn detect() -> Self {
+		Self::detect_in(ROLLUP_DEVICES, std::env::var_os(ROLLUP_URL_VAR).is_some())
+	}
+
+	fn detect_in(devices: &[&str], url_var: bool) -> Self {
+		if url_var || devices.iter().any(|device| Path::new(device).exists()) {
+			Self::Machine
+		} else {
+			Self::Host
+		}
+	}
+
+	pub fn resolve(self) -> Self {
+		match self {
+			Self::Auto => Self::detect(),
+			backend => backend,
+		}
+	}
+
+	/// URL of the rollup server of the backend, used when no rollup URL is set on the options.
+	pub fn rollup_url(self) -> &'static str {
+		match self.resolve() {
+			Self::Host => HOST_ROLLUP_URL,
+			_ => MACHINE_ROLLUP_URL,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_detect_backend() {
+		assert_eq!(Backend::detect_in(&["/crabrolls/missing/device"], false), Backend::Host);
+		assert_eq!(
+			Backend::detect_in(&["/crabrolls/missing/device"], true),
+			Backend::Machine
+		);
+		assert_eq!(Backend::detect_in(&["/"], false), Backend::Machine);
+	}
+
+	#[test]
+	fn test_backend_rollup_url() {
+		assert_eq!(Backend::Host.rollup_url(), HOST_ROLLUP_URL);
+		assert_eq!(Backend::Machine.rollup_url(), MACHINE_ROLLUP_URL);
+		assert_eq!(Backend::Explicit.rollup_url(), MACHINE_ROLLUP_URL);
+		assert_eq!(Backend::Explicit.resolve(), Backend::Explicit);
+	}
+}
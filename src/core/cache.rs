@@ -0,0 +1,53 @@
+use crate::utils::hash::keccak256;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Default)]
+pub struct InspectCache {
+	capacity: usize,
+	entries: HashMap<[u8; 32], Vec<Vec<u8>>>,
+}
+
+impl InspectCache {
+	pub fn new(capacity: usize) -> Self {
+		Self {
+			capacity,
+			entries: HashMap::new(),
+		}
+	}
+
+	pub fn get(&self, payload: &[u8]) -> Option<&Vec<Vec<u8>>> {
+		self.entries.get(&keccak256(payload))
+	}
+
+	pub fn insert(&mut self, payload: &[u8], reports: Vec<Vec<u8>>) {
+		let key = keccak256(payload);
+		if self.entries.len() >= self.capacity && !self.entries.contains_key(&key) {
+			debug!("Inspect cache is full, not caching the response");
+			return;
+		}
+		self.entries.insert(key, reports);
+	}
+
+	pub fn invalidate(&mut self) {
+		self.entries.clear();
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_inspect_cache() {
+		let mut cache = InspectCache::new(1);
+		cache.insert(b"total", vec![b"42".to_vec()]);
+		assert_eq!(cache.get(b"total"), Some(&vec![b"42".to_vec()]));
+		assert_eq!(cache.get(b"other"), None);
+
+		cache.insert(b"other", vec![]);
+		assert_eq!(cache.get(b"other"), None);
+
+		cache.invalidate();
+		assert_eq!(cache.get(b"total"), None);
+	}
+}
@@ -0,0 +1,265 @@
+use super::application::Application;
+use super::dynamic::{DynEnv, DynEnvironment, LocalBoxFuture};
+use super::environment::Environment;
+use crate::types::error::CrabrollsError;
+use crate::types::machine::{Deposit, FinishStatus, Metadata};
+use crate::types::report::ErrorReport;
+use crate::utils::abi::abi::encode::{function_selector, parse_signature};
+use ethabi::param_type::Writer;
+use ethabi::{decode, ParamType, Token};
+use std::collections::HashMap;
+use std::error::Error;
+use std::future::Future;
+use std::sync::Arc;
+
+/// Handler of the calls to a function routed by a `CallRouter`, with the arguments decoded from the calldata.
+pub trait CallHandler {
+	fn call(
+		&self,
+		env: &impl Environment,
+		metadata: Metadata,
+		args: Vec<Token>,
+		deposit: Option<Deposit>,
+	) -> impl Future<Output = Result<FinishStatus, Box<dyn Error>>>;
+}
+
+trait DynCallHandler {
+	fn dyn_call<'a>(
+		&'a self,
+		env: &'a dyn DynEnvironment,
+		metadata: Metadata,
+		args: Vec<Token>,
+		deposit: Option<Deposit>,
+	) -> LocalBoxFuture<'a, Result<FinishStatus, Box<dyn Error>>>;
+}
+
+impl<H: CallHandler> DynCallHandler for H {
+	fn dyn_call<'a>(
+		&'a self,
+		env: &'a dyn DynEnvironment,
+		metadata: Metadata,
+		args: Vec<Token>,
+		deposit: Option<Deposit>,
+	) -> LocalBoxFuture<'a, Result<FinishStatus, Box<dyn Error>>> {
+		Box::pin(async move { self.call(&DynEnv(env), metadata, args, deposit).await })
+	}
+}
+
+#[derive(Clone)]
+struct Route {
+	signature: String,
+	types: Vec<ParamType>,
+	handler: Arc<dyn DynCallHandler + Send + Sync>,
+}
+
+/// Dispatches the advance inputs carrying ABI-encoded calldata, e.g. sent by a frontend with the interface of a
+/// Solidity contract, to the handler registered for the selector of the call. Inputs with an unknown selector are
+/// rejected with a `NO_ROUTE` error and the ones whose arguments don't decode with an `INVALID_PAYLOAD` error.
+/// Deposits are routed like the other inputs when their payload is a call to a registered function, and accepted
+/// otherwise, keeping the deposited assets in the wallet of the sender.
+#[derive(Clone, Default)]
+pub struct CallRouter {
+	routes: HashMap<[u8; 4], Route>,
+}
+
+impl CallRouter {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Routes the calls to a function given by its human-readable signature, e.g. `transfer(address,uint256)`.
+	/// Returns whether a handler was replaced.
+	pub fn route(
+		&mut self,
+		signature: &str,
+		handler: impl CallHandler + Send + Sync + 'static,
+	) -> Result<bool, CrabrollsError> {
+		let (name, types) = parse_signature(signature)?;
+		Ok(self.register(function_selector(&name, &types), types, handler))
+	}
+
+	/// Routes the calls with a selector, whose arguments are decoded as `types`. Returns whether a handler was replaced.
+	pub fn register(
+		&mut self,
+		selector: [u8; 4],
+		types: Vec<ParamType>,
+		handler: impl CallHandler + Send + Sync + 'static,
+	) -> bool {
+		let types_list: Vec<String> = types.iter().map(Writer::write).collect();
+		let signature = format!("0x{}({})", hex::encode(selector), types_list.join(","));
+		debug!("Routing the calls with selector {}", signature);
+
+		let route = Route {
+			signature,
+			types,
+			handler: Arc::new(handler),
+		};
+		self.routes.insert(selector, route).is_some()
+	}
+
+	pub fn unregister(&mut self, selector: [u8; 4]) -> bool {
+		self.routes.remove(&selector).is_some()
+	}
+
+	pub fn contains(&self, selector: [u8; 4]) -> bool {
+		self.routes.contains_key(&selector)
+	}
+
+	pub fn selectors(&self) -> Vec<[u8; 4]> {
+		self.routes.keys().copied().collect()
+	}
+}
+
+impl Application for CallRouter {
+	type Error = Box<dyn Error>;
+
+	async fn advance(
+		&self,
+		env: &impl Environment,
+		metadata: Metadata,
+		payload: &[u8],
+		deposit: Option<Deposit>,
+	) -> Result<FinishStatus, Box<dyn Error>> {
+		let route = payload.get(..4).and_then(|selector| self.routes.get(selector));
+		let route = match (route, &deposit) {
+			(Some(route), _) => route,
+			(None, Some(_)) => return Ok(FinishStatus::Accept),
+			(None, None) => return Err(ErrorReport::no_route().input_index(metadata.input_index).into()),
+		};
+		let args = decode(&route.types, &payload[4..]).map_err(|e| {
+			ErrorReport::invalid_payload(format!("invalid arguments for {}: {}", route.signature, e))
+				.input_index(metadata.input_index)
+		})?;
+
+		debug!("Advance input handled by the call {}", route.signature);
+		route.handler.dyn_call(env, metadata, args, deposit).await
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::core::testing::{MockupOptions, RollupMockup, Tester};
+	use crate::types::primitives::*;
+	use crate::types::testing::ResultUtils;
+	use crate::utils::abi::abi;
+	use std::sync::Mutex;
+
+	/// Keeps the balances of a token shared by the handlers of its calls.
+	#[derive(Clone, Default)]
+	struct Ledger(Arc<Mutex<HashMap<Address, Uint>>>);
+
+	struct Mint(Ledger);
+
+	impl CallHandler for Mint {
+		async fn call(
+			&self,
+			_env: &impl Environment,
+			metadata: Metadata,
+			args: Vec<Token>,
+			_deposit: Option<Deposit>,
+		) -> Result<FinishStatus, Box<dyn Error>> {
+			let amount = abi::extract::uint(&args[0])?;
+			*self.0 .0.lock().unwrap().entry(metadata.sender).or_default() += amount;
+			Ok(FinishStatus::Accept)
+		}
+	}
+
+	struct Transfer(Ledger);
+
+	impl CallHandler for Transfer {
+		async fn call(
+			&self,
+			env: &impl Environment,
+			metadata: Metadata,
+			args: Vec<Token>,
+			_deposit: Option<Deposit>,
+		) -> Result<FinishStatus, Box<dyn Error>> {
+			let (to, amount) = (abi::extract::address(&args[0])?, abi::extract::uint(&args[1])?);
+			{
+				let mut balances = self.0 .0.lock().unwrap();
+				let balance = balances.entry(metadata.sender).or_default();
+				if *balance < amount {
+					return Err("insufficient balance".into());
+				}
+				*balance -= amount;
+				*balances.entry(to).or_default() += amount;
+			}
+			env.send_notice(format!("{:?} -> {:?}: {}", metadata.sender, to, amount)).await?;
+			Ok(FinishStatus::Accept)
+		}
+	}
+
+	#[async_std::test]
+	async fn test_call_router() {
+		let ledger = Ledger::default();
+		let mut router = CallRouter::new();
+		assert!(!router.route("mint(uint)", Mint(ledger.clone())).unwrap());
+		let transfer = abi::encode::selector("transfer(address,uint256)").unwrap();
+		router.register(transfer, vec![ParamType::Address, ParamType::Uint(256)], Transfer(ledger.clone()));
+		assert!(router.contains(transfer));
+		assert!(router.route("mint(uint", Mint(ledger.clone())).is_err());
+		let tester = Tester::new(router, MockupOptions::default());
+
+		let (alice, bob) = (Address::repeat_byte(0xa), Address::repeat_byte(0xb));
+		let mint = abi::encode::call("mint(uint256)", vec![Token::Uint(ethabi::Uint::from(10))]).unwrap();
+		tester.advance(alice, mint).await.assert_accepted().assert_output_count(0);
+
+		let args = vec![Token::Address(bob.into_ethabi()), Token::Uint(ethabi::Uint::from(4))];
+		let call = abi::encode::call("transfer(address,uint256)", args).unwrap();
+		let result = tester.advance(alice, &call).await;
+		result.assert_accepted().assert_notice(format!("{:?} -> {:?}: 4", alice, bob));
+		assert_eq!(ledger.0.lock().unwrap()[&bob], Uint::from(4));
+		let result = tester.advance(Address::repeat_byte(0xc), &call).await;
+		result.assert_rejected().assert_error_contains("insufficient balance");
+
+		let result = tester.advance(alice, abi::encode::call("burn()", vec![]).unwrap()).await;
+		let report = ErrorReport::from_error(result.assert_rejected().get_error().unwrap());
+		assert_eq!((report.code.as_str(), report.input_index), (ErrorReport::NO_ROUTE, Some(3)));
+		let result = tester.advance(alice, &call[..20]).await;
+		let report = ErrorReport::from_error(result.assert_rejected().get_error().unwrap());
+		assert_eq!(report.code, ErrorReport::INVALID_PAYLOAD);
+		assert!(report.message.contains("0xa9059cbb(address,uint256)"));
+
+		let deposit = Deposit::Ether {
+			sender: alice,
+			amount: Uint::from(1),
+		};
+		tester.deposit(deposit).await.assert_accepted();
+	}
+
+	#[async_std::test]
+	async fn test_call_router_deposits() {
+		let ledger = Ledger::default();
+		let mut router = CallRouter::new();
+		router.route("mint(uint256)", Mint(ledger.clone())).unwrap();
+		let env = RollupMockup::new();
+		let alice = Address::repeat_byte(0xa);
+		let metadata = Metadata {
+			input_index: 0,
+			sender: alice,
+			block_number: 0,
+			timestamp: 0,
+			app_contract: None,
+			chain_id: None,
+			prev_randao: None,
+			annotations: Default::default(),
+		};
+		let deposit = Deposit::Ether {
+			sender: alice,
+			amount: Uint::from(1),
+		};
+
+		// Deposits whose payload isn't a routed call are accepted, keeping the deposited assets
+		for payload in [&b""[..], b"not a call", &abi::encode::call("burn()", vec![]).unwrap()] {
+			let status = router.advance(&env, metadata.clone(), payload, Some(deposit.clone()));
+			assert_eq!(status.await.unwrap(), FinishStatus::Accept);
+		}
+		assert!(ledger.0.lock().unwrap().is_empty());
+
+		let mint = abi::encode::call("mint(uint256)", vec![Token::Uint(ethabi::Uint::from(10))]).unwrap();
+		let status = router.advance(&env, metadata, &mint, Some(deposit)).await.unwrap();
+		assert_eq!(status, FinishStatus::Accept);
+		assert_eq!(ledger.0.lock().unwrap()[&alice], Uint::from(10));
+	}
+}
@@ -0,0 +1,165 @@
+use crate::types::primitives::*;
+use crate::utils::hash::keccak256;
+use ethabi::Hash;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::sync::{Arc, RwLock};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Commitment {
+	pub owner: Address,
+	pub hash: Hash,
+	pub committed_at: u64, // Timestamp of the commit input
+	pub deadline: u64,     // Last timestamp at which the value can be revealed
+}
+
+#[derive(Default)]
+struct State {
+	commitments: BTreeMap<Address, Commitment>,
+	revealed: BTreeMap<Address, Vec<u8>>,
+}
+
+/// Commitments of hidden values revealed in a later input, e.g. sealed bids, game moves or contributions to a fair
+/// random seed. Each account has at most one commitment, so a round (auction, game turn, ...) uses its own instance.
+#[derive(Clone, Default)]
+pub struct CommitReveal {
+	state: Arc<RwLock<State>>,
+}
+
+impl CommitReveal {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Hash to commit to `value`, as `keccak256(owner | salt | value)`. The owner is part of it so a commitment can't
+	/// be copied by another account, and the random salt keeps small value sets from being brute-forced.
+	pub fn hash(owner: Address, value: &[u8], salt: [u8; 32]) -> Hash {
+		Hash::from(keccak256([owner.as_bytes(), &salt, value].concat()))
+	}
+
+	/// Records the commitment of the owner, which must be revealed up to the `deadline` timestamp.
+	pub fn commit(&self, owner: Address, hash: Hash, now: u64, deadline: u64) -> Result<(), Box<dyn Error>> {
+		if deadline < now {
+			return Err(format!("reveal deadline {} is before the commit time {}", deadline, now).into());
+		}
+
+		let mut state = self.state.write().expect("Commit-reveal lock poisoned");
+		if state.commitments.contains_key(&owner) || state.revealed.contains_key(&owner) {
+			return Err(format!("{:?} has already committed", owner).into());
+		}
+		state.commitments.insert(
+			owner,
+			Commitment {
+				owner,
+				hash,
+				committed_at: now,
+				deadline,
+			},
+		);
+		debug!("Commitment of {:?} recorded until {}", owner, deadline);
+		Ok(())
+	}
+
+	/// Checks the revealed value against the commitment of the owner and records it. A mismatch keeps the commitment,
+	/// so it can still be revealed before the deadline.
+	pub fn reveal(&self, owner: Address, value: &[u8], salt: [u8; 32], now: u64) -> Result<(), Box<dyn Error>> {
+		let mut state = self.state.write().expect("Commit-reveal lock poisoned");
+		let commitment = state
+			.commitments
+			.get(&owner)
+			.ok_or_else(|| format!("no commitment to reveal for {:?}", owner))?;
+		if now > commitment.deadline {
+			return Err(format!("reveal deadline {} of {:?} has passed", commitment.deadline, owner).into());
+		}
+		if Self::hash(owner, value, salt) != commitment.hash {
+			return Err(format!("revealed value doesn't match the commitment of {:?}", owner).into());
+		}
+
+		state.commitments.remove(&owner);
+		state.revealed.insert(owner, value.to_vec());
+		debug!("Commitment of {:?} revealed", owner);
+		Ok(())
+	}
+
+	/// Removes the commitments not revealed by their deadline and returns them, e.g. to forfeit their stakes.
+	pub fn expire(&self, now: u64) -> Vec<Commitment> {
+		let mut state = self.state.write().expect("Commit-reveal lock poisoned");
+		let expired: Vec<Commitment> = state
+			.commitments
+			.values()
+			.filter(|commitment| now > commitment.deadline)
+			.cloned()
+			.collect();
+		for commitment in &expired {
+			state.commitments.remove(&commitment.owner);
+		}
+		expired
+	}
+
+	pub fn commitment(&self, owner: Address) -> Option<Commitment> {
+		let state = self.state.read().expect("Commit-reveal lock poisoned");
+		state.commitments.get(&owner).cloned()
+	}
+
+	/// Commitments not revealed yet, including the expired ones until `expire` is called.
+	pub fn pending(&self) -> Vec<Commitment> {
+		let state = self.state.read().expect("Commit-reveal lock poisoned");
+		state.commitments.values().cloned().collect()
+	}
+
+	pub fn revealed(&self, owner: Address) -> Option<Vec<u8>> {
+		let state = self.state.read().expect("Commit-reveal lock poisoned");
+		state.revealed.get(&owner).cloned()
+	}
+
+	/// Revealed values by owner, in address order.
+	pub fn reveals(&self) -> Vec<(Address, Vec<u8>)> {
+		let state = self.state.read().expect("Commit-reveal lock poisoned");
+		state
+			.revealed
+			.iter()
+			.map(|(owner, value)| (*owner, value.clone()))
+			.collect()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const ALICE: Address = Address::repeat_byte(0xa1);
+	const BOB: Address = Address::repeat_byte(0xb0);
+	const CAROL: Address = Address::repeat_byte(0xc0);
+
+	#[test]
+	fn test_commit_reveal() {
+		let bids = CommitReveal::new();
+		let salt = [7u8; 32];
+
+		bids.commit(ALICE, CommitReveal::hash(ALICE, b"100", salt), 10, 20)
+			.unwrap();
+		bids.commit(BOB, CommitReveal::hash(ALICE, b"100", salt), 11, 20)
+			.unwrap(); // Copied from Alice
+		bids.commit(CAROL, CommitReveal::hash(CAROL, b"50", salt), 12, 20)
+			.unwrap();
+		assert!(bids.commit(ALICE, Hash::zero(), 13, 20).is_err());
+		assert!(bids.commit(Address::zero(), Hash::zero(), 13, 12).is_err());
+
+		assert!(bids.reveal(ALICE, b"101", salt, 15).is_err());
+		bids.reveal(ALICE, b"100", salt, 15).unwrap();
+		assert!(bids.reveal(BOB, b"100", salt, 16).is_err());
+		assert!(bids.reveal(ALICE, b"100", salt, 16).is_err());
+		assert!(bids.reveal(CAROL, b"50", salt, 21).is_err());
+
+		assert_eq!(bids.revealed(ALICE), Some(b"100".to_vec()));
+		assert_eq!(bids.pending().len(), 2);
+		assert!(bids.expire(20).is_empty());
+
+		let expired = bids.expire(21);
+		let owners: Vec<Address> = expired.iter().map(|commitment| commitment.owner).collect();
+		assert_eq!(owners, vec![BOB, CAROL]);
+		assert!(bids.pending().is_empty());
+		assert_eq!(bids.reveals(), vec![(ALICE, b"100".to_vec())]);
+	}
+}
@@ -0,0 +1,168 @@
+use super::environment::Environment;
+use super::handoff::WalletsSnapshot;
+use crate::utils::hash::keccak256;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::sync::{Arc, RwLock};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StateCommitment {
+	pub input_index: u64,
+	pub wallets_hash: String,
+	pub app_hash: String,
+	pub previous: String, // Root of the previous commitment, zero for the first one
+	pub root: String,     // keccak256(previous | input_index (8 bytes BE) | wallets_hash | app_hash)
+}
+
+#[derive(Debug, Default)]
+struct State {
+	app_hash: [u8; 32],
+	root: [u8; 32],
+	last: Option<StateCommitment>,
+}
+
+/// Rolling commitment over the wallets and the app state, emitted as a `{"state_commitment": ...}` notice after every
+/// accepted advance so watchers can detect diverging nodes.
+#[derive(Clone, Debug, Default)]
+pub struct StateCommitments {
+	state: Arc<RwLock<State>>,
+}
+
+impl StateCommitments {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Sets the hash of the app state committed from now on, e.g. a `canonical_json::hash` of it.
+	pub fn set_app_hash(&self, app_hash: [u8; 32]) {
+		self.state.write().expect("State commitments lock poisoned").app_hash = app_hash;
+	}
+
+	pub fn root(&self) -> [u8; 32] {
+		self.state.read().expect("State commitments lock poisoned").root
+	}
+
+	pub fn last(&self) -> Option<StateCommitment> {
+		self.state.read().expect("State commitments lock poisoned").last.clone()
+	}
+
+	/// Rolls the commitment forward for the input and sends it as a notice.
+	pub async fn emit(&self, env: &impl Environment, input_index: u64) -> Result<StateCommitment, Box<dyn Error>> {
+		let wallets_hash = keccak256(WalletsSnapshot::capture(env).await.encode()?);
+		let (previous, app_hash) = {
+			let state = self.state.read().expect("State commitments lock poisoned");
+			(state.root, state.app_hash)
+		};
+
+		let mut preimage = previous.to_vec();
+		preimage.extend_from_slice(&input_index.to_be_bytes());
+		preimage.extend_from_slice(&wallets_hash);
+		preimage.extend_from_slice(&app_hash);
+		let root = keccak256(preimage);
+
+		let commitment = StateCommitment {
+			input_index,
+			wallets_hash: format!("0x{}", hex::encode(wallets_hash)),
+			app_hash: format!("0x{}", hex::encode(app_hash)),
+			previous: format!("0x{}", hex::encode(previous)),
+			root: format!("0x{}", hex::encode(root)),
+		};
+		env.send_notice(serde_json::to_vec(
+			&serde_json::json!({ "state_commitment": commitment }),
+		)?)
+		.await?;
+
+		let mut state = self.state.write().expect("State commitments lock poisoned");
+		state.root = root;
+		state.last = Some(commitment.clone());
+		Ok(commitment)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::core::application::Application;
+	use crate::core::testing::{MockupOptions, Tester};
+	use crate::types::machine::{Deposit, FinishStatus, Metadata};
+	use crate::types::primitives::*;
+	use crate::types::testing::ResultUtils;
+	use serde_json::Value;
+
+	struct Counter {
+		commitments: StateCommitments,
+	}
+
+	impl Application for Counter {
+		type Error = Box<dyn Error>;
+
+		async fn advance(
+			&self,
+			_env: &impl Environment,
+			_metadata: Metadata,
+			payload: &[u8],
+			_deposit: Option<Deposit>,
+		) -> Result<FinishStatus, Box<dyn Error>> {
+			match payload {
+				b"reject" => Ok(FinishStatus::Reject),
+				_ => {
+					self.commitments.set_app_hash(keccak256(payload));
+					Ok(FinishStatus::Accept)
+				}
+			}
+		}
+	}
+
+	fn tester(commitments: &StateCommitments) -> Tester<Counter> {
+		let options = MockupOptions::builder().state_commitments(commitments.clone()).build();
+		Tester::new(
+			Counter {
+				commitments: commitments.clone(),
+			},
+			options,
+		)
+	}
+
+	#[async_std::test]
+	async fn test_commitment_notices() {
+		let commitments = StateCommitments::new();
+		let tester = tester(&commitments);
+
+		let result = tester.advance(Address::zero(), b"first").await;
+		let notices = result.notices_as::<Value>().unwrap();
+		let first: StateCommitment = serde_json::from_value(notices[0]["state_commitment"].clone()).unwrap();
+		assert_eq!(first.input_index, 0);
+		assert_eq!(first.previous, format!("0x{}", hex::encode([0u8; 32])));
+		assert_eq!(first.app_hash, format!("0x{}", hex::encode(keccak256(b"first"))));
+		assert_eq!(first.root, format!("0x{}", hex::encode(commitments.root())));
+
+		let result = tester.advance(Address::zero(), b"reject").await;
+		assert!(result.notices().is_empty());
+		assert_eq!(commitments.last().unwrap(), first);
+
+		tester.advance(Address::zero(), b"second").await;
+		let second = commitments.last().unwrap();
+		assert_eq!(second.input_index, 2);
+		assert_eq!(second.previous, first.root);
+	}
+
+	#[async_std::test]
+	async fn test_commitments_follow_the_state() {
+		let (left, right) = (StateCommitments::new(), StateCommitments::new());
+		let (left_tester, right_tester) = (tester(&left), tester(&right));
+		for tester in [&left_tester, &right_tester] {
+			tester.advance(Address::zero(), b"same").await;
+		}
+		assert_eq!(left.root(), right.root());
+
+		right_tester
+			.deposit(Deposit::Ether {
+				sender: Address::zero(),
+				amount: Uint::one(),
+			})
+			.await;
+		left_tester.advance(Address::zero(), b"same").await;
+		assert_ne!(left.last().unwrap().wallets_hash, right.last().unwrap().wallets_hash);
+		assert_ne!(left.root(), right.root());
+	}
+}
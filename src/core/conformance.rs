@@ -0,0 +1,189 @@
+use crate::types::machine::{FinishStatus, IndexResponse, Input, Output};
+use crate::types::primitives::*;
+use crate::utils::codec;
+use serde_json::Value;
+use std::error::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Route {
+	Finish,  // POST /finish
+	Voucher, // POST /voucher
+	Notice,  // POST /notice
+	Report,  // POST /report
+}
+
+/// A request sent by the application to the rollup HTTP server, and the node's response to it.
+#[derive(Debug, Clone, Copy)]
+pub struct Fixture {
+	pub name: &'static str,
+	pub route: Route,
+	pub request: &'static str,
+	pub status: u16,
+	pub response: &'static str, // Empty when the node sends no body
+}
+
+pub const FIXTURES: &[Fixture] = &[
+	Fixture {
+		name: "finish accept, advance input",
+		route: Route::Finish,
+		request: r#"{"status":"accept"}"#,
+		status: 200,
+		response: r#"{
+			"request_type": "advance_state",
+			"data": {
+				"metadata": {
+					"msg_sender": "0xf39fd6e51aad88f6f4ce6ab8827279cfffb92266",
+					"epoch_index": 0,
+					"input_index": 0,
+					"block_number": 11,
+					"timestamp": 1700000000
+				},
+				"payload": "0x48656c6c6f"
+			}
+		}"#,
+	},
+	Fixture {
+		name: "finish accept, advance input with the v2 metadata",
+		route: Route::Finish,
+		request: r#"{"status":"accept"}"#,
+		status: 200,
+		response: r#"{
+			"request_type": "advance_state",
+			"data": {
+				"metadata": {
+					"chain_id": 31337,
+					"app_contract": "0xab7528bb862fb57e8a2bcd567a2e929a0be56a5e",
+					"msg_sender": "0xf39fd6e51aad88f6f4ce6ab8827279cfffb92266",
+					"input_index": 1,
+					"block_number": 12,
+					"block_timestamp": 1700000012,
+					"prev_randao": "0x0000000000000000000000000000000000000000000000000000000000000001"
+				},
+				"payload": "0x"
+			}
+		}"#,
+	},
+	Fixture {
+		name: "finish reject, inspect input",
+		route: Route::Finish,
+		request: r#"{"status":"reject"}"#,
+		status: 200,
+		response: r#"{"request_type": "inspect_state", "data": {"payload": "0x7374617465"}}"#,
+	},
+	Fixture {
+		name: "finish accept, no pending input",
+		route: Route::Finish,
+		request: r#"{"status":"accept"}"#,
+		status: 202,
+		response: "",
+	},
+	Fixture {
+		name: "voucher",
+		route: Route::Voucher,
+		request: r#"{"destination":"0x59b22d57d4f067708ab0c00552767405926dc768","value":"0x0000000000000000000000000000000000000000000000000000000000000000","payload":"0xa9059cbb"}"#,
+		status: 201,
+		response: r#"{"index": 0}"#,
+	},
+	Fixture {
+		name: "voucher with value",
+		route: Route::Voucher,
+		request: r#"{"destination":"0x70997970c51812dc3a010c7d01b50e0d17dc79c8","value":"0x0000000000000000000000000000000000000000000000000de0b6b3a7640000","payload":"0x"}"#,
+		status: 201,
+		response: r#"{"index": 1}"#,
+	},
+	Fixture {
+		name: "notice",
+		route: Route::Notice,
+		request: r#"{"payload":"0x48656c6c6f"}"#,
+		status: 201,
+		response: r#"{"index": 3}"#,
+	},
+	Fixture {
+		name: "report",
+		route: Route::Report,
+		request: r#"{"payload":"0x"}"#,
+		status: 202,
+		response: "",
+	},
+];
+
+/// Checks that crabrolls encodes the fixture request identically and accepts the node response.
+pub fn check(fixture: &Fixture) -> Result<(), Box<dyn Error>> {
+	let request: Value = serde_json::from_str(fixture.request)?;
+	let encoded = match fixture.route {
+		Route::Finish => serde_json::to_value(serde_json::from_value::<FinishStatus>(request.clone())?)?,
+		route => output_from_request(route, &request)?.to_request()?,
+	};
+	if encoded != request {
+		return Err(format!("{}: crabrolls sends {} instead of {}", fixture.name, encoded, request).into());
+	}
+
+	match (fixture.route, fixture.status) {
+		(Route::Finish, 200) => {
+			Input::from_finish_response(serde_json::from_str(fixture.response)?)
+				.map_err(|e| format!("{}: {}", fixture.name, e))?;
+		}
+		(Route::Voucher | Route::Notice, 200 | 201) => {
+			serde_json::from_str::<IndexResponse>(fixture.response).map_err(|e| format!("{}: {}", fixture.name, e))?;
+		}
+		(Route::Finish | Route::Report, 202) => {}
+		(_, status) => return Err(format!("{}: unexpected status {}", fixture.name, status).into()),
+	}
+	Ok(())
+}
+
+pub fn check_all() -> Result<(), Box<dyn Error>> {
+	FIXTURES.iter().try_for_each(check)
+}
+
+fn output_from_request(route: Route, request: &Value) -> Result<Output, Box<dyn Error>> {
+	let payload = codec::decode_hex(request["payload"].as_str().ok_or("missing payload")?.as_bytes())?;
+	match route {
+		Route::Voucher => Ok(Output::Voucher {
+			destination: serde_json::from_value::<Address>(request["destination"].clone())?,
+			value: match request.get("value") {
+				Some(value) => {
+					Uint::from_big_endian(&codec::decode_hex(value.as_str().ok_or("invalid value")?.as_bytes())?)
+				}
+				None => Uint::zero(),
+			},
+			payload,
+		}),
+		Route::Notice => Ok(Output::Notice { payload }),
+		Route::Report => Ok(Output::Report { payload }),
+		Route::Finish => Err("finish requests are not outputs".into()),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_fixtures() {
+		check_all().unwrap();
+	}
+
+	#[test]
+	fn test_broken_fixtures() {
+		let fixture = Fixture {
+			name: "missing timestamp",
+			response: r#"{
+				"request_type": "advance_state",
+				"data": {
+					"metadata": { "msg_sender": "0xf39fd6e51aad88f6f4ce6ab8827279cfffb92266", "input_index": 0, "block_number": 11 },
+					"payload": "0x"
+				}
+			}"#,
+			..FIXTURES[0]
+		};
+		assert!(check(&fixture).is_err());
+
+		let fixture = Fixture {
+			name: "uppercase status",
+			request: r#"{"status":"ACCEPT"}"#,
+			..FIXTURES[3]
+		};
+		assert!(check(&fixture).is_err());
+	}
+}
@@ -1,56 +1,414 @@
+#[cfg(feature = "rollup")]
+use super::about::{About, BuildInfo, ABOUT_ROUTE};
+#[cfg(feature = "rollup")]
+use super::activity::InputKind;
+#[cfg(feature = "rollup")]
+use super::activity::DEFAULT_ACTIVITY_WINDOW;
+#[cfg(feature = "rollup")]
+use super::application::Application;
+#[cfg(feature = "rollup")]
+use super::backend::{Backend, MACHINE_ROLLUP_URL, ROLLUP_URL_VAR};
+#[cfg(feature = "rollup")]
+use super::cache::InspectCache;
+#[cfg(feature = "rollup")]
+use super::commitment::StateCommitments;
+#[cfg(feature = "rollup")]
+use super::contracts::decoder::DepositDecoder;
+use super::contracts::decoder::DepositDecoders;
+#[cfg(feature = "rollup")]
 use super::environment::Rollup;
-use super::{application::Application, environment::RollupInternalEnvironment};
+use super::environment::{Environment, RollupInternalEnvironment};
+#[cfg(feature = "rollup")]
+use super::handoff::{self, HandoffOptions};
+#[cfg(feature = "rollup")]
+use super::heartbeat::Heartbeat;
+#[cfg(feature = "rollup")]
+use super::idempotency::{DedupWindow, IdempotencyKeys};
+#[cfg(feature = "memory-tracking")]
+use super::memory::{self, MemoryLimit};
+#[cfg(feature = "rollup")]
+use super::middleware::{Middleware, Middlewares};
+#[cfg(feature = "rollup")]
+use super::playground::Playground;
+#[cfg(feature = "rollup")]
+use super::retry::{self, RetryPolicy};
+#[cfg(feature = "rollup")]
+use super::schedule::VoucherSchedule;
+#[cfg(feature = "rollup")]
+use crate::types::address_book::AddressBook;
+#[cfg(feature = "rollup")]
+use crate::types::balance::{BalanceChange, DepositReceipt};
+use crate::types::error::CrabrollsError;
+#[cfg(feature = "rollup")]
 use crate::types::machine::{Advance, Inspect};
+use crate::types::machine::{Metadata, PreProcess};
+#[cfg(feature = "rollup")]
+use crate::types::machine::{OutputLimits, PortalHandlerConfig, VoucherDedupPolicy};
+use crate::types::machine::SenderPolicy;
+use crate::types::report::ErrorReport;
+#[cfg(feature = "rollup")]
+use crate::utils::codec;
+use crate::utils::codec::PayloadCodec;
 use crate::{
 	prelude::Deposit,
-	types::address_book::AddressBook,
-	types::machine::{FinishStatus, Input, PortalHandlerConfig},
+	types::machine::{DepositRejectPolicy, FinishStatus, Input},
 };
-use ethabi::Address;
+use crate::types::primitives::*;
+use std::collections::HashSet;
 use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+#[cfg(feature = "rollup")]
+use std::time::Duration;
 
+type PreProcessFuture = Pin<Box<dyn Future<Output = Result<PreProcess, Box<dyn Error>>>>>;
+
+#[derive(Clone)]
+pub struct PreProcessor(Arc<dyn Fn(Metadata, Vec<u8>) -> PreProcessFuture + Send + Sync>);
+
+impl PreProcessor {
+	pub fn new<F, Fut>(hook: F) -> Self
+	where
+		F: Fn(Metadata, Vec<u8>) -> Fut + Send + Sync + 'static,
+		Fut: Future<Output = Result<PreProcess, Box<dyn Error>>> + 'static,
+	{
+		Self(Arc::new(move |metadata, payload| Box::pin(hook(metadata, payload))))
+	}
+
+	pub async fn call(&self, metadata: Metadata, payload: Vec<u8>) -> Result<PreProcess, Box<dyn Error>> {
+		(self.0)(metadata, payload).await
+	}
+}
+
+impl fmt::Debug for PreProcessor {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str("PreProcessor")
+	}
+}
+
+pub async fn pre_process(
+	pre_processor: Option<&PreProcessor>,
+	metadata: &mut Metadata,
+	payload: Vec<u8>,
+) -> Result<PreProcess, Box<dyn Error>> {
+	let Some(pre_processor) = pre_processor else {
+		return Ok(PreProcess::Continue {
+			payload,
+			annotations: Default::default(),
+		});
+	};
+
+	let result = pre_processor.call(metadata.clone(), payload).await?;
+	if let PreProcess::Continue { annotations, .. } = &result {
+		metadata.annotations.extend(annotations.clone());
+	}
+	Ok(result)
+}
+
+/// Checks the sender of a plain advance input against the sender policy, sending an `UNAUTHORIZED` report if the
+/// input is rejected and `report` is set.
+pub async fn permits_sender(env: &impl Environment, policy: &SenderPolicy, report: bool, metadata: &Metadata) -> bool {
+	if policy.permits(metadata.sender) {
+		return true;
+	}
+
+	debug!("Sender {:?} isn't allowed by the sender policy, rejecting the advance input", metadata.sender);
+	if report {
+		let report = ErrorReport::unauthorized(format!("sender {:?} is not allowed", metadata.sender))
+			.input_index(metadata.input_index);
+		if let Err(e) = env.send_error_report(&report).await {
+			error!("Error sending the error report: {}", e);
+		}
+	}
+	false
+}
+
+#[cfg(feature = "rollup")]
+pub async fn record_activity(env: &impl RollupInternalEnvironment, input: &Input) {
+	let activity = env.get_activity();
+	let mut activity = activity.write().await;
+	match input {
+		Input::Advance(advance_input) => {
+			let kind = match env.get_address_book().is_portal(advance_input.metadata.sender) {
+				true => InputKind::Deposit,
+				false => InputKind::Advance,
+			};
+			activity.record_advance(&advance_input.metadata, kind);
+		}
+		Input::Inspect(_) => activity.record_inspect(),
+	}
+}
+
+#[cfg(feature = "rollup")]
+async fn report_error(
+	env: &impl Environment,
+	options: &RunOptions,
+	error: &(dyn Error + 'static),
+	input_index: Option<u64>,
+) {
+	if !options.error_reports {
+		return;
+	}
+
+	let mut report = ErrorReport::from_error(error);
+	report.input_index = report.input_index.or(input_index);
+	if let Err(e) = env.send_error_report(&report).await {
+		error!("Error sending the error report: {}", e);
+	}
+}
+
+#[cfg(feature = "rollup")]
 #[derive(Debug, Clone)]
 pub struct RunOptions {
-	pub rollup_url: &'static str,
+	pub rollup_url: String,
+	pub backend: Backend,
+	pub connect_timeout: Option<Duration>,
+	pub read_timeout: Option<Duration>,
+	pub request_timeout: Option<Duration>, // Whole request, including the long poll of `finish`
+	pub poll_interval: Option<Duration>,   // Pause before finishing again when the server has no pending input
+	pub keep_alive: bool,                  // Reuse the connections to the rollup server between requests
+	pub retry_policy: RetryPolicy,
+	pub wait_for_server: Option<Duration>, // Wait up to this long for the rollup server to accept connections at startup
 	pub address_book: AddressBook,
 	pub portal_config: PortalHandlerConfig,
+	pub deposit_decoders: DepositDecoders,
+	pub deposit_reject_policy: DepositRejectPolicy,
+	pub sender_policy: SenderPolicy,
+	pub sender_reports: bool, // Send an `UNAUTHORIZED` report for the inputs rejected by the sender policy
+	pub transactional_wallets: bool,
+	pub output_limits: OutputLimits,
+	pub voucher_dedup: VoucherDedupPolicy,
+	pub legacy_vouchers: bool, // Post vouchers in the format of the rollups node v1, without value, on by default
+	pub buffered_outputs: bool,
+	pub handoff: Option<HandoffOptions>,
+	pub pre_processor: Option<PreProcessor>,
+	pub middlewares: Middlewares,
+	pub heartbeat_interval: Option<u64>,
+	pub error_reports: bool,
+	pub inspect_cache: Option<usize>,
+	pub playground: Option<usize>,
+	pub activity_window: u64,
+	pub balance_notices: bool,
+	pub idempotency_keys: Option<IdempotencyKeys>,
+	pub dedup_window: Option<DedupWindow>,
+	pub deposit_receipts: bool,
+	pub hex_payloads: bool,
+	pub about: bool,
+	pub build_info: Option<BuildInfo>,
+	pub voucher_schedule: Option<VoucherSchedule>,
+	pub state_commitments: Option<StateCommitments>,
+	#[cfg(feature = "memory-tracking")]
+	pub memory_limit: Option<MemoryLimit>,
 }
 
+#[cfg(feature = "rollup")]
 impl Default for RunOptions {
 	fn default() -> Self {
 		Self {
-			rollup_url: "http://127.0.0.1:5004",
+			rollup_url: MACHINE_ROLLUP_URL.to_string(),
+			backend: Backend::default(),
+			connect_timeout: None,
+			read_timeout: None,
+			request_timeout: None,
+			poll_interval: None,
+			keep_alive: true,
+			retry_policy: RetryPolicy::none(),
+			wait_for_server: None,
 			address_book: AddressBook::default(),
 			portal_config: PortalHandlerConfig::default(),
+			deposit_decoders: DepositDecoders::default(),
+			deposit_reject_policy: DepositRejectPolicy::default(),
+			sender_policy: SenderPolicy::default(),
+			sender_reports: false,
+			transactional_wallets: false,
+			output_limits: OutputLimits::default(),
+			voucher_dedup: VoucherDedupPolicy::default(),
+			legacy_vouchers: true,
+			buffered_outputs: false,
+			handoff: None,
+			pre_processor: None,
+			middlewares: Middlewares::default(),
+			heartbeat_interval: None,
+			error_reports: false,
+			inspect_cache: None,
+			playground: None,
+			activity_window: DEFAULT_ACTIVITY_WINDOW,
+			balance_notices: false,
+			idempotency_keys: None,
+			dedup_window: None,
+			deposit_receipts: false,
+			hex_payloads: false,
+			about: false,
+			build_info: None,
+			voucher_schedule: None,
+			state_commitments: None,
+			#[cfg(feature = "memory-tracking")]
+			memory_limit: None,
 		}
 	}
 }
 
+#[cfg(feature = "rollup")]
 impl RunOptions {
 	pub fn builder() -> RunOptionsBuilder {
 		RunOptionsBuilder::default()
 	}
+
+	/// Default options with the rollup server URL of `ROLLUP_HTTP_SERVER_URL`, as the Cartesi images configure it.
+	pub fn from_env() -> Self {
+		Self::builder().rollup_url_from_env().build()
+	}
 }
 
+#[cfg(feature = "rollup")]
 pub struct RunOptionsBuilder {
-	rollup_url: &'static str,
+	rollup_url: Option<String>,
+	backend: Backend,
+	connect_timeout: Option<Duration>,
+	read_timeout: Option<Duration>,
+	request_timeout: Option<Duration>,
+	poll_interval: Option<Duration>,
+	keep_alive: bool,
+	retry_policy: RetryPolicy,
+	wait_for_server: Option<Duration>,
 	address_book: AddressBook,
 	portal_config: PortalHandlerConfig,
+	deposit_decoders: DepositDecoders,
+	deposit_reject_policy: DepositRejectPolicy,
+	sender_policy: SenderPolicy,
+	sender_reports: bool,
+	transactional_wallets: bool,
+	output_limits: OutputLimits,
+	voucher_dedup: VoucherDedupPolicy,
+	legacy_vouchers: bool,
+	buffered_outputs: bool,
+	handoff: Option<HandoffOptions>,
+	pre_processor: Option<PreProcessor>,
+	middlewares: Middlewares,
+	heartbeat_interval: Option<u64>,
+	error_reports: bool,
+	inspect_cache: Option<usize>,
+	playground: Option<usize>,
+	activity_window: u64,
+	balance_notices: bool,
+	idempotency_keys: Option<IdempotencyKeys>,
+	dedup_window: Option<DedupWindow>,
+	deposit_receipts: bool,
+	hex_payloads: bool,
+	about: bool,
+	build_info: Option<BuildInfo>,
+	voucher_schedule: Option<VoucherSchedule>,
+	state_commitments: Option<StateCommitments>,
+	#[cfg(feature = "memory-tracking")]
+	memory_limit: Option<MemoryLimit>,
 }
 
+#[cfg(feature = "rollup")]
 impl Default for RunOptionsBuilder {
 	fn default() -> Self {
 		Self {
-			rollup_url: "http://127.0.0.1:5004",
+			rollup_url: None,
+			backend: Backend::default(),
+			connect_timeout: None,
+			read_timeout: None,
+			request_timeout: None,
+			poll_interval: None,
+			keep_alive: true,
+			retry_policy: RetryPolicy::none(),
+			wait_for_server: None,
 			address_book: AddressBook::default(),
 			portal_config: PortalHandlerConfig::default(),
+			deposit_decoders: DepositDecoders::default(),
+			deposit_reject_policy: DepositRejectPolicy::default(),
+			sender_policy: SenderPolicy::default(),
+			sender_reports: false,
+			transactional_wallets: false,
+			output_limits: OutputLimits::default(),
+			voucher_dedup: VoucherDedupPolicy::default(),
+			legacy_vouchers: true,
+			buffered_outputs: false,
+			handoff: None,
+			pre_processor: None,
+			middlewares: Middlewares::default(),
+			heartbeat_interval: None,
+			error_reports: false,
+			inspect_cache: None,
+			playground: None,
+			activity_window: DEFAULT_ACTIVITY_WINDOW,
+			balance_notices: false,
+			idempotency_keys: None,
+			dedup_window: None,
+			deposit_receipts: false,
+			hex_payloads: false,
+			about: false,
+			build_info: None,
+			voucher_schedule: None,
+			state_commitments: None,
+			#[cfg(feature = "memory-tracking")]
+			memory_limit: None,
 		}
 	}
 }
 
+#[cfg(feature = "rollup")]
 impl RunOptionsBuilder {
-	pub fn rollup_url(mut self, rollup_url: &'static str) -> Self {
-		self.rollup_url = rollup_url;
+	pub fn rollup_url(mut self, rollup_url: impl Into<String>) -> Self {
+		self.rollup_url = Some(rollup_url.into());
+		self
+	}
+
+	/// Takes the rollup URL from `ROLLUP_HTTP_SERVER_URL`, keeping the current one when the variable is unset or empty.
+	pub fn rollup_url_from_env(self) -> Self {
+		self.rollup_url_from_var(std::env::var(ROLLUP_URL_VAR).ok())
+	}
+
+	fn rollup_url_from_var(mut self, url: Option<String>) -> Self {
+		if let Some(url) = url.filter(|url| !url.is_empty()) {
+			self.rollup_url = Some(url);
+		}
+		self
+	}
+
+	/// Picks the rollup URL from the backend when none is set, resolving `Backend::Auto` when the options are built.
+	pub fn backend(mut self, backend: Backend) -> Self {
+		self.backend = backend;
+		self
+	}
+
+	pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+		self.connect_timeout = Some(timeout);
+		self
+	}
+
+	pub fn read_timeout(mut self, timeout: Duration) -> Self {
+		self.read_timeout = Some(timeout);
+		self
+	}
+
+	pub fn request_timeout(mut self, timeout: Duration) -> Self {
+		self.request_timeout = Some(timeout);
+		self
+	}
+
+	pub fn poll_interval(mut self, interval: Duration) -> Self {
+		self.poll_interval = Some(interval);
+		self
+	}
+
+	pub fn keep_alive(mut self, keep_alive: bool) -> Self {
+		self.keep_alive = keep_alive;
+		self
+	}
+
+	pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+		self.retry_policy = retry_policy;
+		self
+	}
+
+	pub fn wait_for_server(mut self, timeout: Duration) -> Self {
+		self.wait_for_server = Some(timeout);
 		self
 	}
 
@@ -64,38 +422,218 @@ impl RunOptionsBuilder {
 		self
 	}
 
+	pub fn deposit_decoder(mut self, decoder: impl DepositDecoder + 'static) -> Self {
+		self.deposit_decoders.register(decoder);
+		self
+	}
+
+	pub fn deposit_reject_policy(mut self, deposit_reject_policy: DepositRejectPolicy) -> Self {
+		self.deposit_reject_policy = deposit_reject_policy;
+		self
+	}
+
+	pub fn sender_policy(mut self, sender_policy: SenderPolicy) -> Self {
+		self.sender_policy = sender_policy;
+		self
+	}
+
+	pub fn sender_reports(mut self, sender_reports: bool) -> Self {
+		self.sender_reports = sender_reports;
+		self
+	}
+
+	pub fn transactional_wallets(mut self, transactional_wallets: bool) -> Self {
+		self.transactional_wallets = transactional_wallets;
+		self
+	}
+
+	pub fn output_limits(mut self, output_limits: OutputLimits) -> Self {
+		self.output_limits = output_limits;
+		self
+	}
+
+	pub fn legacy_vouchers(mut self, legacy_vouchers: bool) -> Self {
+		self.legacy_vouchers = legacy_vouchers;
+		self
+	}
+
+	pub fn buffered_outputs(mut self, buffered_outputs: bool) -> Self {
+		self.buffered_outputs = buffered_outputs;
+		self
+	}
+
+	pub fn voucher_dedup(mut self, voucher_dedup: VoucherDedupPolicy) -> Self {
+		self.voucher_dedup = voucher_dedup;
+		self
+	}
+
+	pub fn handoff(mut self, handoff: HandoffOptions) -> Self {
+		self.handoff = Some(handoff);
+		self
+	}
+
+	pub fn pre_processor<F, Fut>(mut self, hook: F) -> Self
+	where
+		F: Fn(Metadata, Vec<u8>) -> Fut + Send + Sync + 'static,
+		Fut: Future<Output = Result<PreProcess, Box<dyn Error>>> + 'static,
+	{
+		self.pre_processor = Some(PreProcessor::new(hook));
+		self
+	}
+
+	pub fn middleware(mut self, middleware: impl Middleware + 'static) -> Self {
+		self.middlewares.register(middleware);
+		self
+	}
+
+	pub fn heartbeat(mut self, interval: u64) -> Self {
+		self.heartbeat_interval = Some(interval);
+		self
+	}
+
+	pub fn error_reports(mut self, error_reports: bool) -> Self {
+		self.error_reports = error_reports;
+		self
+	}
+
+	pub fn inspect_cache(mut self, capacity: usize) -> Self {
+		self.inspect_cache = Some(capacity);
+		self
+	}
+
+	pub fn playground(mut self, capacity: usize) -> Self {
+		self.playground = Some(capacity);
+		self
+	}
+
+	pub fn activity_window(mut self, seconds: u64) -> Self {
+		self.activity_window = seconds;
+		self
+	}
+
+	pub fn balance_notices(mut self, balance_notices: bool) -> Self {
+		self.balance_notices = balance_notices;
+		self
+	}
+
+	pub fn idempotency_keys(mut self, idempotency_keys: IdempotencyKeys) -> Self {
+		self.idempotency_keys = Some(idempotency_keys);
+		self
+	}
+
+	pub fn dedup_window(mut self, dedup_window: DedupWindow) -> Self {
+		self.dedup_window = Some(dedup_window);
+		self
+	}
+
+	pub fn deposit_receipts(mut self, deposit_receipts: bool) -> Self {
+		self.deposit_receipts = deposit_receipts;
+		self
+	}
+
+	pub fn hex_payloads(mut self, hex_payloads: bool) -> Self {
+		self.hex_payloads = hex_payloads;
+		self
+	}
+
+	pub fn about(mut self, about: bool) -> Self {
+		self.about = about;
+		self
+	}
+
+	pub fn build_info(mut self, build_info: BuildInfo) -> Self {
+		self.build_info = Some(build_info);
+		self
+	}
+
+	pub fn voucher_schedule(mut self, voucher_schedule: VoucherSchedule) -> Self {
+		self.voucher_schedule = Some(voucher_schedule);
+		self
+	}
+
+	pub fn state_commitments(mut self, state_commitments: StateCommitments) -> Self {
+		self.state_commitments = Some(state_commitments);
+		self
+	}
+
+	#[cfg(feature = "memory-tracking")]
+	pub fn memory_limit(mut self, memory_limit: MemoryLimit) -> Self {
+		self.memory_limit = Some(memory_limit);
+		self
+	}
+
 	pub fn build(self) -> RunOptions {
 		RunOptions {
-			rollup_url: self.rollup_url,
+			rollup_url: self.rollup_url.unwrap_or_else(|| self.backend.rollup_url().to_string()),
+			backend: self.backend,
+			connect_timeout: self.connect_timeout,
+			read_timeout: self.read_timeout,
+			request_timeout: self.request_timeout,
+			poll_interval: self.poll_interval,
+			keep_alive: self.keep_alive,
+			retry_policy: self.retry_policy,
+			wait_for_server: self.wait_for_server,
 			address_book: self.address_book,
 			portal_config: self.portal_config,
+			deposit_decoders: self.deposit_decoders,
+			deposit_reject_policy: self.deposit_reject_policy,
+			sender_policy: self.sender_policy,
+			sender_reports: self.sender_reports,
+			transactional_wallets: self.transactional_wallets,
+			output_limits: self.output_limits,
+			voucher_dedup: self.voucher_dedup,
+			legacy_vouchers: self.legacy_vouchers,
+			buffered_outputs: self.buffered_outputs,
+			handoff: self.handoff,
+			pre_processor: self.pre_processor,
+			middlewares: self.middlewares,
+			heartbeat_interval: self.heartbeat_interval,
+			error_reports: self.error_reports,
+			inspect_cache: self.inspect_cache,
+			playground: self.playground,
+			activity_window: self.activity_window,
+			balance_notices: self.balance_notices,
+			idempotency_keys: self.idempotency_keys,
+			dedup_window: self.dedup_window,
+			deposit_receipts: self.deposit_receipts,
+			hex_payloads: self.hex_payloads,
+			about: self.about,
+			build_info: self.build_info,
+			voucher_schedule: self.voucher_schedule,
+			state_commitments: self.state_commitments,
+			#[cfg(feature = "memory-tracking")]
+			memory_limit: self.memory_limit,
 		}
 	}
 }
 
 pub async fn handle_portals<R: RollupInternalEnvironment>(
 	rollup: &R,
+	decoders: &DepositDecoders,
 	sender: Address,
 	payload: Vec<u8>,
-) -> Result<Option<Deposit>, Box<dyn Error>> {
+) -> Result<Option<Deposit>, CrabrollsError> {
 	match sender {
 		sender if sender == rollup.get_address_book().ether_portal => {
 			debug!("Advance input from EtherPortal({})", sender);
 			let (ether_deposit, _) = rollup.get_ether_wallet().write().await.deposit(payload.clone())?;
 			Ok(Some(ether_deposit))
 		}
+		#[cfg(feature = "erc20")]
 		sender if sender == rollup.get_address_book().erc20_portal => {
 			debug!("Advance input from ERC20Portal({})", sender);
 			let (erc20_deposit, _) = rollup.get_erc20_wallet().write().await.deposit(payload.clone())?;
 
 			Ok(Some(erc20_deposit))
 		}
+		#[cfg(feature = "erc721")]
 		sender if sender == rollup.get_address_book().erc721_portal => {
 			debug!("Advance input from ERC721Portal({})", sender);
 			let (erc721_deposit, _) = rollup.get_erc721_wallet().write().await.deposit(payload.clone())?;
 
 			Ok(Some(erc721_deposit))
 		}
+		#[cfg(feature = "erc1155")]
 		sender if sender == rollup.get_address_book().erc1155_single_portal => {
 			debug!("Advance input from ERC1155SinglePortal({})", sender);
 			let (erc1155_deposit, _) = rollup
@@ -106,6 +644,7 @@ pub async fn handle_portals<R: RollupInternalEnvironment>(
 
 			Ok(Some(erc1155_deposit))
 		}
+		#[cfg(feature = "erc1155")]
 		sender if sender == rollup.get_address_book().erc1155_batch_portal => {
 			debug!("Advance input from ERC1155BatchPortal({})", sender);
 			let (erc1155_deposit, _) = rollup
@@ -116,50 +655,592 @@ pub async fn handle_portals<R: RollupInternalEnvironment>(
 
 			Ok(Some(erc1155_deposit))
 		}
-		_ => {
-			debug!("Advance input from an unknown address");
-			Ok(None)
+		#[cfg(not(all(feature = "erc20", feature = "erc721", feature = "erc1155")))]
+		sender if rollup.get_address_book().is_portal(sender) => Err(CrabrollsError::portal(format!(
+			"deposits from the portal {:?} need a wallet feature that isn't enabled",
+			sender
+		))),
+		_ => match decoders.decode(sender, &payload)? {
+			Some(deposit) => {
+				debug!("Advance input decoded as a deposit from {}", sender);
+				credit_deposit(rollup, &deposit).await?;
+				Ok(Some(deposit))
+			}
+			None => {
+				debug!("Advance input from an unknown address");
+				Ok(None)
+			}
+		},
+	}
+}
+
+#[cfg(not(all(feature = "erc20", feature = "erc721", feature = "erc1155")))]
+fn unsupported_deposit(deposit: &Deposit) -> CrabrollsError {
+	CrabrollsError::portal(format!("the wallet feature of the deposit isn't enabled: {:?}", deposit))
+}
+
+pub async fn credit_deposit<R: RollupInternalEnvironment>(rollup: &R, deposit: &Deposit) -> Result<(), CrabrollsError> {
+	let overflow = || CrabrollsError::wallet("deposit overflows the wallet balance");
+
+	match deposit.clone() {
+		Deposit::Ether { sender, amount } => {
+			rollup.get_ether_wallet().write().await.credit(sender, amount)?;
+		}
+		#[cfg(feature = "erc20")]
+		Deposit::ERC20 { sender, token, amount } => {
+			let erc20_wallet = rollup.get_erc20_wallet();
+			let mut erc20_wallet = erc20_wallet.write().await;
+			let balance = erc20_wallet
+				.balance_of(sender, token)
+				.checked_add(amount)
+				.ok_or_else(overflow)?;
+			erc20_wallet.set_balance(sender, token, balance);
+		}
+		#[cfg(feature = "erc721")]
+		Deposit::ERC721 { sender, token, id } => {
+			rollup.get_erc721_wallet().write().await.add_token(sender, token, id);
+		}
+		#[cfg(feature = "erc1155")]
+		Deposit::ERC1155 {
+			sender,
+			token,
+			ids_amounts,
+		} => {
+			let erc1155_wallet = rollup.get_erc1155_wallet();
+			let mut erc1155_wallet = erc1155_wallet.write().await;
+			for (id, amount) in ids_amounts {
+				let balance = erc1155_wallet
+					.balance_of(sender, token, id)
+					.checked_add(amount)
+					.ok_or_else(overflow)?;
+				erc1155_wallet.set_balance(sender, token, id, balance);
+			}
+		}
+		Deposit::ERC4626 {
+			sender, vault, shares, ..
+		} => {
+			let erc4626_wallet = rollup.get_erc4626_wallet();
+			let mut erc4626_wallet = erc4626_wallet.write().await;
+			let balance = erc4626_wallet
+				.balance_of(sender, vault)
+				.checked_add(shares)
+				.ok_or_else(overflow)?;
+			erc4626_wallet.set_balance(sender, vault, balance);
+		}
+		Deposit::Custom { .. } => {}
+		#[cfg(not(all(feature = "erc20", feature = "erc721", feature = "erc1155")))]
+		deposit => return Err(unsupported_deposit(&deposit)),
+	}
+
+	Ok(())
+}
+
+/// Starts journaling the changes on every wallet, until `end_wallet_transaction` commits or rolls them back.
+pub async fn begin_wallet_transaction<R: RollupInternalEnvironment>(rollup: &R) {
+	rollup.get_ether_wallet().write().await.begin();
+	#[cfg(feature = "erc20")]
+	rollup.get_erc20_wallet().write().await.begin();
+	#[cfg(feature = "erc721")]
+	rollup.get_erc721_wallet().write().await.begin();
+	#[cfg(feature = "erc1155")]
+	rollup.get_erc1155_wallet().write().await.begin();
+	rollup.get_erc4626_wallet().write().await.begin();
+}
+
+/// Commits the wallet changes made since `begin_wallet_transaction` on accepted inputs, rolling them back otherwise.
+pub async fn end_wallet_transaction<R: RollupInternalEnvironment>(rollup: &R, status: FinishStatus) {
+	let rollback = status == FinishStatus::Reject;
+	if rollback {
+		debug!("Rolling back the wallet changes of the rejected advance");
+	}
+
+	let ether_wallet = rollup.get_ether_wallet();
+	let mut ether_wallet = ether_wallet.write().await;
+	if rollback {
+		ether_wallet.rollback();
+	} else {
+		ether_wallet.commit();
+	}
+	#[cfg(feature = "erc20")]
+	{
+		let erc20_wallet = rollup.get_erc20_wallet();
+		let mut erc20_wallet = erc20_wallet.write().await;
+		if rollback {
+			erc20_wallet.rollback();
+		} else {
+			erc20_wallet.commit();
+		}
+	}
+	#[cfg(feature = "erc721")]
+	{
+		let erc721_wallet = rollup.get_erc721_wallet();
+		let mut erc721_wallet = erc721_wallet.write().await;
+		if rollback {
+			erc721_wallet.rollback();
+		} else {
+			erc721_wallet.commit();
+		}
+	}
+	#[cfg(feature = "erc1155")]
+	{
+		let erc1155_wallet = rollup.get_erc1155_wallet();
+		let mut erc1155_wallet = erc1155_wallet.write().await;
+		if rollback {
+			erc1155_wallet.rollback();
+		} else {
+			erc1155_wallet.commit();
 		}
 	}
+	let erc4626_wallet = rollup.get_erc4626_wallet();
+	let mut erc4626_wallet = erc4626_wallet.write().await;
+	if rollback {
+		erc4626_wallet.rollback();
+	} else {
+		erc4626_wallet.commit();
+	}
+}
+
+/// Commits the credit of a deposit that `DepositRejectPolicy::Keep` keeps on rejected inputs, so rolling back the
+/// wallet transaction of the advance only undoes the changes made by the app.
+pub async fn keep_deposit_credit<R: RollupInternalEnvironment>(rollup: &R, transactional: bool, policy: DepositRejectPolicy) {
+	if transactional && policy == DepositRejectPolicy::Keep {
+		end_wallet_transaction(rollup, FinishStatus::Accept).await;
+		begin_wallet_transaction(rollup).await;
+	}
+}
+
+pub async fn rollback_deposit<R: RollupInternalEnvironment>(
+	rollup: &R,
+	deposit: &Deposit,
+) -> Result<(), CrabrollsError> {
+	let insufficient = || CrabrollsError::wallet("insufficient funds to roll back the deposit");
+
+	match deposit.clone() {
+		Deposit::Ether { sender, amount } => {
+			rollup.get_ether_wallet().write().await.uncredit(sender, amount)?;
+		}
+		#[cfg(feature = "erc20")]
+		Deposit::ERC20 { sender, token, amount } => {
+			let erc20_wallet = rollup.get_erc20_wallet();
+			let mut erc20_wallet = erc20_wallet.write().await;
+			let balance = erc20_wallet
+				.balance_of(sender, token)
+				.checked_sub(amount)
+				.ok_or_else(insufficient)?;
+			erc20_wallet.set_balance(sender, token, balance);
+		}
+		#[cfg(feature = "erc721")]
+		Deposit::ERC721 { sender, token, id } => {
+			let erc721_wallet = rollup.get_erc721_wallet();
+			let mut erc721_wallet = erc721_wallet.write().await;
+			if erc721_wallet.owner_of(token, id) != Some(sender) {
+				return Err(insufficient());
+			}
+			erc721_wallet.remove_token(sender, token, id);
+		}
+		#[cfg(feature = "erc1155")]
+		Deposit::ERC1155 {
+			sender,
+			token,
+			ids_amounts,
+		} => {
+			let erc1155_wallet = rollup.get_erc1155_wallet();
+			let mut erc1155_wallet = erc1155_wallet.write().await;
+			let mut balances = Vec::new();
+			for (id, amount) in ids_amounts {
+				let balance = erc1155_wallet
+					.balance_of(sender, token, id)
+					.checked_sub(amount)
+					.ok_or_else(insufficient)?;
+				balances.push((id, balance));
+			}
+			for (id, balance) in balances {
+				erc1155_wallet.set_balance(sender, token, id, balance);
+			}
+		}
+		Deposit::ERC4626 {
+			sender, vault, shares, ..
+		} => {
+			let erc4626_wallet = rollup.get_erc4626_wallet();
+			let mut erc4626_wallet = erc4626_wallet.write().await;
+			let balance = erc4626_wallet
+				.balance_of(sender, vault)
+				.checked_sub(shares)
+				.ok_or_else(insufficient)?;
+			erc4626_wallet.set_balance(sender, vault, balance);
+		}
+		Deposit::Custom { .. } => {}
+		#[cfg(not(all(feature = "erc20", feature = "erc721", feature = "erc1155")))]
+		deposit => return Err(unsupported_deposit(&deposit)),
+	}
+
+	Ok(())
+}
+
+pub async fn refund_deposit<E: Environment>(env: &E, deposit: &Deposit) -> Result<(), CrabrollsError> {
+	match deposit.clone() {
+		Deposit::Ether { sender, amount } => env.ether_withdraw(sender, amount).await,
+		#[cfg(feature = "erc20")]
+		Deposit::ERC20 { sender, token, amount } => env.erc20_withdraw(sender, token, amount).await,
+		#[cfg(feature = "erc721")]
+		Deposit::ERC721 { sender, token, id } => env.erc721_withdraw(sender, token, id).await,
+		#[cfg(feature = "erc1155")]
+		Deposit::ERC1155 {
+			sender,
+			token,
+			ids_amounts,
+		} => env.erc1155_withdraw(sender, token, ids_amounts, None).await,
+		Deposit::ERC4626 {
+			sender, vault, shares, ..
+		} => env.erc4626_withdraw(sender, vault, shares).await,
+		Deposit::Custom { .. } => Err(CrabrollsError::portal("custom deposits can't be refunded")),
+		#[cfg(not(all(feature = "erc20", feature = "erc721", feature = "erc1155")))]
+		deposit => Err(unsupported_deposit(&deposit)),
+	}
+}
+
+pub async fn settle_rejected_deposit<E: Environment>(
+	env: &E,
+	policy: DepositRejectPolicy,
+	deposit: &Deposit,
+) -> FinishStatus {
+	match policy {
+		DepositRejectPolicy::Keep => {
+			debug!("Keeping the deposit of the rejected advance: {:?}", deposit);
+			FinishStatus::Reject
+		}
+		DepositRejectPolicy::Rollback => {
+			if let Err(e) = rollback_deposit(env, deposit).await {
+				error!("Error rolling back the deposit: {}", e);
+			}
+			FinishStatus::Reject
+		}
+		DepositRejectPolicy::Refund => match refund_deposit(env, deposit).await {
+			Ok(()) => {
+				debug!("Refunded the deposit of the rejected advance: {:?}", deposit);
+				FinishStatus::Accept
+			}
+			Err(e) => {
+				error!("Error refunding the deposit, rolling it back instead: {}", e);
+				if let Err(e) = rollback_deposit(env, deposit).await {
+					error!("Error rolling back the deposit: {}", e);
+				}
+				FinishStatus::Reject
+			}
+		},
+	}
+}
+
+type PayloadPredicate = Arc<dyn Fn(&[u8]) -> bool + Send + Sync>;
+
+#[derive(Clone)]
+pub enum Selector {
+	Any,                       // Receives every input
+	Senders(HashSet<Address>), // Receives advance inputs sent by one of the addresses
+	Payload(PayloadPredicate), // Receives inputs whose payload matches the predicate
+}
+
+impl Selector {
+	pub fn senders(senders: impl IntoIterator<Item = Address>) -> Self {
+		Self::Senders(senders.into_iter().collect())
+	}
+
+	pub fn payload(predicate: impl Fn(&[u8]) -> bool + Send + Sync + 'static) -> Self {
+		Self::Payload(Arc::new(predicate))
+	}
+
+	pub fn prefix(prefix: impl AsRef<[u8]>) -> Self {
+		let prefix = prefix.as_ref().to_vec();
+		Self::payload(move |payload| payload.starts_with(&prefix))
+	}
+
+	pub fn codec(codec: PayloadCodec) -> Self {
+		Self::payload(move |payload| codec.matches(payload))
+	}
+
+	pub fn matches_advance(&self, metadata: &Metadata, payload: &[u8]) -> bool {
+		match self {
+			Self::Any => true,
+			Self::Senders(senders) => senders.contains(&metadata.sender),
+			Self::Payload(predicate) => predicate(payload),
+		}
+	}
+
+	pub fn matches_inspect(&self, payload: &[u8]) -> bool {
+		match self {
+			Self::Any => true,
+			Self::Senders(_) => false,
+			Self::Payload(predicate) => predicate(payload),
+		}
+	}
+}
+
+impl fmt::Debug for Selector {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Any => f.write_str("Any"),
+			Self::Senders(senders) => f.debug_tuple("Senders").field(senders).finish(),
+			Self::Payload(_) => f.write_str("Payload"),
+		}
+	}
+}
+
+type StopPredicate = Arc<dyn Fn(&Input, FinishStatus) -> bool + Send + Sync>;
+
+#[derive(Clone)]
+pub enum StopCondition {
+	Inputs(u64),              // Stop after processing the given number of inputs
+	InputIndex(u64),          // Stop after processing the advance input with the given index
+	Predicate(StopPredicate), // Stop after the first input for which the predicate returns true
 }
 
+impl StopCondition {
+	pub fn predicate(predicate: impl Fn(&Input, FinishStatus) -> bool + Send + Sync + 'static) -> Self {
+		Self::Predicate(Arc::new(predicate))
+	}
+
+	pub fn should_stop(&self, processed: u64, input: &Input, status: FinishStatus) -> bool {
+		match self {
+			Self::Inputs(count) => processed >= *count,
+			Self::InputIndex(index) => {
+				matches!(input, Input::Advance(advance) if advance.metadata.input_index >= *index)
+			}
+			Self::Predicate(predicate) => predicate(input, status),
+		}
+	}
+}
+
+impl fmt::Debug for StopCondition {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Inputs(count) => f.debug_tuple("Inputs").field(count).finish(),
+			Self::InputIndex(index) => f.debug_tuple("InputIndex").field(index).finish(),
+			Self::Predicate(_) => f.write_str("Predicate"),
+		}
+	}
+}
+
+#[cfg(feature = "rollup")]
 pub struct Supervisor;
 
+#[cfg(feature = "rollup")]
 impl Supervisor {
 	pub async fn run(app: impl Application, options: RunOptions) -> Result<(), Box<dyn Error>> {
-		pretty_env_logger::init();
-		let rollup = Rollup::new(options.rollup_url, options.address_book.clone());
-		let mut status = FinishStatus::Accept;
+		Self::run_many(vec![(Selector::Any, app)], options).await
+	}
+
+	pub async fn run_many<A: Application>(apps: Vec<(Selector, A)>, options: RunOptions) -> Result<(), Box<dyn Error>> {
+		Self::run_loop(apps, options, None).await
+	}
+
+	pub async fn run_until(
+		app: impl Application,
+		options: RunOptions,
+		stop_condition: StopCondition,
+	) -> Result<(), Box<dyn Error>> {
+		Self::run_loop(vec![(Selector::Any, app)], options, Some(stop_condition)).await
+	}
+
+	async fn run_loop<A: Application>(
+		apps: Vec<(Selector, A)>,
+		options: RunOptions,
+		stop_condition: Option<StopCondition>,
+	) -> Result<(), Box<dyn Error>> {
+		if apps.is_empty() {
+			return Err("no application to run".into());
+		}
+
+		let _ = pretty_env_logger::try_init();
+		let options = RunOptions {
+			backend: options.backend.resolve(),
+			..options
+		};
+		if let Some(timeout) = options.wait_for_server {
+			retry::wait_for_server(&options.rollup_url, timeout, &options.retry_policy).await?;
+		}
+		let rollup = Rollup::new(&options);
+
+		if let Some(import) = options.handoff.as_ref().and_then(|handoff| handoff.import.as_ref()) {
+			handoff::import(&rollup, &import.dump, import.hash).await?;
+		}
+		for (_, app) in &apps {
+			app.on_start(&rollup).await.map_err(Into::into)?;
+		}
 
 		println!(
-			"Starting the application... Listening for inputs on {}",
-			options.rollup_url
+			"Starting the application... Listening for inputs on {} ({:?} backend)",
+			options.rollup_url, options.backend
 		);
 
+		// The apps are shut down even if the loop fails, e.g. when the rollup server goes away.
+		let result = Self::process_inputs(&rollup, &options, &apps, stop_condition).await;
+		for (_, app) in &apps {
+			if let Err(e) = app.on_shutdown(&rollup).await {
+				error!("Error in shutdown hook: {}", e.into());
+			}
+		}
+		let (processed, status) = result?;
+
+		if let Some(input) = rollup.finish_and_get_next(status).await? {
+			warn!(
+				"Input fetched after the stop condition was left unprocessed: {:?}",
+				input
+			);
+		}
+		println!("Stopping the application after {} inputs", processed);
+		Ok(())
+	}
+
+	/// Processes inputs until the stop condition is met, returning the number of inputs and the last status, which
+	/// is left unfinished.
+	async fn process_inputs<A: Application>(
+		rollup: &Rollup,
+		options: &RunOptions,
+		apps: &[(Selector, A)],
+		stop_condition: Option<StopCondition>,
+	) -> Result<(u64, FinishStatus), Box<dyn Error>> {
+		let mut status = FinishStatus::Accept;
+		let mut frozen = false;
+		let mut processed = 0;
+		let mut heartbeat = options.heartbeat_interval.map(Heartbeat::new);
+		let mut inspect_cache = options.inspect_cache.map(InspectCache::new);
+		let mut playground = options.playground.map(Playground::new);
+
 		loop {
-			let input = rollup.finish_and_get_next(status.clone()).await?;
+			let Some(input) = rollup.finish_and_get_next(status).await? else {
+				debug!("Waiting for next input");
+				if let Some(interval) = options.poll_interval {
+					retry::sleep(interval).await;
+				}
+				continue;
+			};
 
-			match input {
-				Some(Input::Advance(advance_input)) => {
-					status = Self::handle_advance_input(&rollup, &options, &app, advance_input).await?;
+			let stop_input = stop_condition.as_ref().map(|_| input.clone());
+			let input_index = match &input {
+				Input::Advance(advance_input) => Some(advance_input.metadata.input_index),
+				Input::Inspect(_) => None,
+			};
+			let playground_input = match (&input, &playground) {
+				(Input::Advance(advance_input), Some(_)) => {
+					rollup.record_outputs().await;
+					Some(advance_input.clone())
+				}
+				_ => None,
+			};
+			let transaction = options.transactional_wallets && input_index.is_some();
+			if transaction {
+				begin_wallet_transaction(rollup).await;
+			}
+			status = match (input, inspect_cache.as_mut(), playground.as_ref()) {
+				(Input::Inspect(inspect_input), _, Some(playground))
+					if Playground::is_route(&inspect_input.payload) =>
+				{
+					Self::handle_playground_input(rollup, options, playground, inspect_input).await?
 				}
-				Some(Input::Inspect(inspect_input)) => {
-					status = Self::handle_inspect_input(&rollup, &app, inspect_input).await?;
+				(Input::Inspect(inspect_input), Some(cache), _) => {
+					Self::handle_cached_inspect_input(rollup, options, apps, inspect_input, cache).await?
 				}
-				None => {
-					debug!("Waiting for next input");
+				(input, _, _) => Self::handle_input(rollup, options, apps, input, &mut frozen).await?,
+			};
+			if transaction {
+				end_wallet_transaction(rollup, status).await;
+			}
+			processed += 1;
+
+			// Rejected advances may still have changed the in-memory state of the app.
+			if let (Some(cache), Some(_)) = (inspect_cache.as_mut(), input_index) {
+				cache.invalidate();
+			}
+
+			if let (Some(heartbeat), Some(input_index)) = (heartbeat.as_mut(), input_index) {
+				if heartbeat.record(status) {
+					if let Err(e) = heartbeat.emit(rollup, input_index).await {
+						error!("Error emitting the heartbeat notice: {}", e);
+					}
+				}
+			}
+
+			if let (Some(playground), Some(advance_input)) = (playground.as_mut(), playground_input) {
+				let outputs = rollup.take_recorded_outputs().await;
+				playground.record(&advance_input.metadata, &advance_input.payload, status, outputs);
+			}
+
+			if let (Some(keys), Some(_)) = (&options.idempotency_keys, input_index) {
+				keys.finish(status);
+			}
+			if let (Some(window), Some(_)) = (&options.dedup_window, input_index) {
+				window.finish(status);
+			}
+
+			if let (Some(stop_condition), Some(input)) = (&stop_condition, stop_input) {
+				if stop_condition.should_stop(processed, &input, status) {
+					return Ok((processed, status));
 				}
 			}
 		}
 	}
 
-	async fn handle_advance_input(
+	async fn handle_input<A: Application>(
 		rollup: &Rollup,
 		options: &RunOptions,
-		app: &impl Application,
-		advance_input: Advance,
+		apps: &[(Selector, A)],
+		input: Input,
+		frozen: &mut bool,
+	) -> Result<FinishStatus, Box<dyn Error>> {
+		record_activity(rollup, &input).await;
+
+		match input {
+			Input::Advance(advance_input) if *frozen => {
+				debug!("Wallets were handed off, rejecting advance input: {:?}", advance_input);
+				Ok(FinishStatus::Reject)
+			}
+			Input::Advance(advance_input)
+				if options.handoff.as_ref().is_some_and(|handoff| {
+					handoff.is_export_request(advance_input.metadata.sender, &advance_input.payload)
+				}) =>
+			{
+				match handoff::export(rollup).await {
+					Ok(_) => {
+						*frozen = true;
+						Ok(FinishStatus::Accept)
+					}
+					Err(e) => {
+						error!("Error in hand-off export: {}", e);
+						Ok(FinishStatus::Reject)
+					}
+				}
+			}
+			Input::Advance(advance_input) => {
+				let metadata = advance_input.metadata.clone();
+				#[cfg(feature = "memory-tracking")]
+				if options.memory_limit.is_some() {
+					memory::reset_peak();
+				}
+				let status = Self::handle_advance_input(rollup, options, apps, advance_input).await?;
+				if let (FinishStatus::Accept, Some(schedule)) = (status, &options.voucher_schedule) {
+					if let Err(e) = schedule.emit_due(rollup, &metadata).await {
+						error!("Error emitting the scheduled vouchers: {}", e);
+					}
+				}
+				if let (FinishStatus::Accept, Some(commitments)) = (status, &options.state_commitments) {
+					if let Err(e) = commitments.emit(rollup, metadata.input_index).await {
+						error!("Error emitting the state commitment notice: {}", e);
+					}
+				}
+				Ok(status)
+			}
+			Input::Inspect(inspect_input) => Self::handle_inspect_input(rollup, options, apps, inspect_input).await,
+		}
+	}
+
+	async fn handle_advance_input<A: Application>(
+		rollup: &Rollup,
+		options: &RunOptions,
+		apps: &[(Selector, A)],
+		mut advance_input: Advance,
 	) -> Result<FinishStatus, Box<dyn Error>> {
 		debug!("New Advance input: {:?}", advance_input);
+		let input_index = advance_input.metadata.input_index;
 
 		if advance_input.metadata.sender == rollup.get_address_book().app_address_relay {
 			debug!("Advance input from AppAddressRelay({})", advance_input.metadata.sender);
@@ -168,10 +1249,72 @@ impl Supervisor {
 			return Ok(FinishStatus::Accept);
 		}
 
+		if !rollup.get_address_book().is_portal(advance_input.metadata.sender)
+			&& !permits_sender(rollup, &options.sender_policy, options.sender_reports, &advance_input.metadata).await
+		{
+			return Ok(FinishStatus::Reject);
+		}
+
+		if options.hex_payloads && !rollup.get_address_book().is_portal(advance_input.metadata.sender) {
+			advance_input.payload = codec::unwrap_hex(advance_input.payload);
+		}
+
+		match pre_process(
+			options.pre_processor.as_ref(),
+			&mut advance_input.metadata,
+			advance_input.payload,
+		)
+		.await
+		{
+			Ok(PreProcess::Continue { payload, .. }) => advance_input.payload = payload,
+			Ok(PreProcess::Finish(status)) => {
+				debug!("Advance input finished by the pre-processor: {:?}", status);
+				return Ok(status);
+			}
+			Err(e) => {
+				error!("Error in pre-processor: {}", e);
+				report_error(rollup, options, e.as_ref(), Some(input_index)).await;
+				return Ok(FinishStatus::Reject);
+			}
+		}
+
+		if !rollup.get_address_book().is_portal(advance_input.metadata.sender) {
+			if let Some(keys) = &options.idempotency_keys {
+				let payload = std::mem::take(&mut advance_input.payload);
+				match keys.process(rollup, &advance_input.metadata, payload).await? {
+					Some(payload) => advance_input.payload = payload,
+					None => return Ok(FinishStatus::Accept),
+				}
+			}
+			if let Some(window) = &options.dedup_window {
+				if !window
+					.process(rollup, &advance_input.metadata, &advance_input.payload)
+					.await?
+				{
+					return Ok(FinishStatus::Accept);
+				}
+			}
+		}
+
 		let mut deposit: Option<Deposit> = None;
 
 		if let PortalHandlerConfig::Handle { .. } = options.portal_config {
-			deposit = handle_portals(rollup, advance_input.metadata.sender, advance_input.payload.clone()).await?;
+			match handle_portals(
+				rollup,
+				&options.deposit_decoders,
+				advance_input.metadata.sender,
+				advance_input.payload.clone(),
+			)
+			.await
+			{
+				Ok(handled) => deposit = handled,
+				Err(e) => {
+					error!("Error handling the deposit: {}", e);
+					let report = ErrorReport::invalid_payload(e.to_string());
+					report_error(rollup, options, &report, Some(input_index)).await;
+					return Ok(FinishStatus::Reject);
+				}
+			}
 		} else if rollup.get_address_book().is_portal(advance_input.metadata.sender)
 			&& options.portal_config == PortalHandlerConfig::Dispense
 		{
@@ -179,44 +1322,626 @@ impl Supervisor {
 			return Ok(FinishStatus::Accept);
 		}
 
-		if deposit.is_some() {
+		if let Some(deposit) = &deposit {
 			debug!("Deposited: {:?}", deposit);
+			keep_deposit_credit(rollup, options.transactional_wallets, options.deposit_reject_policy).await;
+
+			// A deposit whose notices can't be sent is rejected like the ones the app rejects, instead of stopping
+			if let Some(change) = BalanceChange::from_deposit(deposit) {
+				if let Err(e) = rollup.notify_balance_change(change).await {
+					error!("Error sending the balance change notice of the deposit: {}", e);
+					report_error(rollup, options, &e, Some(input_index)).await;
+					return Ok(settle_rejected_deposit(rollup, options.deposit_reject_policy, deposit).await);
+				}
+			}
+			if let (true, Some(receipt)) = (
+				options.deposit_receipts,
+				DepositReceipt::from_deposit(deposit, input_index),
+			) {
+				if let Err(e) = rollup.send_notice(receipt.to_bytes()).await {
+					error!("Error sending the deposit receipt: {}", e);
+					report_error(rollup, options, &e, Some(input_index)).await;
+					return Ok(settle_rejected_deposit(rollup, options.deposit_reject_policy, deposit).await);
+				}
+			}
 
 			if options.portal_config == (PortalHandlerConfig::Handle { advance: false }) {
 				return Ok(FinishStatus::Accept);
 			}
 		}
 
-		match app
-			.advance(rollup, advance_input.metadata, &advance_input.payload, deposit)
-			.await
-		{
+		let app = apps
+			.iter()
+			.find(|(selector, _)| selector.matches_advance(&advance_input.metadata, &advance_input.payload))
+			.map(|(_, app)| app);
+
+		let status = match app {
+			Some(app) => {
+				Self::advance_app(
+					app,
+					rollup,
+					options,
+					advance_input.metadata,
+					&advance_input.payload,
+					deposit.clone(),
+				)
+				.await
+			}
+			None => {
+				debug!("No application selected for the advance input, rejecting it");
+				report_error(rollup, options, &ErrorReport::no_route(), Some(input_index)).await;
+				FinishStatus::Reject
+			}
+		};
+
+		match deposit {
+			Some(deposit) if status == FinishStatus::Reject => {
+				Ok(settle_rejected_deposit(rollup, options.deposit_reject_policy, &deposit).await)
+			}
+			_ => Ok(status),
+		}
+	}
+
+	async fn advance_app(
+		app: &impl Application,
+		rollup: &Rollup,
+		options: &RunOptions,
+		metadata: Metadata,
+		payload: &[u8],
+		deposit: Option<Deposit>,
+	) -> FinishStatus {
+		let input_index = metadata.input_index;
+		let status = match options.middlewares.advance(app, rollup, metadata, payload, deposit).await {
 			Ok(result_status) => {
 				debug!("Advance status: {:?}", result_status);
-				Ok(result_status)
+				result_status
 			}
 			Err(e) => {
 				error!("Error in advance: {}", e);
+				report_error(rollup, options, e.as_ref(), Some(input_index)).await;
+				app.on_error(rollup, e.as_ref()).await;
+				FinishStatus::Reject
+			}
+		};
+
+		// Checked before the deposit of the input is settled, so the rejection applies the deposit reject policy
+		if let Err(e) = rollup.get_ether_wallet().read().await.check_supply() {
+			error!("Rejecting the advance input: {}", e);
+			return FinishStatus::Reject;
+		}
+		#[cfg(feature = "memory-tracking")]
+		if let Some(Err(e)) = options.memory_limit.map(|limit| limit.check()) {
+			error!("Rejecting the advance input: {}", e);
+			return FinishStatus::Reject;
+		}
+		status
+	}
+
+	async fn handle_playground_input(
+		rollup: &Rollup,
+		options: &RunOptions,
+		playground: &Playground,
+		inspect_input: Inspect,
+	) -> Result<FinishStatus, Box<dyn Error>> {
+		rollup.get_activity().write().await.record_inspect();
+		match playground.respond(rollup, &inspect_input.payload).await {
+			Ok(()) => Ok(FinishStatus::Accept),
+			Err(e) => {
+				error!("Error in playground route: {}", e);
+				report_error(rollup, options, e.as_ref(), None).await;
 				Ok(FinishStatus::Reject)
 			}
 		}
 	}
 
-	async fn handle_inspect_input(
+	async fn handle_cached_inspect_input<A: Application>(
 		rollup: &Rollup,
-		app: &impl Application,
+		options: &RunOptions,
+		apps: &[(Selector, A)],
 		inspect_input: Inspect,
+		cache: &mut InspectCache,
+	) -> Result<FinishStatus, Box<dyn Error>> {
+		rollup.get_activity().write().await.record_inspect();
+		if let Some(reports) = cache.get(&inspect_input.payload) {
+			debug!(
+				"Replaying the cached response of the inspect input: {:?}",
+				inspect_input
+			);
+			for report in reports {
+				rollup.send_report(report).await?;
+			}
+			return Ok(FinishStatus::Accept);
+		}
+
+		let payload = inspect_input.payload.clone();
+		rollup.record_reports().await;
+		let status = Self::handle_inspect_input(rollup, options, apps, inspect_input).await;
+		let reports = rollup.take_recorded_reports().await;
+
+		if let Ok(FinishStatus::Accept) = status {
+			cache.insert(&payload, reports);
+		}
+		status
+	}
+
+	async fn handle_inspect_input<A: Application>(
+		rollup: &Rollup,
+		options: &RunOptions,
+		apps: &[(Selector, A)],
+		mut inspect_input: Inspect,
 	) -> Result<FinishStatus, Box<dyn Error>> {
 		debug!("Inspect input: {:?}", inspect_input);
-		match app.inspect(rollup, &inspect_input.payload).await {
+		if options.hex_payloads {
+			inspect_input.payload = codec::unwrap_hex(inspect_input.payload);
+		}
+
+		if options.about && inspect_input.payload == ABOUT_ROUTE {
+			let about = About::new(&options.portal_config, apps.iter().map(|(selector, _)| selector))
+				.build(options.build_info.clone());
+			rollup.send_report(about.to_bytes()?).await?;
+			return Ok(FinishStatus::Accept);
+		}
+
+		let Some((_, app)) = apps
+			.iter()
+			.find(|(selector, _)| selector.matches_inspect(&inspect_input.payload))
+		else {
+			debug!("No application selected for the inspect input, rejecting it");
+			report_error(rollup, options, &ErrorReport::no_route(), None).await;
+			return Ok(FinishStatus::Reject);
+		};
+
+		match options.middlewares.inspect(app, rollup, &inspect_input.payload).await {
 			Ok(result_status) => {
 				debug!("Inspect status: {:?}", result_status);
 				Ok(result_status)
 			}
 			Err(e) => {
 				error!("Error in inspect: {}", e);
+				report_error(rollup, options, e.as_ref(), None).await;
+				app.on_error(rollup, e.as_ref()).await;
 				Ok(FinishStatus::Reject)
 			}
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::address;
+	use crate::types::machine::{Advance, Inspect};
+
+	fn metadata(sender: Address) -> Metadata {
+		Metadata {
+			input_index: 0,
+			sender,
+			block_number: 0,
+			timestamp: 0,
+			app_contract: None,
+			chain_id: None,
+			prev_randao: None,
+			annotations: Default::default(),
+		}
+	}
+
+	#[test]
+	fn test_selector_matches() {
+		let admin = address!("0x0000000000000000000000000000000000000001");
+		let selector = Selector::senders([admin]);
+		assert!(selector.matches_advance(&metadata(admin), b""));
+		assert!(!selector.matches_advance(&metadata(Address::zero()), b""));
+		assert!(!selector.matches_inspect(b""));
+
+		let selector = Selector::payload(|payload| payload.starts_with(b"billing:"));
+		assert!(selector.matches_advance(&metadata(admin), b"billing:pay"));
+		assert!(!selector.matches_advance(&metadata(admin), b"other"));
+		assert!(selector.matches_inspect(b"billing:invoices"));
+
+		let selector = Selector::prefix(b"admin:");
+		assert!(selector.matches_advance(&metadata(admin), b"admin:pause"));
+		assert!(!selector.matches_inspect(b"pause"));
+
+		assert!(Selector::Any.matches_advance(&metadata(admin), b""));
+		assert!(Selector::Any.matches_inspect(b""));
+	}
+
+	#[test]
+	fn test_stop_condition() {
+		let advance = |input_index| {
+			let mut metadata = metadata(Address::zero());
+			metadata.input_index = input_index;
+			Input::Advance(Advance {
+				metadata,
+				payload: b"payload".to_vec(),
+			})
+		};
+		let inspect = Input::Inspect(Inspect {
+			payload: b"stop".to_vec(),
+		});
+
+		let condition = StopCondition::Inputs(2);
+		assert!(!condition.should_stop(1, &advance(0), FinishStatus::Accept));
+		assert!(condition.should_stop(2, &inspect, FinishStatus::Accept));
+
+		let condition = StopCondition::InputIndex(3);
+		assert!(!condition.should_stop(1, &advance(2), FinishStatus::Accept));
+		assert!(!condition.should_stop(1, &inspect, FinishStatus::Accept));
+		assert!(condition.should_stop(1, &advance(3), FinishStatus::Accept));
+
+		let condition = StopCondition::predicate(|input, status| {
+			status == FinishStatus::Reject || matches!(input, Input::Inspect(inspect) if inspect.payload == b"stop")
+		});
+		assert!(!condition.should_stop(1, &advance(0), FinishStatus::Accept));
+		assert!(condition.should_stop(1, &advance(0), FinishStatus::Reject));
+		assert!(condition.should_stop(1, &inspect, FinishStatus::Accept));
+	}
+
+	#[cfg(feature = "rollup")]
+	#[async_std::test]
+	async fn test_report_error() {
+		use crate::core::testing::RollupMockup;
+		use crate::types::machine::Output;
+
+		let env = RollupMockup::new();
+		let error: Box<dyn Error> = "boom".into();
+		report_error(&env, &RunOptions::default(), error.as_ref(), Some(3)).await;
+		report_error(
+			&env,
+			&RunOptions::builder().error_reports(true).build(),
+			error.as_ref(),
+			Some(3),
+		)
+		.await;
+
+		let outputs = env.advance(FinishStatus::Accept).await.unwrap().unwrap();
+		assert_eq!(
+			outputs,
+			vec![Output::Report {
+				payload: ErrorReport::internal("boom").input_index(3).to_bytes()
+			}]
+		);
+	}
+
+	#[async_std::test]
+	async fn test_permits_sender() {
+		use crate::core::testing::RollupMockup;
+		use crate::types::machine::Output;
+
+		let env = RollupMockup::new();
+		let (alice, bob) = (Address::repeat_byte(0xa1), Address::repeat_byte(0xb0));
+		let policy = SenderPolicy::deny([bob]);
+		assert!(permits_sender(&env, &policy, true, &metadata(alice)).await);
+		assert!(!permits_sender(&env, &policy, false, &metadata(bob)).await);
+		assert!(!permits_sender(&env, &policy, true, &metadata(bob)).await);
+
+		let outputs = env.advance(FinishStatus::Accept).await.unwrap().unwrap();
+		let report = ErrorReport::unauthorized(format!("sender {:?} is not allowed", bob)).input_index(0);
+		assert_eq!(
+			outputs,
+			vec![Output::Report {
+				payload: report.to_bytes()
+			}]
+		);
+	}
+
+	#[cfg(feature = "rollup")]
+	#[test]
+	fn test_rollup_url_from_var() {
+		let options = RunOptions::builder()
+			.rollup_url_from_var(Some("http://devnet:5004".to_string()))
+			.build();
+		assert_eq!(options.rollup_url, "http://devnet:5004");
+
+		let options = RunOptions::builder().rollup_url_from_var(Some(String::new())).build();
+		assert_eq!(options.rollup_url, MACHINE_ROLLUP_URL);
+		let options = RunOptions::builder()
+			.rollup_url("http://devnet:5004")
+			.rollup_url_from_var(None)
+			.build();
+		assert_eq!(options.rollup_url, "http://devnet:5004");
+	}
+
+	#[cfg(feature = "rollup")]
+	#[test]
+	fn test_backend_rollup_url() {
+		let options = RunOptions::default();
+		assert_eq!((options.backend, options.rollup_url.as_str()), (Backend::Explicit, MACHINE_ROLLUP_URL));
+		let options = RunOptions::builder().backend(Backend::Host).build();
+		assert_eq!(options.rollup_url, crate::core::backend::HOST_ROLLUP_URL);
+		let options = RunOptions::builder()
+			.rollup_url(MACHINE_ROLLUP_URL)
+			.backend(Backend::Host)
+			.build();
+		assert_eq!(options.rollup_url, MACHINE_ROLLUP_URL);
+	}
+
+	#[cfg(feature = "rollup")]
+	#[async_std::test]
+	async fn test_read_timeout() {
+		let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+		let url = format!("http://{}", listener.local_addr().unwrap());
+		let options = RunOptions::builder()
+			.rollup_url(url)
+			.read_timeout(Duration::from_millis(100))
+			.build();
+
+		let rollup = Rollup::new(&options);
+		assert!(rollup.finish_and_get_next(FinishStatus::Accept).await.is_err());
+		drop(listener);
+	}
+
+	/// Serves the rollup routes, answering outputs with increasing indices and `finish` with the next of `inputs`, or
+	/// no pending input once they run out.
+	#[cfg(feature = "rollup")]
+	fn serve_rollup(
+		listener: std::net::TcpListener,
+		routes: std::sync::Arc<std::sync::Mutex<Vec<String>>>,
+		inputs: Vec<String>,
+	) {
+		use std::io::{BufRead, BufReader, Read, Write};
+
+		let mut inputs = inputs.into_iter();
+		let mut next_index = 5;
+		for stream in listener.incoming() {
+			let Ok(stream) = stream else { break };
+			let mut reader = BufReader::new(stream.try_clone().unwrap());
+			let mut stream = stream;
+			loop {
+				let mut request_line = String::new();
+				if reader.read_line(&mut request_line).unwrap_or(0) == 0 {
+					break;
+				}
+				let mut content_length = 0;
+				loop {
+					let mut header = String::new();
+					reader.read_line(&mut header).unwrap();
+					if header.trim().is_empty() {
+						break;
+					}
+					if let Some((name, value)) = header.split_once(':') {
+						if name.eq_ignore_ascii_case("content-length") {
+							content_length = value.trim().parse().unwrap();
+						}
+					}
+				}
+				reader.read_exact(&mut vec![0; content_length]).unwrap();
+
+				let route = request_line.split_whitespace().nth(1).unwrap_or_default().to_string();
+				let response = match route.as_str() {
+					"/finish" => match inputs.next() {
+						Some(body) => format!(
+							"HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+							body.len(),
+							body
+						),
+						None => "HTTP/1.1 202 Accepted\r\nContent-Length: 0\r\n\r\n".to_string(),
+					},
+					_ => {
+						let body = format!("{{\"index\":{}}}", next_index);
+						next_index += 1;
+						format!(
+							"HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+							body.len(),
+							body
+						)
+					}
+				};
+				routes.lock().unwrap().push(route);
+				stream.write_all(response.as_bytes()).unwrap();
+			}
+		}
+	}
+
+	#[cfg(feature = "rollup")]
+	#[async_std::test]
+	async fn test_buffered_outputs() {
+		let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+		let url = format!("http://{}", listener.local_addr().unwrap());
+		let routes = Arc::new(std::sync::Mutex::new(Vec::new()));
+		let server_routes = routes.clone();
+		std::thread::spawn(move || serve_rollup(listener, server_routes, Vec::new()));
+
+		let options = RunOptions::builder().rollup_url(url).buffered_outputs(true).build();
+		let rollup = Rollup::new(&options);
+
+		assert_eq!(rollup.send_notice(b"first").await.unwrap(), None);
+		assert_eq!(rollup.send_voucher(Address::zero(), b"pay").await.unwrap(), None);
+		assert_eq!(rollup.send_notice(b"second").await.unwrap(), None);
+		rollup.send_report(b"kept").await.unwrap();
+		assert!(rollup
+			.finish_and_get_next(FinishStatus::Reject)
+			.await
+			.unwrap()
+			.is_none());
+		assert_eq!(*routes.lock().unwrap(), vec!["/report", "/finish"]);
+
+		assert_eq!(rollup.send_notice(b"third").await.unwrap(), None);
+		assert!(rollup
+			.finish_and_get_next(FinishStatus::Accept)
+			.await
+			.unwrap()
+			.is_none());
+		assert_eq!(rollup.send_notice(b"fourth").await.unwrap(), None);
+		assert_eq!(
+			*routes.lock().unwrap(),
+			vec!["/report", "/finish", "/notice", "/finish"]
+		);
+	}
+
+	#[cfg(feature = "rollup")]
+	#[async_std::test]
+	async fn test_heartbeat_waits_for_accepted_input() {
+		use crate::types::machine::Deposit;
+
+		struct RejectApp;
+
+		impl Application for RejectApp {
+			type Error = Box<dyn Error>;
+
+			async fn advance(
+				&self,
+				_env: &impl Environment,
+				_metadata: Metadata,
+				payload: &[u8],
+				_deposit: Option<Deposit>,
+			) -> Result<FinishStatus, Box<dyn Error>> {
+				match payload {
+					b"reject" => Ok(FinishStatus::Reject),
+					_ => Ok(FinishStatus::Accept),
+				}
+			}
+		}
+
+		let advance = |input_index: u64, payload: &[u8]| {
+			serde_json::json!({
+				"request_type": "advance_state",
+				"data": {
+					"metadata": {
+						"msg_sender": format!("{:?}", Address::zero()),
+						"epoch_index": 0,
+						"input_index": input_index,
+						"block_number": 0,
+						"timestamp": 0
+					},
+					"payload": format!("0x{}", hex::encode(payload))
+				}
+			})
+			.to_string()
+		};
+		let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+		let url = format!("http://{}", listener.local_addr().unwrap());
+		let routes = Arc::new(std::sync::Mutex::new(Vec::new()));
+		let server_routes = routes.clone();
+		let inputs = vec![advance(0, b"reject"), advance(1, b"accept")];
+		std::thread::spawn(move || serve_rollup(listener, server_routes, inputs));
+
+		// The heartbeat due on the rejected input would be discarded with it, so it's sent with the next accepted one.
+		let options = RunOptions::builder().rollup_url(url).heartbeat(1).build();
+		Supervisor::run_until(RejectApp, options, StopCondition::Inputs(2))
+			.await
+			.unwrap();
+		assert_eq!(*routes.lock().unwrap(), vec!["/finish", "/finish", "/notice", "/finish"]);
+	}
+
+	#[cfg(feature = "rollup")]
+	#[async_std::test]
+	async fn test_failed_deposit_notices_reject_the_input() {
+		use crate::core::contracts::ether::EtherWallet;
+		use crate::types::machine::Deposit;
+
+		struct AcceptApp;
+
+		impl Application for AcceptApp {
+			type Error = Box<dyn Error>;
+
+			async fn advance(
+				&self,
+				_env: &impl Environment,
+				_metadata: Metadata,
+				_payload: &[u8],
+				_deposit: Option<Deposit>,
+			) -> Result<FinishStatus, Box<dyn Error>> {
+				Ok(FinishStatus::Accept)
+			}
+		}
+
+		let payload = EtherWallet::deposit_payload(Address::repeat_byte(0xa), Uint::one()).unwrap();
+		let deposit = serde_json::json!({
+			"request_type": "advance_state",
+			"data": {
+				"metadata": {
+					"msg_sender": format!("{:?}", AddressBook::default().ether_portal),
+					"epoch_index": 0,
+					"input_index": 0,
+					"block_number": 0,
+					"timestamp": 0
+				},
+				"payload": format!("0x{}", hex::encode(payload))
+			}
+		})
+		.to_string();
+		let limits = OutputLimits {
+			max_notice_size: 1,
+			..OutputLimits::default()
+		};
+
+		for builder in [
+			RunOptions::builder().balance_notices(true),
+			RunOptions::builder().deposit_receipts(true),
+		] {
+			let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+			let url = format!("http://{}", listener.local_addr().unwrap());
+			let routes = Arc::new(std::sync::Mutex::new(Vec::new()));
+			let server_routes = routes.clone();
+			let inputs = vec![deposit.clone()];
+			std::thread::spawn(move || serve_rollup(listener, server_routes, inputs));
+
+			// The notice over the limit rejects the deposit with an error report, instead of stopping the supervisor
+			let options = builder.rollup_url(url).output_limits(limits).error_reports(true).build();
+			Supervisor::run_until(AcceptApp, options, StopCondition::Inputs(1))
+				.await
+				.unwrap();
+			assert_eq!(*routes.lock().unwrap(), vec!["/finish", "/report", "/finish"]);
+		}
+	}
+
+	#[cfg(feature = "rollup")]
+	#[async_std::test]
+	async fn test_lifecycle_hooks() {
+		use crate::types::machine::Deposit;
+		use std::sync::Mutex;
+
+		struct HookApp {
+			fail_start: bool,
+			events: Arc<Mutex<Vec<&'static str>>>,
+		}
+
+		impl Application for HookApp {
+			type Error = Box<dyn Error>;
+
+			async fn advance(
+				&self,
+				_env: &impl Environment,
+				_metadata: Metadata,
+				_payload: &[u8],
+				_deposit: Option<Deposit>,
+			) -> Result<FinishStatus, Box<dyn Error>> {
+				Ok(FinishStatus::Accept)
+			}
+
+			async fn on_start(&self, _env: &impl Environment) -> Result<(), Box<dyn Error>> {
+				self.events.lock().unwrap().push("start");
+				match self.fail_start {
+					true => Err("cold cache".into()),
+					false => Ok(()),
+				}
+			}
+
+			async fn on_shutdown(&self, _env: &impl Environment) -> Result<(), Box<dyn Error>> {
+				self.events.lock().unwrap().push("shutdown");
+				Ok(())
+			}
+		}
+
+		// Nothing listens on the address, so the loop fails on the first `finish`.
+		let address = std::net::TcpListener::bind("127.0.0.1:0")
+			.unwrap()
+			.local_addr()
+			.unwrap();
+		let options = RunOptions::builder()
+			.rollup_url(format!("http://{}", address))
+			.retry_policy(RetryPolicy::none())
+			.build();
+
+		for (fail_start, expected) in [(false, vec!["start", "shutdown"]), (true, vec!["start"])] {
+			let events = Arc::new(Mutex::new(Vec::new()));
+			let app = HookApp {
+				fail_start,
+				events: events.clone(),
+			};
+			assert!(Supervisor::run(app, options.clone()).await.is_err());
+			assert_eq!(*events.lock().unwrap(), expected);
+		}
+	}
+}
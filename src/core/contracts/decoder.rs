@@ -0,0 +1,125 @@
+use crate::types::machine::Deposit;
+use crate::types::primitives::*;
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+
+pub trait DepositDecoder: Send + Sync {
+	fn decode(&self, sender: Address, payload: &[u8]) -> Result<Option<Deposit>, Box<dyn Error>>;
+}
+
+impl<F> DepositDecoder for F
+where
+	F: Fn(Address, &[u8]) -> Result<Option<Deposit>, Box<dyn Error>> + Send + Sync,
+{
+	fn decode(&self, sender: Address, payload: &[u8]) -> Result<Option<Deposit>, Box<dyn Error>> {
+		self(sender, payload)
+	}
+}
+
+#[derive(Clone, Default)]
+pub struct DepositDecoders {
+	decoders: Vec<Arc<dyn DepositDecoder>>,
+}
+
+impl DepositDecoders {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn register(&mut self, decoder: impl DepositDecoder + 'static) {
+		self.decoders.push(Arc::new(decoder));
+	}
+
+	pub fn len(&self) -> usize {
+		self.decoders.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.decoders.is_empty()
+	}
+
+	pub fn decode(&self, sender: Address, payload: &[u8]) -> Result<Option<Deposit>, Box<dyn Error>> {
+		for decoder in &self.decoders {
+			if let Some(deposit) = decoder.decode(sender, payload)? {
+				return Ok(Some(deposit));
+			}
+		}
+		Ok(None)
+	}
+}
+
+impl fmt::Debug for DepositDecoders {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "DepositDecoders({})", self.decoders.len())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{address, uint};
+
+	struct VaultDecoder {
+		portal: Address,
+	}
+
+	impl DepositDecoder for VaultDecoder {
+		fn decode(&self, sender: Address, payload: &[u8]) -> Result<Option<Deposit>, Box<dyn Error>> {
+			if sender != self.portal {
+				return Ok(None);
+			}
+			if payload.len() < 40 {
+				return Err("invalid vault deposit payload".into());
+			}
+			Ok(Some(Deposit::ERC20 {
+				sender: Address::from_slice(&payload[..20]),
+				token: Address::from_slice(&payload[20..40]),
+				amount: uint!(1),
+			}))
+		}
+	}
+
+	#[test]
+	fn test_decoders_pick_the_first_match() {
+		let portal = address!("0x0000000000000000000000000000000000000001");
+		let mut decoders = DepositDecoders::new();
+		decoders.register(VaultDecoder { portal });
+		decoders.register(
+			|sender: Address, payload: &[u8]| -> Result<Option<Deposit>, Box<dyn Error>> {
+				Ok(Some(Deposit::Custom {
+					portal: sender,
+					sender: Address::zero(),
+					payload: payload.to_vec(),
+				}))
+			},
+		);
+		assert_eq!(decoders.len(), 2);
+
+		let payload = [[0xaa; 20], [0xbb; 20]].concat();
+		assert_eq!(
+			decoders.decode(portal, &payload).unwrap(),
+			Some(Deposit::ERC20 {
+				sender: Address::repeat_byte(0xaa),
+				token: Address::repeat_byte(0xbb),
+				amount: uint!(1),
+			})
+		);
+
+		let other = address!("0x0000000000000000000000000000000000000002");
+		assert!(matches!(
+			decoders.decode(other, b"data").unwrap(),
+			Some(Deposit::Custom { .. })
+		));
+	}
+
+	#[test]
+	fn test_decoder_errors_are_propagated() {
+		let portal = address!("0x0000000000000000000000000000000000000001");
+		let mut decoders = DepositDecoders::new();
+		decoders.register(VaultDecoder { portal });
+
+		assert!(decoders.decode(portal, b"short").is_err());
+		assert_eq!(decoders.decode(Address::zero(), b"short").unwrap(), None);
+	}
+}
@@ -1,8 +1,8 @@
+use super::{Journal, WalletMap};
+use crate::types::error::CrabrollsError;
 use crate::types::machine::Deposit;
+use crate::types::primitives::*;
 use crate::utils::abi::abi;
-use ethabi::{Address, Uint};
-use std::collections::HashMap;
-use std::error::Error;
 use std::future::Future;
 
 pub trait IntoIdsAmountsIter {
@@ -38,16 +38,23 @@ impl IntoIdsIter for Vec<Uint> {
 }
 
 pub struct ERC1155Wallet {
-	balances: HashMap<(Address, Address, Uint), Uint>,
+	balances: WalletMap<(Address, Address, Uint), Uint>,
+	journal: Journal<((Address, Address, Uint), Uint)>, // Previous balances of the changed wallets
 }
 
 impl ERC1155Wallet {
 	pub fn new() -> Self {
 		ERC1155Wallet {
-			balances: HashMap::new(),
+			balances: WalletMap::default(),
+			journal: Journal::default(),
 		}
 	}
 
+	/// Pre-sizes the storage for `additional` more balances, e.g. before a bulk import.
+	pub fn reserve(&mut self, additional: usize) {
+		self.balances.reserve(additional);
+	}
+
 	pub fn addresses(&self) -> Vec<Address> {
 		let mut addresses: Vec<Address> = self.balances.keys().map(|(a, _, _)| *a).collect();
 		addresses.sort();
@@ -55,7 +62,20 @@ impl ERC1155Wallet {
 		addresses
 	}
 
+	pub fn balances(&self) -> Vec<(Address, Address, Uint, Uint)> {
+		let mut balances: Vec<(Address, Address, Uint, Uint)> = self
+			.balances
+			.iter()
+			.map(|((owner, token, id), amount)| (*owner, *token, *id, *amount))
+			.collect();
+		balances.sort();
+		balances
+	}
+
 	pub fn set_balance(&mut self, owner: Address, token_address: Address, token_id: Uint, amount: Uint) {
+		let previous = self.balance_of(owner, token_address, token_id);
+		self.journal.record(|| ((owner, token_address, token_id), previous));
+
 		if amount.is_zero() {
 			self.balances.remove(&(owner, token_address, token_id));
 		} else {
@@ -63,6 +83,22 @@ impl ERC1155Wallet {
 		}
 	}
 
+	/// Starts journaling the balance changes, so they can be rolled back until `commit` is called.
+	pub fn begin(&mut self) {
+		self.journal.begin();
+	}
+
+	pub fn commit(&mut self) {
+		self.journal.commit();
+	}
+
+	/// Restores the balances changed since `begin`.
+	pub fn rollback(&mut self) {
+		for ((owner, token_address, token_id), amount) in self.journal.rollback() {
+			self.set_balance(owner, token_address, token_id, amount);
+		}
+	}
+
 	pub fn balance_of(&self, owner: Address, token_address: Address, token_id: Uint) -> Uint {
 		self.balances
 			.get(&(owner, token_address, token_id))
@@ -70,18 +106,28 @@ impl ERC1155Wallet {
 			.unwrap_or_else(Uint::zero)
 	}
 
+	pub fn balance_of_batch<I>(&self, owner: Address, token_address: Address, token_ids: I) -> Vec<Uint>
+	where
+		I: IntoIdsIter,
+	{
+		token_ids
+			.into_inner_iter()
+			.map(|token_id| self.balance_of(owner, token_address, token_id))
+			.collect()
+	}
+
 	pub fn transfer<I>(
 		&mut self,
 		src_wallet: Address,
 		dst_wallet: Address,
 		token_address: Address,
 		transfers: I,
-	) -> Result<(), Box<dyn Error>>
+	) -> Result<(), CrabrollsError>
 	where
 		I: IntoIdsAmountsIter,
 	{
 		if src_wallet == dst_wallet {
-			return Err("can't transfer to self".into());
+			return Err(CrabrollsError::SelfTransfer);
 		}
 
 		let transfers: Vec<(Uint, Uint)> = transfers.into_inner_iter().collect();
@@ -89,7 +135,7 @@ impl ERC1155Wallet {
 		for (token_id, amount) in &transfers {
 			let src_balance = self.balance_of(src_wallet, token_address, *token_id);
 			if src_balance < *amount {
-				return Err("insufficient funds".into());
+				return Err(CrabrollsError::InsufficientFunds);
 			}
 		}
 
@@ -104,7 +150,7 @@ impl ERC1155Wallet {
 		Ok(())
 	}
 
-	pub fn single_deposit(&mut self, payload: Vec<u8>) -> Result<(Deposit, Vec<u8>), Box<dyn Error>> {
+	pub fn single_deposit(&mut self, payload: Vec<u8>) -> Result<(Deposit, Vec<u8>), CrabrollsError> {
 		let args = abi::erc1155::single_deposit(payload.clone())?;
 
 		let token_address = abi::extract::address(&args[0])?;
@@ -130,7 +176,7 @@ impl ERC1155Wallet {
 		))
 	}
 
-	pub fn batch_deposit(&mut self, payload: Vec<u8>) -> Result<(Deposit, Vec<u8>), Box<dyn Error>> {
+	pub fn batch_deposit(&mut self, payload: Vec<u8>) -> Result<(Deposit, Vec<u8>), CrabrollsError> {
 		let args = abi::erc1155::batch_deposit(payload.clone())?;
 
 		let token_address = abi::extract::address(&args[0])?;
@@ -162,7 +208,7 @@ impl ERC1155Wallet {
 		wallet_address: Address,
 		token_address: Address,
 		deposits: I,
-	) -> Result<Vec<u8>, Box<dyn Error>>
+	) -> Result<Vec<u8>, CrabrollsError>
 	where
 		I: IntoIdsAmountsIter,
 	{
@@ -183,7 +229,7 @@ impl ERC1155Wallet {
 		token_address: Address,
 		withdrawals: I,
 		data: Option<Vec<u8>>,
-	) -> Result<Vec<u8>, Box<dyn Error>>
+	) -> Result<Vec<u8>, CrabrollsError>
 	where
 		I: IntoIdsAmountsIter,
 	{
@@ -192,7 +238,7 @@ impl ERC1155Wallet {
 		for (token_id, amount) in &withdrawals {
 			let owner_balance = self.balance_of(wallet_address, token_address, *token_id);
 			if owner_balance < *amount {
-				return Err("insufficient funds".into());
+				return Err(CrabrollsError::InsufficientFunds);
 			}
 			changes.push((*token_id, owner_balance - amount));
 		}
@@ -209,6 +255,14 @@ impl ERC1155Wallet {
 			Err(e) => Err(e),
 		}
 	}
+
+	/// Gives back the tokens of a withdrawal whose voucher couldn't be sent.
+	pub fn cancel_withdraw(&mut self, wallet_address: Address, token_address: Address, withdrawals: &[(Uint, Uint)]) {
+		for (token_id, amount) in withdrawals {
+			let balance = self.balance_of(wallet_address, token_address, *token_id).saturating_add(*amount);
+			self.set_balance(wallet_address, token_address, *token_id, balance);
+		}
+	}
 }
 
 pub trait ERC1155Environment {
@@ -219,7 +273,7 @@ pub trait ERC1155Environment {
 		token_address: Address,
 		withdrawals: I,
 		data: Option<Vec<u8>>,
-	) -> impl Future<Output = Result<(), Box<dyn Error>>>
+	) -> impl Future<Output = Result<(), CrabrollsError>>
 	where
 		I: IntoIdsAmountsIter;
 	fn erc1155_transfer<I>(
@@ -228,7 +282,7 @@ pub trait ERC1155Environment {
 		dst_wallet: Address,
 		token_address: Address,
 		transfers: I,
-	) -> impl Future<Output = Result<(), Box<dyn Error>>>
+	) -> impl Future<Output = Result<(), CrabrollsError>>
 	where
 		I: IntoIdsAmountsIter;
 	fn erc1155_balance(
@@ -237,6 +291,14 @@ pub trait ERC1155Environment {
 		token_address: Address,
 		token_id: Uint,
 	) -> impl Future<Output = Uint>;
+	fn erc1155_balance_of_batch<I>(
+		&self,
+		wallet_address: Address,
+		token_address: Address,
+		token_ids: I,
+	) -> impl Future<Output = Vec<Uint>>
+	where
+		I: IntoIdsIter;
 }
 
 #[cfg(test)]
@@ -277,6 +339,22 @@ mod tests {
 		assert_eq!(wallet.balance_of(owner, token_address, token_id), Uint::zero());
 	}
 
+	#[test]
+	fn test_balance_of_batch() {
+		let mut wallet = ERC1155Wallet::new();
+		let owner = Address::repeat_byte(1);
+		let token_address = Address::repeat_byte(2);
+
+		wallet.set_balance(owner, token_address, uint!(1), uint!(10));
+		wallet.set_balance(owner, token_address, uint!(3), uint!(30));
+
+		assert_eq!(
+			wallet.balance_of_batch(owner, token_address, vec![uint!(3), uint!(2), uint!(1)]),
+			vec![uint!(30), uint!(0), uint!(10)]
+		);
+		assert_eq!(wallet.balance_of_batch(owner, token_address, uint!(1)), vec![uint!(10)]);
+	}
+
 	#[test]
 	fn test_transfer() {
 		let mut wallet = ERC1155Wallet::new();
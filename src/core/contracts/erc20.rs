@@ -1,28 +1,50 @@
+use super::decoder::DepositDecoder;
+use super::{Journal, WalletMap};
+use crate::types::error::CrabrollsError;
 use crate::types::machine::Deposit;
+use crate::types::primitives::*;
 use crate::utils::abi::abi;
-use ethabi::{Address, Uint};
-use std::collections::HashMap;
 use std::error::Error;
 use std::future::Future;
 
 pub struct ERC20Wallet {
-	balance: HashMap<(Address, Address), Uint>,
+	balance: WalletMap<(Address, Address), Uint>,
+	journal: Journal<((Address, Address), Uint)>, // Previous balances of the changed wallets
 }
 
 impl ERC20Wallet {
 	pub fn new() -> Self {
 		ERC20Wallet {
-			balance: HashMap::new(),
+			balance: WalletMap::default(),
+			journal: Journal::default(),
 		}
 	}
 
+	/// Pre-sizes the storage for `additional` more balances, e.g. before a bulk import.
+	pub fn reserve(&mut self, additional: usize) {
+		self.balance.reserve(additional);
+	}
+
 	pub fn addresses(&self) -> Vec<Address> {
-		let mut addresses: Vec<Address> = self.balance.keys().map(|(a, _)| a.clone()).collect();
-		addresses.sort_by(|a, b| a.cmp(b));
+		let mut addresses: Vec<Address> = self.balance.keys().map(|(a, _)| *a).collect();
+		addresses.sort();
 		addresses
 	}
 
+	pub fn balances(&self) -> Vec<(Address, Address, Uint)> {
+		let mut balances: Vec<(Address, Address, Uint)> = self
+			.balance
+			.iter()
+			.map(|((wallet, token), value)| (*wallet, *token, *value))
+			.collect();
+		balances.sort();
+		balances
+	}
+
 	pub fn set_balance(&mut self, wallet_address: Address, token_address: Address, value: Uint) {
+		let previous = self.balance_of(wallet_address, token_address);
+		self.journal.record(|| ((wallet_address, token_address), previous));
+
 		if value.is_zero() {
 			self.balance.remove(&(wallet_address, token_address));
 		} else {
@@ -30,6 +52,22 @@ impl ERC20Wallet {
 		}
 	}
 
+	/// Starts journaling the balance changes, so they can be rolled back until `commit` is called.
+	pub fn begin(&mut self) {
+		self.journal.begin();
+	}
+
+	pub fn commit(&mut self) {
+		self.journal.commit();
+	}
+
+	/// Restores the balances changed since `begin`.
+	pub fn rollback(&mut self) {
+		for ((wallet_address, token_address), value) in self.journal.rollback() {
+			self.set_balance(wallet_address, token_address, value);
+		}
+	}
+
 	pub fn balance_of(&self, wallet_address: Address, token_address: Address) -> Uint {
 		self.balance
 			.get(&(wallet_address, token_address))
@@ -43,31 +81,31 @@ impl ERC20Wallet {
 		dst_wallet: Address,
 		token_address: Address,
 		value: Uint,
-	) -> Result<(), Box<dyn Error>> {
+	) -> Result<(), CrabrollsError> {
 		if src_wallet == dst_wallet {
-			return Err("can't transfer to self".into());
+			return Err(CrabrollsError::SelfTransfer);
 		}
 
 		let new_src_balance = self
 			.balance_of(src_wallet, token_address)
 			.checked_sub(value)
-			.ok_or("insufficient funds")?;
+			.ok_or(CrabrollsError::InsufficientFunds)?;
 		let new_dst_balance = self
 			.balance_of(dst_wallet, token_address)
 			.checked_add(value)
-			.ok_or("balance overflow")?;
+			.ok_or(CrabrollsError::BalanceOverflow)?;
 
 		self.set_balance(src_wallet, token_address, new_src_balance);
 		self.set_balance(dst_wallet, token_address, new_dst_balance);
 		Ok(())
 	}
 
-	pub fn deposit(&mut self, payload: Vec<u8>) -> Result<(Deposit, Vec<u8>), Box<dyn Error>> {
+	pub fn deposit(&mut self, payload: Vec<u8>) -> Result<(Deposit, Vec<u8>), CrabrollsError> {
 		let args = abi::erc20::deposit(payload.clone())?;
 
 		let success = abi::extract::bool(&args[0])?;
 		if !success {
-			return Err("received failed deposit transaction".into());
+			return Err(CrabrollsError::portal("received failed deposit transaction"));
 		}
 		let token_address = abi::extract::address(&args[1])?;
 		let wallet_address = abi::extract::address(&args[2])?;
@@ -91,20 +129,60 @@ impl ERC20Wallet {
 		wallet_address: Address,
 		token_address: Address,
 		value: Uint,
-	) -> Result<Vec<u8>, Box<dyn Error>> {
+	) -> Result<Vec<u8>, CrabrollsError> {
 		abi::erc20::deposit_payload(wallet_address, token_address, value)
 	}
 
+	/// Decodes a deposit forwarded by an ERC777 portal, returning it along with the `userData` of the transfer.
+	pub fn decode_erc777_deposit(payload: &[u8]) -> Result<(Deposit, Vec<u8>), CrabrollsError> {
+		let args = abi::erc777::deposit(payload.to_vec())?;
+
+		let token_address = abi::extract::address(&args[0])?;
+		let wallet_address = abi::extract::address(&args[2])?;
+		let value = abi::extract::uint(&args[4])?;
+		let user_data = abi::extract::bytes(&args[5])?;
+
+		debug!("new ERC777 deposit from {:?} with value {:?}", wallet_address, value);
+
+		let deposit = Deposit::ERC20 {
+			sender: wallet_address,
+			token: token_address,
+			amount: value,
+		};
+
+		Ok((deposit, user_data))
+	}
+
+	/// Decodes a deposit forwarded by an ERC677 portal, returning it along with the `data` of the `transferAndCall`.
+	pub fn decode_erc677_deposit(payload: &[u8]) -> Result<(Deposit, Vec<u8>), CrabrollsError> {
+		let args = abi::erc677::deposit(payload.to_vec())?;
+
+		let token_address = abi::extract::address(&args[0])?;
+		let wallet_address = abi::extract::address(&args[1])?;
+		let value = abi::extract::uint(&args[2])?;
+		let data = abi::extract::bytes(&args[3])?;
+
+		debug!("new ERC677 deposit from {:?} with value {:?}", wallet_address, value);
+
+		let deposit = Deposit::ERC20 {
+			sender: wallet_address,
+			token: token_address,
+			amount: value,
+		};
+
+		Ok((deposit, data))
+	}
+
 	pub fn withdraw(
 		&mut self,
 		wallet_address: Address,
 		token_address: Address,
 		value: Uint,
-	) -> Result<Vec<u8>, Box<dyn Error>> {
+	) -> Result<Vec<u8>, CrabrollsError> {
 		let new_balance = self
 			.balance_of(wallet_address, token_address)
 			.checked_sub(value)
-			.ok_or("insufficient funds")?;
+			.ok_or(CrabrollsError::InsufficientFunds)?;
 
 		let result = abi::erc20::withdraw(wallet_address, value);
 
@@ -116,6 +194,56 @@ impl ERC20Wallet {
 			Err(e) => Err(e),
 		}
 	}
+
+	/// Gives back the tokens of a withdrawal whose voucher couldn't be sent.
+	pub fn cancel_withdraw(&mut self, wallet_address: Address, token_address: Address, value: Uint) {
+		let balance = self.balance_of(wallet_address, token_address).saturating_add(value);
+		self.set_balance(wallet_address, token_address, balance);
+	}
+}
+
+/// Decoder of the deposits of an ERC777 portal, which receives the tokens on its `tokensReceived` hook and forwards
+/// the token address followed by the hook arguments, ABI encoded. The tokens are credited on the ERC20 wallet.
+pub struct ERC777PortalDecoder {
+	pub portal: Address,
+}
+
+impl ERC777PortalDecoder {
+	pub fn new(portal: Address) -> Self {
+		Self { portal }
+	}
+}
+
+impl DepositDecoder for ERC777PortalDecoder {
+	fn decode(&self, sender: Address, payload: &[u8]) -> Result<Option<Deposit>, Box<dyn Error>> {
+		if sender != self.portal {
+			return Ok(None);
+		}
+		let (deposit, _) = ERC20Wallet::decode_erc777_deposit(payload)?;
+		Ok(Some(deposit))
+	}
+}
+
+/// Decoder of the deposits of an ERC677 portal, which receives the tokens through `transferAndCall` and forwards the
+/// token address followed by the `onTokenTransfer` arguments, ABI encoded. The tokens are credited on the ERC20 wallet.
+pub struct ERC677PortalDecoder {
+	pub portal: Address,
+}
+
+impl ERC677PortalDecoder {
+	pub fn new(portal: Address) -> Self {
+		Self { portal }
+	}
+}
+
+impl DepositDecoder for ERC677PortalDecoder {
+	fn decode(&self, sender: Address, payload: &[u8]) -> Result<Option<Deposit>, Box<dyn Error>> {
+		if sender != self.portal {
+			return Ok(None);
+		}
+		let (deposit, _) = ERC20Wallet::decode_erc677_deposit(payload)?;
+		Ok(Some(deposit))
+	}
 }
 
 pub trait ERC20Environment {
@@ -125,14 +253,14 @@ pub trait ERC20Environment {
 		wallet_address: Address,
 		token_address: Address,
 		value: Uint,
-	) -> impl Future<Output = Result<(), Box<dyn Error>>>;
+	) -> impl Future<Output = Result<(), CrabrollsError>>;
 	fn erc20_transfer(
 		&self,
 		src_wallet: Address,
 		dst_wallet: Address,
 		token_address: Address,
 		value: Uint,
-	) -> impl Future<Output = Result<(), Box<dyn Error>>>;
+	) -> impl Future<Output = Result<(), CrabrollsError>>;
 	fn erc20_balance(&self, wallet_address: Address, token_address: Address) -> impl Future<Output = Uint>;
 }
 
@@ -144,7 +272,7 @@ mod tests {
 	#[test]
 	fn test_erc20_wallet_initialization() {
 		let wallet = ERC20Wallet::new();
-		assert_eq!(wallet.balance, HashMap::new());
+		assert_eq!(wallet.balance, WalletMap::default());
 	}
 
 	#[test]
@@ -256,4 +384,43 @@ mod tests {
 		let result = wallet.withdraw(wallet_address, token_address, uint!(50u64));
 		assert_eq!(result.unwrap_err().to_string(), "insufficient funds");
 	}
+
+	#[test]
+	fn test_erc777_and_erc677_decoders() {
+		let portal = address!("0x0000000000000000000000000000000000000042");
+		let wallet_address = address!("0x0000000000000000000000000000000000000001");
+		let token_address = address!("0x0000000000000000000000000000000000000002");
+		let deposit = Deposit::ERC20 {
+			sender: wallet_address,
+			token: token_address,
+			amount: uint!(250u64),
+		};
+
+		let payload = abi::erc777::deposit_payload(
+			token_address,
+			wallet_address,
+			wallet_address,
+			portal,
+			uint!(250u64),
+			b"bid".to_vec(),
+		)
+		.unwrap();
+		assert_eq!(
+			ERC20Wallet::decode_erc777_deposit(&payload).unwrap(),
+			(deposit.clone(), b"bid".to_vec())
+		);
+		let decoder = ERC777PortalDecoder::new(portal);
+		assert_eq!(decoder.decode(portal, &payload).unwrap(), Some(deposit.clone()));
+		assert_eq!(decoder.decode(Address::zero(), &payload).unwrap(), None);
+		assert!(decoder.decode(portal, &payload[..64]).is_err());
+
+		let payload = abi::erc677::deposit_payload(token_address, wallet_address, uint!(250u64), vec![]).unwrap();
+		assert_eq!(
+			ERC20Wallet::decode_erc677_deposit(&payload).unwrap(),
+			(deposit.clone(), vec![])
+		);
+		let decoder = ERC677PortalDecoder::new(portal);
+		assert_eq!(decoder.decode(portal, &payload).unwrap(), Some(deposit));
+		assert!(decoder.decode(portal, b"short").is_err());
+	}
 }
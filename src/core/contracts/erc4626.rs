@@ -0,0 +1,254 @@
+use super::decoder::DepositDecoder;
+use super::{Journal, WalletMap};
+use crate::types::error::CrabrollsError;
+use crate::types::machine::Deposit;
+use crate::types::primitives::*;
+use crate::utils::abi::abi;
+use std::error::Error;
+use std::future::Future;
+
+pub struct ERC4626Wallet {
+	shares: WalletMap<(Address, Address), Uint>,
+	journal: Journal<((Address, Address), Uint)>, // Previous shares of the changed wallets
+}
+
+impl ERC4626Wallet {
+	pub fn new() -> Self {
+		ERC4626Wallet {
+			shares: WalletMap::default(),
+			journal: Journal::default(),
+		}
+	}
+
+	/// Pre-sizes the storage for `additional` more balances, e.g. before a bulk import.
+	pub fn reserve(&mut self, additional: usize) {
+		self.shares.reserve(additional);
+	}
+
+	pub fn addresses(&self) -> Vec<Address> {
+		let mut addresses: Vec<Address> = self.shares.keys().map(|(a, _)| *a).collect();
+		addresses.sort();
+		addresses.dedup();
+		addresses
+	}
+
+	pub fn balances(&self) -> Vec<(Address, Address, Uint)> {
+		let mut balances: Vec<(Address, Address, Uint)> = self
+			.shares
+			.iter()
+			.map(|((wallet, vault), shares)| (*wallet, *vault, *shares))
+			.collect();
+		balances.sort();
+		balances
+	}
+
+	pub fn set_balance(&mut self, wallet_address: Address, vault_address: Address, shares: Uint) {
+		let previous = self.balance_of(wallet_address, vault_address);
+		self.journal.record(|| ((wallet_address, vault_address), previous));
+
+		if shares.is_zero() {
+			self.shares.remove(&(wallet_address, vault_address));
+		} else {
+			self.shares.insert((wallet_address, vault_address), shares);
+		}
+	}
+
+	/// Starts journaling the balance changes, so they can be rolled back until `commit` is called.
+	pub fn begin(&mut self) {
+		self.journal.begin();
+	}
+
+	pub fn commit(&mut self) {
+		self.journal.commit();
+	}
+
+	/// Restores the shares changed since `begin`.
+	pub fn rollback(&mut self) {
+		for ((wallet_address, vault_address), shares) in self.journal.rollback() {
+			self.set_balance(wallet_address, vault_address, shares);
+		}
+	}
+
+	pub fn balance_of(&self, wallet_address: Address, vault_address: Address) -> Uint {
+		self.shares
+			.get(&(wallet_address, vault_address))
+			.cloned()
+			.unwrap_or_else(Uint::zero)
+	}
+
+	pub fn transfer(
+		&mut self,
+		src_wallet: Address,
+		dst_wallet: Address,
+		vault_address: Address,
+		shares: Uint,
+	) -> Result<(), CrabrollsError> {
+		if src_wallet == dst_wallet {
+			return Err(CrabrollsError::SelfTransfer);
+		}
+
+		let new_src_balance = self
+			.balance_of(src_wallet, vault_address)
+			.checked_sub(shares)
+			.ok_or(CrabrollsError::InsufficientShares)?;
+		let new_dst_balance = self
+			.balance_of(dst_wallet, vault_address)
+			.checked_add(shares)
+			.ok_or(CrabrollsError::BalanceOverflow)?;
+
+		self.set_balance(src_wallet, vault_address, new_src_balance);
+		self.set_balance(dst_wallet, vault_address, new_dst_balance);
+		Ok(())
+	}
+
+	pub fn decode_deposit(portal: Address, payload: &[u8]) -> Result<(Deposit, Vec<u8>), CrabrollsError> {
+		let args = abi::erc4626::deposit(payload.to_vec())?;
+
+		let vault_address = abi::extract::address(&args[0])?;
+		let wallet_address = abi::extract::address(&args[1])?;
+		let shares = abi::extract::uint(&args[2])?;
+
+		debug!(
+			"new ERC4626 deposit from {:?} with {:?} shares of {:?}",
+			wallet_address, shares, vault_address
+		);
+
+		let deposit = Deposit::ERC4626 {
+			portal,
+			sender: wallet_address,
+			vault: vault_address,
+			shares,
+		};
+
+		Ok((deposit, payload[abi::utils::size_of_packed_tokens(&args)..].to_vec()))
+	}
+
+	pub fn deposit_payload(
+		wallet_address: Address,
+		vault_address: Address,
+		shares: Uint,
+	) -> Result<Vec<u8>, CrabrollsError> {
+		abi::erc4626::deposit_payload(wallet_address, vault_address, shares)
+	}
+
+	pub fn withdraw(
+		&mut self,
+		wallet_address: Address,
+		vault_address: Address,
+		shares: Uint,
+	) -> Result<Vec<u8>, CrabrollsError> {
+		let new_balance = self
+			.balance_of(wallet_address, vault_address)
+			.checked_sub(shares)
+			.ok_or(CrabrollsError::InsufficientShares)?;
+
+		let payload = abi::erc20::withdraw(wallet_address, shares)?;
+		self.set_balance(wallet_address, vault_address, new_balance);
+		Ok(payload)
+	}
+
+	/// Gives back the shares of a withdrawal whose voucher couldn't be sent.
+	pub fn cancel_withdraw(&mut self, wallet_address: Address, vault_address: Address, shares: Uint) {
+		let balance = self.balance_of(wallet_address, vault_address).saturating_add(shares);
+		self.set_balance(wallet_address, vault_address, balance);
+	}
+}
+
+pub struct ERC4626PortalDecoder {
+	pub portal: Address,
+}
+
+impl ERC4626PortalDecoder {
+	pub fn new(portal: Address) -> Self {
+		Self { portal }
+	}
+}
+
+impl DepositDecoder for ERC4626PortalDecoder {
+	fn decode(&self, sender: Address, payload: &[u8]) -> Result<Option<Deposit>, Box<dyn Error>> {
+		if sender != self.portal {
+			return Ok(None);
+		}
+		let (deposit, _) = ERC4626Wallet::decode_deposit(self.portal, payload)?;
+		Ok(Some(deposit))
+	}
+}
+
+pub trait ERC4626Environment {
+	fn erc4626_addresses(&self) -> impl Future<Output = Vec<Address>>;
+	fn erc4626_withdraw(
+		&self,
+		wallet_address: Address,
+		vault_address: Address,
+		shares: Uint,
+	) -> impl Future<Output = Result<(), CrabrollsError>>;
+	fn erc4626_transfer(
+		&self,
+		src_wallet: Address,
+		dst_wallet: Address,
+		vault_address: Address,
+		shares: Uint,
+	) -> impl Future<Output = Result<(), CrabrollsError>>;
+	fn erc4626_balance(&self, wallet_address: Address, vault_address: Address) -> impl Future<Output = Uint>;
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{address, uint};
+
+	#[test]
+	fn test_deposit_payload_roundtrip() {
+		let portal = address!("0x0000000000000000000000000000000000000042");
+		let wallet_address = address!("0x0000000000000000000000000000000000000001");
+		let vault_address = address!("0x0000000000000000000000000000000000000002");
+
+		let mut payload = ERC4626Wallet::deposit_payload(wallet_address, vault_address, uint!(250u64)).unwrap();
+		payload.extend_from_slice(b"extra");
+
+		let (deposit, rest) = ERC4626Wallet::decode_deposit(portal, &payload).unwrap();
+		assert_eq!(
+			deposit,
+			Deposit::ERC4626 {
+				portal,
+				sender: wallet_address,
+				vault: vault_address,
+				shares: uint!(250u64),
+			}
+		);
+		assert_eq!(rest, b"extra");
+	}
+
+	#[test]
+	fn test_portal_decoder() {
+		let portal = address!("0x0000000000000000000000000000000000000042");
+		let decoder = ERC4626PortalDecoder::new(portal);
+		let payload =
+			ERC4626Wallet::deposit_payload(Address::repeat_byte(1), Address::repeat_byte(2), uint!(1u64)).unwrap();
+
+		assert!(decoder.decode(Address::zero(), &payload).unwrap().is_none());
+		assert!(decoder.decode(portal, &payload).unwrap().is_some());
+		assert!(decoder.decode(portal, &payload[..10]).is_err());
+	}
+
+	#[test]
+	fn test_withdraw_and_transfer() {
+		let mut wallet = ERC4626Wallet::new();
+		let src_wallet = address!("0x0000000000000000000000000000000000000001");
+		let dst_wallet = address!("0x0000000000000000000000000000000000000002");
+		let vault_address = address!("0x0000000000000000000000000000000000000003");
+
+		wallet.set_balance(src_wallet, vault_address, uint!(100u64));
+		wallet
+			.transfer(src_wallet, dst_wallet, vault_address, uint!(40u64))
+			.unwrap();
+		assert_eq!(wallet.balance_of(src_wallet, vault_address), uint!(60u64));
+		assert_eq!(wallet.balance_of(dst_wallet, vault_address), uint!(40u64));
+
+		assert!(wallet.withdraw(dst_wallet, vault_address, uint!(41u64)).is_err());
+		let payload = wallet.withdraw(dst_wallet, vault_address, uint!(40u64)).unwrap();
+		assert_eq!(payload, abi::erc20::withdraw(dst_wallet, uint!(40u64)).unwrap());
+		assert_eq!(wallet.balance_of(dst_wallet, vault_address), Uint::zero());
+		assert_eq!(wallet.addresses(), vec![src_wallet]);
+	}
+}
@@ -1,18 +1,29 @@
+use super::{Journal, WalletMap, WalletSet};
+pub use crate::types::balance::TokenMetadata;
+use crate::types::error::CrabrollsError;
 use crate::types::machine::Deposit;
+use crate::types::primitives::*;
 use crate::utils::abi::abi;
-use ethabi::{Address, Uint};
-use std::collections::{HashMap, HashSet};
-use std::error::Error;
 use std::future::Future;
 
 pub struct ERC721Wallet {
-	ownership: HashMap<Address, HashSet<(Address, Uint)>>,
+	ownership: WalletMap<Address, WalletSet<(Address, Uint)>>,
+	metadata: WalletMap<(Address, Uint), TokenMetadata>,
+	journal: Journal<ERC721Undo>,
+}
+
+enum ERC721Undo {
+	Added(Address, Address, Uint),                  // Token added to an owner
+	Removed(Address, Address, Uint),                // Token removed from an owner
+	Metadata(Address, Uint, Option<TokenMetadata>), // Previous metadata of a token
 }
 
 impl ERC721Wallet {
 	pub fn new() -> Self {
 		ERC721Wallet {
-			ownership: HashMap::new(),
+			ownership: WalletMap::default(),
+			metadata: WalletMap::default(),
+			journal: Journal::default(),
 		}
 	}
 
@@ -22,45 +33,141 @@ impl ERC721Wallet {
 		addresses
 	}
 
+	pub fn tokens(&self) -> Vec<(Address, Address, Uint)> {
+		let mut tokens: Vec<(Address, Address, Uint)> = self
+			.ownership
+			.iter()
+			.flat_map(|(owner, tokens)| tokens.iter().map(move |(token, id)| (*owner, *token, *id)))
+			.collect();
+		tokens.sort();
+		tokens
+	}
+
 	pub fn add_token(&mut self, owner: Address, token_address: Address, token_id: Uint) {
-		self.ownership
+		let added = self
+			.ownership
 			.entry(owner)
-			.or_insert_with(HashSet::new)
+			.or_default()
 			.insert((token_address, token_id));
+		if added {
+			self.journal
+				.record(|| ERC721Undo::Added(owner, token_address, token_id));
+		}
 	}
 
 	pub fn remove_token(&mut self, owner: Address, token_address: Address, token_id: Uint) {
 		if let Some(tokens) = self.ownership.get_mut(&owner) {
-			tokens.remove(&(token_address, token_id));
+			let removed = tokens.remove(&(token_address, token_id));
 			if tokens.is_empty() {
 				self.ownership.remove(&owner);
 			}
+			if removed {
+				self.journal
+					.record(|| ERC721Undo::Removed(owner, token_address, token_id));
+			}
+		}
+	}
+
+	/// Starts journaling the ownership and metadata changes, so they can be rolled back until `commit` is called.
+	pub fn begin(&mut self) {
+		self.journal.begin();
+	}
+
+	pub fn commit(&mut self) {
+		self.journal.commit();
+	}
+
+	/// Restores the ownership and metadata changed since `begin`.
+	pub fn rollback(&mut self) {
+		for undo in self.journal.rollback() {
+			match undo {
+				ERC721Undo::Added(owner, token_address, token_id) => self.remove_token(owner, token_address, token_id),
+				ERC721Undo::Removed(owner, token_address, token_id) => self.add_token(owner, token_address, token_id),
+				ERC721Undo::Metadata(token_address, token_id, Some(metadata)) => {
+					self.metadata.insert((token_address, token_id), metadata);
+				}
+				ERC721Undo::Metadata(token_address, token_id, None) => {
+					self.metadata.remove(&(token_address, token_id));
+				}
+			}
 		}
 	}
 
+	fn replace_metadata(&mut self, token_address: Address, token_id: Uint, metadata: Option<TokenMetadata>) {
+		let previous = match metadata {
+			Some(metadata) => self.metadata.insert((token_address, token_id), metadata),
+			None => self.metadata.remove(&(token_address, token_id)),
+		};
+		self.journal
+			.record(|| ERC721Undo::Metadata(token_address, token_id, previous));
+	}
+
 	pub fn owner_of(&self, token_address: Address, token_id: Uint) -> Option<Address> {
 		for (owner, tokens) in &self.ownership {
 			if tokens.contains(&(token_address, token_id)) {
-				return Some(owner.clone());
+				return Some(*owner);
 			}
 		}
 		None
 	}
 
+	pub fn tokens_of(&self, owner: Address) -> Vec<(Address, Uint)> {
+		let mut tokens: Vec<(Address, Uint)> = self
+			.ownership
+			.get(&owner)
+			.map(|tokens| tokens.iter().cloned().collect())
+			.unwrap_or_default();
+		tokens.sort();
+		tokens
+	}
+
+	pub fn tokens_of_collection(&self, owner: Address, token_address: Address) -> Vec<Uint> {
+		self.tokens_of(owner)
+			.into_iter()
+			.filter(|(token, _)| *token == token_address)
+			.map(|(_, id)| id)
+			.collect()
+	}
+
+	pub fn set_metadata(
+		&mut self,
+		token_address: Address,
+		token_id: Uint,
+		metadata: TokenMetadata,
+	) -> Result<(), CrabrollsError> {
+		self.owner_of(token_address, token_id).ok_or(CrabrollsError::TokenNotOwned)?;
+		self.replace_metadata(token_address, token_id, Some(metadata));
+		Ok(())
+	}
+
+	pub fn metadata_of(&self, token_address: Address, token_id: Uint) -> Option<&TokenMetadata> {
+		self.metadata.get(&(token_address, token_id))
+	}
+
+	pub fn all_metadata(&self) -> Vec<(Address, Uint, TokenMetadata)> {
+		let mut metadata: Vec<(Address, Uint, TokenMetadata)> = self
+			.metadata
+			.iter()
+			.map(|((token, id), metadata)| (*token, *id, metadata.clone()))
+			.collect();
+		metadata.sort_by_key(|(token, id, _)| (*token, *id));
+		metadata
+	}
+
 	pub fn transfer(
 		&mut self,
 		src_wallet: Address,
 		dst_wallet: Address,
 		token_address: Address,
 		token_id: Uint,
-	) -> Result<(), Box<dyn Error>> {
+	) -> Result<(), CrabrollsError> {
 		if src_wallet == dst_wallet {
-			return Err("can't transfer to self".into());
+			return Err(CrabrollsError::SelfTransfer);
 		}
 
-		let owner = self.owner_of(token_address, token_id).ok_or("token not owned")?;
+		let owner = self.owner_of(token_address, token_id).ok_or(CrabrollsError::TokenNotOwned)?;
 		if owner != src_wallet {
-			return Err("source wallet does not own the token".into());
+			return Err(CrabrollsError::NotOwner);
 		}
 
 		self.remove_token(src_wallet, token_address, token_id);
@@ -68,7 +175,7 @@ impl ERC721Wallet {
 		Ok(())
 	}
 
-	pub fn deposit(&mut self, payload: Vec<u8>) -> Result<(Deposit, Vec<u8>), Box<dyn Error>> {
+	pub fn deposit(&mut self, payload: Vec<u8>) -> Result<(Deposit, Vec<u8>), CrabrollsError> {
 		let args = abi::erc721::deposit(payload.clone())?;
 
 		let token_address = abi::extract::address(&args[0])?;
@@ -95,7 +202,7 @@ impl ERC721Wallet {
 		wallet_address: Address,
 		token_address: Address,
 		token_id: Uint,
-	) -> Result<Vec<u8>, Box<dyn Error>> {
+	) -> Result<Vec<u8>, CrabrollsError> {
 		abi::erc721::deposit_payload(wallet_address, token_address, token_id)
 	}
 
@@ -105,10 +212,10 @@ impl ERC721Wallet {
 		wallet_address: Address,
 		token_address: Address,
 		token_id: Uint,
-	) -> Result<Vec<u8>, Box<dyn Error>> {
-		let owner = self.owner_of(token_address, token_id).ok_or("token not owned")?;
+	) -> Result<Vec<u8>, CrabrollsError> {
+		let owner = self.owner_of(token_address, token_id).ok_or(CrabrollsError::TokenNotOwned)?;
 		if owner != wallet_address {
-			return Err("wallet does not own the token".into());
+			return Err(CrabrollsError::NotOwner);
 		}
 
 		let result = abi::erc721::withdraw(dapp_address, wallet_address, token_id);
@@ -116,11 +223,26 @@ impl ERC721Wallet {
 		match result {
 			Ok(payload) => {
 				self.remove_token(wallet_address, token_address, token_id);
+				self.replace_metadata(token_address, token_id, None);
 				Ok(payload)
 			}
 			Err(e) => Err(e),
 		}
 	}
+
+	/// Gives back the token of a withdrawal whose voucher couldn't be sent, with the metadata it had.
+	pub fn cancel_withdraw(
+		&mut self,
+		wallet_address: Address,
+		token_address: Address,
+		token_id: Uint,
+		metadata: Option<TokenMetadata>,
+	) {
+		self.add_token(wallet_address, token_address, token_id);
+		if metadata.is_some() {
+			self.replace_metadata(token_address, token_id, metadata);
+		}
+	}
 }
 
 pub trait ERC721Environment {
@@ -130,15 +252,25 @@ pub trait ERC721Environment {
 		wallet_address: Address,
 		token_address: Address,
 		token_id: Uint,
-	) -> impl Future<Output = Result<(), Box<dyn Error>>>;
+	) -> impl Future<Output = Result<(), CrabrollsError>>;
 	fn erc721_transfer(
 		&self,
 		source_wallet: Address,
 		destination_wallet: Address,
 		token_address: Address,
 		token_id: Uint,
-	) -> impl Future<Output = Result<(), Box<dyn Error>>>;
+	) -> impl Future<Output = Result<(), CrabrollsError>>;
 	fn erc721_owner_of(&self, token_address: Address, token_id: Uint) -> impl Future<Output = Option<Address>>;
+	fn erc721_tokens_of(&self, owner: Address) -> impl Future<Output = Vec<(Address, Uint)>>;
+	fn erc721_tokens_of_collection(&self, owner: Address, token_address: Address) -> impl Future<Output = Vec<Uint>>;
+	fn erc721_set_metadata(
+		&self,
+		token_address: Address,
+		token_id: Uint,
+		metadata: TokenMetadata,
+	) -> impl Future<Output = Result<(), CrabrollsError>>;
+	fn erc721_metadata_of(&self, token_address: Address, token_id: Uint)
+		-> impl Future<Output = Option<TokenMetadata>>;
 }
 
 #[cfg(test)]
@@ -165,6 +297,52 @@ mod tests {
 		assert_eq!(wallet.owner_of(token_address, uint!(1)), None);
 	}
 
+	#[test]
+	fn test_tokens_of() {
+		let mut wallet = ERC721Wallet::new();
+		let owner = address!("0x0000000000000000000000000000000000000001");
+		let collection_a = address!("0x0000000000000000000000000000000000000002");
+		let collection_b = address!("0x0000000000000000000000000000000000000003");
+
+		wallet.add_token(owner, collection_b, uint!(1));
+		wallet.add_token(owner, collection_a, uint!(7));
+		wallet.add_token(owner, collection_a, uint!(2));
+
+		assert_eq!(
+			wallet.tokens_of(owner),
+			vec![
+				(collection_a, uint!(2)),
+				(collection_a, uint!(7)),
+				(collection_b, uint!(1))
+			]
+		);
+		assert_eq!(
+			wallet.tokens_of_collection(owner, collection_a),
+			vec![uint!(2), uint!(7)]
+		);
+		assert!(wallet.tokens_of(collection_a).is_empty());
+	}
+
+	#[test]
+	fn test_token_metadata() {
+		let mut wallet = ERC721Wallet::new();
+		let owner = address!("0x0000000000000000000000000000000000000001");
+		let token_address = address!("0x0000000000000000000000000000000000000002");
+		let metadata = TokenMetadata::Uri("ipfs://token/1".to_string());
+
+		let result = wallet.set_metadata(token_address, uint!(1), metadata.clone());
+		assert_eq!(result.unwrap_err().to_string(), "token not owned");
+
+		wallet.add_token(owner, token_address, uint!(1));
+		wallet.set_metadata(token_address, uint!(1), metadata.clone()).unwrap();
+		assert_eq!(wallet.metadata_of(token_address, uint!(1)), Some(&metadata));
+
+		wallet
+			.withdraw(Address::zero(), owner, token_address, uint!(1))
+			.unwrap();
+		assert_eq!(wallet.metadata_of(token_address, uint!(1)), None);
+	}
+
 	#[test]
 	fn test_transfer() {
 		let mut wallet = ERC721Wallet::new();
@@ -199,7 +377,7 @@ mod tests {
 		let mut token_id_bytes = [0u8; 32];
 		token_id.to_big_endian(&mut token_id_bytes);
 
-		let mut payload = vec![0u8; 72];
+		let mut payload = [0u8; 72];
 		payload[0..20].copy_from_slice(token_address.as_bytes());
 		payload[20..40].copy_from_slice(wallet_address.as_bytes());
 		payload[40..72].copy_from_slice(&token_id_bytes);
@@ -248,5 +426,42 @@ mod tests {
 
 		let result = wallet.withdraw(dapp_address, wallet_address, token_address, uint!(1));
 		assert_eq!(result.unwrap_err().to_string(), "token not owned");
+
+		wallet.add_token(dapp_address, token_address, uint!(1));
+		let result = wallet.withdraw(dapp_address, wallet_address, token_address, uint!(1));
+		assert!(matches!(result, Err(CrabrollsError::NotOwner)));
+	}
+
+	#[test]
+	fn test_rollback() {
+		let mut wallet = ERC721Wallet::new();
+		let dapp_address = address!("0x0000000000000000000000000000000000000042");
+		let alice = address!("0x0000000000000000000000000000000000000001");
+		let bob = address!("0x0000000000000000000000000000000000000002");
+		let token_address = address!("0x0000000000000000000000000000000000000003");
+		let metadata = TokenMetadata::Uri("ipfs://first".to_string());
+
+		wallet.add_token(alice, token_address, uint!(1));
+		wallet.set_metadata(token_address, uint!(1), metadata.clone()).unwrap();
+		wallet.add_token(alice, token_address, uint!(2));
+		let tokens = wallet.tokens();
+
+		wallet.begin();
+		wallet.transfer(alice, bob, token_address, uint!(1)).unwrap();
+		wallet.withdraw(dapp_address, bob, token_address, uint!(1)).unwrap();
+		wallet
+			.set_metadata(token_address, uint!(2), TokenMetadata::Uri("ipfs://second".to_string()))
+			.unwrap();
+		wallet.add_token(bob, token_address, uint!(3));
+		wallet.rollback();
+
+		assert_eq!(wallet.tokens(), tokens);
+		assert_eq!(wallet.all_metadata(), vec![(token_address, uint!(1), metadata)]);
+
+		wallet.begin();
+		wallet.remove_token(alice, token_address, uint!(2));
+		wallet.commit();
+		wallet.rollback();
+		assert_eq!(wallet.tokens_of(alice), vec![(token_address, uint!(1))]);
 	}
 }
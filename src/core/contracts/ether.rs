@@ -1,28 +1,57 @@
+use super::{Journal, WalletMap};
+use crate::types::error::CrabrollsError;
 use crate::types::machine::Deposit;
+use crate::types::primitives::*;
 use crate::utils::abi::abi;
-use ethabi::{Address, Uint};
-use std::collections::HashMap;
-use std::error::Error;
 use std::future::Future;
 
 pub struct EtherWallet {
-	balance: HashMap<Address, Uint>,
+	balance: WalletMap<Address, Uint>,
+	total_supply: Uint,
+	total_deposited: Uint,
+	journal: Journal<EtherUndo>,
+}
+
+enum EtherUndo {
+	Balance(Address, Uint), // Previous balance of a changed wallet
+	Deposited(Uint),        // Previous total of credited deposits
 }
 
 impl EtherWallet {
 	pub fn new() -> Self {
 		EtherWallet {
-			balance: HashMap::new(),
+			balance: WalletMap::default(),
+			total_supply: Uint::zero(),
+			total_deposited: Uint::zero(),
+			journal: Journal::default(),
 		}
 	}
 
+	/// Pre-sizes the storage for `additional` more balances, e.g. before a bulk import.
+	pub fn reserve(&mut self, additional: usize) {
+		self.balance.reserve(additional);
+	}
+
 	pub fn addresses(&self) -> Vec<Address> {
 		let mut addresses: Vec<Address> = self.balance.keys().cloned().collect();
-		addresses.sort_by(|a, b| a.cmp(b));
+		addresses.sort();
 		addresses
 	}
 
+	pub fn balances(&self) -> Vec<(Address, Uint)> {
+		let mut balances: Vec<(Address, Uint)> = self.balance.iter().map(|(a, v)| (*a, *v)).collect();
+		balances.sort();
+		balances
+	}
+
 	pub fn set_balance(&mut self, address: Address, value: Uint) {
+		let previous = self.balance_of(address);
+		self.journal.record(|| EtherUndo::Balance(address, previous));
+		self.total_supply = self
+			.total_supply
+			.saturating_sub(self.balance_of(address))
+			.saturating_add(value);
+
 		if value.is_zero() {
 			self.balance.remove(&address);
 		} else {
@@ -30,11 +59,68 @@ impl EtherWallet {
 		}
 	}
 
+	/// Starts journaling the balance changes, so they can be rolled back until `commit` is called.
+	pub fn begin(&mut self) {
+		self.journal.begin();
+	}
+
+	pub fn commit(&mut self) {
+		self.journal.commit();
+	}
+
+	/// Restores the balances and credited deposits changed since `begin`.
+	pub fn rollback(&mut self) {
+		for undo in self.journal.rollback() {
+			match undo {
+				EtherUndo::Balance(address, value) => self.set_balance(address, value),
+				EtherUndo::Deposited(total_deposited) => self.total_deposited = total_deposited,
+			}
+		}
+	}
+
 	pub fn balance_of(&self, address: Address) -> Uint {
-		self.balance.get(&address).cloned().unwrap_or_else(|| Uint::zero())
+		self.balance.get(&address).cloned().unwrap_or_else(Uint::zero)
+	}
+
+	pub fn total_supply(&self) -> Uint {
+		self.total_supply
 	}
 
-	pub fn deposit(&mut self, payload: Vec<u8>) -> Result<(Deposit, Vec<u8>), Box<dyn Error>> {
+	pub fn total_deposited(&self) -> Uint {
+		self.total_deposited
+	}
+
+	pub fn credit(&mut self, address: Address, value: Uint) -> Result<(), CrabrollsError> {
+		let overflow = || CrabrollsError::wallet("deposit overflows the wallet balance");
+		let new_balance = self.balance_of(address).checked_add(value).ok_or_else(overflow)?;
+		let previous = self.total_deposited;
+		self.journal.record(|| EtherUndo::Deposited(previous));
+		self.total_deposited = self.total_deposited.checked_add(value).ok_or_else(overflow)?;
+		self.set_balance(address, new_balance);
+		Ok(())
+	}
+
+	pub fn uncredit(&mut self, address: Address, value: Uint) -> Result<(), CrabrollsError> {
+		let insufficient = || CrabrollsError::wallet("insufficient funds to roll back the deposit");
+		let new_balance = self.balance_of(address).checked_sub(value).ok_or_else(insufficient)?;
+		let previous = self.total_deposited;
+		self.journal.record(|| EtherUndo::Deposited(previous));
+		self.total_deposited = self.total_deposited.checked_sub(value).ok_or_else(insufficient)?;
+		self.set_balance(address, new_balance);
+		Ok(())
+	}
+
+	pub fn check_supply(&self) -> Result<(), CrabrollsError> {
+		if self.total_supply > self.total_deposited {
+			return Err(CrabrollsError::wallet(format!(
+				"ether total supply {} exceeds the credited deposits {}",
+				self.total_supply, self.total_deposited
+			)));
+		}
+		Ok(())
+	}
+
+	pub fn deposit(&mut self, payload: Vec<u8>) -> Result<(Deposit, Vec<u8>), CrabrollsError> {
 		let args = abi::ether::deposit(payload.clone())?;
 
 		let sender: Address = abi::extract::address(&args[0])?;
@@ -42,38 +128,37 @@ impl EtherWallet {
 
 		debug!("new ether deposit from {:?} with value {:?}", sender, value);
 
-		let new_balance = self.balance_of(sender) + value;
-		self.set_balance(sender, new_balance);
+		self.credit(sender, value)?;
 
 		let deposit = Deposit::Ether { sender, amount: value };
 		Ok((deposit, payload[abi::utils::size_of_packed_tokens(&args)..].to_vec()))
 	}
 
-	pub fn deposit_payload(sender: Address, value: Uint) -> Result<Vec<u8>, Box<dyn Error>> {
+	pub fn deposit_payload(sender: Address, value: Uint) -> Result<Vec<u8>, CrabrollsError> {
 		abi::ether::deposit_payload(sender, value)
 	}
 
-	pub fn transfer(&mut self, src: Address, dst: Address, value: Uint) -> Result<(), Box<dyn Error>> {
+	pub fn transfer(&mut self, src: Address, dst: Address, value: Uint) -> Result<(), CrabrollsError> {
 		if src == dst {
-			return Err("can't transfer to self".into());
+			return Err(CrabrollsError::SelfTransfer);
 		}
 
-		let new_src_balance = self.balance_of(src).checked_sub(value).ok_or("insufficient funds")?;
-		let new_dst_balance = self.balance_of(dst).checked_add(value).ok_or("balance overflow")?;
+		let new_src_balance = self.balance_of(src).checked_sub(value).ok_or(CrabrollsError::InsufficientFunds)?;
+		let new_dst_balance = self.balance_of(dst).checked_add(value).ok_or(CrabrollsError::BalanceOverflow)?;
 
 		self.set_balance(src, new_src_balance);
 		self.set_balance(dst, new_dst_balance);
 		Ok(())
 	}
 
-	pub fn withdraw(&mut self, address: Address, value: Uint) -> Result<Vec<u8>, Box<dyn Error>> {
+	pub fn withdraw(&mut self, address: Address, value: Uint) -> Result<Vec<u8>, CrabrollsError> {
 		let new_balance = self
 			.balance_of(address)
 			.checked_sub(value)
-			.ok_or("insufficient funds")?;
+			.ok_or(CrabrollsError::InsufficientFunds)?;
 
 		if new_balance < Uint::zero() {
-			return Err("insufficient funds".into());
+			return Err(CrabrollsError::InsufficientFunds);
 		}
 
 		let result = abi::ether::withdraw(address, value);
@@ -83,21 +168,27 @@ impl EtherWallet {
 				self.set_balance(address, new_balance);
 				Ok(payload)
 			}
-			Err(err) => Err(err.into()),
+			Err(err) => Err(err),
 		}
 	}
+
+	/// Gives back the funds of a withdrawal whose voucher couldn't be sent.
+	pub fn cancel_withdraw(&mut self, address: Address, value: Uint) {
+		self.set_balance(address, self.balance_of(address).saturating_add(value));
+	}
 }
 
 pub trait EtherEnvironment {
 	fn ether_addresses(&self) -> impl Future<Output = Vec<Address>>;
-	fn ether_withdraw(&self, address: Address, value: Uint) -> impl Future<Output = Result<(), Box<dyn Error>>>;
+	fn ether_withdraw(&self, address: Address, value: Uint) -> impl Future<Output = Result<(), CrabrollsError>>;
 	fn ether_transfer(
 		&self,
 		source: Address,
 		destination: Address,
 		value: Uint,
-	) -> impl Future<Output = Result<(), Box<dyn Error>>>;
+	) -> impl Future<Output = Result<(), CrabrollsError>>;
 	fn ether_balance(&self, address: Address) -> impl Future<Output = Uint>;
+	fn ether_total_supply(&self) -> impl Future<Output = Uint>;
 }
 
 #[cfg(test)]
@@ -142,7 +233,7 @@ mod tests {
 	#[test]
 	fn test_ether_wallet_initialization() {
 		let wallet = EtherWallet::new();
-		assert_eq!(wallet.balance, HashMap::new());
+		assert_eq!(wallet.balance, WalletMap::default());
 	}
 
 	#[test]
@@ -195,6 +286,7 @@ mod tests {
 		wallet.set_balance(dst, uint!(50u64));
 
 		let result = wallet.transfer(src, dst, uint!(20u64));
+		assert!(matches!(result, Err(CrabrollsError::InsufficientFunds)));
 		assert_eq!(result.unwrap_err().to_string(), "insufficient funds");
 	}
 
@@ -206,7 +298,7 @@ mod tests {
 		wallet.set_balance(address, uint!(100u64));
 
 		let result = wallet.transfer(address, address, uint!(10u64));
-		assert_eq!(result.unwrap_err().to_string(), "can't transfer to self");
+		assert!(matches!(result, Err(CrabrollsError::SelfTransfer)));
 	}
 
 	#[test]
@@ -247,7 +339,7 @@ mod tests {
 		payload[20..52].copy_from_slice(&value_bytes);
 		payload.extend_from_slice(&[16u8; 16]);
 
-		let result = wallet.deposit(payload.into());
+		let result = wallet.deposit(payload);
 
 		assert!(result.is_ok());
 
@@ -265,4 +357,30 @@ mod tests {
 
 		assert_eq!(remaining_payload, vec![16u8; 16]);
 	}
+
+	#[test]
+	fn test_total_supply() {
+		let mut wallet = EtherWallet::new();
+		let alice = address!("0x0000000000000000000000000000000000000001");
+		let bob = address!("0x0000000000000000000000000000000000000002");
+
+		wallet
+			.deposit(EtherWallet::deposit_payload(alice, uint!(100)).unwrap())
+			.unwrap();
+		wallet.transfer(alice, bob, uint!(40)).unwrap();
+		wallet.withdraw(bob, uint!(15)).unwrap();
+		assert_eq!(wallet.total_supply(), uint!(85));
+		assert_eq!(wallet.total_deposited(), uint!(100));
+		assert!(wallet.check_supply().is_ok());
+
+		wallet.uncredit(alice, uint!(60)).unwrap();
+		assert_eq!(wallet.total_supply(), uint!(25));
+		assert!(wallet.check_supply().is_ok());
+
+		wallet.set_balance(alice, uint!(100));
+		assert_eq!(
+			wallet.check_supply().unwrap_err().to_string(),
+			"ether total supply 125 exceeds the credited deposits 40"
+		);
+	}
 }
@@ -1,4 +1,52 @@
+pub mod decoder;
+#[cfg(feature = "erc1155")]
 pub mod erc1155;
+#[cfg(feature = "erc20")]
 pub mod erc20;
+pub mod erc4626;
+#[cfg(feature = "erc721")]
 pub mod erc721;
 pub mod ether;
+
+#[cfg(feature = "fast-hash")]
+pub(crate) type WalletHasher = rustc_hash::FxBuildHasher;
+#[cfg(not(feature = "fast-hash"))]
+pub(crate) type WalletHasher = std::collections::hash_map::RandomState;
+
+// Wallet storage, keyed by addresses and ids that SipHash is needlessly slow for
+pub(crate) type WalletMap<K, V> = std::collections::HashMap<K, V, WalletHasher>;
+#[cfg(feature = "erc721")]
+pub(crate) type WalletSet<T> = std::collections::HashSet<T, WalletHasher>;
+
+/// Undo log of the mutations made on a wallet since `begin`, replayed backwards to roll them back.
+pub(crate) struct Journal<T> {
+	entries: Option<Vec<T>>,
+}
+
+impl<T> Default for Journal<T> {
+	fn default() -> Self {
+		Self { entries: None }
+	}
+}
+
+impl<T> Journal<T> {
+	pub fn begin(&mut self) {
+		self.entries = Some(Vec::new());
+	}
+
+	/// Records the entry built by `undo`, only called while a transaction is open.
+	pub fn record(&mut self, undo: impl FnOnce() -> T) {
+		if let Some(entries) = self.entries.as_mut() {
+			entries.push(undo());
+		}
+	}
+
+	pub fn commit(&mut self) {
+		self.entries = None;
+	}
+
+	/// Closes the transaction, returning its entries from the most recent one.
+	pub fn rollback(&mut self) -> impl Iterator<Item = T> {
+		self.entries.take().unwrap_or_default().into_iter().rev()
+	}
+}
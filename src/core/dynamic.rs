@@ -0,0 +1,935 @@
+use super::about::BuildInfo;
+use super::activity::ActivityCounters;
+use super::application::Application;
+#[cfg(feature = "erc1155")]
+use super::contracts::erc1155::{ERC1155Environment, ERC1155Wallet, IntoIdsAmountsIter, IntoIdsIter};
+#[cfg(feature = "erc20")]
+use super::contracts::erc20::{ERC20Environment, ERC20Wallet};
+#[cfg(feature = "erc721")]
+use super::contracts::erc721::{ERC721Environment, ERC721Wallet, TokenMetadata};
+use super::contracts::{
+	erc4626::{ERC4626Environment, ERC4626Wallet},
+	ether::{EtherEnvironment, EtherWallet},
+};
+use super::environment::{Environment, RollupInternalEnvironment};
+use crate::types::address_book::AddressBook;
+use crate::types::error::CrabrollsError;
+use crate::types::machine::{Deposit, FinishStatus, GioResponse, Metadata};
+use async_lock::RwLock;
+use crate::types::primitives::*;
+use std::error::Error;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+pub type LocalBoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + 'a>>;
+
+pub trait DynEnvironment {
+	fn dyn_send_voucher_with_value(
+		&self,
+		destination: Address,
+		value: Uint,
+		payload: Vec<u8>,
+	) -> BoxFuture<'_, Result<Option<u64>, CrabrollsError>>;
+	fn dyn_send_delegate_call_voucher(
+		&self,
+		destination: Address,
+		payload: Vec<u8>,
+	) -> BoxFuture<'_, Result<Option<u64>, CrabrollsError>>;
+	fn dyn_send_notice(&self, payload: Vec<u8>) -> BoxFuture<'_, Result<Option<u64>, CrabrollsError>>;
+	fn dyn_send_report(&self, payload: Vec<u8>) -> BoxFuture<'_, Result<(), CrabrollsError>>;
+	fn dyn_gio(&self, domain: u16, payload: Vec<u8>) -> BoxFuture<'_, Result<GioResponse, CrabrollsError>>;
+	fn dyn_build_info(&self) -> Option<BuildInfo>;
+
+	fn dyn_ether_addresses(&self) -> LocalBoxFuture<'_, Vec<Address>>;
+	fn dyn_ether_withdraw(&self, address: Address, value: Uint) -> LocalBoxFuture<'_, Result<(), CrabrollsError>>;
+	fn dyn_ether_transfer(
+		&self,
+		source: Address,
+		destination: Address,
+		value: Uint,
+	) -> LocalBoxFuture<'_, Result<(), CrabrollsError>>;
+	fn dyn_ether_balance(&self, address: Address) -> LocalBoxFuture<'_, Uint>;
+	fn dyn_ether_total_supply(&self) -> LocalBoxFuture<'_, Uint>;
+
+	#[cfg(feature = "erc20")]
+	fn dyn_erc20_addresses(&self) -> LocalBoxFuture<'_, Vec<Address>>;
+	#[cfg(feature = "erc20")]
+	fn dyn_erc20_withdraw(
+		&self,
+		wallet_address: Address,
+		token_address: Address,
+		value: Uint,
+	) -> LocalBoxFuture<'_, Result<(), CrabrollsError>>;
+	#[cfg(feature = "erc20")]
+	fn dyn_erc20_transfer(
+		&self,
+		src_wallet: Address,
+		dst_wallet: Address,
+		token_address: Address,
+		value: Uint,
+	) -> LocalBoxFuture<'_, Result<(), CrabrollsError>>;
+	#[cfg(feature = "erc20")]
+	fn dyn_erc20_balance(&self, wallet_address: Address, token_address: Address) -> LocalBoxFuture<'_, Uint>;
+
+	fn dyn_erc4626_addresses(&self) -> LocalBoxFuture<'_, Vec<Address>>;
+	fn dyn_erc4626_withdraw(
+		&self,
+		wallet_address: Address,
+		vault_address: Address,
+		shares: Uint,
+	) -> LocalBoxFuture<'_, Result<(), CrabrollsError>>;
+	fn dyn_erc4626_transfer(
+		&self,
+		src_wallet: Address,
+		dst_wallet: Address,
+		vault_address: Address,
+		shares: Uint,
+	) -> LocalBoxFuture<'_, Result<(), CrabrollsError>>;
+	fn dyn_erc4626_balance(&self, wallet_address: Address, vault_address: Address) -> LocalBoxFuture<'_, Uint>;
+
+	#[cfg(feature = "erc721")]
+	fn dyn_erc721_addresses(&self) -> LocalBoxFuture<'_, Vec<Address>>;
+	#[cfg(feature = "erc721")]
+	fn dyn_erc721_withdraw(
+		&self,
+		wallet_address: Address,
+		token_address: Address,
+		token_id: Uint,
+	) -> LocalBoxFuture<'_, Result<(), CrabrollsError>>;
+	#[cfg(feature = "erc721")]
+	fn dyn_erc721_transfer(
+		&self,
+		src_wallet: Address,
+		dst_wallet: Address,
+		token_address: Address,
+		token_id: Uint,
+	) -> LocalBoxFuture<'_, Result<(), CrabrollsError>>;
+	#[cfg(feature = "erc721")]
+	fn dyn_erc721_owner_of(&self, token_address: Address, token_id: Uint) -> LocalBoxFuture<'_, Option<Address>>;
+	#[cfg(feature = "erc721")]
+	fn dyn_erc721_tokens_of(&self, owner: Address) -> LocalBoxFuture<'_, Vec<(Address, Uint)>>;
+	#[cfg(feature = "erc721")]
+	fn dyn_erc721_tokens_of_collection(&self, owner: Address, token_address: Address) -> LocalBoxFuture<'_, Vec<Uint>>;
+	#[cfg(feature = "erc721")]
+	fn dyn_erc721_set_metadata(
+		&self,
+		token_address: Address,
+		token_id: Uint,
+		metadata: TokenMetadata,
+	) -> LocalBoxFuture<'_, Result<(), CrabrollsError>>;
+	#[cfg(feature = "erc721")]
+	fn dyn_erc721_metadata_of(
+		&self,
+		token_address: Address,
+		token_id: Uint,
+	) -> LocalBoxFuture<'_, Option<TokenMetadata>>;
+
+	#[cfg(feature = "erc1155")]
+	fn dyn_erc1155_addresses(&self) -> LocalBoxFuture<'_, Vec<Address>>;
+	#[cfg(feature = "erc1155")]
+	fn dyn_erc1155_withdraw(
+		&self,
+		wallet_address: Address,
+		token_address: Address,
+		withdrawals: Vec<(Uint, Uint)>,
+		data: Option<Vec<u8>>,
+	) -> LocalBoxFuture<'_, Result<(), CrabrollsError>>;
+	#[cfg(feature = "erc1155")]
+	fn dyn_erc1155_transfer(
+		&self,
+		src_wallet: Address,
+		dst_wallet: Address,
+		token_address: Address,
+		transfers: Vec<(Uint, Uint)>,
+	) -> LocalBoxFuture<'_, Result<(), CrabrollsError>>;
+	#[cfg(feature = "erc1155")]
+	fn dyn_erc1155_balance(
+		&self,
+		wallet_address: Address,
+		token_address: Address,
+		token_id: Uint,
+	) -> LocalBoxFuture<'_, Uint>;
+	#[cfg(feature = "erc1155")]
+	fn dyn_erc1155_balance_of_batch(
+		&self,
+		wallet_address: Address,
+		token_address: Address,
+		token_ids: Vec<Uint>,
+	) -> LocalBoxFuture<'_, Vec<Uint>>;
+
+	fn dyn_address_book(&self) -> AddressBook;
+	fn dyn_ether_wallet(&self) -> Arc<RwLock<EtherWallet>>;
+	#[cfg(feature = "erc20")]
+	fn dyn_erc20_wallet(&self) -> Arc<RwLock<ERC20Wallet>>;
+	#[cfg(feature = "erc721")]
+	fn dyn_erc721_wallet(&self) -> Arc<RwLock<ERC721Wallet>>;
+	#[cfg(feature = "erc1155")]
+	fn dyn_erc1155_wallet(&self) -> Arc<RwLock<ERC1155Wallet>>;
+	fn dyn_erc4626_wallet(&self) -> Arc<RwLock<ERC4626Wallet>>;
+	fn dyn_activity(&self) -> Arc<RwLock<ActivityCounters>>;
+	fn dyn_balance_notices(&self) -> bool;
+}
+
+impl<E: Environment> DynEnvironment for E {
+	fn dyn_send_voucher_with_value(
+		&self,
+		destination: Address,
+		value: Uint,
+		payload: Vec<u8>,
+	) -> BoxFuture<'_, Result<Option<u64>, CrabrollsError>> {
+		Box::pin(self.send_voucher_with_value(destination, value, payload))
+	}
+
+	fn dyn_send_delegate_call_voucher(
+		&self,
+		destination: Address,
+		payload: Vec<u8>,
+	) -> BoxFuture<'_, Result<Option<u64>, CrabrollsError>> {
+		Box::pin(self.send_delegate_call_voucher(destination, payload))
+	}
+
+	fn dyn_send_notice(&self, payload: Vec<u8>) -> BoxFuture<'_, Result<Option<u64>, CrabrollsError>> {
+		Box::pin(self.send_notice(payload))
+	}
+
+	fn dyn_send_report(&self, payload: Vec<u8>) -> BoxFuture<'_, Result<(), CrabrollsError>> {
+		Box::pin(self.send_report(payload))
+	}
+
+	fn dyn_gio(&self, domain: u16, payload: Vec<u8>) -> BoxFuture<'_, Result<GioResponse, CrabrollsError>> {
+		Box::pin(self.gio(domain, payload))
+	}
+
+	fn dyn_build_info(&self) -> Option<BuildInfo> {
+		self.build_info()
+	}
+
+	fn dyn_ether_addresses(&self) -> LocalBoxFuture<'_, Vec<Address>> {
+		Box::pin(self.ether_addresses())
+	}
+
+	fn dyn_ether_withdraw(&self, address: Address, value: Uint) -> LocalBoxFuture<'_, Result<(), CrabrollsError>> {
+		Box::pin(self.ether_withdraw(address, value))
+	}
+
+	fn dyn_ether_transfer(
+		&self,
+		source: Address,
+		destination: Address,
+		value: Uint,
+	) -> LocalBoxFuture<'_, Result<(), CrabrollsError>> {
+		Box::pin(self.ether_transfer(source, destination, value))
+	}
+
+	fn dyn_ether_balance(&self, address: Address) -> LocalBoxFuture<'_, Uint> {
+		Box::pin(self.ether_balance(address))
+	}
+
+	fn dyn_ether_total_supply(&self) -> LocalBoxFuture<'_, Uint> {
+		Box::pin(self.ether_total_supply())
+	}
+
+	#[cfg(feature = "erc20")]
+	fn dyn_erc20_addresses(&self) -> LocalBoxFuture<'_, Vec<Address>> {
+		Box::pin(self.erc20_addresses())
+	}
+
+	#[cfg(feature = "erc20")]
+	fn dyn_erc20_withdraw(
+		&self,
+		wallet_address: Address,
+		token_address: Address,
+		value: Uint,
+	) -> LocalBoxFuture<'_, Result<(), CrabrollsError>> {
+		Box::pin(self.erc20_withdraw(wallet_address, token_address, value))
+	}
+
+	#[cfg(feature = "erc20")]
+	fn dyn_erc20_transfer(
+		&self,
+		src_wallet: Address,
+		dst_wallet: Address,
+		token_address: Address,
+		value: Uint,
+	) -> LocalBoxFuture<'_, Result<(), CrabrollsError>> {
+		Box::pin(self.erc20_transfer(src_wallet, dst_wallet, token_address, value))
+	}
+
+	#[cfg(feature = "erc20")]
+	fn dyn_erc20_balance(&self, wallet_address: Address, token_address: Address) -> LocalBoxFuture<'_, Uint> {
+		Box::pin(self.erc20_balance(wallet_address, token_address))
+	}
+
+	fn dyn_erc4626_addresses(&self) -> LocalBoxFuture<'_, Vec<Address>> {
+		Box::pin(self.erc4626_addresses())
+	}
+
+	fn dyn_erc4626_withdraw(
+		&self,
+		wallet_address: Address,
+		vault_address: Address,
+		shares: Uint,
+	) -> LocalBoxFuture<'_, Result<(), CrabrollsError>> {
+		Box::pin(self.erc4626_withdraw(wallet_address, vault_address, shares))
+	}
+
+	fn dyn_erc4626_transfer(
+		&self,
+		src_wallet: Address,
+		dst_wallet: Address,
+		vault_address: Address,
+		shares: Uint,
+	) -> LocalBoxFuture<'_, Result<(), CrabrollsError>> {
+		Box::pin(self.erc4626_transfer(src_wallet, dst_wallet, vault_address, shares))
+	}
+
+	fn dyn_erc4626_balance(&self, wallet_address: Address, vault_address: Address) -> LocalBoxFuture<'_, Uint> {
+		Box::pin(self.erc4626_balance(wallet_address, vault_address))
+	}
+
+	#[cfg(feature = "erc721")]
+	fn dyn_erc721_addresses(&self) -> LocalBoxFuture<'_, Vec<Address>> {
+		Box::pin(self.erc721_addresses())
+	}
+
+	#[cfg(feature = "erc721")]
+	fn dyn_erc721_withdraw(
+		&self,
+		wallet_address: Address,
+		token_address: Address,
+		token_id: Uint,
+	) -> LocalBoxFuture<'_, Result<(), CrabrollsError>> {
+		Box::pin(self.erc721_withdraw(wallet_address, token_address, token_id))
+	}
+
+	#[cfg(feature = "erc721")]
+	fn dyn_erc721_transfer(
+		&self,
+		src_wallet: Address,
+		dst_wallet: Address,
+		token_address: Address,
+		token_id: Uint,
+	) -> LocalBoxFuture<'_, Result<(), CrabrollsError>> {
+		Box::pin(self.erc721_transfer(src_wallet, dst_wallet, token_address, token_id))
+	}
+
+	#[cfg(feature = "erc721")]
+	fn dyn_erc721_owner_of(&self, token_address: Address, token_id: Uint) -> LocalBoxFuture<'_, Option<Address>> {
+		Box::pin(self.erc721_owner_of(token_address, token_id))
+	}
+
+	#[cfg(feature = "erc721")]
+	fn dyn_erc721_tokens_of(&self, owner: Address) -> LocalBoxFuture<'_, Vec<(Address, Uint)>> {
+		Box::pin(self.erc721_tokens_of(owner))
+	}
+
+	#[cfg(feature = "erc721")]
+	fn dyn_erc721_tokens_of_collection(&self, owner: Address, token_address: Address) -> LocalBoxFuture<'_, Vec<Uint>> {
+		Box::pin(self.erc721_tokens_of_collection(owner, token_address))
+	}
+
+	#[cfg(feature = "erc721")]
+	fn dyn_erc721_set_metadata(
+		&self,
+		token_address: Address,
+		token_id: Uint,
+		metadata: TokenMetadata,
+	) -> LocalBoxFuture<'_, Result<(), CrabrollsError>> {
+		Box::pin(self.erc721_set_metadata(token_address, token_id, metadata))
+	}
+
+	#[cfg(feature = "erc721")]
+	fn dyn_erc721_metadata_of(
+		&self,
+		token_address: Address,
+		token_id: Uint,
+	) -> LocalBoxFuture<'_, Option<TokenMetadata>> {
+		Box::pin(self.erc721_metadata_of(token_address, token_id))
+	}
+
+	#[cfg(feature = "erc1155")]
+	fn dyn_erc1155_addresses(&self) -> LocalBoxFuture<'_, Vec<Address>> {
+		Box::pin(self.erc1155_addresses())
+	}
+
+	#[cfg(feature = "erc1155")]
+	fn dyn_erc1155_withdraw(
+		&self,
+		wallet_address: Address,
+		token_address: Address,
+		withdrawals: Vec<(Uint, Uint)>,
+		data: Option<Vec<u8>>,
+	) -> LocalBoxFuture<'_, Result<(), CrabrollsError>> {
+		Box::pin(self.erc1155_withdraw(wallet_address, token_address, withdrawals, data))
+	}
+
+	#[cfg(feature = "erc1155")]
+	fn dyn_erc1155_transfer(
+		&self,
+		src_wallet: Address,
+		dst_wallet: Address,
+		token_address: Address,
+		transfers: Vec<(Uint, Uint)>,
+	) -> LocalBoxFuture<'_, Result<(), CrabrollsError>> {
+		Box::pin(self.erc1155_transfer(src_wallet, dst_wallet, token_address, transfers))
+	}
+
+	#[cfg(feature = "erc1155")]
+	fn dyn_erc1155_balance(
+		&self,
+		wallet_address: Address,
+		token_address: Address,
+		token_id: Uint,
+	) -> LocalBoxFuture<'_, Uint> {
+		Box::pin(self.erc1155_balance(wallet_address, token_address, token_id))
+	}
+
+	#[cfg(feature = "erc1155")]
+	fn dyn_erc1155_balance_of_batch(
+		&self,
+		wallet_address: Address,
+		token_address: Address,
+		token_ids: Vec<Uint>,
+	) -> LocalBoxFuture<'_, Vec<Uint>> {
+		Box::pin(self.erc1155_balance_of_batch(wallet_address, token_address, token_ids))
+	}
+
+	fn dyn_address_book(&self) -> AddressBook {
+		self.get_address_book()
+	}
+
+	fn dyn_ether_wallet(&self) -> Arc<RwLock<EtherWallet>> {
+		self.get_ether_wallet()
+	}
+
+	#[cfg(feature = "erc20")]
+	fn dyn_erc20_wallet(&self) -> Arc<RwLock<ERC20Wallet>> {
+		self.get_erc20_wallet()
+	}
+
+	#[cfg(feature = "erc721")]
+	fn dyn_erc721_wallet(&self) -> Arc<RwLock<ERC721Wallet>> {
+		self.get_erc721_wallet()
+	}
+
+	#[cfg(feature = "erc1155")]
+	fn dyn_erc1155_wallet(&self) -> Arc<RwLock<ERC1155Wallet>> {
+		self.get_erc1155_wallet()
+	}
+
+	fn dyn_erc4626_wallet(&self) -> Arc<RwLock<ERC4626Wallet>> {
+		self.get_erc4626_wallet()
+	}
+
+	fn dyn_activity(&self) -> Arc<RwLock<ActivityCounters>> {
+		self.get_activity()
+	}
+
+	fn dyn_balance_notices(&self) -> bool {
+		self.balance_notices()
+	}
+}
+
+pub struct DynEnv<'a>(pub &'a dyn DynEnvironment);
+
+impl Environment for DynEnv<'_> {
+	fn send_voucher_with_value(
+		&self,
+		destination: Address,
+		value: Uint,
+		payload: impl AsRef<[u8]> + Send,
+	) -> impl Future<Output = Result<Option<u64>, CrabrollsError>> + Send {
+		self.0
+			.dyn_send_voucher_with_value(destination, value, payload.as_ref().to_vec())
+	}
+
+	fn send_delegate_call_voucher(
+		&self,
+		destination: Address,
+		payload: impl AsRef<[u8]> + Send,
+	) -> impl Future<Output = Result<Option<u64>, CrabrollsError>> + Send {
+		self.0
+			.dyn_send_delegate_call_voucher(destination, payload.as_ref().to_vec())
+	}
+
+	fn send_notice(
+		&self,
+		payload: impl AsRef<[u8]> + Send,
+	) -> impl Future<Output = Result<Option<u64>, CrabrollsError>> + Send {
+		self.0.dyn_send_notice(payload.as_ref().to_vec())
+	}
+
+	fn send_report(&self, payload: impl AsRef<[u8]> + Send) -> impl Future<Output = Result<(), CrabrollsError>> + Send {
+		self.0.dyn_send_report(payload.as_ref().to_vec())
+	}
+
+	fn gio(
+		&self,
+		domain: u16,
+		payload: impl AsRef<[u8]> + Send,
+	) -> impl Future<Output = Result<GioResponse, CrabrollsError>> + Send {
+		self.0.dyn_gio(domain, payload.as_ref().to_vec())
+	}
+
+	fn build_info(&self) -> Option<BuildInfo> {
+		self.0.dyn_build_info()
+	}
+}
+
+impl EtherEnvironment for DynEnv<'_> {
+	fn ether_addresses(&self) -> impl Future<Output = Vec<Address>> {
+		self.0.dyn_ether_addresses()
+	}
+
+	fn ether_withdraw(&self, address: Address, value: Uint) -> impl Future<Output = Result<(), CrabrollsError>> {
+		self.0.dyn_ether_withdraw(address, value)
+	}
+
+	fn ether_transfer(
+		&self,
+		source: Address,
+		destination: Address,
+		value: Uint,
+	) -> impl Future<Output = Result<(), CrabrollsError>> {
+		self.0.dyn_ether_transfer(source, destination, value)
+	}
+
+	fn ether_balance(&self, address: Address) -> impl Future<Output = Uint> {
+		self.0.dyn_ether_balance(address)
+	}
+
+	fn ether_total_supply(&self) -> impl Future<Output = Uint> {
+		self.0.dyn_ether_total_supply()
+	}
+}
+
+#[cfg(feature = "erc20")]
+impl ERC20Environment for DynEnv<'_> {
+	fn erc20_addresses(&self) -> impl Future<Output = Vec<Address>> {
+		self.0.dyn_erc20_addresses()
+	}
+
+	fn erc20_withdraw(
+		&self,
+		wallet_address: Address,
+		token_address: Address,
+		value: Uint,
+	) -> impl Future<Output = Result<(), CrabrollsError>> {
+		self.0.dyn_erc20_withdraw(wallet_address, token_address, value)
+	}
+
+	fn erc20_transfer(
+		&self,
+		src_wallet: Address,
+		dst_wallet: Address,
+		token_address: Address,
+		value: Uint,
+	) -> impl Future<Output = Result<(), CrabrollsError>> {
+		self.0.dyn_erc20_transfer(src_wallet, dst_wallet, token_address, value)
+	}
+
+	fn erc20_balance(&self, wallet_address: Address, token_address: Address) -> impl Future<Output = Uint> {
+		self.0.dyn_erc20_balance(wallet_address, token_address)
+	}
+}
+
+impl ERC4626Environment for DynEnv<'_> {
+	fn erc4626_addresses(&self) -> impl Future<Output = Vec<Address>> {
+		self.0.dyn_erc4626_addresses()
+	}
+
+	fn erc4626_withdraw(
+		&self,
+		wallet_address: Address,
+		vault_address: Address,
+		shares: Uint,
+	) -> impl Future<Output = Result<(), CrabrollsError>> {
+		self.0.dyn_erc4626_withdraw(wallet_address, vault_address, shares)
+	}
+
+	fn erc4626_transfer(
+		&self,
+		src_wallet: Address,
+		dst_wallet: Address,
+		vault_address: Address,
+		shares: Uint,
+	) -> impl Future<Output = Result<(), CrabrollsError>> {
+		self.0
+			.dyn_erc4626_transfer(src_wallet, dst_wallet, vault_address, shares)
+	}
+
+	fn erc4626_balance(&self, wallet_address: Address, vault_address: Address) -> impl Future<Output = Uint> {
+		self.0.dyn_erc4626_balance(wallet_address, vault_address)
+	}
+}
+
+#[cfg(feature = "erc721")]
+impl ERC721Environment for DynEnv<'_> {
+	fn erc721_addresses(&self) -> impl Future<Output = Vec<Address>> {
+		self.0.dyn_erc721_addresses()
+	}
+
+	fn erc721_withdraw(
+		&self,
+		wallet_address: Address,
+		token_address: Address,
+		token_id: Uint,
+	) -> impl Future<Output = Result<(), CrabrollsError>> {
+		self.0.dyn_erc721_withdraw(wallet_address, token_address, token_id)
+	}
+
+	fn erc721_transfer(
+		&self,
+		source_wallet: Address,
+		destination_wallet: Address,
+		token_address: Address,
+		token_id: Uint,
+	) -> impl Future<Output = Result<(), CrabrollsError>> {
+		self.0
+			.dyn_erc721_transfer(source_wallet, destination_wallet, token_address, token_id)
+	}
+
+	fn erc721_owner_of(&self, token_address: Address, token_id: Uint) -> impl Future<Output = Option<Address>> {
+		self.0.dyn_erc721_owner_of(token_address, token_id)
+	}
+
+	fn erc721_tokens_of(&self, owner: Address) -> impl Future<Output = Vec<(Address, Uint)>> {
+		self.0.dyn_erc721_tokens_of(owner)
+	}
+
+	fn erc721_tokens_of_collection(&self, owner: Address, token_address: Address) -> impl Future<Output = Vec<Uint>> {
+		self.0.dyn_erc721_tokens_of_collection(owner, token_address)
+	}
+
+	fn erc721_set_metadata(
+		&self,
+		token_address: Address,
+		token_id: Uint,
+		metadata: TokenMetadata,
+	) -> impl Future<Output = Result<(), CrabrollsError>> {
+		self.0.dyn_erc721_set_metadata(token_address, token_id, metadata)
+	}
+
+	fn erc721_metadata_of(
+		&self,
+		token_address: Address,
+		token_id: Uint,
+	) -> impl Future<Output = Option<TokenMetadata>> {
+		self.0.dyn_erc721_metadata_of(token_address, token_id)
+	}
+}
+
+#[cfg(feature = "erc1155")]
+impl ERC1155Environment for DynEnv<'_> {
+	fn erc1155_addresses(&self) -> impl Future<Output = Vec<Address>> {
+		self.0.dyn_erc1155_addresses()
+	}
+
+	fn erc1155_withdraw<I>(
+		&self,
+		wallet_address: Address,
+		token_address: Address,
+		withdrawals: I,
+		data: Option<Vec<u8>>,
+	) -> impl Future<Output = Result<(), CrabrollsError>>
+	where
+		I: IntoIdsAmountsIter,
+	{
+		self.0.dyn_erc1155_withdraw(
+			wallet_address,
+			token_address,
+			withdrawals.into_inner_iter().collect(),
+			data,
+		)
+	}
+
+	fn erc1155_transfer<I>(
+		&self,
+		src_wallet: Address,
+		dst_wallet: Address,
+		token_address: Address,
+		transfers: I,
+	) -> impl Future<Output = Result<(), CrabrollsError>>
+	where
+		I: IntoIdsAmountsIter,
+	{
+		self.0.dyn_erc1155_transfer(
+			src_wallet,
+			dst_wallet,
+			token_address,
+			transfers.into_inner_iter().collect(),
+		)
+	}
+
+	fn erc1155_balance(
+		&self,
+		wallet_address: Address,
+		token_address: Address,
+		token_id: Uint,
+	) -> impl Future<Output = Uint> {
+		self.0.dyn_erc1155_balance(wallet_address, token_address, token_id)
+	}
+
+	fn erc1155_balance_of_batch<I>(
+		&self,
+		wallet_address: Address,
+		token_address: Address,
+		token_ids: I,
+	) -> impl Future<Output = Vec<Uint>>
+	where
+		I: IntoIdsIter,
+	{
+		self.0
+			.dyn_erc1155_balance_of_batch(wallet_address, token_address, token_ids.into_inner_iter().collect())
+	}
+}
+
+impl RollupInternalEnvironment for DynEnv<'_> {
+	fn get_address_book(&self) -> AddressBook {
+		self.0.dyn_address_book()
+	}
+
+	fn get_ether_wallet(&self) -> Arc<RwLock<EtherWallet>> {
+		self.0.dyn_ether_wallet()
+	}
+
+	#[cfg(feature = "erc20")]
+	fn get_erc20_wallet(&self) -> Arc<RwLock<ERC20Wallet>> {
+		self.0.dyn_erc20_wallet()
+	}
+
+	#[cfg(feature = "erc721")]
+	fn get_erc721_wallet(&self) -> Arc<RwLock<ERC721Wallet>> {
+		self.0.dyn_erc721_wallet()
+	}
+
+	#[cfg(feature = "erc1155")]
+	fn get_erc1155_wallet(&self) -> Arc<RwLock<ERC1155Wallet>> {
+		self.0.dyn_erc1155_wallet()
+	}
+
+	fn get_erc4626_wallet(&self) -> Arc<RwLock<ERC4626Wallet>> {
+		self.0.dyn_erc4626_wallet()
+	}
+
+	fn get_activity(&self) -> Arc<RwLock<ActivityCounters>> {
+		self.0.dyn_activity()
+	}
+
+	fn balance_notices(&self) -> bool {
+		self.0.dyn_balance_notices()
+	}
+}
+
+pub trait DynApplication {
+	fn dyn_advance<'a>(
+		&'a self,
+		env: &'a dyn DynEnvironment,
+		metadata: Metadata,
+		payload: &'a [u8],
+		deposit: Option<Deposit>,
+	) -> LocalBoxFuture<'a, Result<FinishStatus, Box<dyn Error>>>;
+
+	fn dyn_inspect<'a>(
+		&'a self,
+		env: &'a dyn DynEnvironment,
+		payload: &'a [u8],
+	) -> LocalBoxFuture<'a, Result<FinishStatus, Box<dyn Error>>>;
+
+	fn dyn_validate(&self, metadata: &Metadata, payload: &[u8]) -> Result<(), Box<dyn Error>>;
+
+	fn dyn_on_start<'a>(&'a self, env: &'a dyn DynEnvironment) -> LocalBoxFuture<'a, Result<(), Box<dyn Error>>>;
+
+	fn dyn_on_shutdown<'a>(&'a self, env: &'a dyn DynEnvironment) -> LocalBoxFuture<'a, Result<(), Box<dyn Error>>>;
+
+	fn dyn_on_error<'a>(
+		&'a self,
+		env: &'a dyn DynEnvironment,
+		error: &'a (dyn Error + 'static),
+	) -> LocalBoxFuture<'a, ()>;
+}
+
+impl<A: Application> DynApplication for A {
+	fn dyn_advance<'a>(
+		&'a self,
+		env: &'a dyn DynEnvironment,
+		metadata: Metadata,
+		payload: &'a [u8],
+		deposit: Option<Deposit>,
+	) -> LocalBoxFuture<'a, Result<FinishStatus, Box<dyn Error>>> {
+		Box::pin(async move {
+			self.advance(&DynEnv(env), metadata, payload, deposit)
+				.await
+				.map_err(Into::into)
+		})
+	}
+
+	fn dyn_inspect<'a>(
+		&'a self,
+		env: &'a dyn DynEnvironment,
+		payload: &'a [u8],
+	) -> LocalBoxFuture<'a, Result<FinishStatus, Box<dyn Error>>> {
+		Box::pin(async move { self.inspect(&DynEnv(env), payload).await.map_err(Into::into) })
+	}
+
+	fn dyn_validate(&self, metadata: &Metadata, payload: &[u8]) -> Result<(), Box<dyn Error>> {
+		self.validate(metadata, payload).map_err(Into::into)
+	}
+
+	fn dyn_on_start<'a>(&'a self, env: &'a dyn DynEnvironment) -> LocalBoxFuture<'a, Result<(), Box<dyn Error>>> {
+		Box::pin(async move { self.on_start(&DynEnv(env)).await.map_err(Into::into) })
+	}
+
+	fn dyn_on_shutdown<'a>(&'a self, env: &'a dyn DynEnvironment) -> LocalBoxFuture<'a, Result<(), Box<dyn Error>>> {
+		Box::pin(async move { self.on_shutdown(&DynEnv(env)).await.map_err(Into::into) })
+	}
+
+	fn dyn_on_error<'a>(
+		&'a self,
+		env: &'a dyn DynEnvironment,
+		error: &'a (dyn Error + 'static),
+	) -> LocalBoxFuture<'a, ()> {
+		Box::pin(async move { self.on_error(&DynEnv(env), error).await })
+	}
+}
+
+impl Application for Box<dyn DynApplication> {
+	type Error = Box<dyn Error>;
+
+	async fn advance(
+		&self,
+		env: &impl Environment,
+		metadata: Metadata,
+		payload: &[u8],
+		deposit: Option<Deposit>,
+	) -> Result<FinishStatus, Box<dyn Error>> {
+		self.as_ref().dyn_advance(env, metadata, payload, deposit).await
+	}
+
+	async fn inspect(&self, env: &impl Environment, payload: &[u8]) -> Result<FinishStatus, Box<dyn Error>> {
+		self.as_ref().dyn_inspect(env, payload).await
+	}
+
+	fn validate(&self, metadata: &Metadata, payload: &[u8]) -> Result<(), Box<dyn Error>> {
+		self.as_ref().dyn_validate(metadata, payload)
+	}
+
+	async fn on_start(&self, env: &impl Environment) -> Result<(), Box<dyn Error>> {
+		self.as_ref().dyn_on_start(env).await
+	}
+
+	async fn on_shutdown(&self, env: &impl Environment) -> Result<(), Box<dyn Error>> {
+		self.as_ref().dyn_on_shutdown(env).await
+	}
+
+	async fn on_error(&self, env: &impl Environment, error: &(dyn Error + 'static)) {
+		self.as_ref().dyn_on_error(env, error).await
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::core::testing::{MockupOptions, RollupMockup, Tester};
+	use crate::types::machine::Output;
+	use crate::types::testing::ResultUtils;
+
+	struct Notifier;
+
+	impl Application for Notifier {
+		type Error = Box<dyn Error>;
+
+		async fn advance(
+			&self,
+			env: &impl Environment,
+			_metadata: Metadata,
+			payload: &[u8],
+			_deposit: Option<Deposit>,
+		) -> Result<FinishStatus, Box<dyn Error>> {
+			env.send_notice(payload).await?;
+			Ok(FinishStatus::Accept)
+		}
+
+		async fn inspect(&self, env: &impl Environment, payload: &[u8]) -> Result<FinishStatus, Box<dyn Error>> {
+			env.send_report(payload).await?;
+			Ok(FinishStatus::Accept)
+		}
+	}
+
+	struct Treasurer;
+
+	impl Application for Treasurer {
+		type Error = Box<dyn Error>;
+
+		async fn advance(
+			&self,
+			env: &impl Environment,
+			metadata: Metadata,
+			_payload: &[u8],
+			_deposit: Option<Deposit>,
+		) -> Result<FinishStatus, Box<dyn Error>> {
+			let balance = env.ether_balance(metadata.sender).await;
+			env.send_report_str(balance).await?;
+			Ok(FinishStatus::Accept)
+		}
+
+		async fn inspect(&self, _env: &impl Environment, _payload: &[u8]) -> Result<FinishStatus, Box<dyn Error>> {
+			Ok(FinishStatus::Reject)
+		}
+	}
+
+	#[async_std::test]
+	async fn test_heterogeneous_applications() {
+		let env = RollupMockup::new();
+		env.get_ether_wallet()
+			.write()
+			.await
+			.set_balance(Address::zero(), Uint::from(7));
+
+		let apps: Vec<Box<dyn DynApplication>> = vec![Box::new(Notifier), Box::new(Treasurer)];
+		for app in &apps {
+			let metadata = Metadata {
+				input_index: 0,
+				sender: Address::zero(),
+				block_number: 0,
+				timestamp: 0,
+				app_contract: None,
+				chain_id: None,
+				prev_randao: None,
+				annotations: Default::default(),
+			};
+			let status = app.dyn_advance(&env, metadata, b"hello", None).await.unwrap();
+			assert_eq!(status, FinishStatus::Accept);
+		}
+
+		assert_eq!(
+			env.advance(FinishStatus::Accept).await.unwrap().unwrap(),
+			vec![
+				Output::Notice {
+					payload: b"hello".to_vec()
+				},
+				Output::Report { payload: b"7".to_vec() },
+			]
+		);
+	}
+
+	#[async_std::test]
+	async fn test_boxed_application_in_tester() {
+		let app: Box<dyn DynApplication> = Box::new(Notifier);
+		let tester = Tester::new(app, MockupOptions::default());
+
+		let result = tester.advance(Address::zero(), b"boxed").await;
+		assert!(result.is_accepted());
+		assert_eq!(
+			result.get_outputs(),
+			vec![Output::Notice {
+				payload: b"boxed".to_vec()
+			}]
+		);
+
+		let result = tester.inspect(b"query").await;
+		assert_eq!(result.get_reports(), vec![b"query".to_vec()]);
+	}
+}
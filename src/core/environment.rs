@@ -1,61 +1,310 @@
+use super::about::BuildInfo;
+use super::activity::ActivityCounters;
+#[cfg(feature = "rollup")]
+use super::context::RunOptions;
+#[cfg(all(feature = "rollup", feature = "erc1155"))]
+use super::contracts::erc1155::IntoIdsIter;
+#[cfg(feature = "erc1155")]
 use super::contracts::erc1155::{ERC1155Environment, ERC1155Wallet, IntoIdsAmountsIter};
+#[cfg(feature = "erc20")]
 use super::contracts::erc20::{ERC20Environment, ERC20Wallet};
+use super::contracts::erc4626::{ERC4626Environment, ERC4626Wallet};
+#[cfg(all(feature = "rollup", feature = "erc721"))]
+use super::contracts::erc721::TokenMetadata;
+#[cfg(feature = "erc721")]
 use super::contracts::erc721::{ERC721Environment, ERC721Wallet};
 use super::contracts::ether::{EtherEnvironment, EtherWallet};
+#[cfg(feature = "rollup")]
+use super::guards::VoucherGuard;
 use crate::types::address_book::AddressBook;
-use crate::types::machine::{Advance, FinishStatus, Input, Inspect, Output};
+use crate::types::balance::{BalanceChange, BalanceChangeKind};
+use crate::types::error::CrabrollsError;
+use crate::types::machine::GioResponse;
+#[cfg(feature = "rollup")]
+use crate::types::machine::{FinishStatus, GioRequest, IndexResponse, Input, Output, OutputLimits, VoucherDedupPolicy};
+use crate::types::report::ErrorReport;
+#[cfg(feature = "rollup")]
 use crate::utils::requests::ClientWrapper;
-use async_std::sync::RwLock;
-use ethabi::{Address, Uint};
+#[cfg(feature = "rollup")]
+use async_lock::Mutex;
+use async_lock::RwLock;
+use crate::types::primitives::*;
+use serde::Serialize;
+#[cfg(feature = "rollup")]
 use serde_json::Value;
-use std::error::Error;
+use std::fmt::Display;
 use std::future::Future;
 use std::sync::Arc;
 
+/// Declares a supertrait of `Environment` standing for the wallet trait when its cargo feature is enabled, and for
+/// nothing otherwise, since supertraits can't be conditionally compiled.
+macro_rules! wallet_support {
+	($support:ident, $environment:ident, $feature:literal) => {
+		#[cfg(feature = $feature)]
+		pub trait $support: $environment {}
+		#[cfg(feature = $feature)]
+		impl<T: $environment> $support for T {}
+		#[cfg(not(feature = $feature))]
+		pub trait $support {}
+		#[cfg(not(feature = $feature))]
+		impl<T> $support for T {}
+	};
+}
+
+wallet_support!(ERC20Support, ERC20Environment, "erc20");
+wallet_support!(ERC721Support, ERC721Environment, "erc721");
+wallet_support!(ERC1155Support, ERC1155Environment, "erc1155");
+
 pub trait Environment:
-	EtherEnvironment + ERC20Environment + ERC721Environment + ERC1155Environment + RollupInternalEnvironment
+	EtherEnvironment + ERC20Support + ERC721Support + ERC1155Support + ERC4626Environment + RollupInternalEnvironment
 {
+	/// Sends a voucher forwarding `value` wei of the application contract with the call (Cartesi Rollups v2).
+	/// Like the other outputs, returns its index, or `None` when it's buffered until the input is accepted.
+	fn send_voucher_with_value(
+		&self,
+		destination: Address,
+		value: Uint,
+		payload: impl AsRef<[u8]> + Send,
+	) -> impl Future<Output = Result<Option<u64>, CrabrollsError>> + Send;
+
 	fn send_voucher(
 		&self,
 		destination: Address,
 		payload: impl AsRef<[u8]> + Send,
-	) -> impl Future<Output = Result<i32, Box<dyn Error>>> + Send;
+	) -> impl Future<Output = Result<Option<u64>, CrabrollsError>> + Send {
+		self.send_voucher_with_value(destination, Uint::zero(), payload)
+	}
+
+	/// Sends a voucher executed with `DELEGATECALL` by the application contract (Cartesi Rollups v2), running the code
+	/// of `destination` on the storage of the application contract.
+	fn send_delegate_call_voucher(
+		&self,
+		destination: Address,
+		payload: impl AsRef<[u8]> + Send,
+	) -> impl Future<Output = Result<Option<u64>, CrabrollsError>> + Send;
+
+	fn send_notice(
+		&self,
+		payload: impl AsRef<[u8]> + Send,
+	) -> impl Future<Output = Result<Option<u64>, CrabrollsError>> + Send;
+
+	fn send_report(&self, payload: impl AsRef<[u8]> + Send) -> impl Future<Output = Result<(), CrabrollsError>> + Send;
+
+	/// Asks the rollups node v2 for the data of `domain` identified by `payload`, e.g. to dehash a preimage or reach an
+	/// oracle, through its generic IO (GIO) endpoint.
+	fn gio(
+		&self,
+		domain: u16,
+		payload: impl AsRef<[u8]> + Send,
+	) -> impl Future<Output = Result<GioResponse, CrabrollsError>> + Send;
+
+	fn build_info(&self) -> Option<BuildInfo>;
+
+	fn send_report_str(&self, value: impl Display) -> impl Future<Output = Result<(), CrabrollsError>> + Send {
+		self.send_report(value.to_string())
+	}
+
+	fn send_report_json(&self, value: &impl Serialize) -> impl Future<Output = Result<(), CrabrollsError>> + Send {
+		let report = serde_json::to_vec(value)
+			.map(|payload| self.send_report(payload))
+			.map_err(CrabrollsError::from);
+		async move { report?.await }
+	}
 
-	fn send_notice(&self, payload: impl AsRef<[u8]> + Send)
-		-> impl Future<Output = Result<i32, Box<dyn Error>>> + Send;
+	fn send_error_report(&self, report: &ErrorReport) -> impl Future<Output = Result<(), CrabrollsError>> + Send {
+		self.send_report(report.to_bytes())
+	}
 
-	fn send_report(&self, payload: impl AsRef<[u8]> + Send) -> impl Future<Output = Result<(), Box<dyn Error>>> + Send;
+	fn send_transfer_notice(
+		&self,
+		change: BalanceChange,
+		notify: bool,
+	) -> impl Future<Output = Result<(), CrabrollsError>> + Send {
+		let notice = (notify || self.balance_notices()).then(|| self.send_notice(change.to_bytes()));
+		async move {
+			if let Some(notice) = notice {
+				notice.await?;
+			}
+			Ok(())
+		}
+	}
+
+	fn ether_transfer_with_memo(
+		&self,
+		source: Address,
+		destination: Address,
+		value: Uint,
+		memo: impl Into<String>,
+		notify: bool,
+	) -> impl Future<Output = Result<(), CrabrollsError>> + Send {
+		let change =
+			BalanceChange::ether(BalanceChangeKind::Transfer, Some(source), Some(destination), value).memo(memo);
+		// Taken before the future so it doesn't borrow `self`, the notice only goes out when polled after the transfer.
+		let ether_wallet = self.get_ether_wallet();
+		let notice = self.send_transfer_notice(change, notify);
+		async move {
+			ether_wallet.write().await.transfer(source, destination, value)?;
+			notice.await
+		}
+	}
+
+	#[cfg(feature = "erc20")]
+	fn erc20_transfer_with_memo(
+		&self,
+		src_wallet: Address,
+		dst_wallet: Address,
+		token_address: Address,
+		value: Uint,
+		memo: impl Into<String>,
+		notify: bool,
+	) -> impl Future<Output = Result<(), CrabrollsError>> + Send {
+		let change = BalanceChange::erc20(
+			BalanceChangeKind::Transfer,
+			token_address,
+			Some(src_wallet),
+			Some(dst_wallet),
+			value,
+		)
+		.memo(memo);
+		let erc20_wallet = self.get_erc20_wallet();
+		let notice = self.send_transfer_notice(change, notify);
+		async move {
+			erc20_wallet
+				.write()
+				.await
+				.transfer(src_wallet, dst_wallet, token_address, value)?;
+			notice.await
+		}
+	}
+
+	#[cfg(feature = "erc721")]
+	fn erc721_transfer_with_memo(
+		&self,
+		src_wallet: Address,
+		dst_wallet: Address,
+		token_address: Address,
+		token_id: Uint,
+		memo: impl Into<String>,
+		notify: bool,
+	) -> impl Future<Output = Result<(), CrabrollsError>> + Send {
+		let change = BalanceChange::erc721(
+			BalanceChangeKind::Transfer,
+			token_address,
+			Some(src_wallet),
+			Some(dst_wallet),
+			token_id,
+		)
+		.memo(memo);
+		let erc721_wallet = self.get_erc721_wallet();
+		let notice = self.send_transfer_notice(change, notify);
+		async move {
+			erc721_wallet
+				.write()
+				.await
+				.transfer(src_wallet, dst_wallet, token_address, token_id)?;
+			notice.await
+		}
+	}
+
+	#[cfg(feature = "erc1155")]
+	fn erc1155_transfer_with_memo<I>(
+		&self,
+		src_wallet: Address,
+		dst_wallet: Address,
+		token_address: Address,
+		transfers: I,
+		memo: impl Into<String>,
+		notify: bool,
+	) -> impl Future<Output = Result<(), CrabrollsError>> + Send
+	where
+		I: IntoIdsAmountsIter,
+	{
+		let transfers: Vec<(Uint, Uint)> = transfers.into_inner_iter().collect();
+		let change = BalanceChange::erc1155(
+			BalanceChangeKind::Transfer,
+			token_address,
+			Some(src_wallet),
+			Some(dst_wallet),
+			&transfers,
+		)
+		.memo(memo);
+		let erc1155_wallet = self.get_erc1155_wallet();
+		let notice = self.send_transfer_notice(change, notify);
+		async move {
+			erc1155_wallet
+				.write()
+				.await
+				.transfer(src_wallet, dst_wallet, token_address, transfers)?;
+			notice.await
+		}
+	}
 }
 
 pub trait RollupInternalEnvironment {
 	fn get_address_book(&self) -> AddressBook;
 	fn get_ether_wallet(&self) -> Arc<RwLock<EtherWallet>>;
+	#[cfg(feature = "erc20")]
 	fn get_erc20_wallet(&self) -> Arc<RwLock<ERC20Wallet>>;
+	#[cfg(feature = "erc721")]
 	fn get_erc721_wallet(&self) -> Arc<RwLock<ERC721Wallet>>;
+	#[cfg(feature = "erc1155")]
 	fn get_erc1155_wallet(&self) -> Arc<RwLock<ERC1155Wallet>>;
+	fn get_erc4626_wallet(&self) -> Arc<RwLock<ERC4626Wallet>>;
+	fn get_activity(&self) -> Arc<RwLock<ActivityCounters>>;
+	fn balance_notices(&self) -> bool;
 }
 
+#[cfg(feature = "rollup")]
 pub struct Rollup {
 	client: ClientWrapper,
 	app_address: Arc<RwLock<Option<Address>>>,
+	inspecting: RwLock<bool>,
+	recorded_reports: Mutex<Option<Vec<Vec<u8>>>>,
+	recorded_outputs: Mutex<Option<Vec<Output>>>,
+	output_buffer: Option<Mutex<Vec<Output>>>, // Vouchers and notices of the current input, posted once it's accepted
 
 	address_book: AddressBook,
+	output_limits: OutputLimits,
+	voucher_guard: Mutex<VoucherGuard>,
+	legacy_vouchers: bool, // Post vouchers in the format of the rollups node v1
+	balance_notices: bool,
+	build_info: Option<BuildInfo>,
 	ether_wallet: Arc<RwLock<EtherWallet>>,
+	#[cfg(feature = "erc20")]
 	erc20_wallet: Arc<RwLock<ERC20Wallet>>,
+	#[cfg(feature = "erc721")]
 	erc721_wallet: Arc<RwLock<ERC721Wallet>>,
+	#[cfg(feature = "erc1155")]
 	erc1155_wallet: Arc<RwLock<ERC1155Wallet>>,
+	erc4626_wallet: Arc<RwLock<ERC4626Wallet>>,
+	activity: Arc<RwLock<ActivityCounters>>,
 }
 
+#[cfg(feature = "rollup")]
 impl Rollup {
-	pub fn new(url: &'static str, address_book: AddressBook) -> Self {
+	pub fn new(options: &RunOptions) -> Self {
 		Self {
-			client: ClientWrapper::new(url.into()),
+			client: ClientWrapper::new(options),
 			app_address: Arc::new(RwLock::new(None)),
-			address_book: address_book,
+			inspecting: RwLock::new(false),
+			recorded_reports: Mutex::new(None),
+			recorded_outputs: Mutex::new(None),
+			output_buffer: options.buffered_outputs.then(|| Mutex::new(Vec::new())),
+			address_book: options.address_book.clone(),
+			output_limits: options.output_limits,
+			voucher_guard: Mutex::new(VoucherGuard::new(options.voucher_dedup)),
+			legacy_vouchers: options.legacy_vouchers,
+			balance_notices: options.balance_notices,
+			build_info: options.build_info.clone(),
 			ether_wallet: Arc::new(RwLock::new(EtherWallet::new())),
+			#[cfg(feature = "erc20")]
 			erc20_wallet: Arc::new(RwLock::new(ERC20Wallet::new())),
+			#[cfg(feature = "erc721")]
 			erc721_wallet: Arc::new(RwLock::new(ERC721Wallet::new())),
+			#[cfg(feature = "erc1155")]
 			erc1155_wallet: Arc::new(RwLock::new(ERC1155Wallet::new())),
+			erc4626_wallet: Arc::new(RwLock::new(ERC4626Wallet::new())),
+			activity: Arc::new(RwLock::new(ActivityCounters::new(options.activity_window))),
 		}
 	}
 
@@ -65,10 +314,106 @@ impl Rollup {
 	}
 
 	pub async fn get_app_address(&self) -> Option<Address> {
-		self.app_address.read().await.clone()
+		*self.app_address.read().await
+	}
+
+	pub(crate) async fn record_reports(&self) {
+		*self.recorded_reports.lock().await = Some(Vec::new());
+	}
+
+	pub(crate) async fn take_recorded_reports(&self) -> Vec<Vec<u8>> {
+		self.recorded_reports.lock().await.take().unwrap_or_default()
+	}
+
+	pub(crate) async fn record_outputs(&self) {
+		*self.recorded_outputs.lock().await = Some(Vec::new());
+	}
+
+	pub(crate) async fn take_recorded_outputs(&self) -> Vec<Output> {
+		self.recorded_outputs.lock().await.take().unwrap_or_default()
+	}
+
+	async fn push_recorded_output(&self, output: Output) {
+		if let Some(recorded_outputs) = self.recorded_outputs.lock().await.as_mut() {
+			recorded_outputs.push(output);
+		}
+	}
+
+	fn check_output_format(&self, output: &Output) -> Result<(), CrabrollsError> {
+		match self.legacy_vouchers {
+			true => Ok(output.check_v1()?),
+			false => Ok(()),
+		}
+	}
+
+	/// Checks the voucher against the dedup policy, returning whether its duplicate must be reported.
+	async fn guard_voucher(&self, voucher: &Output) -> Result<bool, CrabrollsError> {
+		let mut voucher_guard = self.voucher_guard.lock().await;
+		let duplicate = voucher_guard.check(voucher)?;
+		Ok(duplicate && voucher_guard.policy() == VoucherDedupPolicy::Report)
+	}
+
+	async fn post_output(&self, output: &Output) -> Result<u64, CrabrollsError> {
+		let request = match self.legacy_vouchers {
+			true => output.to_v1_request()?,
+			false => output.to_request()?,
+		};
+		let response = self.client.post(output.kind(), &request).await?;
+		let response: IndexResponse = self
+			.client
+			.parse_response(response)
+			.map_err(|e| CrabrollsError::transport(format!("invalid {} response: {}", output.kind(), e)))?;
+		Ok(response.index)
+	}
+
+	/// Posts the output, returning the index given by the rollup server, or buffers it until the input is accepted,
+	/// when its index isn't known yet.
+	async fn emit_output(&self, output: Output) -> Result<Option<u64>, CrabrollsError> {
+		let index = match &self.output_buffer {
+			Some(buffer) => {
+				buffer.lock().await.push(output.clone());
+				None
+			}
+			None => Some(self.post_output(&output).await?),
+		};
+		self.push_recorded_output(output).await;
+		Ok(index)
+	}
+
+	async fn flush_outputs(&self, status: FinishStatus) -> Result<(), CrabrollsError> {
+		let Some(buffer) = &self.output_buffer else {
+			return Ok(());
+		};
+		let outputs = std::mem::take(&mut *buffer.lock().await);
+		if status == FinishStatus::Reject {
+			if !outputs.is_empty() {
+				debug!("Discarding {} outputs of the rejected input", outputs.len());
+			}
+			return Ok(());
+		}
+
+		for output in &outputs {
+			self.post_output(output).await?;
+		}
+		Ok(())
+	}
+
+	pub(crate) async fn notify_balance_change(&self, change: BalanceChange) -> Result<(), CrabrollsError> {
+		if self.balance_notices && !*self.inspecting.read().await {
+			self.send_notice(change.to_bytes()).await?;
+		}
+		Ok(())
+	}
+
+	async fn ensure_not_inspecting(&self, output: &Output) -> Result<(), CrabrollsError> {
+		if *self.inspecting.read().await {
+			return Err(CrabrollsError::other(format!("can't emit a {} during inspect", output.kind())));
+		}
+		Ok(())
 	}
 }
 
+#[cfg(feature = "rollup")]
 impl RollupInternalEnvironment for Rollup {
 	fn get_address_book(&self) -> AddressBook {
 		self.address_book.clone()
@@ -78,75 +423,173 @@ impl RollupInternalEnvironment for Rollup {
 		self.ether_wallet.clone()
 	}
 
+	#[cfg(feature = "erc20")]
 	fn get_erc20_wallet(&self) -> Arc<RwLock<ERC20Wallet>> {
 		self.erc20_wallet.clone()
 	}
 
+	#[cfg(feature = "erc721")]
 	fn get_erc721_wallet(&self) -> Arc<RwLock<ERC721Wallet>> {
 		self.erc721_wallet.clone()
 	}
 
+	#[cfg(feature = "erc1155")]
 	fn get_erc1155_wallet(&self) -> Arc<RwLock<ERC1155Wallet>> {
 		self.erc1155_wallet.clone()
 	}
+
+	fn get_erc4626_wallet(&self) -> Arc<RwLock<ERC4626Wallet>> {
+		self.erc4626_wallet.clone()
+	}
+
+	fn get_activity(&self) -> Arc<RwLock<ActivityCounters>> {
+		self.activity.clone()
+	}
+
+	fn balance_notices(&self) -> bool {
+		self.balance_notices
+	}
 }
 
+#[cfg(feature = "rollup")]
 impl Environment for Rollup {
-	async fn send_voucher(
+	async fn send_voucher_with_value(
 		&self,
 		destination: Address,
+		value: Uint,
 		payload: impl AsRef<[u8]> + Send,
-	) -> Result<i32, Box<dyn Error>> {
+	) -> Result<Option<u64>, CrabrollsError> {
 		let voucher = Output::Voucher {
 			destination,
+			value,
 			payload: payload.as_ref().to_vec(),
 		};
-		let response = self.client.post("voucher", &voucher).await?;
-		let output: serde_json::Value = self.client.parse_response(response).await?;
-		Ok(output["index"].as_i64().unwrap_or(0) as i32)
+		self.ensure_not_inspecting(&voucher).await?;
+		self.check_output_format(&voucher)?;
+		self.output_limits.check(&voucher)?;
+		let report_duplicate = self.guard_voucher(&voucher).await?;
+
+		let index = self.emit_output(voucher).await?;
+		if report_duplicate {
+			self.send_report(format!("duplicate voucher to {:?}", destination))
+				.await?;
+		}
+		Ok(index)
 	}
 
-	async fn send_notice(&self, payload: impl AsRef<[u8]> + Send) -> Result<i32, Box<dyn Error>> {
-		let notice = Output::Notice {
+	async fn send_delegate_call_voucher(
+		&self,
+		destination: Address,
+		payload: impl AsRef<[u8]> + Send,
+	) -> Result<Option<u64>, CrabrollsError> {
+		let voucher = Output::DelegateCallVoucher {
+			destination,
 			payload: payload.as_ref().to_vec(),
 		};
-		let response = self.client.post("notice", &notice).await?;
-		let output: Value = self.client.parse_response(response).await?;
-		Ok(output["index"].as_i64().unwrap_or(0) as i32)
+		self.ensure_not_inspecting(&voucher).await?;
+		self.check_output_format(&voucher)?;
+		self.output_limits.check(&voucher)?;
+		let report_duplicate = self.guard_voucher(&voucher).await?;
+
+		let index = self.emit_output(voucher).await?;
+		if report_duplicate {
+			self.send_report(format!("duplicate delegate call voucher to {:?}", destination))
+				.await?;
+		}
+		Ok(index)
 	}
 
-	async fn send_report(&self, payload: impl AsRef<[u8]> + Send) -> Result<(), Box<dyn Error>> {
-		let report = Output::Report {
+	async fn send_notice(&self, payload: impl AsRef<[u8]> + Send) -> Result<Option<u64>, CrabrollsError> {
+		let notice = Output::Notice {
 			payload: payload.as_ref().to_vec(),
 		};
-		self.client.post("report", &report).await?;
+		self.ensure_not_inspecting(&notice).await?;
+		self.output_limits.check(&notice)?;
+		self.emit_output(notice).await
+	}
+
+	async fn send_report(&self, payload: impl AsRef<[u8]> + Send) -> Result<(), CrabrollsError> {
+		let chunks = self.output_limits.report_chunks(payload.as_ref())?;
+		for chunk in chunks {
+			let report = Output::Report {
+				payload: chunk.to_vec(),
+			};
+			let request = report.to_request()?;
+			self.client.post("report", &request).await?;
+		}
+		if let Some(recorded_reports) = self.recorded_reports.lock().await.as_mut() {
+			recorded_reports.push(payload.as_ref().to_vec());
+		}
+		self.push_recorded_output(Output::Report {
+			payload: payload.as_ref().to_vec(),
+		})
+		.await;
 		Ok(())
 	}
+
+	async fn gio(&self, domain: u16, payload: impl AsRef<[u8]> + Send) -> Result<GioResponse, CrabrollsError> {
+		let request = GioRequest {
+			domain,
+			payload: payload.as_ref().to_vec(),
+		};
+		self.client.gio(&request).await
+	}
+
+	fn build_info(&self) -> Option<BuildInfo> {
+		self.build_info.clone()
+	}
 }
 
+#[cfg(feature = "rollup")]
 impl EtherEnvironment for Rollup {
 	async fn ether_addresses(&self) -> Vec<Address> {
 		self.ether_wallet.read().await.addresses()
 	}
 
-	async fn ether_withdraw(&self, address: Address, value: Uint) -> Result<(), Box<dyn Error>> {
+	async fn ether_withdraw(&self, address: Address, value: Uint) -> Result<(), CrabrollsError> {
 		let app_address = self.get_app_address().await;
-		if app_address.is_none() {
-			return Err(Box::from("App address is not set"));
+		if self.legacy_vouchers && app_address.is_none() {
+			return Err(CrabrollsError::other("App address is not set"));
 		}
 
 		let mut ether_wallet = self.ether_wallet.write().await;
 		let payload = ether_wallet.withdraw(address, value)?;
 
-		self.send_voucher(app_address.expect("App address is not set"), payload)
-			.await?;
+		// The v1 application contract pays withdrawals through its `withdrawEther` function, while v2 vouchers carry
+		// the ether themselves.
+		let sent = match self.legacy_vouchers {
+			true => {
+				self.send_voucher(app_address.expect("App address is not set"), payload)
+					.await
+			}
+			false => self.send_voucher_with_value(address, value, Vec::new()).await,
+		};
+		if let Err(e) = sent {
+			// Without its voucher the withdrawal never happened, so the funds stay in the wallet
+			ether_wallet.cancel_withdraw(address, value);
+			return Err(e);
+		}
+		self.notify_balance_change(BalanceChange::ether(
+			BalanceChangeKind::Withdraw,
+			Some(address),
+			None,
+			value,
+		))
+		.await?;
 
 		Ok(())
 	}
 
-	async fn ether_transfer(&self, source: Address, destination: Address, value: Uint) -> Result<(), Box<dyn Error>> {
+	async fn ether_transfer(&self, source: Address, destination: Address, value: Uint) -> Result<(), CrabrollsError> {
 		let mut ether_wallet = self.ether_wallet.write().await;
 		ether_wallet.transfer(source, destination, value)?;
+		self.notify_balance_change(BalanceChange::ether(
+			BalanceChangeKind::Transfer,
+			Some(source),
+			Some(destination),
+			value,
+		))
+		.await?;
 
 		Ok(())
 	}
@@ -154,8 +597,13 @@ impl EtherEnvironment for Rollup {
 	async fn ether_balance(&self, address: Address) -> Uint {
 		self.ether_wallet.read().await.balance_of(address)
 	}
+
+	async fn ether_total_supply(&self) -> Uint {
+		self.ether_wallet.read().await.total_supply()
+	}
 }
 
+#[cfg(all(feature = "rollup", feature = "erc20"))]
 impl ERC20Environment for Rollup {
 	async fn erc20_addresses(&self) -> Vec<Address> {
 		self.erc20_wallet.read().await.addresses()
@@ -166,11 +614,22 @@ impl ERC20Environment for Rollup {
 		wallet_address: Address,
 		token_address: Address,
 		value: Uint,
-	) -> Result<(), Box<dyn Error>> {
+	) -> Result<(), CrabrollsError> {
 		let mut erc20_wallet = self.erc20_wallet.write().await;
 		let payload = erc20_wallet.withdraw(wallet_address, token_address, value)?;
 
-		self.send_voucher(token_address, payload).await?;
+		if let Err(e) = self.send_voucher(token_address, payload).await {
+			erc20_wallet.cancel_withdraw(wallet_address, token_address, value);
+			return Err(e);
+		}
+		self.notify_balance_change(BalanceChange::erc20(
+			BalanceChangeKind::Withdraw,
+			token_address,
+			Some(wallet_address),
+			None,
+			value,
+		))
+		.await?;
 
 		Ok(())
 	}
@@ -181,9 +640,17 @@ impl ERC20Environment for Rollup {
 		dst_wallet: Address,
 		token_address: Address,
 		value: Uint,
-	) -> Result<(), Box<dyn Error>> {
+	) -> Result<(), CrabrollsError> {
 		let mut erc20_wallet = self.erc20_wallet.write().await;
 		erc20_wallet.transfer(src_wallet, dst_wallet, token_address, value)?;
+		self.notify_balance_change(BalanceChange::erc20(
+			BalanceChangeKind::Transfer,
+			token_address,
+			Some(src_wallet),
+			Some(dst_wallet),
+			value,
+		))
+		.await?;
 
 		Ok(())
 	}
@@ -193,6 +660,67 @@ impl ERC20Environment for Rollup {
 	}
 }
 
+#[cfg(feature = "rollup")]
+impl ERC4626Environment for Rollup {
+	async fn erc4626_addresses(&self) -> Vec<Address> {
+		self.erc4626_wallet.read().await.addresses()
+	}
+
+	async fn erc4626_withdraw(
+		&self,
+		wallet_address: Address,
+		vault_address: Address,
+		shares: Uint,
+	) -> Result<(), CrabrollsError> {
+		let mut erc4626_wallet = self.erc4626_wallet.write().await;
+		let payload = erc4626_wallet.withdraw(wallet_address, vault_address, shares)?;
+
+		if let Err(e) = self.send_voucher(vault_address, payload).await {
+			erc4626_wallet.cancel_withdraw(wallet_address, vault_address, shares);
+			return Err(e);
+		}
+		self.notify_balance_change(BalanceChange::erc20(
+			BalanceChangeKind::Withdraw,
+			vault_address,
+			Some(wallet_address),
+			None,
+			shares,
+		))
+		.await?;
+
+		Ok(())
+	}
+
+	async fn erc4626_transfer(
+		&self,
+		src_wallet: Address,
+		dst_wallet: Address,
+		vault_address: Address,
+		shares: Uint,
+	) -> Result<(), CrabrollsError> {
+		let mut erc4626_wallet = self.erc4626_wallet.write().await;
+		erc4626_wallet.transfer(src_wallet, dst_wallet, vault_address, shares)?;
+		self.notify_balance_change(BalanceChange::erc20(
+			BalanceChangeKind::Transfer,
+			vault_address,
+			Some(src_wallet),
+			Some(dst_wallet),
+			shares,
+		))
+		.await?;
+
+		Ok(())
+	}
+
+	async fn erc4626_balance(&self, wallet_address: Address, vault_address: Address) -> Uint {
+		self.erc4626_wallet
+			.read()
+			.await
+			.balance_of(wallet_address, vault_address)
+	}
+}
+
+#[cfg(all(feature = "rollup", feature = "erc721"))]
 impl ERC721Environment for Rollup {
 	async fn erc721_addresses(&self) -> Vec<Address> {
 		self.erc721_wallet.read().await.addresses()
@@ -203,13 +731,14 @@ impl ERC721Environment for Rollup {
 		wallet_address: Address,
 		token_address: Address,
 		token_id: Uint,
-	) -> Result<(), Box<dyn Error>> {
+	) -> Result<(), CrabrollsError> {
 		let app_address = self.get_app_address().await;
 		if app_address.is_none() {
-			return Err(Box::from("App address is not set"));
+			return Err(CrabrollsError::other("App address is not set"));
 		}
 
 		let mut erc721_wallet = self.erc721_wallet.write().await;
+		let metadata = erc721_wallet.metadata_of(token_address, token_id).cloned();
 		let payload = erc721_wallet.withdraw(
 			app_address.expect("App address is not set"),
 			wallet_address,
@@ -217,7 +746,18 @@ impl ERC721Environment for Rollup {
 			token_id,
 		)?;
 
-		self.send_voucher(token_address, payload).await?;
+		if let Err(e) = self.send_voucher(token_address, payload).await {
+			erc721_wallet.cancel_withdraw(wallet_address, token_address, token_id, metadata);
+			return Err(e);
+		}
+		self.notify_balance_change(BalanceChange::erc721(
+			BalanceChangeKind::Withdraw,
+			token_address,
+			Some(wallet_address),
+			None,
+			token_id,
+		))
+		.await?;
 
 		Ok(())
 	}
@@ -228,9 +768,17 @@ impl ERC721Environment for Rollup {
 		dst_wallet: Address,
 		token_address: Address,
 		token_id: Uint,
-	) -> Result<(), Box<dyn Error>> {
+	) -> Result<(), CrabrollsError> {
 		let mut erc721_wallet = self.erc721_wallet.write().await;
 		erc721_wallet.transfer(src_wallet, dst_wallet, token_address, token_id)?;
+		self.notify_balance_change(BalanceChange::erc721(
+			BalanceChangeKind::Transfer,
+			token_address,
+			Some(src_wallet),
+			Some(dst_wallet),
+			token_id,
+		))
+		.await?;
 
 		Ok(())
 	}
@@ -238,8 +786,38 @@ impl ERC721Environment for Rollup {
 	async fn erc721_owner_of(&self, token_address: Address, token_id: Uint) -> Option<Address> {
 		self.erc721_wallet.read().await.owner_of(token_address, token_id)
 	}
+
+	async fn erc721_tokens_of(&self, owner: Address) -> Vec<(Address, Uint)> {
+		self.erc721_wallet.read().await.tokens_of(owner)
+	}
+
+	async fn erc721_tokens_of_collection(&self, owner: Address, token_address: Address) -> Vec<Uint> {
+		self.erc721_wallet
+			.read()
+			.await
+			.tokens_of_collection(owner, token_address)
+	}
+
+	async fn erc721_set_metadata(
+		&self,
+		token_address: Address,
+		token_id: Uint,
+		metadata: TokenMetadata,
+	) -> Result<(), CrabrollsError> {
+		let mut erc721_wallet = self.erc721_wallet.write().await;
+		erc721_wallet.set_metadata(token_address, token_id, metadata)
+	}
+
+	async fn erc721_metadata_of(&self, token_address: Address, token_id: Uint) -> Option<TokenMetadata> {
+		self.erc721_wallet
+			.read()
+			.await
+			.metadata_of(token_address, token_id)
+			.cloned()
+	}
 }
 
+#[cfg(all(feature = "rollup", feature = "erc1155"))]
 impl ERC1155Environment for Rollup {
 	async fn erc1155_addresses(&self) -> Vec<Address> {
 		self.erc1155_wallet.read().await.addresses()
@@ -251,13 +829,14 @@ impl ERC1155Environment for Rollup {
 		token_address: Address,
 		withdrawals: I,
 		data: Option<Vec<u8>>,
-	) -> Result<(), Box<dyn Error>>
+	) -> Result<(), CrabrollsError>
 	where
 		I: IntoIdsAmountsIter,
 	{
+		let withdrawals: Vec<(Uint, Uint)> = withdrawals.into_inner_iter().collect();
 		let app_address = self.get_app_address().await;
 		if app_address.is_none() {
-			return Err(Box::from("App address is not set"));
+			return Err(CrabrollsError::other("App address is not set"));
 		}
 
 		let mut erc1155_wallet = self.erc1155_wallet.write().await;
@@ -265,11 +844,22 @@ impl ERC1155Environment for Rollup {
 			app_address.expect("App address is not set"),
 			wallet_address,
 			token_address,
-			withdrawals,
+			withdrawals.clone(),
 			data,
 		)?;
 
-		self.send_voucher(token_address, payload).await?;
+		if let Err(e) = self.send_voucher(token_address, payload).await {
+			erc1155_wallet.cancel_withdraw(wallet_address, token_address, &withdrawals);
+			return Err(e);
+		}
+		self.notify_balance_change(BalanceChange::erc1155(
+			BalanceChangeKind::Withdraw,
+			token_address,
+			Some(wallet_address),
+			None,
+			&withdrawals,
+		))
+		.await?;
 
 		Ok(())
 	}
@@ -280,12 +870,21 @@ impl ERC1155Environment for Rollup {
 		dst_wallet: Address,
 		token_address: Address,
 		transfers: I,
-	) -> Result<(), Box<dyn Error>>
+	) -> Result<(), CrabrollsError>
 	where
 		I: IntoIdsAmountsIter,
 	{
+		let transfers: Vec<(Uint, Uint)> = transfers.into_inner_iter().collect();
 		let mut erc1155_wallet = self.erc1155_wallet.write().await;
-		erc1155_wallet.transfer(src_wallet, dst_wallet, token_address, transfers)?;
+		erc1155_wallet.transfer(src_wallet, dst_wallet, token_address, transfers.clone())?;
+		self.notify_balance_change(BalanceChange::erc1155(
+			BalanceChangeKind::Transfer,
+			token_address,
+			Some(src_wallet),
+			Some(dst_wallet),
+			&transfers,
+		))
+		.await?;
 
 		Ok(())
 	}
@@ -296,36 +895,51 @@ impl ERC1155Environment for Rollup {
 			.await
 			.balance_of(wallet_address, token_address, token_id)
 	}
+
+	async fn erc1155_balance_of_batch<I>(
+		&self,
+		wallet_address: Address,
+		token_address: Address,
+		token_ids: I,
+	) -> Vec<Uint>
+	where
+		I: IntoIdsIter,
+	{
+		self.erc1155_wallet
+			.read()
+			.await
+			.balance_of_batch(wallet_address, token_address, token_ids)
+	}
 }
 
+#[cfg(feature = "rollup")]
 impl Rollup {
-	pub async fn finish_and_get_next(&self, status: FinishStatus) -> Result<Option<Input>, Box<dyn Error>> {
+	pub async fn finish_and_get_next(&self, status: FinishStatus) -> Result<Option<Input>, CrabrollsError> {
+		self.voucher_guard.lock().await.finish(status);
+		self.flush_outputs(status).await?;
 		let response = self.client.post("finish", &status).await?;
 
 		let response_status = response.status();
 
 		if response_status != 200 && response_status != 202 {
-			return Err(Box::from("Failed to finish the current state"));
+			return Err(CrabrollsError::transport("Failed to finish the current state"));
 		} else if response_status == 202 {
 			return Ok(None);
 		}
 
-		let value: Value = self.client.parse_response(response).await?;
+		let value: Value = self.client.parse_response(response)?;
 		debug!("Received input: {:?}", value);
 
-		let request_type = value["request_type"].as_str().ok_or("Invalid request type")?;
-		let data = value["data"].clone();
+		let input = Input::from_finish_response(value)?;
+		*self.inspecting.write().await = matches!(input, Input::Inspect(_));
 
-		match request_type {
-			"advance_state" => {
-				let advance_input: Advance = serde_json::from_value(data)?;
-				Ok(Some(Input::Advance(advance_input)))
-			}
-			"inspect_state" => {
-				let inspect_input: Inspect = serde_json::from_value(data)?;
-				Ok(Some(Input::Inspect(inspect_input)))
+		if let Input::Advance(advance_input) = &input {
+			if let Some(app_contract) = advance_input.metadata.app_contract {
+				if self.get_app_address().await != Some(app_contract) {
+					self.set_app_address(app_contract).await;
+				}
 			}
-			_ => Err(Box::from("Invalid request type")),
 		}
+		Ok(Some(input))
 	}
 }
@@ -0,0 +1,274 @@
+use super::application::{validate_and_advance, Application};
+use super::environment::Environment;
+use crate::types::machine::{Deposit, FinishStatus, Metadata};
+use crate::types::primitives::*;
+use crate::types::report::ErrorReport;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::{Arc, RwLock};
+
+pub const DEFAULT_FAUCET_COOLDOWN: u64 = 24 * 60 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FaucetSource {
+	Mint,             // Credits the token out of thin air, only meant for internal test assets
+	Account(Address), // Transfers the token from a pre-funded account
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FaucetInput {
+	Claim,
+}
+
+#[derive(Deserialize)]
+struct Envelope {
+	faucet: FaucetInput,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Claims {
+	last: u64,
+	count: u64,
+}
+
+/// Dispenses a fixed amount of an ERC20 token to any requesting sender, rate limited by a per-sender cooldown.
+#[derive(Clone)]
+pub struct Faucet {
+	token: Address,
+	amount: Uint,
+	source: FaucetSource,
+	cooldown: u64,
+	max_claims: Option<u64>,
+	claims: Arc<RwLock<HashMap<Address, Claims>>>,
+}
+
+impl Faucet {
+	pub fn new(token: Address, amount: Uint) -> Self {
+		Self {
+			token,
+			amount,
+			source: FaucetSource::Mint,
+			cooldown: DEFAULT_FAUCET_COOLDOWN,
+			max_claims: None,
+			claims: Arc::default(),
+		}
+	}
+
+	pub fn source(mut self, source: FaucetSource) -> Self {
+		self.source = source;
+		self
+	}
+
+	/// Seconds a sender must wait between claims.
+	pub fn cooldown(mut self, cooldown: u64) -> Self {
+		self.cooldown = cooldown;
+		self
+	}
+
+	/// Maximum number of claims per sender.
+	pub fn max_claims(mut self, max_claims: u64) -> Self {
+		self.max_claims = Some(max_claims);
+		self
+	}
+
+	pub fn token(&self) -> Address {
+		self.token
+	}
+
+	/// Timestamp from which the sender can claim again, `None` if the sender reached the maximum number of claims.
+	pub fn next_claim_at(&self, sender: Address) -> Option<u64> {
+		let claims = self.claims.read().expect("Faucet lock poisoned");
+		match claims.get(&sender) {
+			Some(claims) if self.max_claims.is_some_and(|max_claims| claims.count >= max_claims) => None,
+			Some(claims) => Some(claims.last.saturating_add(self.cooldown)),
+			None => Some(0),
+		}
+	}
+
+	/// Dispenses the amount to the sender at the input timestamp `now`, returning it.
+	pub async fn claim(&self, env: &impl Environment, sender: Address, now: u64) -> Result<Uint, Box<dyn Error>> {
+		match self.next_claim_at(sender) {
+			None => {
+				return Err(ErrorReport::unauthorized(format!("{:?} reached the faucet claim limit", sender)).into())
+			}
+			Some(next) if now < next => {
+				return Err(ErrorReport::unauthorized(format!("{:?} can only claim again at {}", sender, next)).into())
+			}
+			_ => {}
+		}
+
+		match self.source {
+			FaucetSource::Mint => {
+				let wallet = env.get_erc20_wallet();
+				let mut wallet = wallet.write().await;
+				let balance = wallet
+					.balance_of(sender, self.token)
+					.checked_add(self.amount)
+					.ok_or("faucet balance overflow")?;
+				wallet.set_balance(sender, self.token, balance);
+			}
+			FaucetSource::Account(account) => env.erc20_transfer(account, sender, self.token, self.amount).await?,
+		}
+
+		let mut claims = self.claims.write().expect("Faucet lock poisoned");
+		let claims = claims.entry(sender).or_insert(Claims { last: now, count: 0 });
+		claims.last = now;
+		claims.count += 1;
+		debug!("Faucet dispensed {} of {:?} to {:?}", self.amount, self.token, sender);
+		Ok(self.amount)
+	}
+
+	/// Applies a `{"faucet": "claim"}` input, returning `None` for any other payload.
+	pub async fn handle_input(
+		&self,
+		env: &impl Environment,
+		metadata: &Metadata,
+		payload: &[u8],
+	) -> Option<Result<Uint, Box<dyn Error>>> {
+		let envelope = serde_json::from_slice::<Envelope>(payload).ok()?;
+		Some(match envelope.faucet {
+			FaucetInput::Claim => self.claim(env, metadata.sender, metadata.timestamp).await,
+		})
+	}
+}
+
+/// Handles the faucet claims and passes every other input to the app.
+pub struct FaucetGate<A: Application> {
+	app: A,
+	faucet: Faucet,
+}
+
+impl<A: Application> FaucetGate<A> {
+	pub fn new(app: A, faucet: Faucet) -> Self {
+		Self { app, faucet }
+	}
+
+	pub fn faucet(&self) -> &Faucet {
+		&self.faucet
+	}
+}
+
+impl<A: Application> Application for FaucetGate<A> {
+	type Error = Box<dyn Error>;
+
+	async fn advance(
+		&self,
+		env: &impl Environment,
+		metadata: Metadata,
+		payload: &[u8],
+		deposit: Option<Deposit>,
+	) -> Result<FinishStatus, Box<dyn Error>> {
+		if deposit.is_none() {
+			if let Some(result) = self.faucet.handle_input(env, &metadata, payload).await {
+				result?;
+				return Ok(FinishStatus::Accept);
+			}
+		}
+		validate_and_advance(&self.app, env, metadata, payload, deposit).await
+	}
+
+	async fn inspect(&self, env: &impl Environment, payload: &[u8]) -> Result<FinishStatus, Box<dyn Error>> {
+		self.app.inspect(env, payload).await.map_err(Into::into)
+	}
+
+	async fn on_start(&self, env: &impl Environment) -> Result<(), Box<dyn Error>> {
+		self.app.on_start(env).await.map_err(Into::into)
+	}
+
+	async fn on_shutdown(&self, env: &impl Environment) -> Result<(), Box<dyn Error>> {
+		self.app.on_shutdown(env).await.map_err(Into::into)
+	}
+
+	async fn on_error(&self, env: &impl Environment, error: &(dyn Error + 'static)) {
+		self.app.on_error(env, error).await
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::core::testing::{MockupOptions, Tester};
+	use crate::types::testing::ResultUtils;
+
+	const TOKEN: Address = Address::repeat_byte(0x70);
+	const ALICE: Address = Address::repeat_byte(0xa1);
+	const BOB: Address = Address::repeat_byte(0xb0);
+
+	struct Echo;
+
+	impl Application for Echo {
+		type Error = Box<dyn Error>;
+
+		async fn advance(
+			&self,
+			env: &impl Environment,
+			_metadata: Metadata,
+			payload: &[u8],
+			_deposit: Option<Deposit>,
+		) -> Result<FinishStatus, Box<dyn Error>> {
+			env.send_notice(payload).await?;
+			Ok(FinishStatus::Accept)
+		}
+	}
+
+	#[async_std::test]
+	async fn test_mint_with_cooldown() {
+		let faucet = Faucet::new(TOKEN, Uint::from(100)).cooldown(60).max_claims(2);
+		let tester = Tester::new(Echo, MockupOptions::default());
+		let env = tester.env();
+
+		assert_eq!(faucet.next_claim_at(ALICE), Some(0));
+		assert_eq!(faucet.claim(env.as_ref(), ALICE, 1000).await.unwrap(), Uint::from(100));
+		assert_eq!(faucet.next_claim_at(ALICE), Some(1060));
+		assert!(faucet.claim(env.as_ref(), ALICE, 1059).await.is_err());
+		faucet.claim(env.as_ref(), BOB, 1059).await.unwrap();
+		faucet.claim(env.as_ref(), ALICE, 1060).await.unwrap();
+
+		assert_eq!(faucet.next_claim_at(ALICE), None);
+		assert!(faucet.claim(env.as_ref(), ALICE, 5000).await.is_err());
+		assert_eq!(tester.erc20_balance(ALICE, TOKEN).await, Uint::from(200));
+		assert_eq!(tester.erc20_balance(BOB, TOKEN).await, Uint::from(100));
+	}
+
+	#[async_std::test]
+	async fn test_pre_funded_account() {
+		let treasury = Address::repeat_byte(0xee);
+		let faucet = Faucet::new(TOKEN, Uint::from(60))
+			.source(FaucetSource::Account(treasury))
+			.cooldown(0);
+		let tester = Tester::new(Echo, MockupOptions::default());
+		let env = tester.env();
+		tester
+			.deposit(Deposit::ERC20 {
+				sender: treasury,
+				token: TOKEN,
+				amount: Uint::from(100),
+			})
+			.await;
+
+		faucet.claim(env.as_ref(), ALICE, 0).await.unwrap();
+		assert!(faucet.claim(env.as_ref(), ALICE, 0).await.is_err()); // The account ran dry
+		assert_eq!(faucet.next_claim_at(ALICE), Some(0));
+		assert_eq!(tester.erc20_balance(ALICE, TOKEN).await, Uint::from(60));
+		assert_eq!(tester.erc20_balance(treasury, TOKEN).await, Uint::from(40));
+	}
+
+	#[async_std::test]
+	async fn test_faucet_gate() {
+		let faucet = Faucet::new(TOKEN, Uint::from(100));
+		let tester = Tester::new(FaucetGate::new(Echo, faucet.clone()), MockupOptions::default());
+
+		let result = tester.advance(ALICE, r#"{"faucet":"claim"}"#).await;
+		assert!(result.is_accepted());
+		assert!(result.notices().is_empty());
+		assert_eq!(tester.erc20_balance(ALICE, TOKEN).await, Uint::from(100));
+
+		let result = tester.advance(ALICE, r#"{"faucet":"claim"}"#).await;
+		assert!(result.is_rejected());
+
+		let result = tester.advance(ALICE, b"hello").await;
+		assert_eq!(result.notices(), vec![b"hello".to_vec()]);
+	}
+}
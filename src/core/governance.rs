@@ -0,0 +1,537 @@
+use super::approvals::Operation;
+use super::environment::Environment;
+use crate::types::machine::Metadata;
+use crate::types::primitives::*;
+use crate::types::report::ErrorReport;
+use crate::utils::math;
+use crate::utils::parsers::deserializers::{deserialize_string_of_bytes, serialize_bytes_as_string};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::sync::{Arc, RwLock};
+
+pub const DEFAULT_VOTING_PERIOD: u64 = 3 * 24 * 60 * 60;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Vote {
+	For,
+	Against,
+	Abstain,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProposalState {
+	Active,    // Voting is still open
+	Succeeded, // Reached the quorum and the approval threshold, waiting for execution
+	Defeated,  // Voting ended without reaching the quorum or the approval threshold
+	Executed,
+	Cancelled,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Tally {
+	pub for_votes: Uint,
+	pub against_votes: Uint,
+	pub abstain_votes: Uint,
+}
+
+impl Tally {
+	pub fn turnout(&self) -> Uint {
+		self.for_votes + self.against_votes + self.abstain_votes
+	}
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Proposal {
+	pub id: u64,
+	pub proposer: Address,
+	pub description: String,
+	pub operations: Vec<Operation>, // Executed by the governance, in order, once the proposal succeeds
+	#[serde(
+		serialize_with = "serialize_bytes_as_string",
+		deserialize_with = "deserialize_string_of_bytes"
+	)]
+	pub data: Vec<u8>, // App-defined action, handled by the app after the execution
+	pub created_at: u64,
+	pub deadline: u64,
+	pub snapshot_supply: Uint, // Token supply held by the accounts at proposal time
+	pub tally: Tally,
+	pub executed: bool,
+	pub cancelled: bool,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum GovernanceInput {
+	Propose {
+		description: String,
+		#[serde(default)]
+		operations: Vec<Operation>,
+		#[serde(default, deserialize_with = "deserialize_string_of_bytes")]
+		data: Vec<u8>,
+	},
+	Vote {
+		id: u64,
+		vote: Vote,
+	},
+	Execute(u64),
+	Cancel(u64),
+}
+
+#[derive(Deserialize)]
+struct Envelope {
+	governance: GovernanceInput,
+}
+
+#[derive(Default)]
+struct State {
+	next_id: u64,
+	proposals: BTreeMap<u64, Proposal>,
+	snapshots: HashMap<u64, HashMap<Address, Uint>>,
+	votes: HashMap<u64, HashMap<Address, Vote>>,
+}
+
+/// Token-weighted governance, with the voting power of each account snapshotted from its ERC20 wallet balance of
+/// the governance token when the proposal is created.
+#[derive(Clone)]
+pub struct Governance {
+	token: Address,
+	voting_period: u64,
+	quorum_bps: u64,
+	threshold_bps: u64,
+	proposal_threshold: Uint,
+	state: Arc<RwLock<State>>,
+}
+
+impl Governance {
+	pub fn new(token: Address) -> Self {
+		Self {
+			token,
+			voting_period: DEFAULT_VOTING_PERIOD,
+			quorum_bps: 0,
+			threshold_bps: math::BASIS_POINTS / 2,
+			proposal_threshold: Uint::zero(),
+			state: Arc::default(),
+		}
+	}
+
+	/// Seconds after the proposal creation during which the votes are accepted.
+	pub fn voting_period(mut self, voting_period: u64) -> Self {
+		self.voting_period = voting_period;
+		self
+	}
+
+	/// Minimum turnout (including abstentions), in basis points of the snapshotted supply.
+	pub fn quorum_bps(mut self, quorum_bps: u64) -> Self {
+		self.quorum_bps = quorum_bps;
+		self
+	}
+
+	/// Share of the `for` and `against` votes the `for` votes must exceed, in basis points (a simple majority by default).
+	pub fn threshold_bps(mut self, threshold_bps: u64) -> Self {
+		self.threshold_bps = threshold_bps;
+		self
+	}
+
+	/// Minimum balance required to create a proposal.
+	pub fn proposal_threshold(mut self, proposal_threshold: Uint) -> Self {
+		self.proposal_threshold = proposal_threshold;
+		self
+	}
+
+	pub fn token(&self) -> Address {
+		self.token
+	}
+
+	pub fn get(&self, id: u64) -> Option<Proposal> {
+		self.state
+			.read()
+			.expect("Governance lock poisoned")
+			.proposals
+			.get(&id)
+			.cloned()
+	}
+
+	pub fn proposals(&self) -> Vec<Proposal> {
+		let state = self.state.read().expect("Governance lock poisoned");
+		state.proposals.values().cloned().collect()
+	}
+
+	pub fn voting_power(&self, id: u64, account: Address) -> Uint {
+		let state = self.state.read().expect("Governance lock poisoned");
+		state
+			.snapshots
+			.get(&id)
+			.and_then(|snapshot| snapshot.get(&account))
+			.cloned()
+			.unwrap_or_default()
+	}
+
+	pub fn vote_of(&self, id: u64, account: Address) -> Option<Vote> {
+		let state = self.state.read().expect("Governance lock poisoned");
+		state.votes.get(&id)?.get(&account).cloned()
+	}
+
+	pub fn state_of(&self, id: u64, now: u64) -> Option<ProposalState> {
+		self.get(id).map(|proposal| self.evaluate(&proposal, now))
+	}
+
+	fn evaluate(&self, proposal: &Proposal, now: u64) -> ProposalState {
+		if proposal.cancelled {
+			return ProposalState::Cancelled;
+		}
+		if proposal.executed {
+			return ProposalState::Executed;
+		}
+		if now <= proposal.deadline {
+			return ProposalState::Active;
+		}
+
+		let tally = &proposal.tally;
+		let quorum = math::bps_of_up(proposal.snapshot_supply, self.quorum_bps).unwrap_or(Uint::MAX);
+		let threshold = math::bps_of(tally.for_votes + tally.against_votes, self.threshold_bps).unwrap_or(Uint::MAX);
+		match tally.turnout() >= quorum && tally.for_votes > threshold {
+			true => ProposalState::Succeeded,
+			false => ProposalState::Defeated,
+		}
+	}
+
+	/// Creates a proposal open for votes until `now` plus the voting period, snapshotting the token balances.
+	pub async fn propose(
+		&self,
+		env: &impl Environment,
+		proposer: Address,
+		description: impl Into<String>,
+		operations: Vec<Operation>,
+		data: Vec<u8>,
+		now: u64,
+	) -> Result<u64, Box<dyn Error>> {
+		let mut snapshot = HashMap::new();
+		for account in env.erc20_addresses().await {
+			let balance = env.erc20_balance(account, self.token).await;
+			if !balance.is_zero() {
+				snapshot.insert(account, balance);
+			}
+		}
+		let power = snapshot.get(&proposer).cloned().unwrap_or_default();
+		if power.is_zero() || power < self.proposal_threshold {
+			return Err(ErrorReport::unauthorized(format!(
+				"{:?} doesn't hold enough tokens to create a proposal",
+				proposer
+			))
+			.into());
+		}
+
+		let mut state = self.state.write().expect("Governance lock poisoned");
+		let id = state.next_id;
+		state.next_id += 1;
+		state.proposals.insert(
+			id,
+			Proposal {
+				id,
+				proposer,
+				description: description.into(),
+				operations,
+				data,
+				created_at: now,
+				deadline: now.saturating_add(self.voting_period),
+				snapshot_supply: snapshot.values().fold(Uint::zero(), |total, balance| total + balance),
+				tally: Tally::default(),
+				executed: false,
+				cancelled: false,
+			},
+		);
+		state.snapshots.insert(id, snapshot);
+		debug!("Proposal {} created by {:?}", id, proposer);
+		Ok(id)
+	}
+
+	/// Casts the vote with the voter balance at proposal time, returning that voting power.
+	pub fn vote(&self, voter: Address, id: u64, vote: Vote, now: u64) -> Result<Uint, Box<dyn Error>> {
+		let mut guard = self.state.write().expect("Governance lock poisoned");
+		let state = &mut *guard;
+		let proposal = state
+			.proposals
+			.get_mut(&id)
+			.ok_or_else(|| ErrorReport::not_found(format!("proposal {} not found", id)))?;
+		if self.evaluate(proposal, now) != ProposalState::Active {
+			return Err(ErrorReport::invalid_payload(format!("proposal {} is not open for votes", id)).into());
+		}
+		let power = state
+			.snapshots
+			.get(&id)
+			.and_then(|snapshot| snapshot.get(&voter))
+			.cloned()
+			.unwrap_or_default();
+		if power.is_zero() {
+			return Err(
+				ErrorReport::unauthorized(format!("{:?} has no voting power on proposal {}", voter, id)).into(),
+			);
+		}
+		let votes = state.votes.entry(id).or_default();
+		if votes.contains_key(&voter) {
+			return Err(ErrorReport::invalid_payload(format!("{:?} already voted on proposal {}", voter, id)).into());
+		}
+		votes.insert(voter, vote);
+
+		match vote {
+			Vote::For => proposal.tally.for_votes += power,
+			Vote::Against => proposal.tally.against_votes += power,
+			Vote::Abstain => proposal.tally.abstain_votes += power,
+		}
+		Ok(power)
+	}
+
+	/// Executes the operations of a succeeded proposal, returning it so the app can apply its `data`.
+	pub async fn execute(&self, env: &impl Environment, id: u64, now: u64) -> Result<Proposal, Box<dyn Error>> {
+		let proposal = self
+			.get(id)
+			.ok_or_else(|| ErrorReport::not_found(format!("proposal {} not found", id)))?;
+		let state = self.evaluate(&proposal, now);
+		if state != ProposalState::Succeeded {
+			return Err(ErrorReport::invalid_payload(format!("proposal {} can't be executed: {:?}", id, state)).into());
+		}
+
+		for operation in &proposal.operations {
+			operation.execute(env).await?;
+		}
+		let mut state = self.state.write().expect("Governance lock poisoned");
+		let proposal = state.proposals.get_mut(&id).expect("proposal exists");
+		proposal.executed = true;
+		debug!("Proposal {} executed", id);
+		Ok(proposal.clone())
+	}
+
+	/// Cancels an active proposal, only allowed to its proposer.
+	pub fn cancel(&self, caller: Address, id: u64, now: u64) -> Result<(), Box<dyn Error>> {
+		let mut state = self.state.write().expect("Governance lock poisoned");
+		let proposal = state
+			.proposals
+			.get_mut(&id)
+			.ok_or_else(|| ErrorReport::not_found(format!("proposal {} not found", id)))?;
+		if proposal.proposer != caller {
+			return Err(ErrorReport::unauthorized(format!("{:?} is not the proposer of {}", caller, id)).into());
+		}
+		if self.evaluate(proposal, now) != ProposalState::Active {
+			return Err(ErrorReport::invalid_payload(format!("proposal {} is not active", id)).into());
+		}
+		proposal.cancelled = true;
+		Ok(())
+	}
+
+	/// Applies a `{"governance": ...}` input, returning `None` for any other payload. The result holds the proposal
+	/// executed by the input, if any, as the execution hook of the app.
+	pub async fn handle_input(
+		&self,
+		env: &impl Environment,
+		metadata: &Metadata,
+		payload: &[u8],
+	) -> Option<Result<Option<Proposal>, Box<dyn Error>>> {
+		let envelope = serde_json::from_slice::<Envelope>(payload).ok()?;
+		let (sender, now) = (metadata.sender, metadata.timestamp);
+		Some(match envelope.governance {
+			GovernanceInput::Propose {
+				description,
+				operations,
+				data,
+			} => self
+				.propose(env, sender, description, operations, data, now)
+				.await
+				.map(|_| None),
+			GovernanceInput::Vote { id, vote } => self.vote(sender, id, vote, now).map(|_| None),
+			GovernanceInput::Execute(id) => self.execute(env, id, now).await.map(Some),
+			GovernanceInput::Cancel(id) => self.cancel(sender, id, now).map(|_| None),
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::core::application::Application;
+	use crate::core::testing::{MockupOptions, Tester};
+	use crate::types::machine::{Deposit, FinishStatus};
+	use crate::types::testing::ResultUtils;
+
+	const TOKEN: Address = Address::repeat_byte(0x20);
+	const ALICE: Address = Address::repeat_byte(0xa1);
+	const BOB: Address = Address::repeat_byte(0xb0);
+	const CAROL: Address = Address::repeat_byte(0xca);
+
+	struct Dao {
+		governance: Governance,
+	}
+
+	impl Application for Dao {
+		type Error = Box<dyn Error>;
+
+		async fn advance(
+			&self,
+			env: &impl Environment,
+			metadata: Metadata,
+			payload: &[u8],
+			_deposit: Option<Deposit>,
+		) -> Result<FinishStatus, Box<dyn Error>> {
+			if let Some(result) = self.governance.handle_input(env, &metadata, payload).await {
+				if let Some(proposal) = result? {
+					env.send_notice(proposal.data).await?;
+				}
+			}
+			Ok(FinishStatus::Accept)
+		}
+	}
+
+	async fn tester(governance: Governance) -> Tester<Dao> {
+		let tester = Tester::new(Dao { governance }, MockupOptions::default());
+		for (holder, amount) in [(ALICE, 60), (BOB, 30), (CAROL, 10)] {
+			tester
+				.deposit(Deposit::ERC20 {
+					sender: holder,
+					token: TOKEN,
+					amount: Uint::from(amount),
+				})
+				.await;
+		}
+		tester
+	}
+
+	#[async_std::test]
+	async fn test_snapshot_and_tally() {
+		let governance = Governance::new(TOKEN).voting_period(100);
+		let tester = tester(governance.clone()).await;
+		let env = tester.env();
+
+		let id = governance
+			.propose(env.as_ref(), BOB, "raise fees", vec![], vec![], 1000)
+			.await
+			.unwrap();
+		let proposal = governance.get(id).unwrap();
+		assert_eq!(proposal.snapshot_supply, Uint::from(100));
+		assert_eq!(proposal.deadline, 1100);
+
+		// Balances moved after the proposal don't change the voting power
+		tester
+			.erc20_transfer(ALICE, CAROL, TOKEN, Uint::from(60))
+			.await
+			.unwrap();
+		assert_eq!(governance.voting_power(id, CAROL), Uint::from(10));
+		assert_eq!(governance.vote(CAROL, id, Vote::For, 1010).unwrap(), Uint::from(10));
+		assert!(governance.vote(CAROL, id, Vote::Against, 1010).is_err());
+		assert!(governance
+			.vote(Address::repeat_byte(0xdd), id, Vote::For, 1010)
+			.is_err());
+
+		governance.vote(ALICE, id, Vote::Against, 1020).unwrap();
+		governance.vote(BOB, id, Vote::Abstain, 1020).unwrap();
+		assert!(governance.vote(BOB, id, Vote::For, 1101).is_err());
+
+		let tally = governance.get(id).unwrap().tally;
+		assert_eq!(tally.for_votes, Uint::from(10));
+		assert_eq!(tally.against_votes, Uint::from(60));
+		assert_eq!(tally.abstain_votes, Uint::from(30));
+		assert_eq!(governance.vote_of(id, BOB), Some(Vote::Abstain));
+		assert_eq!(governance.state_of(id, 1100), Some(ProposalState::Active));
+		assert_eq!(governance.state_of(id, 1101), Some(ProposalState::Defeated));
+		assert!(governance.execute(env.as_ref(), id, 1101).await.is_err());
+	}
+
+	#[async_std::test]
+	async fn test_quorum_and_threshold() {
+		let governance = Governance::new(TOKEN)
+			.voting_period(10)
+			.quorum_bps(5000)
+			.threshold_bps(6000)
+			.proposal_threshold(Uint::from(20));
+		let tester = tester(governance.clone()).await;
+		let env = tester.env();
+
+		assert!(governance
+			.propose(env.as_ref(), CAROL, "", vec![], vec![], 0)
+			.await
+			.is_err());
+
+		let no_quorum = governance
+			.propose(env.as_ref(), BOB, "", vec![], vec![], 0)
+			.await
+			.unwrap();
+		governance.vote(BOB, no_quorum, Vote::For, 5).unwrap();
+		governance.vote(CAROL, no_quorum, Vote::For, 5).unwrap();
+		assert_eq!(governance.state_of(no_quorum, 11), Some(ProposalState::Defeated));
+
+		let no_threshold = governance
+			.propose(env.as_ref(), BOB, "", vec![], vec![], 0)
+			.await
+			.unwrap();
+		governance.vote(ALICE, no_threshold, Vote::For, 5).unwrap();
+		governance.vote(BOB, no_threshold, Vote::Against, 5).unwrap();
+		governance.vote(CAROL, no_threshold, Vote::Against, 5).unwrap();
+		assert_eq!(governance.state_of(no_threshold, 11), Some(ProposalState::Defeated)); // 60% isn't above 60%
+
+		let passed = governance
+			.propose(env.as_ref(), ALICE, "", vec![], vec![], 0)
+			.await
+			.unwrap();
+		governance.vote(ALICE, passed, Vote::For, 5).unwrap();
+		governance.vote(CAROL, passed, Vote::Abstain, 5).unwrap();
+		assert_eq!(governance.state_of(passed, 11), Some(ProposalState::Succeeded));
+
+		assert!(governance.cancel(BOB, passed, 5).is_err());
+		assert!(governance.cancel(ALICE, passed, 11).is_err());
+		governance.cancel(ALICE, passed, 5).unwrap();
+		assert_eq!(governance.state_of(passed, 11), Some(ProposalState::Cancelled));
+		assert!(governance.vote(BOB, passed, Vote::For, 6).is_err());
+	}
+
+	#[async_std::test]
+	async fn test_execution_hook() {
+		let governance = Governance::new(TOKEN).voting_period(10);
+		let tester = tester(governance.clone()).await;
+
+		let result = tester
+			.advance(
+				ALICE,
+				r#"{"governance":{"propose":{"description":"pay","data":"0x0102","operations":[{"erc20_withdraw":{"wallet":"0xa1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1","token":"0x2020202020202020202020202020202020202020","amount":"0x5"}}]}}}"#,
+			)
+			.await;
+		assert!(result.is_accepted());
+		let proposed = governance.proposals()[0].clone();
+		assert_eq!(proposed.data, vec![1, 2]);
+
+		// Proposed at the epoch, so the inputs (with the current time) are past the deadline
+		let id = governance
+			.propose(
+				tester.env().as_ref(),
+				ALICE,
+				proposed.description,
+				proposed.operations,
+				proposed.data,
+				0,
+			)
+			.await
+			.unwrap();
+		governance.vote(ALICE, id, Vote::For, 5).unwrap();
+
+		let result = tester
+			.advance(
+				BOB,
+				format!(r#"{{"governance":{{"vote":{{"id":{},"vote":"for"}}}}}}"#, id),
+			)
+			.await;
+		assert!(result.is_rejected());
+
+		let result = tester
+			.advance(CAROL, format!(r#"{{"governance":{{"execute":{}}}}}"#, id))
+			.await;
+		assert!(result.is_accepted());
+		assert_eq!(result.notices(), vec![vec![1, 2]]);
+		assert_eq!(result.vouchers().len(), 1);
+		assert_eq!(tester.erc20_balance(ALICE, TOKEN).await, Uint::from(55));
+
+		let result = tester
+			.advance(CAROL, format!(r#"{{"governance":{{"execute":{}}}}}"#, id))
+			.await;
+		assert!(result.is_rejected());
+	}
+}
@@ -0,0 +1,126 @@
+use crate::types::machine::{FinishStatus, Output, VoucherDedupPolicy};
+use crate::types::primitives::*;
+use std::collections::HashSet;
+use std::error::Error;
+
+type VoucherKey = (Address, Option<Uint>, Vec<u8>); // Value of the call vouchers, none for the delegate calls
+
+pub struct VoucherGuard {
+	policy: VoucherDedupPolicy,
+	emitted: HashSet<VoucherKey>,
+	pending: HashSet<VoucherKey>,
+}
+
+impl VoucherGuard {
+	pub fn new(policy: VoucherDedupPolicy) -> Self {
+		Self {
+			policy,
+			emitted: HashSet::new(),
+			pending: HashSet::new(),
+		}
+	}
+
+	pub fn policy(&self) -> VoucherDedupPolicy {
+		self.policy
+	}
+
+	/// Returns whether the voucher duplicates another one, failing for the duplicates refused by the policy. Only the
+	/// vouchers of the current input are refused, since repeating one of an earlier input can be legitimate, e.g. a
+	/// second withdrawal of the same amount.
+	pub fn check(&mut self, voucher: &Output) -> Result<bool, Box<dyn Error>> {
+		let (destination, key) = match voucher {
+			Output::Voucher {
+				destination,
+				value,
+				payload,
+			} => (*destination, (*destination, Some(*value), payload.clone())),
+			Output::DelegateCallVoucher { destination, payload } => (*destination, (*destination, None, payload.clone())),
+			_ => return Ok(false),
+		};
+		let duplicate = match self.policy {
+			VoucherDedupPolicy::Allow => return Ok(false),
+			VoucherDedupPolicy::Reject => self.pending.contains(&key),
+			_ => self.pending.contains(&key) || self.emitted.contains(&key),
+		};
+
+		if duplicate {
+			match self.policy {
+				VoucherDedupPolicy::Reject => {
+					return Err(format!("duplicate voucher to {:?} rejected", destination).into());
+				}
+				_ => warn!("Duplicate voucher to {:?} emitted", destination),
+			}
+		}
+
+		self.pending.insert(key);
+		Ok(duplicate)
+	}
+
+	pub fn finish(&mut self, status: FinishStatus) {
+		match status {
+			FinishStatus::Accept if self.policy != VoucherDedupPolicy::Reject => self.emitted.extend(self.pending.drain()),
+			_ => self.pending.clear(),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn voucher(value: Uint, payload: &[u8]) -> Output {
+		Output::Voucher {
+			destination: Address::zero(),
+			value,
+			payload: payload.to_vec(),
+		}
+	}
+
+	#[test]
+	fn test_allow_never_flags() {
+		let mut guard = VoucherGuard::new(VoucherDedupPolicy::Allow);
+		assert!(!guard.check(&voucher(Uint::zero(), b"payload")).unwrap());
+		guard.finish(FinishStatus::Accept);
+		assert!(!guard.check(&voucher(Uint::zero(), b"payload")).unwrap());
+	}
+
+	#[test]
+	fn test_warn_flags_duplicates_across_inputs() {
+		let mut guard = VoucherGuard::new(VoucherDedupPolicy::Warn);
+		assert!(!guard.check(&voucher(Uint::zero(), b"payload")).unwrap());
+		guard.finish(FinishStatus::Accept);
+		assert!(guard.check(&voucher(Uint::zero(), b"payload")).unwrap());
+		assert!(!guard.check(&voucher(Uint::zero(), b"other")).unwrap());
+		assert!(!guard.check(&voucher(Uint::one(), b"payload")).unwrap());
+	}
+
+	#[test]
+	fn test_reject_fails_on_duplicate() {
+		let mut guard = VoucherGuard::new(VoucherDedupPolicy::Reject);
+		guard.check(&voucher(Uint::zero(), b"payload")).unwrap();
+		assert!(guard.check(&voucher(Uint::zero(), b"payload")).is_err());
+
+		let delegate_call = Output::DelegateCallVoucher {
+			destination: Address::zero(),
+			payload: b"payload".to_vec(),
+		};
+		assert!(!guard.check(&delegate_call).unwrap());
+		assert!(guard.check(&delegate_call).is_err());
+	}
+
+	#[test]
+	fn test_reject_allows_repeats_of_earlier_inputs() {
+		let mut guard = VoucherGuard::new(VoucherDedupPolicy::Reject);
+		guard.check(&voucher(Uint::zero(), b"payload")).unwrap();
+		guard.finish(FinishStatus::Accept);
+		assert!(!guard.check(&voucher(Uint::zero(), b"payload")).unwrap());
+	}
+
+	#[test]
+	fn test_rejected_inputs_are_forgotten() {
+		let mut guard = VoucherGuard::new(VoucherDedupPolicy::Reject);
+		guard.check(&voucher(Uint::zero(), b"payload")).unwrap();
+		guard.finish(FinishStatus::Reject);
+		assert!(!guard.check(&voucher(Uint::zero(), b"payload")).unwrap());
+	}
+}
@@ -0,0 +1,300 @@
+#[cfg(feature = "rollup")]
+use super::environment::Environment;
+use super::environment::RollupInternalEnvironment;
+use crate::types::balance::TokenMetadata;
+use crate::types::error::CrabrollsError;
+use crate::types::primitives::*;
+#[cfg(feature = "rollup")]
+use crate::utils::hash::keccak256;
+use serde::{Deserialize, Serialize};
+
+pub const HANDOFF_EXPORT_PAYLOAD: &[u8] = b"crabrolls:handoff:export";
+#[cfg(feature = "rollup")]
+pub const HANDOFF_CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HandoffOptions {
+	pub admin: Address,
+	pub import: Option<HandoffImport>,
+}
+
+impl HandoffOptions {
+	pub fn new(admin: Address) -> Self {
+		Self { admin, import: None }
+	}
+
+	pub fn import(mut self, dump: Vec<u8>, hash: [u8; 32]) -> Self {
+		self.import = Some(HandoffImport { dump, hash });
+		self
+	}
+
+	pub fn is_export_request(&self, sender: Address, payload: &[u8]) -> bool {
+		sender == self.admin && payload == HANDOFF_EXPORT_PAYLOAD
+	}
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HandoffImport {
+	pub dump: Vec<u8>,
+	pub hash: [u8; 32],
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct WalletsSnapshot {
+	pub ether: Vec<(Address, Uint)>,
+	pub erc20: Vec<(Address, Address, Uint)>,
+	pub erc721: Vec<(Address, Address, Uint)>,
+	pub erc1155: Vec<(Address, Address, Uint, Uint)>,
+	#[serde(default)]
+	pub erc721_metadata: Vec<(Address, Uint, TokenMetadata)>,
+	#[serde(default)]
+	pub erc4626: Vec<(Address, Address, Uint)>,
+}
+
+impl WalletsSnapshot {
+	pub async fn capture(env: &impl RollupInternalEnvironment) -> Self {
+		// The balances of the wallets left out by the cargo features stay empty
+		let snapshot = Self {
+			ether: env.get_ether_wallet().read().await.balances(),
+			erc4626: env.get_erc4626_wallet().read().await.balances(),
+			..Default::default()
+		};
+		#[cfg(feature = "erc20")]
+		let snapshot = Self {
+			erc20: env.get_erc20_wallet().read().await.balances(),
+			..snapshot
+		};
+		#[cfg(feature = "erc721")]
+		let snapshot = Self {
+			erc721: env.get_erc721_wallet().read().await.tokens(),
+			erc721_metadata: env.get_erc721_wallet().read().await.all_metadata(),
+			..snapshot
+		};
+		#[cfg(feature = "erc1155")]
+		let snapshot = Self {
+			erc1155: env.get_erc1155_wallet().read().await.balances(),
+			..snapshot
+		};
+		snapshot
+	}
+
+	pub async fn restore(&self, env: &impl RollupInternalEnvironment) -> Result<(), CrabrollsError> {
+		for (feature, missing) in [
+			("erc20", cfg!(not(feature = "erc20")) && !self.erc20.is_empty()),
+			(
+				"erc721",
+				cfg!(not(feature = "erc721")) && !(self.erc721.is_empty() && self.erc721_metadata.is_empty()),
+			),
+			("erc1155", cfg!(not(feature = "erc1155")) && !self.erc1155.is_empty()),
+		] {
+			if missing {
+				return Err(CrabrollsError::other(format!(
+					"the hand-off dump has {} balances but the feature isn't enabled",
+					feature
+				)));
+			}
+		}
+
+		let ether_wallet = env.get_ether_wallet();
+		#[cfg(feature = "erc20")]
+		let erc20_wallet = env.get_erc20_wallet();
+		#[cfg(feature = "erc721")]
+		let erc721_wallet = env.get_erc721_wallet();
+		#[cfg(feature = "erc1155")]
+		let erc1155_wallet = env.get_erc1155_wallet();
+		let erc4626_wallet = env.get_erc4626_wallet();
+
+		let mut ether_wallet = ether_wallet.write().await;
+		#[cfg(feature = "erc20")]
+		let mut erc20_wallet = erc20_wallet.write().await;
+		#[cfg(feature = "erc721")]
+		let mut erc721_wallet = erc721_wallet.write().await;
+		#[cfg(feature = "erc1155")]
+		let mut erc1155_wallet = erc1155_wallet.write().await;
+		let mut erc4626_wallet = erc4626_wallet.write().await;
+
+		let empty = ether_wallet.addresses().is_empty() && erc4626_wallet.addresses().is_empty();
+		#[cfg(feature = "erc20")]
+		let empty = empty && erc20_wallet.addresses().is_empty();
+		#[cfg(feature = "erc721")]
+		let empty = empty && erc721_wallet.addresses().is_empty();
+		#[cfg(feature = "erc1155")]
+		let empty = empty && erc1155_wallet.addresses().is_empty();
+		if !empty {
+			return Err(CrabrollsError::wallet("can't import a hand-off dump into non-empty wallets"));
+		}
+
+		ether_wallet.reserve(self.ether.len());
+		erc4626_wallet.reserve(self.erc4626.len());
+		for (address, value) in &self.ether {
+			ether_wallet.credit(*address, *value)?;
+		}
+		#[cfg(feature = "erc20")]
+		{
+			erc20_wallet.reserve(self.erc20.len());
+			for (wallet, token, value) in &self.erc20 {
+				erc20_wallet.set_balance(*wallet, *token, *value);
+			}
+		}
+		#[cfg(feature = "erc721")]
+		{
+			for (owner, token, id) in &self.erc721 {
+				erc721_wallet.add_token(*owner, *token, *id);
+			}
+			for (token, id, metadata) in &self.erc721_metadata {
+				erc721_wallet.set_metadata(*token, *id, metadata.clone())?;
+			}
+		}
+		#[cfg(feature = "erc1155")]
+		{
+			erc1155_wallet.reserve(self.erc1155.len());
+			for (owner, token, id, amount) in &self.erc1155 {
+				erc1155_wallet.set_balance(*owner, *token, *id, *amount);
+			}
+		}
+		for (wallet, vault, shares) in &self.erc4626 {
+			erc4626_wallet.set_balance(*wallet, *vault, *shares);
+		}
+		Ok(())
+	}
+
+	pub fn encode(&self) -> Result<Vec<u8>, CrabrollsError> {
+		Ok(serde_json::to_vec(self)?)
+	}
+
+	pub fn decode(dump: &[u8]) -> Result<Self, CrabrollsError> {
+		Ok(serde_json::from_slice(dump)?)
+	}
+}
+
+#[cfg(feature = "rollup")]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct HandoffManifest {
+	pub hash: String,
+	pub size: usize,
+	pub chunks: usize,
+}
+
+#[cfg(feature = "rollup")]
+pub async fn export(env: &impl Environment) -> Result<[u8; 32], CrabrollsError> {
+	let dump = WalletsSnapshot::capture(env).await.encode()?;
+	let hash = keccak256(&dump);
+
+	let chunks: Vec<&[u8]> = dump.chunks(HANDOFF_CHUNK_SIZE).collect();
+	for chunk in &chunks {
+		env.send_report(chunk).await?;
+	}
+
+	let manifest = HandoffManifest {
+		hash: format!("0x{}", hex::encode(hash)),
+		size: dump.len(),
+		chunks: chunks.len(),
+	};
+	env.send_notice(serde_json::to_vec(&manifest)?).await?;
+
+	info!("Exported wallets hand-off dump with hash {}", manifest.hash);
+	Ok(hash)
+}
+
+#[cfg(feature = "rollup")]
+pub async fn import(env: &impl RollupInternalEnvironment, dump: &[u8], hash: [u8; 32]) -> Result<(), CrabrollsError> {
+	if keccak256(dump) != hash {
+		return Err(CrabrollsError::other("hand-off dump doesn't match the expected hash"));
+	}
+
+	WalletsSnapshot::decode(dump)?.restore(env).await?;
+	info!("Imported wallets hand-off dump with hash 0x{}", hex::encode(hash));
+	Ok(())
+}
+
+#[cfg(all(test, feature = "rollup", feature = "erc20", feature = "erc721"))]
+mod tests {
+	use super::*;
+	use crate::core::contracts::{erc20::ERC20Environment, erc721::ERC721Environment, ether::EtherEnvironment};
+	use crate::core::testing::RollupMockup;
+	use crate::types::machine::{FinishStatus, Output};
+	use crate::{address, uint};
+
+	async fn funded_mockup() -> RollupMockup {
+		let env = RollupMockup::new();
+		let alice = address!("0x0000000000000000000000000000000000000001");
+		let token = address!("0x0000000000000000000000000000000000000002");
+		env.get_ether_wallet().write().await.set_balance(alice, uint!(100));
+		env.get_erc20_wallet()
+			.write()
+			.await
+			.set_balance(alice, token, uint!(200));
+		env.get_erc721_wallet().write().await.add_token(alice, token, uint!(3));
+		env.erc721_set_metadata(token, uint!(3), TokenMetadata::Uri("ipfs://3".to_string()))
+			.await
+			.unwrap();
+		env
+	}
+
+	#[async_std::test]
+	async fn test_export_import_round_trip() {
+		let old = funded_mockup().await;
+		let hash = export(&old).await.expect("export failed");
+		let outputs = old.advance(FinishStatus::Accept).await.unwrap().unwrap();
+
+		let dump: Vec<u8> = outputs
+			.iter()
+			.filter_map(|output| match output {
+				Output::Report { payload } => Some(payload.clone()),
+				_ => None,
+			})
+			.flatten()
+			.collect();
+		let manifest: HandoffManifest = match outputs.last() {
+			Some(Output::Notice { payload }) => serde_json::from_slice(payload).unwrap(),
+			_ => panic!("expected a manifest notice"),
+		};
+		assert_eq!(manifest.hash, format!("0x{}", hex::encode(hash)));
+		assert_eq!(manifest.size, dump.len());
+
+		let new = RollupMockup::new();
+		import(&new, &dump, hash).await.expect("import failed");
+
+		let alice = address!("0x0000000000000000000000000000000000000001");
+		let token = address!("0x0000000000000000000000000000000000000002");
+		assert_eq!(new.ether_balance(alice).await, uint!(100));
+		assert_eq!(new.erc20_balance(alice, token).await, uint!(200));
+		assert_eq!(new.erc721_owner_of(token, uint!(3)).await, Some(alice));
+		assert_eq!(
+			new.erc721_metadata_of(token, uint!(3)).await,
+			Some(TokenMetadata::Uri("ipfs://3".to_string()))
+		);
+	}
+
+	#[async_std::test]
+	async fn test_import_rejects_tampered_dump() {
+		let old = funded_mockup().await;
+		let dump = WalletsSnapshot::capture(&old).await.encode().unwrap();
+		let hash = keccak256(&dump);
+
+		let mut tampered = dump.clone();
+		tampered[10] ^= 1;
+
+		let new = RollupMockup::new();
+		assert!(import(&new, &tampered, hash).await.is_err());
+		assert!(new.ether_addresses().await.is_empty());
+	}
+
+	#[async_std::test]
+	async fn test_import_requires_empty_wallets() {
+		let old = funded_mockup().await;
+		let dump = WalletsSnapshot::capture(&old).await.encode().unwrap();
+		let hash = keccak256(&dump);
+
+		assert!(import(&old, &dump, hash).await.is_err());
+	}
+
+	#[test]
+	fn test_export_request_detection() {
+		let admin = address!("0x0000000000000000000000000000000000000009");
+		let options = HandoffOptions::new(admin);
+		assert!(options.is_export_request(admin, HANDOFF_EXPORT_PAYLOAD));
+		assert!(!options.is_export_request(Address::zero(), HANDOFF_EXPORT_PAYLOAD));
+		assert!(!options.is_export_request(admin, b"other"));
+	}
+}
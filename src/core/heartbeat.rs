@@ -0,0 +1,102 @@
+use super::environment::Environment;
+use super::handoff::WalletsSnapshot;
+use crate::types::machine::FinishStatus;
+use crate::utils::hash::keccak256;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct HeartbeatNotice {
+	pub input_index: u64,
+	pub accepted: u64,
+	pub rejected: u64,
+	pub wallets_hash: String,
+}
+
+pub struct Heartbeat {
+	interval: u64,
+	accepted: u64,
+	rejected: u64,
+	since_last: u64,
+}
+
+impl Heartbeat {
+	pub fn new(interval: u64) -> Self {
+		Self {
+			interval: interval.max(1),
+			accepted: 0,
+			rejected: 0,
+			since_last: 0,
+		}
+	}
+
+	pub fn record(&mut self, status: FinishStatus) -> bool {
+		match status {
+			FinishStatus::Accept => self.accepted += 1,
+			FinishStatus::Reject => self.rejected += 1,
+		}
+		self.since_last += 1;
+
+		// Notices of rejected inputs are discarded, so a due heartbeat waits for the next accepted input.
+		self.since_last >= self.interval && status == FinishStatus::Accept
+	}
+
+	pub async fn emit(&mut self, env: &impl Environment, input_index: u64) -> Result<HeartbeatNotice, Box<dyn Error>> {
+		let wallets = WalletsSnapshot::capture(env).await.encode()?;
+		let notice = HeartbeatNotice {
+			input_index,
+			accepted: self.accepted,
+			rejected: self.rejected,
+			wallets_hash: format!("0x{}", hex::encode(keccak256(wallets))),
+		};
+
+		env.send_notice(serde_json::to_vec(&serde_json::json!({ "heartbeat": notice }))?)
+			.await?;
+		self.since_last = 0;
+		Ok(notice)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::core::environment::RollupInternalEnvironment;
+	use crate::core::testing::RollupMockup;
+	use crate::types::machine::Output;
+	use crate::types::primitives::*;
+	use crate::uint;
+
+	#[test]
+	fn test_heartbeat_interval() {
+		let mut heartbeat = Heartbeat::new(2);
+		assert!(!heartbeat.record(FinishStatus::Accept));
+		assert!(!heartbeat.record(FinishStatus::Reject));
+		assert!(heartbeat.record(FinishStatus::Accept));
+	}
+
+	#[async_std::test]
+	async fn test_heartbeat_notice() {
+		let env = RollupMockup::new();
+		let mut heartbeat = Heartbeat::new(1);
+		heartbeat.record(FinishStatus::Accept);
+		let empty = heartbeat.emit(&env, 0).await.unwrap();
+		assert_eq!(empty.accepted, 1);
+		assert_eq!(empty.rejected, 0);
+
+		let outputs = env.advance(FinishStatus::Accept).await.unwrap().unwrap();
+		let payload: serde_json::Value = match &outputs[..] {
+			[Output::Notice { payload }] => serde_json::from_slice(payload).unwrap(),
+			_ => panic!("expected a single heartbeat notice"),
+		};
+		assert_eq!(payload["heartbeat"]["input_index"], 0);
+		assert_eq!(payload["heartbeat"]["wallets_hash"], empty.wallets_hash);
+
+		env.get_ether_wallet()
+			.write()
+			.await
+			.set_balance(Address::zero(), uint!(1));
+		heartbeat.record(FinishStatus::Accept);
+		let funded = heartbeat.emit(&env, 1).await.unwrap();
+		assert_ne!(funded.wallets_hash, empty.wallets_hash);
+	}
+}
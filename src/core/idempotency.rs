@@ -0,0 +1,239 @@
+use super::environment::Environment;
+use crate::types::error::CrabrollsError;
+use crate::types::machine::{FinishStatus, Metadata};
+use crate::types::primitives::*;
+use crate::types::report::ErrorReport;
+use crate::utils::hash::keccak256;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, RwLock};
+
+pub const IDEMPOTENCY_HEADER: &[u8] = b"Idempotency-Key:";
+pub const IDEMPOTENCY_FIELD: &str = "idempotency_key";
+
+#[derive(Debug, Default)]
+struct State {
+	capacity: usize,
+	processed: HashSet<(Address, String)>,
+	order: VecDeque<(Address, String)>, // Processed keys from the oldest, to forget them once over the capacity
+	pending: Option<(Address, String)>, // Key of the input being processed, recorded if it's accepted
+}
+
+/// Keys of the accepted advance inputs, per sender, so inputs resubmitted by clients after a timeout are accepted as
+/// a no-op with a `DUPLICATE` report instead of being processed twice.
+#[derive(Clone, Debug)]
+pub struct IdempotencyKeys {
+	state: Arc<RwLock<State>>,
+}
+
+impl IdempotencyKeys {
+	/// Remembers up to `capacity` keys, forgetting the oldest ones first.
+	pub fn new(capacity: usize) -> Self {
+		Self {
+			state: Arc::new(RwLock::new(State {
+				capacity: capacity.max(1),
+				..Default::default()
+			})),
+		}
+	}
+
+	/// Extracts the key of a payload, from an `Idempotency-Key: <key>` first line, which is stripped from the payload,
+	/// or from the `idempotency_key` string field of a JSON object, which is kept.
+	pub fn extract(payload: &[u8]) -> Option<(String, Vec<u8>)> {
+		if let Some(header) = payload.strip_prefix(IDEMPOTENCY_HEADER) {
+			let end = header.iter().position(|&byte| byte == b'\n').unwrap_or(header.len());
+			let key = std::str::from_utf8(&header[..end]).ok()?.trim();
+			let rest = header.get(end + 1..).unwrap_or_default();
+			return (!key.is_empty()).then(|| (key.to_string(), rest.to_vec()));
+		}
+
+		let value: serde_json::Value = serde_json::from_slice(payload).ok()?;
+		let key = value.get(IDEMPOTENCY_FIELD)?.as_str()?;
+		(!key.is_empty()).then(|| (key.to_string(), payload.to_vec()))
+	}
+
+	/// Returns the payload to process, or `None` after sending a `DUPLICATE` report if its key was already accepted.
+	pub async fn process(
+		&self,
+		env: &impl Environment,
+		metadata: &Metadata,
+		payload: Vec<u8>,
+	) -> Result<Option<Vec<u8>>, CrabrollsError> {
+		let Some((key, payload)) = Self::extract(&payload) else {
+			return Ok(Some(payload));
+		};
+
+		if self.contains(metadata.sender, &key) {
+			let message = format!("input with idempotency key {} was already processed", key);
+			report_duplicate(env, metadata, message).await?;
+			return Ok(None);
+		}
+
+		self.state.write().expect("Idempotency keys lock poisoned").pending = Some((metadata.sender, key));
+		Ok(Some(payload))
+	}
+
+	/// Records the key of the processed input if it was accepted.
+	pub fn finish(&self, status: FinishStatus) {
+		let mut state = self.state.write().expect("Idempotency keys lock poisoned");
+		let Some(pending) = state.pending.take() else {
+			return;
+		};
+		if status == FinishStatus::Reject || !state.processed.insert(pending.clone()) {
+			return;
+		}
+
+		state.order.push_back(pending);
+		if state.order.len() > state.capacity {
+			if let Some(oldest) = state.order.pop_front() {
+				state.processed.remove(&oldest);
+			}
+		}
+	}
+
+	pub fn contains(&self, sender: Address, key: &str) -> bool {
+		let state = self.state.read().expect("Idempotency keys lock poisoned");
+		state.processed.contains(&(sender, key.to_string()))
+	}
+
+	pub fn len(&self) -> usize {
+		self.state
+			.read()
+			.expect("Idempotency keys lock poisoned")
+			.processed
+			.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+}
+
+#[derive(Debug, Default)]
+struct WindowState {
+	window: u64,
+	seen: HashMap<[u8; 32], u64>, // Input index of the last accepted input with the hash
+	order: VecDeque<(u64, [u8; 32])>,
+	pending: Option<(u64, [u8; 32])>,
+}
+
+/// Hashes of the sender and payload of the last accepted advance inputs, so exact duplicates arriving within a window
+/// of inputs are accepted as a no-op with a `DUPLICATE` report, guarding against accidental double submissions.
+#[derive(Clone, Debug)]
+pub struct DedupWindow {
+	state: Arc<RwLock<WindowState>>,
+}
+
+impl DedupWindow {
+	/// Skips duplicates of the inputs accepted in the last `window` inputs.
+	pub fn new(window: u64) -> Self {
+		Self {
+			state: Arc::new(RwLock::new(WindowState {
+				window,
+				..Default::default()
+			})),
+		}
+	}
+
+	pub fn hash(sender: Address, payload: &[u8]) -> [u8; 32] {
+		keccak256([sender.as_bytes(), payload].concat())
+	}
+
+	pub fn is_duplicate(&self, metadata: &Metadata, payload: &[u8]) -> bool {
+		let state = self.state.read().expect("Dedup window lock poisoned");
+		state
+			.seen
+			.get(&Self::hash(metadata.sender, payload))
+			.is_some_and(|&input_index| metadata.input_index.saturating_sub(input_index) <= state.window)
+	}
+
+	/// Returns whether to process the input, sending a `DUPLICATE` report if it's a duplicate.
+	pub async fn process(
+		&self,
+		env: &impl Environment,
+		metadata: &Metadata,
+		payload: &[u8],
+	) -> Result<bool, CrabrollsError> {
+		if self.is_duplicate(metadata, payload) {
+			let message = "duplicate of a recently processed input".to_string();
+			report_duplicate(env, metadata, message).await?;
+			return Ok(false);
+		}
+
+		let hash = Self::hash(metadata.sender, payload);
+		self.state.write().expect("Dedup window lock poisoned").pending = Some((metadata.input_index, hash));
+		Ok(true)
+	}
+
+	/// Records the hash of the processed input if it was accepted, forgetting the ones out of the window.
+	pub fn finish(&self, status: FinishStatus) {
+		let mut state = self.state.write().expect("Dedup window lock poisoned");
+		let Some((input_index, hash)) = state.pending.take() else {
+			return;
+		};
+		if status == FinishStatus::Reject {
+			return;
+		}
+
+		state.seen.insert(hash, input_index);
+		state.order.push_back((input_index, hash));
+		while let Some(&(oldest, oldest_hash)) = state.order.front() {
+			if input_index.saturating_sub(oldest) <= state.window {
+				break;
+			}
+			state.order.pop_front();
+			if state.seen.get(&oldest_hash) == Some(&oldest) {
+				state.seen.remove(&oldest_hash);
+			}
+		}
+	}
+}
+
+async fn report_duplicate(env: &impl Environment, metadata: &Metadata, message: String) -> Result<(), CrabrollsError> {
+	debug!("Skipping the advance input: {}", message);
+	let report = ErrorReport::duplicate(message).input_index(metadata.input_index);
+	env.send_report(report.to_bytes()).await
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_extract_key() {
+		assert_eq!(
+			IdempotencyKeys::extract(b"Idempotency-Key: order-1\nbuy 10"),
+			Some(("order-1".to_string(), b"buy 10".to_vec()))
+		);
+		assert_eq!(
+			IdempotencyKeys::extract(b"Idempotency-Key:order-1"),
+			Some(("order-1".to_string(), vec![]))
+		);
+		let json = br#"{"idempotency_key": "order-2", "amount": 10}"#;
+		assert_eq!(
+			IdempotencyKeys::extract(json),
+			Some(("order-2".to_string(), json.to_vec()))
+		);
+
+		assert_eq!(IdempotencyKeys::extract(b"Idempotency-Key: \nbuy 10"), None);
+		assert_eq!(IdempotencyKeys::extract(br#"{"idempotency_key": 2}"#), None);
+		assert_eq!(IdempotencyKeys::extract(b"buy 10"), None);
+	}
+
+	#[test]
+	fn test_keys_capacity() {
+		let keys = IdempotencyKeys::new(2);
+		let alice = Address::repeat_byte(1);
+		for key in ["a", "b", "c"] {
+			keys.state.write().unwrap().pending = Some((alice, key.to_string()));
+			keys.finish(FinishStatus::Accept);
+		}
+		keys.state.write().unwrap().pending = Some((alice, "d".to_string()));
+		keys.finish(FinishStatus::Reject);
+
+		assert_eq!(keys.len(), 2);
+		assert!(!keys.contains(alice, "a"));
+		assert!(keys.contains(alice, "c"));
+		assert!(!keys.contains(alice, "d"));
+		assert!(!keys.contains(Address::zero(), "c"));
+	}
+}
@@ -0,0 +1,204 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::error::Error;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+/// System allocator keeping track of the current and peak heap usage. It only counts once installed by the app:
+///
+/// ```ignore
+/// #[global_allocator]
+/// static ALLOCATOR: TrackingAllocator = TrackingAllocator;
+/// ```
+pub struct TrackingAllocator;
+
+impl TrackingAllocator {
+	fn allocated(size: usize) {
+		let current = CURRENT.fetch_add(size, Ordering::Relaxed) + size;
+		PEAK.fetch_max(current, Ordering::Relaxed);
+	}
+
+	fn deallocated(size: usize) {
+		CURRENT.fetch_sub(size, Ordering::Relaxed);
+	}
+}
+
+unsafe impl GlobalAlloc for TrackingAllocator {
+	unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+		let ptr = System.alloc(layout);
+		if !ptr.is_null() {
+			Self::allocated(layout.size());
+		}
+		ptr
+	}
+
+	unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+		let ptr = System.alloc_zeroed(layout);
+		if !ptr.is_null() {
+			Self::allocated(layout.size());
+		}
+		ptr
+	}
+
+	unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+		System.dealloc(ptr, layout);
+		Self::deallocated(layout.size());
+	}
+
+	unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+		let new_ptr = System.realloc(ptr, layout, new_size);
+		if !new_ptr.is_null() {
+			Self::deallocated(layout.size());
+			Self::allocated(new_size);
+		}
+		new_ptr
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MemoryUsage {
+	pub current: usize, // Bytes currently allocated
+	pub peak: usize,    // Highest allocation since the last reset of the peak
+}
+
+/// Heap usage tracked by the [`TrackingAllocator`], all zeros if it isn't installed.
+pub fn usage() -> MemoryUsage {
+	MemoryUsage {
+		current: CURRENT.load(Ordering::Relaxed),
+		peak: PEAK.load(Ordering::Relaxed),
+	}
+}
+
+/// Starts a new peak measurement from the current usage, done before every input when a [`MemoryLimit`] is set.
+pub fn reset_peak() {
+	PEAK.store(CURRENT.load(Ordering::Relaxed), Ordering::Relaxed);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MemoryLimitAction {
+	Warn,   // Logs a warning and keeps the input status
+	Reject, // Rejects the input, reverting its outputs
+}
+
+/// Soft limit on the peak heap usage of an advance input, to catch a growing app before it hits the hard RAM limit
+/// of the Cartesi machine.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MemoryLimit {
+	pub soft_limit: usize, // In bytes
+	pub action: MemoryLimitAction,
+}
+
+impl MemoryLimit {
+	pub fn warn(soft_limit: usize) -> Self {
+		Self {
+			soft_limit,
+			action: MemoryLimitAction::Warn,
+		}
+	}
+
+	pub fn reject(soft_limit: usize) -> Self {
+		Self {
+			soft_limit,
+			action: MemoryLimitAction::Reject,
+		}
+	}
+
+	/// Checks the peak usage since the last reset, failing only if it's over the limit and the action is to reject.
+	pub fn check(&self) -> Result<MemoryUsage, Box<dyn Error>> {
+		let usage = usage();
+		debug!("Memory usage: {} bytes, peak of {} bytes", usage.current, usage.peak);
+		if usage.peak <= self.soft_limit {
+			return Ok(usage);
+		}
+
+		let message = format!(
+			"peak memory usage of {} bytes exceeds the soft limit of {} bytes",
+			usage.peak, self.soft_limit
+		);
+		match self.action {
+			MemoryLimitAction::Warn => {
+				warn!("{}", message);
+				Ok(usage)
+			}
+			MemoryLimitAction::Reject => Err(message.into()),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::core::application::Application;
+	use crate::core::environment::Environment;
+	use crate::core::testing::{MockupOptions, Tester};
+	use crate::types::machine::{Deposit, DepositRejectPolicy, FinishStatus, Metadata};
+	use crate::types::primitives::*;
+	use crate::types::testing::ResultUtils;
+
+	const MIB: usize = 1024 * 1024;
+
+	#[global_allocator]
+	static ALLOCATOR: TrackingAllocator = TrackingAllocator;
+
+	/// Allocates the number of MiB in the payload while handling the input, 16 for the deposits without payload.
+	struct Hungry;
+
+	impl Application for Hungry {
+		type Error = Box<dyn Error>;
+
+		async fn advance(
+			&self,
+			env: &impl Environment,
+			_metadata: Metadata,
+			payload: &[u8],
+			_deposit: Option<Deposit>,
+		) -> Result<FinishStatus, Box<dyn Error>> {
+			let buffer = vec![1u8; payload.first().copied().unwrap_or(16) as usize * MIB];
+			env.send_notice(&buffer[..1]).await?;
+			Ok(FinishStatus::Accept)
+		}
+	}
+
+	fn hungry(limit: MemoryLimit) -> Tester<Hungry> {
+		Tester::new(Hungry, MockupOptions::builder().memory_limit(limit).build())
+	}
+
+	// A single test, since the peak is shared by the whole test binary
+	#[async_std::test]
+	async fn test_memory_limits() {
+		let before = usage();
+		let buffer = vec![0u8; 4 * MIB];
+		drop(buffer);
+		assert!(usage().peak >= before.current + 4 * MIB);
+
+		let baseline = usage().current;
+		let tester = hungry(MemoryLimit::reject(baseline + 64 * MIB));
+		let result = tester.advance(Address::zero(), [1]).await;
+		assert!(result.is_accepted());
+		assert_eq!(result.notices().len(), 1);
+
+		let tester = hungry(MemoryLimit::reject(baseline + 8 * MIB));
+		let result = tester.advance(Address::zero(), [16]).await;
+		assert!(result.is_rejected());
+		assert!(result.notices().is_empty());
+		assert!(result.error.unwrap().to_string().contains("exceeds the soft limit"));
+
+		let tester = hungry(MemoryLimit::warn(baseline + 8 * MIB));
+		assert!(tester.advance(Address::zero(), [16]).await.is_accepted());
+
+		// The rejection goes through the deposit reject policy, rolling back the deposit
+		let options = MockupOptions::builder()
+			.memory_limit(MemoryLimit::reject(baseline + 8 * MIB))
+			.deposit_reject_policy(DepositRejectPolicy::Rollback)
+			.build();
+		let tester = Tester::new(Hungry, options);
+		let alice = Address::repeat_byte(0xa);
+		let deposit = Deposit::Ether {
+			sender: alice,
+			amount: Uint::from(5),
+		};
+		assert!(tester.deposit(deposit).await.is_rejected());
+		assert_eq!(tester.ether_balance(alice).await, Uint::zero());
+	}
+}
@@ -0,0 +1,259 @@
+use super::application::{validate_and_advance, Application};
+use super::environment::Environment;
+use crate::types::machine::{Deposit, FinishStatus, Metadata, PreProcess};
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+
+/// Step of the input processing shared by all the apps, e.g. authentication, logging, rate limiting or metrics. The
+/// `before_*` methods run in registration order and the `after_*` ones in reverse order, only for the middlewares
+/// the input reached. Deposits skip the middlewares, since their assets are credited anyway.
+pub trait Middleware: Send + Sync {
+	/// Passes on the payload, possibly modified and with annotations merged into the metadata, or finishes the input
+	/// with a status without calling the next middlewares and the app. An error rejects the input.
+	fn before_advance(&self, _metadata: &Metadata, payload: Vec<u8>) -> Result<PreProcess, Box<dyn Error>> {
+		Ok(PreProcess::Continue {
+			payload,
+			annotations: Default::default(),
+		})
+	}
+
+	/// Called with the status the advance input finished with, `Reject` if it failed with an error.
+	fn after_advance(&self, _metadata: &Metadata, _status: FinishStatus) {}
+
+	/// Like `before_advance` for inspect inputs, which have no metadata to annotate.
+	fn before_inspect(&self, payload: Vec<u8>) -> Result<PreProcess, Box<dyn Error>> {
+		Ok(PreProcess::Continue {
+			payload,
+			annotations: Default::default(),
+		})
+	}
+
+	fn after_inspect(&self, _payload: &[u8], _status: FinishStatus) {}
+}
+
+#[derive(Clone, Default)]
+pub struct Middlewares {
+	middlewares: Vec<Arc<dyn Middleware>>,
+}
+
+impl Middlewares {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn register(&mut self, middleware: impl Middleware + 'static) {
+		self.middlewares.push(Arc::new(middleware));
+	}
+
+	pub fn len(&self) -> usize {
+		self.middlewares.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.middlewares.is_empty()
+	}
+
+	/// Runs an advance input through the middlewares and `validate_and_advance`.
+	pub async fn advance(
+		&self,
+		app: &impl Application,
+		env: &impl Environment,
+		mut metadata: Metadata,
+		payload: &[u8],
+		deposit: Option<Deposit>,
+	) -> Result<FinishStatus, Box<dyn Error>> {
+		if deposit.is_some() || self.is_empty() {
+			return validate_and_advance(app, env, metadata, payload, deposit).await;
+		}
+
+		let (reached, before) = self.before(payload.to_vec(), |middleware, payload| {
+			let result = middleware.before_advance(&metadata, payload);
+			if let Ok(PreProcess::Continue { annotations, .. }) = &result {
+				metadata.annotations.extend(annotations.clone());
+			}
+			result
+		});
+		let result = match before {
+			Ok(PreProcess::Continue { payload, .. }) => {
+				validate_and_advance(app, env, metadata.clone(), &payload, None).await
+			}
+			Ok(PreProcess::Finish(status)) => Ok(status),
+			Err(e) => Err(e),
+		};
+
+		let status = result.as_ref().map_or(FinishStatus::Reject, |status| *status);
+		for middleware in self.middlewares[..reached].iter().rev() {
+			middleware.after_advance(&metadata, status);
+		}
+		result
+	}
+
+	/// Runs an inspect input through the middlewares and the `inspect` of the app.
+	pub async fn inspect(
+		&self,
+		app: &impl Application,
+		env: &impl Environment,
+		payload: &[u8],
+	) -> Result<FinishStatus, Box<dyn Error>> {
+		if self.is_empty() {
+			return app.inspect(env, payload).await.map_err(Into::into);
+		}
+
+		let (reached, before) = self.before(payload.to_vec(), |middleware, payload| {
+			middleware.before_inspect(payload)
+		});
+		let result = match before {
+			Ok(PreProcess::Continue { payload, .. }) => app.inspect(env, &payload).await.map_err(Into::into),
+			Ok(PreProcess::Finish(status)) => Ok(status),
+			Err(e) => Err(e),
+		};
+
+		let status = result.as_ref().map_or(FinishStatus::Reject, |status| *status);
+		for middleware in self.middlewares[..reached].iter().rev() {
+			middleware.after_inspect(payload, status);
+		}
+		result
+	}
+
+	/// Runs the `before` hook of the middlewares until one finishes the input, returning how many were reached.
+	fn before(
+		&self,
+		mut payload: Vec<u8>,
+		mut hook: impl FnMut(&dyn Middleware, Vec<u8>) -> Result<PreProcess, Box<dyn Error>>,
+	) -> (usize, Result<PreProcess, Box<dyn Error>>) {
+		for (index, middleware) in self.middlewares.iter().enumerate() {
+			match hook(middleware.as_ref(), payload) {
+				Ok(PreProcess::Continue { payload: next, .. }) => payload = next,
+				result => return (index + 1, result),
+			}
+		}
+		let result = Ok(PreProcess::Continue {
+			payload,
+			annotations: Default::default(),
+		});
+		(self.middlewares.len(), result)
+	}
+}
+
+impl fmt::Debug for Middlewares {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "Middlewares({})", self.middlewares.len())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::core::testing::{MockupOptions, Tester};
+	use crate::types::primitives::*;
+	use crate::types::testing::ResultUtils;
+	use std::sync::Mutex;
+
+	struct Echo;
+
+	impl Application for Echo {
+		type Error = Box<dyn Error>;
+
+		async fn advance(
+			&self,
+			env: &impl Environment,
+			metadata: Metadata,
+			payload: &[u8],
+			_deposit: Option<Deposit>,
+		) -> Result<FinishStatus, Box<dyn Error>> {
+			let user = metadata.annotations.get("user").cloned().unwrap_or_default();
+			env.send_notice([user.as_bytes(), payload].concat()).await?;
+			Ok(FinishStatus::Accept)
+		}
+
+		async fn inspect(&self, env: &impl Environment, payload: &[u8]) -> Result<FinishStatus, Box<dyn Error>> {
+			env.send_report(payload).await?;
+			Ok(FinishStatus::Accept)
+		}
+	}
+
+	/// Strips the `token:` prefix of the payloads, annotating the user, and rejects the inputs without it.
+	struct Auth;
+
+	impl Middleware for Auth {
+		fn before_advance(&self, _metadata: &Metadata, payload: Vec<u8>) -> Result<PreProcess, Box<dyn Error>> {
+			match payload.strip_prefix(b"token:") {
+				Some(payload) => Ok(PreProcess::Continue {
+					payload: payload.to_vec(),
+					annotations: [("user".to_string(), "alice:".to_string())].into(),
+				}),
+				None => Err("unauthenticated".into()),
+			}
+		}
+	}
+
+	/// Finishes the inputs after the first `limit` ones without calling the app.
+	struct RateLimit {
+		limit: usize,
+		seen: Mutex<usize>,
+	}
+
+	impl Middleware for RateLimit {
+		fn before_advance(&self, _metadata: &Metadata, payload: Vec<u8>) -> Result<PreProcess, Box<dyn Error>> {
+			self.before_inspect(payload)
+		}
+
+		fn before_inspect(&self, payload: Vec<u8>) -> Result<PreProcess, Box<dyn Error>> {
+			let mut seen = self.seen.lock().unwrap();
+			*seen += 1;
+			match *seen > self.limit {
+				true => Ok(PreProcess::Finish(FinishStatus::Reject)),
+				false => Ok(PreProcess::Continue {
+					payload,
+					annotations: Default::default(),
+				}),
+			}
+		}
+	}
+
+	#[derive(Clone, Default)]
+	struct Metrics(Arc<Mutex<Vec<(&'static str, FinishStatus)>>>);
+
+	impl Middleware for Metrics {
+		fn after_advance(&self, _metadata: &Metadata, status: FinishStatus) {
+			self.0.lock().unwrap().push(("advance", status));
+		}
+
+		fn after_inspect(&self, _payload: &[u8], status: FinishStatus) {
+			self.0.lock().unwrap().push(("inspect", status));
+		}
+	}
+
+	#[async_std::test]
+	async fn test_middleware_chain() {
+		let metrics = Metrics::default();
+		let options = MockupOptions::builder()
+			.middleware(metrics.clone())
+			.middleware(RateLimit {
+				limit: 3,
+				seen: Mutex::new(0),
+			})
+			.middleware(Auth)
+			.build();
+		let tester = Tester::new(Echo, options);
+
+		let result = tester.advance(Address::zero(), b"token:hello").await;
+		result.assert_accepted().assert_notice(b"alice:hello");
+		let result = tester.advance(Address::zero(), b"hello").await;
+		result.assert_rejected().assert_error_contains("unauthenticated");
+		tester.inspect(b"state").await.assert_accepted().assert_report(b"state");
+		let result = tester.advance(Address::zero(), b"token:again").await;
+		result.assert_rejected().assert_no_error().assert_output_count(0);
+
+		assert_eq!(
+			*metrics.0.lock().unwrap(),
+			vec![
+				("advance", FinishStatus::Accept),
+				("advance", FinishStatus::Reject),
+				("inspect", FinishStatus::Accept),
+				("advance", FinishStatus::Reject),
+			]
+		);
+	}
+}
@@ -1,5 +1,39 @@
+pub mod about;
+pub mod activity;
 pub mod application;
+pub mod approvals;
+#[cfg(feature = "rollup")]
+pub mod backend;
+pub mod cache;
+pub mod calls;
+pub mod commit_reveal;
+pub mod commitment;
+pub mod conformance;
 pub mod context;
 pub mod contracts;
+pub mod dynamic;
 pub mod environment;
+#[cfg(feature = "erc20")]
+pub mod faucet;
+#[cfg(feature = "erc20")]
+pub mod governance;
+pub mod guards;
+pub mod handoff;
+#[cfg(feature = "rollup")]
+pub mod heartbeat;
+pub mod idempotency;
+#[cfg(feature = "memory-tracking")]
+pub mod memory;
+pub mod middleware;
+#[cfg(feature = "orderbook")]
+pub mod orderbook;
+#[cfg(feature = "rollup")]
+pub mod playground;
+pub mod plugins;
+#[cfg(feature = "rollup")]
+pub mod retry;
+pub mod runtime;
+pub mod schedule;
+pub mod sessions;
+pub mod streams;
 pub mod testing;
@@ -0,0 +1,550 @@
+use super::environment::Environment;
+use crate::types::error::CrabrollsError;
+use crate::types::primitives::*;
+use crate::utils::math;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::{Arc, RwLock};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Side {
+	Buy,  // Bid, pays the quote token for the base token
+	Sell, // Ask, pays the base token for the quote token
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Order {
+	pub id: u64,
+	pub owner: Address,
+	pub side: Side,
+	pub price: Uint,  // Quote amount per `price_scale` units of the base token
+	pub amount: Uint, // Remaining base amount
+	pub locked: Uint, // Remaining escrowed amount, in quote for bids and in base for asks
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Fill {
+	pub maker_order: u64,
+	pub taker_order: u64,
+	pub maker: Address,
+	pub taker: Address,
+	pub price: Uint,  // Maker price
+	pub amount: Uint, // Base amount
+	pub quote_amount: Uint,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Placement {
+	pub id: u64,
+	pub fills: Vec<Fill>,
+	pub resting: Option<Order>, // Unfilled part left on the book
+}
+
+#[derive(Default)]
+struct Book {
+	next_id: u64,
+	orders: HashMap<u64, Order>,
+	bids: BTreeMap<Uint, VecDeque<u64>>,
+	asks: BTreeMap<Uint, VecDeque<u64>>,
+}
+
+impl Book {
+	fn levels(&mut self, side: Side) -> &mut BTreeMap<Uint, VecDeque<u64>> {
+		match side {
+			Side::Buy => &mut self.bids,
+			Side::Sell => &mut self.asks,
+		}
+	}
+
+	/// Best level of the opposite side crossing the taker price, if any.
+	fn best_crossing(&self, taker: Side, price: Uint) -> Option<Uint> {
+		match taker {
+			Side::Buy => self.asks.keys().next().filter(|ask| **ask <= price).cloned(),
+			Side::Sell => self.bids.keys().next_back().filter(|bid| **bid >= price).cloned(),
+		}
+	}
+}
+
+struct Transfer {
+	token: Address,
+	destination: Address,
+	amount: Uint,
+}
+
+/// Limit order book with price-time priority for a base/quote pair of ERC20 tokens, with the funds of the open orders
+/// held by the `escrow` account and every fill settled on the ERC20 wallet.
+#[derive(Clone)]
+pub struct OrderBook {
+	base: Address,
+	quote: Address,
+	escrow: Address,
+	price_scale: Uint,
+	book: Arc<RwLock<Book>>,
+}
+
+impl OrderBook {
+	pub fn new(base: Address, quote: Address, escrow: Address) -> Self {
+		Self {
+			base,
+			quote,
+			escrow,
+			price_scale: Uint::exp10(18),
+			book: Arc::default(),
+		}
+	}
+
+	/// Base amount the prices refer to, `10^18` (one token with 18 decimals) by default.
+	pub fn price_scale(mut self, price_scale: Uint) -> Self {
+		self.price_scale = price_scale;
+		self
+	}
+
+	pub fn escrow(&self) -> Address {
+		self.escrow
+	}
+
+	pub fn quote_amount(&self, amount: Uint, price: Uint) -> Result<Uint, CrabrollsError> {
+		Ok(math::mul_div(amount, price, self.price_scale)?)
+	}
+
+	pub fn get(&self, id: u64) -> Option<Order> {
+		self.book
+			.read()
+			.expect("Order book lock poisoned")
+			.orders
+			.get(&id)
+			.cloned()
+	}
+
+	pub fn orders_of(&self, owner: Address) -> Vec<Order> {
+		let book = self.book.read().expect("Order book lock poisoned");
+		let mut orders: Vec<Order> = book
+			.orders
+			.values()
+			.filter(|order| order.owner == owner)
+			.cloned()
+			.collect();
+		orders.sort_by_key(|order| order.id);
+		orders
+	}
+
+	pub fn best_bid(&self) -> Option<Uint> {
+		self.book
+			.read()
+			.expect("Order book lock poisoned")
+			.bids
+			.keys()
+			.next_back()
+			.cloned()
+	}
+
+	pub fn best_ask(&self) -> Option<Uint> {
+		self.book
+			.read()
+			.expect("Order book lock poisoned")
+			.asks
+			.keys()
+			.next()
+			.cloned()
+	}
+
+	/// Aggregated `(price, amount)` of the best `levels` of the side, best price first.
+	pub fn depth(&self, side: Side, levels: usize) -> Vec<(Uint, Uint)> {
+		let book = self.book.read().expect("Order book lock poisoned");
+		let aggregate = |(price, ids): (&Uint, &VecDeque<u64>)| {
+			let amount = ids
+				.iter()
+				.fold(Uint::zero(), |total, id| total + book.orders[id].amount);
+			(*price, amount)
+		};
+		match side {
+			Side::Buy => book.bids.iter().rev().take(levels).map(aggregate).collect(),
+			Side::Sell => book.asks.iter().take(levels).map(aggregate).collect(),
+		}
+	}
+
+	/// Escrows the order funds, matches it against the opposite side and rests the unfilled part on the book.
+	pub async fn place(
+		&self,
+		env: &impl Environment,
+		owner: Address,
+		side: Side,
+		price: Uint,
+		amount: Uint,
+	) -> Result<Placement, CrabrollsError> {
+		if price.is_zero() || amount.is_zero() {
+			return Err(CrabrollsError::other("order price and amount must be positive"));
+		}
+		let (token, locked) = match side {
+			Side::Buy => (self.quote, math::mul_div_up(amount, price, self.price_scale)?),
+			Side::Sell => (self.base, amount),
+		};
+		env.erc20_transfer(owner, self.escrow, token, locked).await?;
+
+		let (placement, transfers) = self.match_order(Order {
+			id: 0,
+			owner,
+			side,
+			price,
+			amount,
+			locked,
+		});
+		self.settle(env, transfers).await?;
+		Ok(placement)
+	}
+
+	/// Removes the open order of the owner, refunding its escrowed funds.
+	pub async fn cancel(&self, env: &impl Environment, owner: Address, id: u64) -> Result<Order, CrabrollsError> {
+		let order = {
+			let mut book = self.book.write().expect("Order book lock poisoned");
+			match book.orders.get(&id) {
+				Some(order) if order.owner == owner => {}
+				Some(_) => return Err(CrabrollsError::other(format!("{:?} is not the owner of order {}", owner, id))),
+				None => return Err(CrabrollsError::other(format!("order {} not found", id))),
+			}
+			let order = book.orders.remove(&id).expect("order exists");
+			let levels = book.levels(order.side);
+			if let Some(ids) = levels.get_mut(&order.price) {
+				ids.retain(|resting| *resting != id);
+				if ids.is_empty() {
+					levels.remove(&order.price);
+				}
+			}
+			order
+		};
+
+		let token = match order.side {
+			Side::Buy => self.quote,
+			Side::Sell => self.base,
+		};
+		env.erc20_transfer(self.escrow, owner, token, order.locked).await?;
+		Ok(order)
+	}
+
+	fn match_order(&self, mut taker: Order) -> (Placement, Vec<Transfer>) {
+		let mut guard = self.book.write().expect("Order book lock poisoned");
+		let book = &mut *guard;
+		taker.id = book.next_id;
+		book.next_id += 1;
+
+		let mut fills = Vec::new();
+		let mut transfers = Vec::new();
+		while !taker.amount.is_zero() {
+			let Some(level_price) = book.best_crossing(taker.side, taker.price) else {
+				break;
+			};
+			let levels = match taker.side {
+				Side::Buy => &mut book.asks,
+				Side::Sell => &mut book.bids,
+			};
+			let ids = levels.get_mut(&level_price).expect("level exists");
+			let maker_id = *ids.front().expect("levels are never empty");
+			let maker = book.orders.get_mut(&maker_id).expect("resting order exists");
+
+			let amount = taker.amount.min(maker.amount);
+			let quote_amount =
+				math::mul_div(amount, maker.price, self.price_scale).expect("fills never exceed the escrowed quote");
+			let (buyer, seller) = match taker.side {
+				Side::Buy => (taker.owner, maker.owner),
+				Side::Sell => (maker.owner, taker.owner),
+			};
+			transfers.push(Transfer {
+				token: self.base,
+				destination: buyer,
+				amount,
+			});
+			transfers.push(Transfer {
+				token: self.quote,
+				destination: seller,
+				amount: quote_amount,
+			});
+			let (taker_spent, maker_spent) = match taker.side {
+				Side::Buy => (quote_amount, amount),
+				Side::Sell => (amount, quote_amount),
+			};
+			taker.amount -= amount;
+			taker.locked -= taker_spent;
+			maker.amount -= amount;
+			maker.locked -= maker_spent;
+
+			fills.push(Fill {
+				maker_order: maker_id,
+				taker_order: taker.id,
+				maker: maker.owner,
+				taker: taker.owner,
+				price: maker.price,
+				amount,
+				quote_amount,
+			});
+
+			if maker.amount.is_zero() {
+				if !maker.locked.is_zero() {
+					// Rounding leftover of a bid filled below its escrow
+					transfers.push(Transfer {
+						token: self.quote,
+						destination: maker.owner,
+						amount: maker.locked,
+					});
+				}
+				book.orders.remove(&maker_id);
+				ids.pop_front();
+				if ids.is_empty() {
+					levels.remove(&level_price);
+				}
+			}
+		}
+
+		let resting = match taker.amount.is_zero() {
+			true => {
+				if !taker.locked.is_zero() {
+					// Price improvement (or rounding leftover) of a filled bid
+					transfers.push(Transfer {
+						token: self.quote,
+						destination: taker.owner,
+						amount: taker.locked,
+					});
+				}
+				None
+			}
+			false => {
+				book.levels(taker.side)
+					.entry(taker.price)
+					.or_default()
+					.push_back(taker.id);
+				book.orders.insert(taker.id, taker.clone());
+				Some(taker.clone())
+			}
+		};
+
+		(
+			Placement {
+				id: taker.id,
+				fills,
+				resting,
+			},
+			transfers,
+		)
+	}
+
+	async fn settle(&self, env: &impl Environment, transfers: Vec<Transfer>) -> Result<(), CrabrollsError> {
+		for transfer in transfers {
+			if !transfer.amount.is_zero() {
+				env.erc20_transfer(self.escrow, transfer.destination, transfer.token, transfer.amount)
+					.await?;
+			}
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::core::application::Application;
+	use crate::core::testing::{MockupOptions, Tester};
+	use crate::types::machine::{Deposit, FinishStatus, Metadata};
+	use std::error::Error;
+
+	struct Noop;
+
+	impl Application for Noop {
+		type Error = Box<dyn Error>;
+
+		async fn advance(
+			&self,
+			_env: &impl Environment,
+			_metadata: Metadata,
+			_payload: &[u8],
+			_deposit: Option<Deposit>,
+		) -> Result<FinishStatus, Box<dyn Error>> {
+			Ok(FinishStatus::Accept)
+		}
+	}
+
+	const BASE: Address = Address::repeat_byte(0xba);
+	const QUOTE: Address = Address::repeat_byte(0x90);
+	const ESCROW: Address = Address::repeat_byte(0xee);
+	const ALICE: Address = Address::repeat_byte(0xa1);
+	const BOB: Address = Address::repeat_byte(0xb0);
+	const CAROL: Address = Address::repeat_byte(0xca);
+
+	async fn tester() -> Tester<Noop> {
+		let tester = Tester::new(Noop, MockupOptions::default());
+		for account in [ALICE, BOB, CAROL] {
+			for token in [BASE, QUOTE] {
+				tester
+					.deposit(Deposit::ERC20 {
+						sender: account,
+						token,
+						amount: Uint::from(10_000),
+					})
+					.await;
+			}
+		}
+		tester
+	}
+
+	fn book() -> OrderBook {
+		OrderBook::new(BASE, QUOTE, ESCROW).price_scale(Uint::one())
+	}
+
+	#[async_std::test]
+	async fn test_resting_orders_and_depth() {
+		let tester = tester().await;
+		let env = tester.env();
+		let book = book();
+
+		book.place(env.as_ref(), ALICE, Side::Buy, Uint::from(9), Uint::from(10))
+			.await
+			.unwrap();
+		book.place(env.as_ref(), BOB, Side::Buy, Uint::from(9), Uint::from(5))
+			.await
+			.unwrap();
+		book.place(env.as_ref(), BOB, Side::Sell, Uint::from(11), Uint::from(7))
+			.await
+			.unwrap();
+
+		assert_eq!(book.best_bid(), Some(Uint::from(9)));
+		assert_eq!(book.best_ask(), Some(Uint::from(11)));
+		assert_eq!(book.depth(Side::Buy, 10), vec![(Uint::from(9), Uint::from(15))]);
+		assert_eq!(book.depth(Side::Sell, 10), vec![(Uint::from(11), Uint::from(7))]);
+		assert_eq!(tester.erc20_balance(ESCROW, QUOTE).await, Uint::from(135));
+		assert_eq!(tester.erc20_balance(ESCROW, BASE).await, Uint::from(7));
+
+		assert!(book
+			.place(env.as_ref(), CAROL, Side::Buy, Uint::from(1), Uint::from(20_000))
+			.await
+			.is_err());
+		assert!(book
+			.place(env.as_ref(), CAROL, Side::Buy, Uint::zero(), Uint::from(1))
+			.await
+			.is_err());
+	}
+
+	#[async_std::test]
+	async fn test_price_time_priority() {
+		let tester = tester().await;
+		let env = tester.env();
+		let book = book();
+
+		let first = book
+			.place(env.as_ref(), ALICE, Side::Sell, Uint::from(10), Uint::from(5))
+			.await
+			.unwrap();
+		let second = book
+			.place(env.as_ref(), BOB, Side::Sell, Uint::from(10), Uint::from(5))
+			.await
+			.unwrap();
+		let cheaper = book
+			.place(env.as_ref(), BOB, Side::Sell, Uint::from(8), Uint::from(2))
+			.await
+			.unwrap();
+
+		let placement = book
+			.place(env.as_ref(), CAROL, Side::Buy, Uint::from(12), Uint::from(10))
+			.await
+			.unwrap();
+		let makers: Vec<(u64, Uint, Uint)> = placement
+			.fills
+			.iter()
+			.map(|fill| (fill.maker_order, fill.price, fill.amount))
+			.collect();
+		assert_eq!(
+			makers,
+			vec![
+				(cheaper.id, Uint::from(8), Uint::from(2)),
+				(first.id, Uint::from(10), Uint::from(5)),
+				(second.id, Uint::from(10), Uint::from(3)),
+			]
+		);
+		assert!(placement.resting.is_none());
+		assert_eq!(book.get(second.id).unwrap().amount, Uint::from(2));
+
+		// Carol escrowed 120 at her limit price but only paid 16 + 50 + 30
+		assert_eq!(tester.erc20_balance(CAROL, QUOTE).await, Uint::from(10_000 - 96));
+		assert_eq!(tester.erc20_balance(CAROL, BASE).await, Uint::from(10_010));
+		assert_eq!(tester.erc20_balance(ALICE, QUOTE).await, Uint::from(10_050));
+		assert_eq!(tester.erc20_balance(BOB, QUOTE).await, Uint::from(10_046));
+		assert_eq!(tester.erc20_balance(ESCROW, QUOTE).await, Uint::zero());
+		assert_eq!(tester.erc20_balance(ESCROW, BASE).await, Uint::from(2));
+	}
+
+	#[async_std::test]
+	async fn test_partial_fill_rests_and_sell_taker() {
+		let tester = tester().await;
+		let env = tester.env();
+		let book = book();
+
+		book.place(env.as_ref(), ALICE, Side::Buy, Uint::from(10), Uint::from(4))
+			.await
+			.unwrap();
+		let placement = book
+			.place(env.as_ref(), BOB, Side::Sell, Uint::from(9), Uint::from(6))
+			.await
+			.unwrap();
+		assert_eq!(placement.fills.len(), 1);
+		assert_eq!(placement.fills[0].quote_amount, Uint::from(40));
+		let resting = placement.resting.unwrap();
+		assert_eq!((resting.price, resting.amount), (Uint::from(9), Uint::from(2)));
+		assert_eq!(book.best_bid(), None);
+		assert_eq!(book.best_ask(), Some(Uint::from(9)));
+
+		assert_eq!(tester.erc20_balance(ALICE, BASE).await, Uint::from(10_004));
+		assert_eq!(tester.erc20_balance(BOB, QUOTE).await, Uint::from(10_040));
+		assert_eq!(tester.erc20_balance(BOB, BASE).await, Uint::from(10_000 - 6));
+	}
+
+	#[async_std::test]
+	async fn test_cancel_refunds() {
+		let tester = tester().await;
+		let env = tester.env();
+		let book = book();
+
+		let bid = book
+			.place(env.as_ref(), ALICE, Side::Buy, Uint::from(3), Uint::from(100))
+			.await
+			.unwrap();
+		let ask = book
+			.place(env.as_ref(), ALICE, Side::Sell, Uint::from(5), Uint::from(100))
+			.await
+			.unwrap();
+		assert_eq!(book.orders_of(ALICE).len(), 2);
+
+		assert!(book.cancel(env.as_ref(), BOB, bid.id).await.is_err());
+		book.cancel(env.as_ref(), ALICE, bid.id).await.unwrap();
+		book.cancel(env.as_ref(), ALICE, ask.id).await.unwrap();
+		assert!(book.cancel(env.as_ref(), ALICE, ask.id).await.is_err());
+
+		assert!(book.orders_of(ALICE).is_empty());
+		assert_eq!(book.best_bid(), None);
+		assert_eq!(book.best_ask(), None);
+		assert_eq!(tester.erc20_balance(ALICE, QUOTE).await, Uint::from(10_000));
+		assert_eq!(tester.erc20_balance(ALICE, BASE).await, Uint::from(10_000));
+	}
+
+	#[async_std::test]
+	async fn test_scaled_price_rounding() {
+		let tester = tester().await;
+		let env = tester.env();
+		let book = OrderBook::new(BASE, QUOTE, ESCROW).price_scale(Uint::from(100));
+
+		// 3 base at 1.5 escrows 5 (rounded up) and each fill of 1 pays 1 (rounded down)
+		book.place(env.as_ref(), ALICE, Side::Buy, Uint::from(150), Uint::from(3))
+			.await
+			.unwrap();
+		assert_eq!(tester.erc20_balance(ESCROW, QUOTE).await, Uint::from(5));
+		for _ in 0..3 {
+			let placement = book
+				.place(env.as_ref(), BOB, Side::Sell, Uint::from(150), Uint::one())
+				.await
+				.unwrap();
+			assert_eq!(placement.fills[0].quote_amount, Uint::one());
+		}
+		assert_eq!(book.best_bid(), None);
+		assert_eq!(tester.erc20_balance(ALICE, QUOTE).await, Uint::from(10_000 - 3));
+		assert_eq!(tester.erc20_balance(BOB, QUOTE).await, Uint::from(10_003));
+		assert_eq!(tester.erc20_balance(ESCROW, QUOTE).await, Uint::zero());
+		assert_eq!(tester.erc20_balance(ESCROW, BASE).await, Uint::zero());
+	}
+}
@@ -0,0 +1,216 @@
+use super::environment::Environment;
+use super::handoff::WalletsSnapshot;
+use crate::types::machine::{FinishStatus, Metadata, Output};
+use crate::types::primitives::*;
+use crate::utils::parsers::deserializers::serialize_bytes_as_string;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::error::Error;
+
+pub const PLAYGROUND_INPUTS_ROUTE: &[u8] = b"/_crabrolls/playground/inputs";
+pub const PLAYGROUND_OUTPUTS_ROUTE: &[u8] = b"/_crabrolls/playground/outputs";
+pub const PLAYGROUND_BALANCES_ROUTE: &[u8] = b"/_crabrolls/playground/balances";
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct PlaygroundInput {
+	pub input_index: u64,
+	pub sender: Address,
+	pub timestamp: u64,
+	#[serde(serialize_with = "serialize_bytes_as_string")]
+	pub payload: Vec<u8>,
+	#[serde(flatten)]
+	pub status: FinishStatus,
+	pub outputs: usize,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct PlaygroundOutput {
+	pub input_index: u64,
+	#[serde(flatten)]
+	pub output: Output,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub decoded: Option<Value>, // The payload as JSON, or as text if it isn't JSON but is valid UTF-8
+}
+
+impl PlaygroundOutput {
+	pub fn new(input_index: u64, output: Output) -> Self {
+		let payload = output.payload();
+		let decoded = serde_json::from_slice(payload).ok().or_else(|| {
+			std::str::from_utf8(payload)
+				.ok()
+				.map(|text| Value::String(text.to_string()))
+		});
+
+		Self {
+			input_index,
+			output,
+			decoded,
+		}
+	}
+}
+
+/// Log of the last processed advance inputs and their outputs, served as JSON on inspect routes so the dapp state can
+/// be watched during local development with `curl` or a simple web page.
+#[derive(Debug, Clone, Default)]
+pub struct Playground {
+	capacity: usize,
+	entries: VecDeque<(PlaygroundInput, Vec<PlaygroundOutput>)>,
+}
+
+impl Playground {
+	pub fn new(capacity: usize) -> Self {
+		Self {
+			capacity: capacity.max(1),
+			entries: VecDeque::new(),
+		}
+	}
+
+	/// Matches the routes with or without the leading slash, as the inspect endpoint of the node strips it from the path.
+	fn route(payload: &[u8]) -> Option<&'static [u8]> {
+		[
+			PLAYGROUND_INPUTS_ROUTE,
+			PLAYGROUND_OUTPUTS_ROUTE,
+			PLAYGROUND_BALANCES_ROUTE,
+		]
+		.into_iter()
+		.find(|route| payload == *route || payload == &route[1..])
+	}
+
+	pub fn is_route(payload: &[u8]) -> bool {
+		Self::route(payload).is_some()
+	}
+
+	/// Records an advance input, dropping the oldest one once the log is full.
+	pub fn record(&mut self, metadata: &Metadata, payload: &[u8], status: FinishStatus, outputs: Vec<Output>) {
+		let input = PlaygroundInput {
+			input_index: metadata.input_index,
+			sender: metadata.sender,
+			timestamp: metadata.timestamp,
+			payload: payload.to_vec(),
+			status,
+			outputs: outputs.len(),
+		};
+		let outputs = outputs
+			.into_iter()
+			.map(|output| PlaygroundOutput::new(metadata.input_index, output))
+			.collect();
+
+		if self.entries.len() >= self.capacity {
+			self.entries.pop_front();
+		}
+		self.entries.push_back((input, outputs));
+	}
+
+	pub fn inputs(&self) -> Vec<&PlaygroundInput> {
+		self.entries.iter().map(|(input, _)| input).collect()
+	}
+
+	pub fn outputs(&self) -> Vec<&PlaygroundOutput> {
+		self.entries.iter().flat_map(|(_, outputs)| outputs).collect()
+	}
+
+	/// Sends the response of a playground route as a report.
+	pub async fn respond(&self, env: &impl Environment, route: &[u8]) -> Result<(), Box<dyn Error>> {
+		let response = match Self::route(route).unwrap_or(route) {
+			PLAYGROUND_INPUTS_ROUTE => serde_json::json!({ "inputs": self.inputs() }),
+			PLAYGROUND_OUTPUTS_ROUTE => serde_json::json!({ "outputs": self.outputs() }),
+			PLAYGROUND_BALANCES_ROUTE => serde_json::json!({ "balances": WalletsSnapshot::capture(env).await }),
+			_ => return Err(format!("unknown playground route: {}", String::from_utf8_lossy(route)).into()),
+		};
+		Ok(env.send_report(serde_json::to_vec(&response)?).await?)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::core::environment::RollupInternalEnvironment;
+	use crate::core::testing::RollupMockup;
+	use crate::{address, uint};
+
+	fn metadata(input_index: u64) -> Metadata {
+		Metadata {
+			input_index,
+			sender: address!("0x0000000000000000000000000000000000000001"),
+			block_number: 0,
+			timestamp: 1700000000,
+			app_contract: None,
+			chain_id: None,
+			prev_randao: None,
+			annotations: Default::default(),
+		}
+	}
+
+	async fn report(env: &RollupMockup, playground: &Playground, route: &[u8]) -> Value {
+		playground.respond(env, route).await.unwrap();
+		match env.advance(FinishStatus::Accept).await.unwrap().unwrap().as_slice() {
+			[Output::Report { payload }] => serde_json::from_slice(payload).unwrap(),
+			outputs => panic!("unexpected outputs: {:?}", outputs),
+		}
+	}
+
+	#[test]
+	fn test_playground_log() {
+		let mut playground = Playground::new(2);
+		playground.record(&metadata(0), b"first", FinishStatus::Accept, vec![]);
+		playground.record(
+			&metadata(1),
+			b"second",
+			FinishStatus::Reject,
+			vec![Output::Report {
+				payload: b"oops".to_vec(),
+			}],
+		);
+		playground.record(
+			&metadata(2),
+			b"third",
+			FinishStatus::Accept,
+			vec![Output::Notice {
+				payload: br#"{"count":1}"#.to_vec(),
+			}],
+		);
+
+		let indices: Vec<u64> = playground.inputs().iter().map(|input| input.input_index).collect();
+		assert_eq!(indices, vec![1, 2]);
+		assert_eq!(playground.outputs().len(), 2);
+		assert_eq!(playground.outputs()[0].decoded, Some(Value::String("oops".to_string())));
+		assert_eq!(playground.outputs()[1].decoded, Some(serde_json::json!({ "count": 1 })));
+
+		let undecoded = PlaygroundOutput::new(0, Output::Notice { payload: vec![0xff] });
+		assert_eq!(undecoded.decoded, None);
+		assert!(Playground::is_route(PLAYGROUND_BALANCES_ROUTE));
+		assert!(Playground::is_route(b"_crabrolls/playground/balances"));
+		assert!(!Playground::is_route(b"/_crabrolls/playground"));
+	}
+
+	#[async_std::test]
+	async fn test_playground_routes() {
+		let env = RollupMockup::new();
+		let mut playground = Playground::new(8);
+		playground.record(
+			&metadata(0),
+			b"hello",
+			FinishStatus::Accept,
+			vec![Output::Notice {
+				payload: b"hello".to_vec(),
+			}],
+		);
+
+		let inputs = report(&env, &playground, PLAYGROUND_INPUTS_ROUTE).await;
+		assert_eq!(inputs["inputs"][0]["input_index"], 0);
+		assert_eq!(inputs["inputs"][0]["payload"], "0x68656c6c6f");
+		assert_eq!(inputs["inputs"][0]["status"], "accept");
+		assert_eq!(inputs["inputs"][0]["outputs"], 1);
+
+		let outputs = report(&env, &playground, b"_crabrolls/playground/outputs").await;
+		assert_eq!(outputs["outputs"][0]["type"], "notice");
+		assert_eq!(outputs["outputs"][0]["decoded"], "hello");
+
+		let alice = address!("0x0000000000000000000000000000000000000002");
+		env.get_ether_wallet().write().await.set_balance(alice, uint!(100));
+		let balances = report(&env, &playground, PLAYGROUND_BALANCES_ROUTE).await;
+		assert_eq!(balances["balances"]["ether"][0][0], format!("{:?}", alice));
+		assert!(playground.respond(&env, b"/_crabrolls/playground/other").await.is_err());
+	}
+}
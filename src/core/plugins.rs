@@ -0,0 +1,235 @@
+use super::application::Application;
+use super::context::Selector;
+use super::dynamic::DynApplication;
+use super::environment::Environment;
+use crate::types::machine::{Deposit, FinishStatus, Metadata};
+use crate::types::report::ErrorReport;
+use std::error::Error;
+use std::sync::{Arc, RwLock};
+
+type Plugin = (String, Selector, Arc<dyn DynApplication + Send + Sync>);
+
+#[derive(Clone, Default)]
+pub struct PluginRegistry {
+	plugins: Arc<RwLock<Vec<Plugin>>>,
+}
+
+impl PluginRegistry {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn register(
+		&self,
+		name: impl Into<String>,
+		selector: Selector,
+		plugin: impl Application + Send + Sync + 'static,
+	) -> bool {
+		let name = name.into();
+		let plugin: Arc<dyn DynApplication + Send + Sync> = Arc::new(plugin);
+		let mut plugins = self.plugins.write().expect("Plugin registry lock poisoned");
+
+		match plugins.iter_mut().find(|(registered, _, _)| *registered == name) {
+			Some(entry) => {
+				debug!("Replacing plugin {}", name);
+				*entry = (name, selector, plugin);
+				true
+			}
+			None => {
+				debug!("Registering plugin {}", name);
+				plugins.push((name, selector, plugin));
+				false
+			}
+		}
+	}
+
+	pub fn unregister(&self, name: &str) -> bool {
+		let mut plugins = self.plugins.write().expect("Plugin registry lock poisoned");
+		let len = plugins.len();
+		plugins.retain(|(registered, _, _)| registered != name);
+		debug!("Unregistering plugin {}", name);
+		plugins.len() != len
+	}
+
+	pub fn contains(&self, name: &str) -> bool {
+		self.names().iter().any(|registered| registered == name)
+	}
+
+	pub fn names(&self) -> Vec<String> {
+		let plugins = self.plugins.read().expect("Plugin registry lock poisoned");
+		plugins.iter().map(|(name, _, _)| name.clone()).collect()
+	}
+
+	fn find(&self, matches: impl Fn(&Selector) -> bool) -> Option<(String, Arc<dyn DynApplication + Send + Sync>)> {
+		let plugins = self.plugins.read().expect("Plugin registry lock poisoned");
+		plugins
+			.iter()
+			.find(|(_, selector, _)| matches(selector))
+			.map(|(name, _, plugin)| (name.clone(), plugin.clone()))
+	}
+}
+
+impl Application for PluginRegistry {
+	type Error = Box<dyn Error>;
+
+	async fn advance(
+		&self,
+		env: &impl Environment,
+		metadata: Metadata,
+		payload: &[u8],
+		deposit: Option<Deposit>,
+	) -> Result<FinishStatus, Box<dyn Error>> {
+		match self.find(|selector| selector.matches_advance(&metadata, payload)) {
+			Some((name, plugin)) => {
+				debug!("Advance input handled by plugin {}", name);
+				plugin.dyn_advance(env, metadata, payload, deposit).await
+			}
+			None => {
+				debug!("No plugin registered for the advance input");
+				Err(ErrorReport::no_route().input_index(metadata.input_index).into())
+			}
+		}
+	}
+
+	async fn inspect(&self, env: &impl Environment, payload: &[u8]) -> Result<FinishStatus, Box<dyn Error>> {
+		match self.find(|selector| selector.matches_inspect(payload)) {
+			Some((name, plugin)) => {
+				debug!("Inspect input handled by plugin {}", name);
+				plugin.dyn_inspect(env, payload).await
+			}
+			None => {
+				debug!("No plugin registered for the inspect input");
+				Err(ErrorReport::no_route().into())
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::core::testing::{MockupOptions, Tester};
+	use crate::types::machine::Output;
+	use crate::types::primitives::*;
+	use crate::types::testing::ResultUtils;
+	use crate::utils::codec::PayloadCodec;
+
+	struct Echo(&'static str);
+
+	impl Application for Echo {
+		type Error = Box<dyn Error>;
+
+		async fn advance(
+			&self,
+			env: &impl Environment,
+			_metadata: Metadata,
+			payload: &[u8],
+			_deposit: Option<Deposit>,
+		) -> Result<FinishStatus, Box<dyn Error>> {
+			env.send_notice([self.0.as_bytes(), payload].concat()).await?;
+			Ok(FinishStatus::Accept)
+		}
+
+		async fn inspect(&self, env: &impl Environment, payload: &[u8]) -> Result<FinishStatus, Box<dyn Error>> {
+			env.send_report(payload).await?;
+			Ok(FinishStatus::Accept)
+		}
+	}
+
+	struct Toggle {
+		registry: PluginRegistry,
+	}
+
+	impl Application for Toggle {
+		type Error = Box<dyn Error>;
+
+		async fn advance(
+			&self,
+			_env: &impl Environment,
+			_metadata: Metadata,
+			payload: &[u8],
+			_deposit: Option<Deposit>,
+		) -> Result<FinishStatus, Box<dyn Error>> {
+			match payload {
+				b"enable" => self.registry.register("shout", Selector::prefix(b"shout:"), Echo("!")),
+				b"disable" => self.registry.unregister("shout"),
+				_ => return Ok(FinishStatus::Reject),
+			};
+			Ok(FinishStatus::Accept)
+		}
+
+		async fn inspect(&self, _env: &impl Environment, _payload: &[u8]) -> Result<FinishStatus, Box<dyn Error>> {
+			Ok(FinishStatus::Reject)
+		}
+	}
+
+	#[test]
+	fn test_register_and_unregister() {
+		let registry = PluginRegistry::new();
+		assert!(!registry.register("a", Selector::Any, Echo("a")));
+		assert!(!registry.register("b", Selector::Any, Echo("b")));
+		assert!(registry.register("a", Selector::Any, Echo("c")));
+		assert_eq!(registry.names(), vec!["a".to_string(), "b".to_string()]);
+
+		assert!(registry.unregister("a"));
+		assert!(!registry.unregister("a"));
+		assert!(!registry.contains("a"));
+		assert!(registry.contains("b"));
+	}
+
+	#[async_std::test]
+	async fn test_plugins_toggled_at_runtime() {
+		let admin = Address::repeat_byte(1);
+		let registry = PluginRegistry::new();
+		registry.register(
+			"admin",
+			Selector::senders([admin]),
+			Toggle {
+				registry: registry.clone(),
+			},
+		);
+		let tester = Tester::new(registry.clone(), MockupOptions::default());
+
+		let result = tester.advance(Address::zero(), b"shout:hi").await;
+		assert!(result.is_rejected());
+		let report = ErrorReport::from_error(result.error.as_deref().unwrap());
+		assert_eq!(report.code, ErrorReport::NO_ROUTE);
+		assert_eq!(report.input_index, Some(0));
+
+		assert!(tester.advance(admin, b"enable").await.is_accepted());
+		let result = tester.advance(Address::zero(), b"shout:hi").await;
+		assert_eq!(
+			result.get_outputs(),
+			vec![Output::Notice {
+				payload: b"!shout:hi".to_vec()
+			}]
+		);
+		assert_eq!(tester.inspect(b"shout:status").await.get_reports().len(), 1);
+
+		assert!(tester.advance(admin, b"disable").await.is_accepted());
+		assert!(tester.advance(Address::zero(), b"shout:hi").await.is_rejected());
+	}
+
+	#[async_std::test]
+	async fn test_plugins_routed_by_codec() {
+		let registry = PluginRegistry::new();
+		registry.register("json", Selector::codec(PayloadCodec::Json), Echo("json:"));
+		registry.register("abi", Selector::codec(PayloadCodec::Hex), Echo("abi:"));
+		registry.register("raw", Selector::Any, Echo("raw:"));
+		let tester = Tester::new(registry, MockupOptions::default());
+
+		for (payload, expected) in [
+			(&br#"{"a":1}"#[..], &br#"json:{"a":1}"#[..]),
+			(b"0xa9059cbb", b"abi:0xa9059cbb"),
+			(&[0xa9, 0x05], &[b'r', b'a', b'w', b':', 0xa9, 0x05]),
+		] {
+			let result = tester.advance(Address::zero(), payload).await;
+			assert_eq!(
+				result.get_outputs(),
+				vec![Output::Notice {
+					payload: expected.to_vec()
+				}]
+			);
+		}
+	}
+}
@@ -0,0 +1,175 @@
+use crate::types::error::CrabrollsError;
+use crate::utils::requests::{resolve, timeout};
+use async_io::{Async, Timer};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+	pub max_attempts: u32, // Attempts of each request, including the first one
+	pub initial_backoff: Duration,
+	pub max_backoff: Duration,
+	pub multiplier: u32, // Growth of the backoff after each failed attempt
+	pub jitter: bool,    // Wait between half and all of each backoff, so restarted apps don't retry in lockstep
+}
+
+impl Default for RetryPolicy {
+	fn default() -> Self {
+		Self {
+			max_attempts: 5,
+			initial_backoff: Duration::from_millis(100),
+			max_backoff: Duration::from_secs(5),
+			multiplier: 2,
+			jitter: true,
+		}
+	}
+}
+
+impl RetryPolicy {
+	/// Fails on the first connection error.
+	pub fn none() -> Self {
+		Self {
+			max_attempts: 1,
+			..Self::default()
+		}
+	}
+
+	/// Backoff before retrying the failed `attempt`, counted from 1.
+	pub fn backoff(&self, attempt: u32) -> Duration {
+		let factor = self.multiplier.saturating_pow(attempt.saturating_sub(1));
+		let backoff = self.initial_backoff.saturating_mul(factor).min(self.max_backoff);
+		match self.jitter {
+			true => backoff.mul_f64(0.5 + random_fraction() / 2.0),
+			false => backoff,
+		}
+	}
+}
+
+/// Random number in `[0, 1)`, from the random keys std seeds its hash maps with.
+fn random_fraction() -> f64 {
+	let random = RandomState::new().build_hasher().finish();
+	(random >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Sleeps on the timer of the `async-io` reactor, so it works with any executor.
+pub async fn sleep(duration: Duration) {
+	Timer::after(duration).await;
+}
+
+/// Waits until the rollup server accepts connections, e.g. while the node starts next to the app, retrying with the
+/// backoff of the policy until the timeout.
+pub async fn wait_for_server(url: &str, timeout: Duration, policy: &RetryPolicy) -> Result<(), CrabrollsError> {
+	let authority = authority(url)?;
+	let deadline = Instant::now() + timeout;
+	let mut attempt = 1;
+	loop {
+		let remaining = deadline
+			.saturating_duration_since(Instant::now())
+			.max(Duration::from_millis(1));
+		let error = match connect(&authority, remaining).await {
+			Ok(()) => return Ok(()),
+			Err(e) => e,
+		};
+
+		let backoff = policy.backoff(attempt);
+		if Instant::now() + backoff >= deadline {
+			return Err(CrabrollsError::transport(format!(
+				"rollup server at {} not reachable after {:?}: {}",
+				url, timeout, error
+			)));
+		}
+		debug!("Waiting for the rollup server at {}: {}", url, error);
+		sleep(backoff).await;
+		attempt += 1;
+	}
+}
+
+async fn connect(authority: &str, duration: Duration) -> Result<(), String> {
+	let (host, port) = authority.rsplit_once(':').unwrap_or((authority, ""));
+	let port = port.parse().map_err(|_| format!("invalid port in {}", authority))?;
+	let addresses = resolve(host, port).await.map_err(|e| e.to_string())?;
+	let mut error = format!("{} doesn't resolve to any address", authority);
+	for address in addresses {
+		match timeout(Some(duration), Async::<TcpStream>::connect(address)).await {
+			Ok(Ok(_)) => return Ok(()),
+			Ok(Err(e)) | Err(e) => error = e.to_string(),
+		}
+	}
+	Err(error)
+}
+
+/// `host:port` of the URL, with the default port of its scheme if it has none.
+fn authority(url: &str) -> Result<String, CrabrollsError> {
+	let (scheme, rest) = url
+		.split_once("://")
+		.ok_or_else(|| CrabrollsError::transport(format!("invalid rollup URL {}", url)))?;
+	let host = rest.split('/').next().unwrap_or_default();
+	if host.is_empty() {
+		return Err(CrabrollsError::transport(format!("invalid rollup URL {}", url)));
+	}
+	match host.rsplit_once(':') {
+		Some((_, port)) if port.parse::<u16>().is_ok() => Ok(host.to_string()),
+		_ => Ok(format!("{}:{}", host, if scheme == "https" { 443 } else { 80 })),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_backoff() {
+		let policy = RetryPolicy {
+			jitter: false,
+			..RetryPolicy::default()
+		};
+		let backoffs: Vec<Duration> = (1..=8).map(|attempt| policy.backoff(attempt)).collect();
+		assert_eq!(backoffs[0], Duration::from_millis(100));
+		assert_eq!(backoffs[3], Duration::from_millis(800));
+		assert_eq!(backoffs[7], Duration::from_secs(5));
+
+		let jittered = RetryPolicy::default().backoff(3);
+		assert!(jittered >= Duration::from_millis(200) && jittered <= Duration::from_millis(400));
+	}
+
+	#[test]
+	fn test_authority() {
+		assert_eq!(authority("http://127.0.0.1:5004").unwrap(), "127.0.0.1:5004");
+		assert_eq!(
+			authority("http://127.0.0.1:8080/host-runner").unwrap(),
+			"127.0.0.1:8080"
+		);
+		assert_eq!(
+			authority("https://node.example.com/rollup").unwrap(),
+			"node.example.com:443"
+		);
+		assert_eq!(authority("http://[::1]").unwrap(), "[::1]:80");
+		assert!(authority("127.0.0.1:5004").is_err());
+	}
+
+	#[async_std::test]
+	async fn test_wait_for_server() {
+		let address = std::net::TcpListener::bind("127.0.0.1:0")
+			.unwrap()
+			.local_addr()
+			.unwrap();
+		let url = format!("http://{}", address);
+		let policy = RetryPolicy {
+			initial_backoff: Duration::from_millis(20),
+			..RetryPolicy::default()
+		};
+		assert!(wait_for_server(&url, Duration::from_millis(100), &policy)
+			.await
+			.is_err());
+
+		let server = std::thread::spawn(move || {
+			std::thread::sleep(Duration::from_millis(100));
+			let listener = std::net::TcpListener::bind(address).unwrap();
+			listener.accept().unwrap();
+		});
+		wait_for_server(&url, Duration::from_secs(5), &policy).await.unwrap();
+		server.join().unwrap();
+	}
+}
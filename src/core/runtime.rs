@@ -0,0 +1,103 @@
+#[cfg(feature = "rollup")]
+use super::application::Application;
+#[cfg(feature = "rollup")]
+use super::context::{RunOptions, Supervisor};
+use std::future::Future;
+
+/// Drives the future to completion on the current thread, e.g. `block_on(Supervisor::run(app, options))` from a sync
+/// `main` without picking an executor.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+	futures::executor::block_on(future)
+}
+
+/// Runs the supervisor to completion on the current thread, e.g. a dedicated thread of a host service, with its error
+/// as a `String` so it can cross threads.
+#[cfg(feature = "rollup")]
+pub fn run_blocking(app: impl Application, options: RunOptions) -> Result<(), String> {
+	block_on(Supervisor::run(app, options)).map_err(|e| e.to_string())
+}
+
+/// Adapter for async-std services. The futures of the apps aren't `Send`, so the supervisor runs on a thread of the
+/// blocking pool for its whole life instead of being spawned as a task.
+#[cfg(feature = "async-std")]
+pub mod async_std {
+	use super::*;
+	use ::async_std::task::{self, JoinHandle};
+
+	pub fn spawn<A: Application + Send + 'static>(app: A, options: RunOptions) -> JoinHandle<Result<(), String>> {
+		task::spawn_blocking(move || run_blocking(app, options))
+	}
+}
+
+/// Adapter for tokio services, running the supervisor on the blocking thread pool like the async-std one.
+#[cfg(feature = "tokio")]
+pub mod tokio {
+	use super::*;
+	use ::tokio::task::{self, JoinHandle};
+
+	pub fn spawn<A: Application + Send + 'static>(app: A, options: RunOptions) -> JoinHandle<Result<(), String>> {
+		task::spawn_blocking(move || run_blocking(app, options))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::core::application::Application;
+	use crate::core::environment::Environment;
+	use crate::core::testing::{MockupOptions, Tester};
+	use crate::types::machine::{Deposit, FinishStatus, Metadata};
+	use crate::types::primitives::*;
+	use crate::types::testing::ResultUtils;
+	use std::error::Error;
+
+	struct Echo;
+
+	impl Application for Echo {
+		type Error = Box<dyn Error>;
+
+		async fn advance(
+			&self,
+			env: &impl Environment,
+			_metadata: Metadata,
+			payload: &[u8],
+			_deposit: Option<Deposit>,
+		) -> Result<FinishStatus, Box<dyn Error>> {
+			env.send_notice(payload).await?;
+			Ok(FinishStatus::Accept)
+		}
+	}
+
+	#[test]
+	fn test_block_on_without_executor() {
+		let tester = Tester::new(Echo, MockupOptions::default());
+		let result = block_on(tester.advance(Address::zero(), b"hello"));
+		assert!(result.is_accepted());
+		assert_eq!(result.notices(), vec![b"hello".to_vec()]);
+	}
+
+	#[cfg(feature = "rollup")]
+	#[test]
+	fn test_unreachable_rollup_server() {
+		let options = RunOptions::builder().rollup_url("http://127.0.0.1:1").build();
+		assert!(run_blocking(Echo, options).is_err());
+	}
+
+	#[cfg(feature = "async-std")]
+	#[test]
+	fn test_async_std_adapter() {
+		let options = RunOptions::builder().rollup_url("http://127.0.0.1:1").build();
+		assert!(block_on(async_std::spawn(Echo, options)).is_err());
+	}
+
+	#[cfg(feature = "tokio")]
+	#[test]
+	fn test_tokio_adapter() {
+		let runtime = ::tokio::runtime::Builder::new_current_thread().build().unwrap();
+		let options = RunOptions::builder().rollup_url("http://127.0.0.1:1").build();
+		assert!(runtime
+			.block_on(async { tokio::spawn(Echo, options).await })
+			.unwrap()
+			.is_err());
+	}
+}
@@ -0,0 +1,193 @@
+use super::environment::Environment;
+use crate::types::machine::Metadata;
+use crate::types::primitives::*;
+use crate::utils::parsers::deserializers::{deserialize_string_of_bytes, serialize_bytes_as_string};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::sync::{Arc, RwLock};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Due {
+	Timestamp(u64), // Emitted on the first accepted advance with a timestamp at or after the given one
+	Input(u64),     // Emitted on the first accepted advance with an input index at or after the given one
+}
+
+impl Due {
+	pub fn is_due(&self, metadata: &Metadata) -> bool {
+		match self {
+			Due::Timestamp(timestamp) => metadata.timestamp >= *timestamp,
+			Due::Input(input_index) => metadata.input_index >= *input_index,
+		}
+	}
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ScheduledVoucher {
+	pub id: u64,
+	pub destination: Address,
+	#[serde(
+		serialize_with = "serialize_bytes_as_string",
+		deserialize_with = "deserialize_string_of_bytes"
+	)]
+	pub payload: Vec<u8>,
+	pub due: Due,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct State {
+	next_id: u64,
+	vouchers: BTreeMap<u64, ScheduledVoucher>,
+}
+
+/// Vouchers queued by the app and emitted by the supervisor once due, at the end of an accepted advance.
+#[derive(Clone, Debug, Default)]
+pub struct VoucherSchedule {
+	state: Arc<RwLock<State>>,
+}
+
+impl VoucherSchedule {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn schedule(&self, destination: Address, payload: impl AsRef<[u8]>, due: Due) -> u64 {
+		let mut state = self.state.write().expect("Voucher schedule lock poisoned");
+		let id = state.next_id;
+		state.next_id += 1;
+		state.vouchers.insert(
+			id,
+			ScheduledVoucher {
+				id,
+				destination,
+				payload: payload.as_ref().to_vec(),
+				due,
+			},
+		);
+		debug!("Scheduled voucher {} to {:?} at {:?}", id, destination, due);
+		id
+	}
+
+	pub fn cancel(&self, id: u64) -> bool {
+		let mut state = self.state.write().expect("Voucher schedule lock poisoned");
+		state.vouchers.remove(&id).is_some()
+	}
+
+	pub fn get(&self, id: u64) -> Option<ScheduledVoucher> {
+		let state = self.state.read().expect("Voucher schedule lock poisoned");
+		state.vouchers.get(&id).cloned()
+	}
+
+	pub fn pending(&self) -> Vec<ScheduledVoucher> {
+		let state = self.state.read().expect("Voucher schedule lock poisoned");
+		state.vouchers.values().cloned().collect()
+	}
+
+	/// Emits the vouchers due for the input, in scheduling order, returning their ids.
+	pub async fn emit_due(&self, env: &impl Environment, metadata: &Metadata) -> Result<Vec<u64>, Box<dyn Error>> {
+		let due: Vec<ScheduledVoucher> = self
+			.pending()
+			.into_iter()
+			.filter(|voucher| voucher.due.is_due(metadata))
+			.collect();
+
+		let mut emitted = Vec::new();
+		for voucher in due {
+			env.send_voucher(voucher.destination, &voucher.payload).await?;
+			self.cancel(voucher.id);
+			emitted.push(voucher.id);
+		}
+		Ok(emitted)
+	}
+
+	/// Serializes the queue, so it can be persisted with the rest of the app state.
+	pub fn encode(&self) -> Result<Vec<u8>, Box<dyn Error>> {
+		Ok(serde_json::to_vec(
+			&*self.state.read().expect("Voucher schedule lock poisoned"),
+		)?)
+	}
+
+	pub fn decode(dump: &[u8]) -> Result<Self, Box<dyn Error>> {
+		Ok(Self {
+			state: Arc::new(RwLock::new(serde_json::from_slice(dump)?)),
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::core::application::Application;
+	use crate::core::testing::{MockupOptions, Tester};
+	use crate::types::machine::{Deposit, FinishStatus};
+	use crate::types::testing::ResultUtils;
+
+	struct Vesting {
+		schedule: VoucherSchedule,
+	}
+
+	impl Application for Vesting {
+		type Error = Box<dyn Error>;
+
+		async fn advance(
+			&self,
+			_env: &impl Environment,
+			metadata: Metadata,
+			payload: &[u8],
+			_deposit: Option<Deposit>,
+		) -> Result<FinishStatus, Box<dyn Error>> {
+			match payload {
+				b"vest" => {
+					self.schedule
+						.schedule(metadata.sender, b"payout", Due::Input(metadata.input_index + 2));
+					Ok(FinishStatus::Accept)
+				}
+				b"reject" => Ok(FinishStatus::Reject),
+				_ => Ok(FinishStatus::Accept),
+			}
+		}
+	}
+
+	#[async_std::test]
+	async fn test_scheduled_voucher_emission() {
+		let schedule = VoucherSchedule::new();
+		let options = MockupOptions::builder().voucher_schedule(schedule.clone()).build();
+		let tester = Tester::new(
+			Vesting {
+				schedule: schedule.clone(),
+			},
+			options,
+		);
+		let sender = Address::repeat_byte(0xaa);
+
+		let result = tester.advance(sender, b"vest").await;
+		assert!(result.vouchers().is_empty());
+		assert_eq!(schedule.pending().len(), 1);
+
+		let result = tester.advance(sender, b"noop").await;
+		assert!(result.vouchers().is_empty());
+
+		let result = tester.advance(sender, b"reject").await;
+		assert!(result.vouchers().is_empty());
+		assert_eq!(schedule.pending().len(), 1);
+
+		let result = tester.advance(sender, b"noop").await;
+		assert_eq!(result.vouchers(), vec![(sender, b"payout".to_vec())]);
+		assert!(schedule.pending().is_empty());
+	}
+
+	#[test]
+	fn test_cancel_and_persistence() {
+		let schedule = VoucherSchedule::new();
+		let first = schedule.schedule(Address::zero(), b"a", Due::Timestamp(100));
+		let second = schedule.schedule(Address::zero(), b"b", Due::Input(5));
+		assert!(schedule.cancel(first));
+		assert!(!schedule.cancel(first));
+
+		let restored = VoucherSchedule::decode(&schedule.encode().unwrap()).unwrap();
+		assert_eq!(restored.pending(), schedule.pending());
+		assert_eq!(restored.get(second).unwrap().payload, b"b");
+		assert_eq!(restored.schedule(Address::zero(), b"c", Due::Input(6)), second + 1);
+	}
+}
@@ -0,0 +1,381 @@
+use super::application::{validate_and_advance, Application};
+use super::environment::Environment;
+use crate::types::machine::{Deposit, FinishStatus, Metadata};
+use crate::types::primitives::*;
+use crate::types::report::ErrorReport;
+use crate::utils::codec;
+use crate::utils::crypto::{ecrecover, personal_message_hash};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::sync::{Arc, RwLock};
+
+pub const SESSION_ANNOTATION: &str = "session"; // Metadata annotation holding the session address that sent the input
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SessionGrant {
+	pub session: Address,
+	pub expires_at: u64, // Input timestamp after which the session is no longer valid
+	#[serde(default)]
+	pub scope: Vec<String>, // Allowed payload kinds, any payload if empty
+	#[serde(default)]
+	pub nonce: u64, // Only used by signed grants, to prevent replays
+}
+
+impl SessionGrant {
+	/// Message signed by the account (with `personal_sign`) to authorize the grant off-chain.
+	pub fn message(&self) -> Vec<u8> {
+		let mut message = self.session.as_bytes().to_vec();
+		message.extend_from_slice(&self.expires_at.to_be_bytes());
+		message.extend_from_slice(&self.nonce.to_be_bytes());
+		message.extend_from_slice(self.scope.join(",").as_bytes());
+		message
+	}
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Session {
+	pub account: Address,
+	pub expires_at: u64,
+	pub scope: Vec<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionInput {
+	Authorize(SessionGrant),
+	Revoke(Address),
+	Signed {
+		account: Address,
+		grant: SessionGrant,
+		signature: String,
+	},
+}
+
+#[derive(Deserialize)]
+struct Envelope {
+	session: SessionInput,
+}
+
+#[derive(Default)]
+struct Sessions {
+	sessions: HashMap<Address, Session>,
+	used_nonces: HashSet<(Address, u64)>,
+}
+
+#[derive(Clone)]
+pub struct SessionKeys {
+	sessions: Arc<RwLock<Sessions>>,
+	scope_field: String,
+}
+
+impl Default for SessionKeys {
+	fn default() -> Self {
+		Self {
+			sessions: Arc::default(),
+			scope_field: "kind".to_string(),
+		}
+	}
+}
+
+impl SessionKeys {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// JSON field of the payload matched against the session scope, `kind` by default.
+	pub fn scope_field(mut self, field: impl Into<String>) -> Self {
+		self.scope_field = field.into();
+		self
+	}
+
+	pub fn authorize(&self, account: Address, grant: SessionGrant) -> Result<(), Box<dyn Error>> {
+		if grant.session == account {
+			return Err("an account can't be its own session".into());
+		}
+		let mut sessions = self.sessions.write().expect("Session keys lock poisoned");
+		if let Some(existing) = sessions.sessions.get(&grant.session) {
+			if existing.account != account {
+				return Err(format!("{:?} is already a session of another account", grant.session).into());
+			}
+		}
+		debug!("Authorizing session {:?} for {:?}", grant.session, account);
+		sessions.sessions.insert(
+			grant.session,
+			Session {
+				account,
+				expires_at: grant.expires_at,
+				scope: grant.scope,
+			},
+		);
+		Ok(())
+	}
+
+	pub fn authorize_signed(
+		&self,
+		account: Address,
+		grant: SessionGrant,
+		signature: &[u8],
+	) -> Result<(), Box<dyn Error>> {
+		let signer = ecrecover(&personal_message_hash(&grant.message()), signature)?;
+		if signer != account {
+			return Err("session grant wasn't signed by the account".into());
+		}
+		if self
+			.sessions
+			.read()
+			.expect("Session keys lock poisoned")
+			.used_nonces
+			.contains(&(account, grant.nonce))
+		{
+			return Err("session grant was already used".into());
+		}
+		let nonce = grant.nonce;
+		self.authorize(account, grant)?;
+		self.sessions
+			.write()
+			.expect("Session keys lock poisoned")
+			.used_nonces
+			.insert((account, nonce));
+		Ok(())
+	}
+
+	pub fn revoke(&self, account: Address, session: Address) -> bool {
+		let mut sessions = self.sessions.write().expect("Session keys lock poisoned");
+		match sessions.sessions.get(&session) {
+			Some(existing) if existing.account == account => {
+				debug!("Revoking session {:?} of {:?}", session, account);
+				sessions.sessions.remove(&session);
+				true
+			}
+			_ => false,
+		}
+	}
+
+	pub fn get(&self, session: Address) -> Option<Session> {
+		self.sessions
+			.read()
+			.expect("Session keys lock poisoned")
+			.sessions
+			.get(&session)
+			.cloned()
+	}
+
+	/// Effective account of `sender` for the payload at the input timestamp `now`, the sender itself when it isn't a session.
+	pub fn resolve(&self, sender: Address, now: u64, payload: &[u8]) -> Result<Address, Box<dyn Error>> {
+		let Some(session) = self.get(sender) else {
+			return Ok(sender);
+		};
+		if now > session.expires_at {
+			return Err(ErrorReport::unauthorized(format!("session {:?} has expired", sender)).into());
+		}
+		if !session.scope.is_empty() {
+			let kind = serde_json::from_slice::<Value>(payload)
+				.ok()
+				.and_then(|value| value.get(&self.scope_field)?.as_str().map(str::to_string));
+			if !kind.is_some_and(|kind| session.scope.contains(&kind)) {
+				return Err(
+					ErrorReport::unauthorized(format!("payload is out of the scope of session {:?}", sender)).into(),
+				);
+			}
+		}
+		Ok(session.account)
+	}
+
+	/// Applies a `{"session": ...}` management input, returning `None` for any other payload.
+	pub fn handle_input(&self, sender: Address, payload: &[u8]) -> Option<Result<(), Box<dyn Error>>> {
+		let envelope = serde_json::from_slice::<Envelope>(payload).ok()?;
+		let result = match envelope.session {
+			SessionInput::Authorize(grant) => self.authorize(sender, grant),
+			SessionInput::Revoke(session) => match self.revoke(sender, session) {
+				true => Ok(()),
+				false => Err(format!("{:?} is not a session of {:?}", session, sender).into()),
+			},
+			SessionInput::Signed {
+				account,
+				grant,
+				signature,
+			} => codec::decode_hex(signature.as_bytes())
+				.and_then(|signature| self.authorize_signed(account, grant, &signature)),
+		};
+		Some(result.map_err(|e| ErrorReport::invalid_payload(e.to_string()).into()))
+	}
+}
+
+/// Handles session management inputs and passes the other advances to the app with the sender resolved to the
+/// effective account (the session address is kept on the `session` annotation).
+pub struct SessionRouter<A: Application> {
+	app: A,
+	sessions: SessionKeys,
+}
+
+impl<A: Application> SessionRouter<A> {
+	pub fn new(app: A, sessions: SessionKeys) -> Self {
+		Self { app, sessions }
+	}
+
+	pub fn sessions(&self) -> &SessionKeys {
+		&self.sessions
+	}
+}
+
+impl<A: Application> Application for SessionRouter<A> {
+	type Error = Box<dyn Error>;
+
+	async fn advance(
+		&self,
+		env: &impl Environment,
+		mut metadata: Metadata,
+		payload: &[u8],
+		deposit: Option<Deposit>,
+	) -> Result<FinishStatus, Box<dyn Error>> {
+		if deposit.is_none() {
+			if let Some(result) = self.sessions.handle_input(metadata.sender, payload) {
+				result?;
+				return Ok(FinishStatus::Accept);
+			}
+
+			let account = self.sessions.resolve(metadata.sender, metadata.timestamp, payload)?;
+			if account != metadata.sender {
+				metadata
+					.annotations
+					.insert(SESSION_ANNOTATION.to_string(), format!("{:?}", metadata.sender));
+				metadata.sender = account;
+			}
+		}
+		validate_and_advance(&self.app, env, metadata, payload, deposit).await
+	}
+
+	async fn inspect(&self, env: &impl Environment, payload: &[u8]) -> Result<FinishStatus, Box<dyn Error>> {
+		self.app.inspect(env, payload).await.map_err(Into::into)
+	}
+
+	async fn on_start(&self, env: &impl Environment) -> Result<(), Box<dyn Error>> {
+		self.app.on_start(env).await.map_err(Into::into)
+	}
+
+	async fn on_shutdown(&self, env: &impl Environment) -> Result<(), Box<dyn Error>> {
+		self.app.on_shutdown(env).await.map_err(Into::into)
+	}
+
+	async fn on_error(&self, env: &impl Environment, error: &(dyn Error + 'static)) {
+		self.app.on_error(env, error).await
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::core::testing::{MockupOptions, Tester};
+	use crate::types::testing::ResultUtils;
+	use k256::ecdsa::SigningKey;
+
+	struct WhoAmI;
+
+	impl Application for WhoAmI {
+		type Error = Box<dyn Error>;
+
+		async fn advance(
+			&self,
+			env: &impl Environment,
+			metadata: Metadata,
+			_payload: &[u8],
+			_deposit: Option<Deposit>,
+		) -> Result<FinishStatus, Box<dyn Error>> {
+			env.send_notice(metadata.sender.as_bytes()).await?;
+			Ok(FinishStatus::Accept)
+		}
+	}
+
+	fn grant(session: Address, expires_at: u64, scope: &[&str]) -> SessionGrant {
+		SessionGrant {
+			session,
+			expires_at,
+			scope: scope.iter().map(|kind| kind.to_string()).collect(),
+			nonce: 0,
+		}
+	}
+
+	#[test]
+	fn test_resolve_scope_and_expiry() {
+		let sessions = SessionKeys::new();
+		let (account, session) = (Address::repeat_byte(0xaa), Address::repeat_byte(0xbb));
+		sessions.authorize(account, grant(session, 100, &["move"])).unwrap();
+
+		assert_eq!(sessions.resolve(account, 50, b"").unwrap(), account);
+		assert_eq!(sessions.resolve(session, 50, br#"{"kind":"move"}"#).unwrap(), account);
+		assert!(sessions.resolve(session, 50, br#"{"kind":"withdraw"}"#).is_err());
+		assert!(sessions.resolve(session, 101, br#"{"kind":"move"}"#).is_err());
+
+		assert!(sessions
+			.authorize(Address::repeat_byte(0xcc), grant(session, 100, &[]))
+			.is_err());
+		assert!(!sessions.revoke(Address::repeat_byte(0xcc), session));
+		assert!(sessions.revoke(account, session));
+		assert_eq!(sessions.resolve(session, 50, b"").unwrap(), session);
+	}
+
+	#[test]
+	fn test_signed_grant() {
+		let key = SigningKey::from_slice(&[7u8; 32]).unwrap();
+		let public_key = key.verifying_key().to_encoded_point(false);
+		let account = Address::from_slice(&crate::utils::hash::keccak256(&public_key.as_bytes()[1..])[12..]);
+
+		let grant = grant(Address::repeat_byte(0xbb), 100, &[]);
+		let (signature, recovery_id) = key
+			.sign_prehash_recoverable(&personal_message_hash(&grant.message()))
+			.unwrap();
+		let mut signature = signature.to_vec();
+		signature.push(27 + recovery_id.to_byte());
+
+		let sessions = SessionKeys::new();
+		assert!(sessions
+			.authorize_signed(Address::repeat_byte(0xcc), grant.clone(), &signature)
+			.is_err());
+		sessions.authorize_signed(account, grant.clone(), &signature).unwrap();
+		assert_eq!(sessions.get(grant.session).unwrap().account, account);
+		assert_eq!(
+			sessions
+				.authorize_signed(account, grant, &signature)
+				.unwrap_err()
+				.to_string(),
+			"session grant was already used"
+		);
+	}
+
+	#[async_std::test]
+	async fn test_session_router() {
+		let tester = Tester::new(SessionRouter::new(WhoAmI, SessionKeys::new()), MockupOptions::default());
+		let (account, session) = (Address::repeat_byte(0xaa), Address::repeat_byte(0xbb));
+
+		let result = tester
+			.advance(
+				account,
+				format!(
+					r#"{{"session":{{"authorize":{{"session":"{:?}","expires_at":{}}}}}}}"#,
+					session,
+					u64::MAX
+				),
+			)
+			.await;
+		assert!(result.is_accepted());
+		assert!(result.get_outputs().is_empty());
+
+		let result = tester.advance(session, b"play").await;
+		assert!(result.is_accepted());
+		assert_eq!(result.notices(), vec![account.as_bytes().to_vec()]);
+
+		let result = tester
+			.advance(session, format!(r#"{{"session":{{"revoke":"{:?}"}}}}"#, session))
+			.await;
+		assert!(result.is_rejected());
+
+		let result = tester
+			.advance(account, format!(r#"{{"session":{{"revoke":"{:?}"}}}}"#, session))
+			.await;
+		assert!(result.is_accepted());
+		let result = tester.advance(session, b"play").await;
+		assert_eq!(result.notices(), vec![session.as_bytes().to_vec()]);
+	}
+}
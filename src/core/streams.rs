@@ -0,0 +1,409 @@
+use super::environment::Environment;
+use crate::types::balance::Asset;
+use crate::types::primitives::*;
+use crate::utils::math;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::ops::Range;
+use std::sync::{Arc, RwLock};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Stream {
+	pub id: u64,
+	pub sender: Address,
+	pub recipient: Address,
+	pub asset: Asset, // Ether or ERC20
+	pub amount: Uint, // Total amount streamed from `start` to `stop`
+	pub start: u64,
+	pub stop: u64,
+	pub withdrawn: Uint,
+}
+
+impl Stream {
+	/// Amount released to the recipient at the timestamp `now`, linearly per second between `start` and `stop`.
+	pub fn accrued(&self, now: u64) -> Uint {
+		if now <= self.start {
+			return Uint::zero();
+		}
+		if now >= self.stop {
+			return self.amount;
+		}
+		math::mul_div(
+			self.amount,
+			Uint::from(now - self.start),
+			Uint::from(self.stop - self.start),
+		)
+		.expect("accrued amount is bounded by the stream amount")
+	}
+
+	pub fn withdrawable(&self, now: u64) -> Uint {
+		self.accrued(now) - self.withdrawn
+	}
+}
+
+#[derive(Default)]
+struct State {
+	next_id: u64,
+	streams: BTreeMap<u64, Stream>,
+}
+
+/// Per-second payment streams between internal accounts, with the unreleased funds held by the `escrow` account.
+#[derive(Clone)]
+pub struct Streams {
+	escrow: Address,
+	state: Arc<RwLock<State>>,
+}
+
+impl Streams {
+	pub fn new(escrow: Address) -> Self {
+		Self {
+			escrow,
+			state: Arc::default(),
+		}
+	}
+
+	pub fn escrow(&self) -> Address {
+		self.escrow
+	}
+
+	pub fn get(&self, id: u64) -> Option<Stream> {
+		self.state
+			.read()
+			.expect("Streams lock poisoned")
+			.streams
+			.get(&id)
+			.cloned()
+	}
+
+	/// Active streams where the account is the sender or the recipient.
+	pub fn streams_of(&self, account: Address) -> Vec<Stream> {
+		let state = self.state.read().expect("Streams lock poisoned");
+		state
+			.streams
+			.values()
+			.filter(|stream| stream.sender == account || stream.recipient == account)
+			.cloned()
+			.collect()
+	}
+
+	/// Moves `amount` from the sender to the escrow and streams it to the recipient over the `period` timestamps.
+	pub async fn create(
+		&self,
+		env: &impl Environment,
+		sender: Address,
+		recipient: Address,
+		asset: Asset,
+		amount: Uint,
+		period: Range<u64>,
+	) -> Result<u64, Box<dyn Error>> {
+		if period.is_empty() {
+			return Err("stream stop must be after its start".into());
+		}
+		if amount.is_zero() {
+			return Err("stream amount must be positive".into());
+		}
+		if sender == recipient || recipient == self.escrow {
+			return Err(format!("invalid stream recipient {:?}", recipient).into());
+		}
+		self.transfer(env, &asset, sender, self.escrow, amount).await?;
+
+		let mut state = self.state.write().expect("Streams lock poisoned");
+		let id = state.next_id;
+		state.next_id += 1;
+		state.streams.insert(
+			id,
+			Stream {
+				id,
+				sender,
+				recipient,
+				asset,
+				amount,
+				start: period.start,
+				stop: period.end,
+				withdrawn: Uint::zero(),
+			},
+		);
+		debug!(
+			"Stream {} of {} from {:?} to {:?} created",
+			id, amount, sender, recipient
+		);
+		Ok(id)
+	}
+
+	/// Transfers the accrued and not yet withdrawn funds to the recipient, returning the amount.
+	pub async fn withdraw(
+		&self,
+		env: &impl Environment,
+		recipient: Address,
+		id: u64,
+		now: u64,
+	) -> Result<Uint, Box<dyn Error>> {
+		let stream = self.get(id).ok_or_else(|| format!("stream {} not found", id))?;
+		if stream.recipient != recipient {
+			return Err(format!("{:?} is not the recipient of stream {}", recipient, id).into());
+		}
+		let amount = stream.withdrawable(now);
+		if amount.is_zero() {
+			return Ok(amount);
+		}
+		self.transfer(env, &stream.asset, self.escrow, recipient, amount)
+			.await?;
+
+		let mut state = self.state.write().expect("Streams lock poisoned");
+		if let Some(stream) = state.streams.get_mut(&id) {
+			stream.withdrawn += amount;
+			if stream.withdrawn == stream.amount {
+				state.streams.remove(&id);
+			}
+		}
+		Ok(amount)
+	}
+
+	/// Ends the stream (by its sender or recipient), paying the accrued funds to the recipient and refunding the rest
+	/// to the sender. Returns the `(recipient, sender)` amounts.
+	pub async fn cancel(
+		&self,
+		env: &impl Environment,
+		caller: Address,
+		id: u64,
+		now: u64,
+	) -> Result<(Uint, Uint), Box<dyn Error>> {
+		let stream = self.get(id).ok_or_else(|| format!("stream {} not found", id))?;
+		if caller != stream.sender && caller != stream.recipient {
+			return Err(format!("{:?} can't cancel stream {}", caller, id).into());
+		}
+		let to_recipient = stream.withdrawable(now);
+		let to_sender = stream.amount - stream.accrued(now);
+
+		self.transfer(env, &stream.asset, self.escrow, stream.recipient, to_recipient)
+			.await?;
+		self.transfer(env, &stream.asset, self.escrow, stream.sender, to_sender)
+			.await?;
+		self.state.write().expect("Streams lock poisoned").streams.remove(&id);
+		debug!("Stream {} cancelled by {:?}", id, caller);
+		Ok((to_recipient, to_sender))
+	}
+
+	async fn transfer(
+		&self,
+		env: &impl Environment,
+		asset: &Asset,
+		source: Address,
+		destination: Address,
+		amount: Uint,
+	) -> Result<(), Box<dyn Error>> {
+		if amount.is_zero() {
+			return Ok(());
+		}
+		match asset {
+			Asset::Ether => Ok(env.ether_transfer(source, destination, amount).await?),
+			#[cfg(feature = "erc20")]
+			Asset::ERC20 { token } => Ok(env.erc20_transfer(source, destination, *token, amount).await?),
+			_ => Err("only ether and ERC20 tokens can be streamed".into()),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::core::application::Application;
+	use crate::core::testing::{MockupOptions, Tester};
+	use crate::types::machine::{Deposit, FinishStatus, Metadata};
+
+	struct Noop;
+
+	impl Application for Noop {
+		type Error = Box<dyn Error>;
+
+		async fn advance(
+			&self,
+			_env: &impl Environment,
+			_metadata: Metadata,
+			_payload: &[u8],
+			_deposit: Option<Deposit>,
+		) -> Result<FinishStatus, Box<dyn Error>> {
+			Ok(FinishStatus::Accept)
+		}
+	}
+
+	const SENDER: Address = Address::repeat_byte(0xaa);
+	const RECIPIENT: Address = Address::repeat_byte(0xbb);
+	const ESCROW: Address = Address::repeat_byte(0xee);
+	const TOKEN: Address = Address::repeat_byte(0x20);
+
+	async fn funded_tester() -> Tester<Noop> {
+		let tester = Tester::new(Noop, MockupOptions::default());
+		tester
+			.deposit(Deposit::Ether {
+				sender: SENDER,
+				amount: Uint::from(1000),
+			})
+			.await;
+		tester
+			.deposit(Deposit::ERC20 {
+				sender: SENDER,
+				token: TOKEN,
+				amount: Uint::from(1000),
+			})
+			.await;
+		tester
+	}
+
+	fn stream(start: u64, stop: u64, amount: u64) -> Stream {
+		Stream {
+			id: 0,
+			sender: SENDER,
+			recipient: RECIPIENT,
+			asset: Asset::Ether,
+			amount: Uint::from(amount),
+			start,
+			stop,
+			withdrawn: Uint::zero(),
+		}
+	}
+
+	#[test]
+	fn test_accrued() {
+		let linear = stream(100, 200, 1000);
+		assert_eq!(linear.accrued(0), Uint::zero());
+		assert_eq!(linear.accrued(100), Uint::zero());
+		assert_eq!(linear.accrued(101), Uint::from(10));
+		assert_eq!(linear.accrued(150), Uint::from(500));
+		assert_eq!(linear.accrued(200), Uint::from(1000));
+		assert_eq!(linear.accrued(u64::MAX), Uint::from(1000));
+
+		let uneven = stream(0, 3, 10);
+		assert_eq!(uneven.accrued(1), Uint::from(3)); // Rounded down, the remainder is released at the end
+		assert_eq!(uneven.accrued(3), Uint::from(10));
+	}
+
+	#[async_std::test]
+	async fn test_create_validation() {
+		let tester = funded_tester().await;
+		let env = tester.env();
+		let streams = Streams::new(ESCROW);
+
+		let create = |recipient, amount: u64, period| {
+			streams.create(
+				env.as_ref(),
+				SENDER,
+				recipient,
+				Asset::Ether,
+				Uint::from(amount),
+				period,
+			)
+		};
+		assert!(create(RECIPIENT, 100, 10..10).await.is_err());
+		assert!(create(RECIPIENT, 0, 0..10).await.is_err());
+		assert!(create(SENDER, 100, 0..10).await.is_err());
+		assert!(create(ESCROW, 100, 0..10).await.is_err());
+		assert!(create(RECIPIENT, 2000, 0..10).await.is_err()); // Not enough funds
+
+		let nft = streams
+			.create(
+				env.as_ref(),
+				SENDER,
+				RECIPIENT,
+				Asset::ERC721 { token: TOKEN },
+				Uint::one(),
+				0..10,
+			)
+			.await;
+		assert!(nft.is_err());
+		assert_eq!(tester.ether_balance(SENDER).await, Uint::from(1000));
+		assert!(streams.streams_of(SENDER).is_empty());
+	}
+
+	#[async_std::test]
+	async fn test_ether_stream_withdrawals() {
+		let tester = funded_tester().await;
+		let env = tester.env();
+		let streams = Streams::new(ESCROW);
+
+		let id = streams
+			.create(env.as_ref(), SENDER, RECIPIENT, Asset::Ether, Uint::from(600), 100..160)
+			.await
+			.unwrap();
+		assert_eq!(tester.ether_balance(SENDER).await, Uint::from(400));
+		assert_eq!(tester.ether_balance(ESCROW).await, Uint::from(600));
+		assert_eq!(streams.streams_of(RECIPIENT).len(), 1);
+
+		assert!(streams.withdraw(env.as_ref(), SENDER, id, 130).await.is_err());
+		assert_eq!(
+			streams.withdraw(env.as_ref(), RECIPIENT, id, 90).await.unwrap(),
+			Uint::zero()
+		);
+		assert_eq!(
+			streams.withdraw(env.as_ref(), RECIPIENT, id, 130).await.unwrap(),
+			Uint::from(300)
+		);
+		assert_eq!(
+			streams.withdraw(env.as_ref(), RECIPIENT, id, 130).await.unwrap(),
+			Uint::zero()
+		);
+		assert_eq!(streams.get(id).unwrap().withdrawn, Uint::from(300));
+
+		assert_eq!(
+			streams.withdraw(env.as_ref(), RECIPIENT, id, 1000).await.unwrap(),
+			Uint::from(300)
+		);
+		assert_eq!(tester.ether_balance(RECIPIENT).await, Uint::from(600));
+		assert_eq!(tester.ether_balance(ESCROW).await, Uint::zero());
+		assert!(streams.get(id).is_none()); // Fully withdrawn streams are removed
+	}
+
+	#[cfg(feature = "erc20")]
+	#[async_std::test]
+	async fn test_erc20_stream_cancel() {
+		let tester = funded_tester().await;
+		let env = tester.env();
+		let streams = Streams::new(ESCROW);
+		let asset = Asset::ERC20 { token: TOKEN };
+
+		let id = streams
+			.create(env.as_ref(), SENDER, RECIPIENT, asset, Uint::from(1000), 0..100)
+			.await
+			.unwrap();
+		streams.withdraw(env.as_ref(), RECIPIENT, id, 10).await.unwrap();
+
+		assert!(streams
+			.cancel(env.as_ref(), Address::repeat_byte(0xcc), id, 40)
+			.await
+			.is_err());
+		assert_eq!(
+			streams.cancel(env.as_ref(), SENDER, id, 40).await.unwrap(),
+			(Uint::from(300), Uint::from(600))
+		);
+		assert_eq!(tester.erc20_balance(RECIPIENT, TOKEN).await, Uint::from(400));
+		assert_eq!(tester.erc20_balance(SENDER, TOKEN).await, Uint::from(600));
+		assert_eq!(tester.erc20_balance(ESCROW, TOKEN).await, Uint::zero());
+		assert!(streams.get(id).is_none());
+		assert!(streams.cancel(env.as_ref(), SENDER, id, 40).await.is_err());
+	}
+
+	#[async_std::test]
+	async fn test_cancel_by_recipient_before_start() {
+		let tester = funded_tester().await;
+		let env = tester.env();
+		let streams = Streams::new(ESCROW);
+
+		let first = streams
+			.create(env.as_ref(), SENDER, RECIPIENT, Asset::Ether, Uint::from(100), 50..150)
+			.await
+			.unwrap();
+		let second = streams
+			.create(env.as_ref(), SENDER, RECIPIENT, Asset::Ether, Uint::from(100), 0..10)
+			.await
+			.unwrap();
+		assert_ne!(first, second);
+
+		assert_eq!(
+			streams.cancel(env.as_ref(), RECIPIENT, first, 20).await.unwrap(),
+			(Uint::zero(), Uint::from(100))
+		);
+		assert_eq!(tester.ether_balance(SENDER).await, Uint::from(900));
+		assert_eq!(streams.streams_of(SENDER).len(), 1);
+	}
+}
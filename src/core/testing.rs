@@ -1,38 +1,90 @@
-use async_std::sync::{Mutex, RwLock};
-use ethabi::{Address, Uint};
-use std::{error::Error, sync::Arc, time::UNIX_EPOCH};
+use async_lock::{Mutex, RwLock};
+use crate::types::primitives::*;
+use ethabi::{Hash, ParamType};
+use serde::Serialize;
+use std::{
+	collections::{HashMap, HashSet},
+	error::Error,
+	future::Future,
+	sync::Arc,
+};
 
 use crate::{
 	address,
 	types::{
 		address_book::AddressBook,
-		machine::{Deposit, FinishStatus, Output, PortalHandlerConfig},
+		balance::{BalanceChange, BalanceChangeKind, DepositReceipt},
+		error::CrabrollsError,
+		machine::{
+			Deposit, DepositRejectPolicy, FinishStatus, GioRequest, GioResponse, Output, OutputLimits,
+			PortalHandlerConfig, PreProcess, SenderPolicy, VoucherDedupPolicy,
+		},
 		testing::{AdvanceResult, InspectResult},
 	},
+	utils::{abi::abi, codec, hash},
 	Application, Environment, Metadata,
 };
 
+#[cfg(feature = "erc1155")]
+use super::contracts::erc1155::{ERC1155Environment, ERC1155Wallet, IntoIdsAmountsIter, IntoIdsIter};
+#[cfg(feature = "erc20")]
+use super::contracts::erc20::{ERC20Environment, ERC20Wallet};
+#[cfg(feature = "erc721")]
+use super::contracts::erc721::{ERC721Environment, ERC721Wallet, TokenMetadata};
+#[cfg(feature = "memory-tracking")]
+use super::memory::{self, MemoryLimit};
 use super::{
-	context::handle_portals,
+	about::{About, BuildInfo, ABOUT_ROUTE},
+	activity::{ActivityCounters, InputKind},
+	cache::InspectCache,
+	commitment::StateCommitments,
+	context::{
+		begin_wallet_transaction, end_wallet_transaction, handle_portals, keep_deposit_credit, permits_sender,
+		pre_process, settle_rejected_deposit, PreProcessor, Selector,
+	},
 	contracts::{
-		erc1155::{ERC1155Environment, ERC1155Wallet, IntoIdsAmountsIter},
-		erc20::{ERC20Environment, ERC20Wallet},
-		erc721::{ERC721Environment, ERC721Wallet},
+		decoder::{DepositDecoder, DepositDecoders},
+		erc4626::{ERC4626Environment, ERC4626Wallet},
 		ether::{EtherEnvironment, EtherWallet},
 	},
 	environment::RollupInternalEnvironment,
+	guards::VoucherGuard,
+	handoff::WalletsSnapshot,
+	idempotency::{DedupWindow, IdempotencyKeys},
+	middleware::{Middleware, Middlewares},
+	schedule::VoucherSchedule,
 };
 
+/// Answers the GIO requests of a domain on the mockup, standing for the node.
+pub type GioHandler = Arc<dyn Fn(&[u8]) -> Result<GioResponse, Box<dyn Error>> + Send + Sync>;
+
 pub struct RollupMockup {
 	outputs: RwLock<Vec<Output>>,
 	input_index: Mutex<u64>,
+	inspecting: RwLock<bool>,
+	illegal_outputs: RwLock<Vec<Output>>,
+	vouchers: RwLock<Vec<Output>>,
+	output_counts: RwLock<HashMap<&'static str, u64>>, // Outputs of accepted inputs, per type
+	executed_vouchers: RwLock<HashSet<u64>>,
 	app_address: Address,
 	address_book: AddressBook,
+	output_limits: OutputLimits,
+	voucher_guard: Mutex<VoucherGuard>,
+	legacy_vouchers: bool,
+	balance_notices: bool,
+	build_info: Option<BuildInfo>,
+	gio_handlers: HashMap<u16, GioHandler>,
+	gio_requests: RwLock<Vec<GioRequest>>,
 
 	ether_wallet: Arc<RwLock<EtherWallet>>,
+	#[cfg(feature = "erc20")]
 	erc20_wallet: Arc<RwLock<ERC20Wallet>>,
+	#[cfg(feature = "erc721")]
 	erc721_wallet: Arc<RwLock<ERC721Wallet>>,
+	#[cfg(feature = "erc1155")]
 	erc1155_wallet: Arc<RwLock<ERC1155Wallet>>,
+	erc4626_wallet: Arc<RwLock<ERC4626Wallet>>,
+	activity: Arc<RwLock<ActivityCounters>>,
 }
 
 impl RollupMockup {
@@ -40,19 +92,74 @@ impl RollupMockup {
 		RollupMockup {
 			outputs: RwLock::new(Vec::new()),
 			input_index: Mutex::new(0),
+			inspecting: RwLock::new(false),
+			illegal_outputs: RwLock::new(Vec::new()),
+			vouchers: RwLock::new(Vec::new()),
+			output_counts: RwLock::new(HashMap::new()),
+			executed_vouchers: RwLock::new(HashSet::new()),
 			address_book: AddressBook::default(),
+			output_limits: OutputLimits::default(),
+			voucher_guard: Mutex::new(VoucherGuard::new(VoucherDedupPolicy::default())),
+			legacy_vouchers: true,
+			balance_notices: false,
+			build_info: None,
+			gio_handlers: HashMap::new(),
+			gio_requests: RwLock::new(Vec::new()),
 			app_address: address!("0xab7528bb862fb57e8a2bcd567a2e929a0be56a5e"),
 			ether_wallet: Arc::new(RwLock::new(EtherWallet::new())),
+			#[cfg(feature = "erc20")]
 			erc20_wallet: Arc::new(RwLock::new(ERC20Wallet::new())),
+			#[cfg(feature = "erc721")]
 			erc721_wallet: Arc::new(RwLock::new(ERC721Wallet::new())),
+			#[cfg(feature = "erc1155")]
 			erc1155_wallet: Arc::new(RwLock::new(ERC1155Wallet::new())),
+			erc4626_wallet: Arc::new(RwLock::new(ERC4626Wallet::new())),
+			activity: Arc::new(RwLock::new(ActivityCounters::default())),
 		}
 	}
 
-	pub async fn handle(&self, output: Output) -> Result<i32, Box<dyn Error>> {
+	pub fn with_options(mockup_options: &MockupOptions) -> Self {
+		let mut env = RollupMockup::new();
+		env.output_limits = mockup_options.output_limits;
+		env.voucher_guard = Mutex::new(VoucherGuard::new(mockup_options.voucher_dedup));
+		env.legacy_vouchers = mockup_options.legacy_vouchers;
+		env.balance_notices = mockup_options.balance_notices;
+		env.build_info = mockup_options.build_info.clone();
+		env.gio_handlers = mockup_options.gio_handlers.clone();
+		env
+	}
+
+	pub async fn handle(&self, output: Output) -> Result<u64, CrabrollsError> {
+		self.ensure_not_inspecting(&output).await?;
+		self.output_limits.check(&output)?;
 		let mut outputs = self.outputs.write().await;
+		let pending = outputs.iter().filter(|pending| pending.kind() == output.kind()).count() as u64;
+		let index = self.output_count(output.kind()).await + pending;
 		outputs.push(output);
-		Ok(outputs.len().try_into()?)
+		Ok(index)
+	}
+
+	/// GIO requests sent by the application, in order.
+	pub async fn gio_requests(&self) -> Vec<GioRequest> {
+		self.gio_requests.read().await.clone()
+	}
+
+	fn check_output_format(&self, output: &Output) -> Result<(), CrabrollsError> {
+		match self.legacy_vouchers {
+			true => Ok(output.check_v1()?),
+			false => Ok(()),
+		}
+	}
+
+	/// Checks the voucher against the dedup policy, returning whether its duplicate must be reported.
+	async fn guard_voucher(&self, voucher: &Output) -> Result<bool, CrabrollsError> {
+		let mut voucher_guard = self.voucher_guard.lock().await;
+		let duplicate = voucher_guard.check(voucher)?;
+		Ok(duplicate && voucher_guard.policy() == VoucherDedupPolicy::Report)
+	}
+
+	pub async fn output_count(&self, kind: &str) -> u64 {
+		self.output_counts.read().await.get(kind).copied().unwrap_or(0)
 	}
 
 	async fn reset(&self) {
@@ -66,45 +173,162 @@ impl RollupMockup {
 
 		let outputs = self.outputs.read().await.clone();
 		self.reset().await;
+		self.voucher_guard.lock().await.finish(status);
 
 		match status {
-			FinishStatus::Accept => Ok(Some(outputs)),
+			FinishStatus::Accept => {
+				let mut output_counts = self.output_counts.write().await;
+				for output in &outputs {
+					*output_counts.entry(output.kind()).or_insert(0) += 1;
+				}
+				drop(output_counts);
+				let vouchers = outputs.iter().filter(|output| matches!(output, Output::Voucher { .. }));
+				self.vouchers.write().await.extend(vouchers.cloned());
+				Ok(Some(outputs))
+			}
 			FinishStatus::Reject => Ok(None),
 		}
 	}
 
+	pub async fn vouchers(&self) -> Vec<Output> {
+		self.vouchers.read().await.clone()
+	}
+
+	pub async fn execute_voucher(&self, index: u64) -> Result<Output, Box<dyn Error>> {
+		let vouchers = self.vouchers.read().await;
+		let voucher = usize::try_from(index)
+			.ok()
+			.and_then(|index| vouchers.get(index).cloned())
+			.ok_or_else(|| format!("voucher {} not found", index))?;
+		if !self.executed_vouchers.write().await.insert(index) {
+			return Err(format!("voucher {} was already executed", index).into());
+		}
+		Ok(voucher)
+	}
+
+	pub async fn was_executed(&self, index: u64) -> bool {
+		self.executed_vouchers.read().await.contains(&index)
+	}
+
+	pub async fn begin_inspect(&self) {
+		*self.inspecting.write().await = true;
+	}
+
+	pub async fn inspect(&self) -> (Vec<Output>, Vec<Output>) {
+		let outputs = self.outputs.read().await.clone();
+		self.reset().await;
+		*self.inspecting.write().await = false;
+		let illegal_outputs = std::mem::take(&mut *self.illegal_outputs.write().await);
+		(outputs, illegal_outputs)
+	}
+
+	pub(crate) async fn notify_balance_change(&self, change: BalanceChange) -> Result<(), CrabrollsError> {
+		if self.balance_notices && !*self.inspecting.read().await {
+			self.send_notice(change.to_bytes()).await?;
+		}
+		Ok(())
+	}
+
+	async fn ensure_not_inspecting(&self, output: &Output) -> Result<(), CrabrollsError> {
+		if *self.inspecting.read().await && !matches!(output, Output::Report { .. }) {
+			self.illegal_outputs.write().await.push(output.clone());
+			return Err(CrabrollsError::other(format!("can't emit a {} during inspect", output.kind())));
+		}
+		Ok(())
+	}
+
 	pub async fn get_input_index(&self) -> u64 {
 		*self.input_index.lock().await
 	}
 }
 
+impl Default for RollupMockup {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
 impl Environment for RollupMockup {
-	async fn send_voucher(
+	async fn send_voucher_with_value(
 		&self,
 		destination: Address,
+		value: Uint,
 		payload: impl AsRef<[u8]> + Send,
-	) -> Result<i32, Box<dyn Error>> {
-		self.handle(Output::Voucher {
+	) -> Result<Option<u64>, CrabrollsError> {
+		let voucher = Output::Voucher {
 			destination,
+			value,
 			payload: payload.as_ref().to_vec(),
-		})
-		.await
+		};
+		self.ensure_not_inspecting(&voucher).await?;
+		self.check_output_format(&voucher)?;
+		self.output_limits.check(&voucher)?;
+		let report_duplicate = self.guard_voucher(&voucher).await?;
+
+		let index = self.handle(voucher).await?;
+		if report_duplicate {
+			self.send_report(format!("duplicate voucher to {:?}", destination))
+				.await?;
+		}
+		Ok(Some(index))
+	}
+
+	async fn send_delegate_call_voucher(
+		&self,
+		destination: Address,
+		payload: impl AsRef<[u8]> + Send,
+	) -> Result<Option<u64>, CrabrollsError> {
+		let voucher = Output::DelegateCallVoucher {
+			destination,
+			payload: payload.as_ref().to_vec(),
+		};
+		self.ensure_not_inspecting(&voucher).await?;
+		self.check_output_format(&voucher)?;
+		self.output_limits.check(&voucher)?;
+		let report_duplicate = self.guard_voucher(&voucher).await?;
+
+		let index = self.handle(voucher).await?;
+		if report_duplicate {
+			self.send_report(format!("duplicate delegate call voucher to {:?}", destination))
+				.await?;
+		}
+		Ok(Some(index))
 	}
 
-	async fn send_notice(&self, payload: impl AsRef<[u8]> + Send) -> Result<i32, Box<dyn Error>> {
+	async fn send_notice(&self, payload: impl AsRef<[u8]> + Send) -> Result<Option<u64>, CrabrollsError> {
 		self.handle(Output::Notice {
 			payload: payload.as_ref().to_vec(),
 		})
 		.await
+		.map(Some)
 	}
 
-	async fn send_report(&self, payload: impl AsRef<[u8]> + Send) -> Result<(), Box<dyn Error>> {
-		self.handle(Output::Report {
-			payload: payload.as_ref().to_vec(),
-		})
-		.await?;
+	async fn send_report(&self, payload: impl AsRef<[u8]> + Send) -> Result<(), CrabrollsError> {
+		let chunks = self.output_limits.report_chunks(payload.as_ref())?;
+		for chunk in chunks {
+			self.handle(Output::Report {
+				payload: chunk.to_vec(),
+			})
+			.await?;
+		}
 		Ok(())
 	}
+
+	async fn gio(&self, domain: u16, payload: impl AsRef<[u8]> + Send) -> Result<GioResponse, CrabrollsError> {
+		self.gio_requests.write().await.push(GioRequest {
+			domain,
+			payload: payload.as_ref().to_vec(),
+		});
+		let handler = self
+			.gio_handlers
+			.get(&domain)
+			.ok_or_else(|| CrabrollsError::other(format!("no GIO handler for domain {}", domain)))?;
+		Ok(handler(payload.as_ref())?)
+	}
+
+	fn build_info(&self) -> Option<BuildInfo> {
+		self.build_info.clone()
+	}
 }
 
 impl EtherEnvironment for RollupMockup {
@@ -112,18 +336,39 @@ impl EtherEnvironment for RollupMockup {
 		self.ether_wallet.read().await.addresses()
 	}
 
-	async fn ether_withdraw(&self, address: Address, value: Uint) -> Result<(), Box<dyn Error>> {
+	async fn ether_withdraw(&self, address: Address, value: Uint) -> Result<(), CrabrollsError> {
 		let mut ether_wallet = self.ether_wallet.write().await;
 		let payload = ether_wallet.withdraw(address, value)?;
 
-		self.send_voucher(self.app_address, payload).await?;
+		let sent = match self.legacy_vouchers {
+			true => self.send_voucher(self.app_address, payload).await,
+			false => self.send_voucher_with_value(address, value, Vec::new()).await,
+		};
+		if let Err(e) = sent {
+			ether_wallet.cancel_withdraw(address, value);
+			return Err(e);
+		}
+		self.notify_balance_change(BalanceChange::ether(
+			BalanceChangeKind::Withdraw,
+			Some(address),
+			None,
+			value,
+		))
+		.await?;
 
 		Ok(())
 	}
 
-	async fn ether_transfer(&self, source: Address, destination: Address, value: Uint) -> Result<(), Box<dyn Error>> {
+	async fn ether_transfer(&self, source: Address, destination: Address, value: Uint) -> Result<(), CrabrollsError> {
 		let mut ether_wallet = self.ether_wallet.write().await;
 		ether_wallet.transfer(source, destination, value)?;
+		self.notify_balance_change(BalanceChange::ether(
+			BalanceChangeKind::Transfer,
+			Some(source),
+			Some(destination),
+			value,
+		))
+		.await?;
 
 		Ok(())
 	}
@@ -131,8 +376,13 @@ impl EtherEnvironment for RollupMockup {
 	async fn ether_balance(&self, address: Address) -> Uint {
 		self.ether_wallet.read().await.balance_of(address)
 	}
+
+	async fn ether_total_supply(&self) -> Uint {
+		self.ether_wallet.read().await.total_supply()
+	}
 }
 
+#[cfg(feature = "erc20")]
 impl ERC20Environment for RollupMockup {
 	async fn erc20_addresses(&self) -> Vec<Address> {
 		self.erc20_wallet.read().await.addresses()
@@ -143,11 +393,22 @@ impl ERC20Environment for RollupMockup {
 		wallet_address: Address,
 		token_address: Address,
 		value: Uint,
-	) -> Result<(), Box<dyn Error>> {
+	) -> Result<(), CrabrollsError> {
 		let mut erc20_wallet = self.erc20_wallet.write().await;
 		let payload = erc20_wallet.withdraw(wallet_address, token_address, value)?;
 
-		self.send_voucher(token_address, payload).await?;
+		if let Err(e) = self.send_voucher(token_address, payload).await {
+			erc20_wallet.cancel_withdraw(wallet_address, token_address, value);
+			return Err(e);
+		}
+		self.notify_balance_change(BalanceChange::erc20(
+			BalanceChangeKind::Withdraw,
+			token_address,
+			Some(wallet_address),
+			None,
+			value,
+		))
+		.await?;
 
 		Ok(())
 	}
@@ -158,9 +419,17 @@ impl ERC20Environment for RollupMockup {
 		dst_wallet: Address,
 		token_address: Address,
 		value: Uint,
-	) -> Result<(), Box<dyn Error>> {
+	) -> Result<(), CrabrollsError> {
 		let mut erc20_wallet = self.erc20_wallet.write().await;
 		erc20_wallet.transfer(src_wallet, dst_wallet, token_address, value)?;
+		self.notify_balance_change(BalanceChange::erc20(
+			BalanceChangeKind::Transfer,
+			token_address,
+			Some(src_wallet),
+			Some(dst_wallet),
+			value,
+		))
+		.await?;
 
 		Ok(())
 	}
@@ -170,6 +439,66 @@ impl ERC20Environment for RollupMockup {
 	}
 }
 
+impl ERC4626Environment for RollupMockup {
+	async fn erc4626_addresses(&self) -> Vec<Address> {
+		self.erc4626_wallet.read().await.addresses()
+	}
+
+	async fn erc4626_withdraw(
+		&self,
+		wallet_address: Address,
+		vault_address: Address,
+		shares: Uint,
+	) -> Result<(), CrabrollsError> {
+		let mut erc4626_wallet = self.erc4626_wallet.write().await;
+		let payload = erc4626_wallet.withdraw(wallet_address, vault_address, shares)?;
+
+		if let Err(e) = self.send_voucher(vault_address, payload).await {
+			erc4626_wallet.cancel_withdraw(wallet_address, vault_address, shares);
+			return Err(e);
+		}
+		self.notify_balance_change(BalanceChange::erc20(
+			BalanceChangeKind::Withdraw,
+			vault_address,
+			Some(wallet_address),
+			None,
+			shares,
+		))
+		.await?;
+
+		Ok(())
+	}
+
+	async fn erc4626_transfer(
+		&self,
+		src_wallet: Address,
+		dst_wallet: Address,
+		vault_address: Address,
+		shares: Uint,
+	) -> Result<(), CrabrollsError> {
+		let mut erc4626_wallet = self.erc4626_wallet.write().await;
+		erc4626_wallet.transfer(src_wallet, dst_wallet, vault_address, shares)?;
+		self.notify_balance_change(BalanceChange::erc20(
+			BalanceChangeKind::Transfer,
+			vault_address,
+			Some(src_wallet),
+			Some(dst_wallet),
+			shares,
+		))
+		.await?;
+
+		Ok(())
+	}
+
+	async fn erc4626_balance(&self, wallet_address: Address, vault_address: Address) -> Uint {
+		self.erc4626_wallet
+			.read()
+			.await
+			.balance_of(wallet_address, vault_address)
+	}
+}
+
+#[cfg(feature = "erc721")]
 impl ERC721Environment for RollupMockup {
 	async fn erc721_addresses(&self) -> Vec<Address> {
 		self.erc721_wallet.read().await.addresses()
@@ -180,11 +509,23 @@ impl ERC721Environment for RollupMockup {
 		wallet_address: Address,
 		token_address: Address,
 		token_id: Uint,
-	) -> Result<(), Box<dyn Error>> {
+	) -> Result<(), CrabrollsError> {
 		let mut erc721_wallet = self.erc721_wallet.write().await;
+		let metadata = erc721_wallet.metadata_of(token_address, token_id).cloned();
 		let payload = erc721_wallet.withdraw(self.app_address, wallet_address, token_address, token_id)?;
 
-		self.send_voucher(token_address, payload).await?;
+		if let Err(e) = self.send_voucher(token_address, payload).await {
+			erc721_wallet.cancel_withdraw(wallet_address, token_address, token_id, metadata);
+			return Err(e);
+		}
+		self.notify_balance_change(BalanceChange::erc721(
+			BalanceChangeKind::Withdraw,
+			token_address,
+			Some(wallet_address),
+			None,
+			token_id,
+		))
+		.await?;
 
 		Ok(())
 	}
@@ -195,9 +536,17 @@ impl ERC721Environment for RollupMockup {
 		dst_wallet: Address,
 		token_address: Address,
 		token_id: Uint,
-	) -> Result<(), Box<dyn Error>> {
+	) -> Result<(), CrabrollsError> {
 		let mut erc721_wallet = self.erc721_wallet.write().await;
 		erc721_wallet.transfer(src_wallet, dst_wallet, token_address, token_id)?;
+		self.notify_balance_change(BalanceChange::erc721(
+			BalanceChangeKind::Transfer,
+			token_address,
+			Some(src_wallet),
+			Some(dst_wallet),
+			token_id,
+		))
+		.await?;
 
 		Ok(())
 	}
@@ -205,8 +554,38 @@ impl ERC721Environment for RollupMockup {
 	async fn erc721_owner_of(&self, token_address: Address, token_id: Uint) -> Option<Address> {
 		self.erc721_wallet.read().await.owner_of(token_address, token_id)
 	}
+
+	async fn erc721_tokens_of(&self, owner: Address) -> Vec<(Address, Uint)> {
+		self.erc721_wallet.read().await.tokens_of(owner)
+	}
+
+	async fn erc721_tokens_of_collection(&self, owner: Address, token_address: Address) -> Vec<Uint> {
+		self.erc721_wallet
+			.read()
+			.await
+			.tokens_of_collection(owner, token_address)
+	}
+
+	async fn erc721_set_metadata(
+		&self,
+		token_address: Address,
+		token_id: Uint,
+		metadata: TokenMetadata,
+	) -> Result<(), CrabrollsError> {
+		let mut erc721_wallet = self.erc721_wallet.write().await;
+		erc721_wallet.set_metadata(token_address, token_id, metadata)
+	}
+
+	async fn erc721_metadata_of(&self, token_address: Address, token_id: Uint) -> Option<TokenMetadata> {
+		self.erc721_wallet
+			.read()
+			.await
+			.metadata_of(token_address, token_id)
+			.cloned()
+	}
 }
 
+#[cfg(feature = "erc1155")]
 impl ERC1155Environment for RollupMockup {
 	async fn erc1155_addresses(&self) -> Vec<Address> {
 		self.erc1155_wallet.read().await.addresses()
@@ -218,14 +597,32 @@ impl ERC1155Environment for RollupMockup {
 		token_address: Address,
 		withdrawals: I,
 		data: Option<Vec<u8>>,
-	) -> Result<(), Box<dyn Error>>
+	) -> Result<(), CrabrollsError>
 	where
 		I: IntoIdsAmountsIter,
 	{
+		let withdrawals: Vec<(Uint, Uint)> = withdrawals.into_inner_iter().collect();
 		let mut erc1155_wallet = self.erc1155_wallet.write().await;
-		let payload = erc1155_wallet.withdraw(self.app_address, wallet_address, token_address, withdrawals, data)?;
-
-		self.send_voucher(token_address, payload).await?;
+		let payload = erc1155_wallet.withdraw(
+			self.app_address,
+			wallet_address,
+			token_address,
+			withdrawals.clone(),
+			data,
+		)?;
+
+		if let Err(e) = self.send_voucher(token_address, payload).await {
+			erc1155_wallet.cancel_withdraw(wallet_address, token_address, &withdrawals);
+			return Err(e);
+		}
+		self.notify_balance_change(BalanceChange::erc1155(
+			BalanceChangeKind::Withdraw,
+			token_address,
+			Some(wallet_address),
+			None,
+			&withdrawals,
+		))
+		.await?;
 
 		Ok(())
 	}
@@ -236,12 +633,21 @@ impl ERC1155Environment for RollupMockup {
 		dst_wallet: Address,
 		token_address: Address,
 		transfers: I,
-	) -> Result<(), Box<dyn Error>>
+	) -> Result<(), CrabrollsError>
 	where
 		I: IntoIdsAmountsIter,
 	{
+		let transfers: Vec<(Uint, Uint)> = transfers.into_inner_iter().collect();
 		let mut erc1155_wallet = self.erc1155_wallet.write().await;
-		erc1155_wallet.transfer(src_wallet, dst_wallet, token_address, transfers)?;
+		erc1155_wallet.transfer(src_wallet, dst_wallet, token_address, transfers.clone())?;
+		self.notify_balance_change(BalanceChange::erc1155(
+			BalanceChangeKind::Transfer,
+			token_address,
+			Some(src_wallet),
+			Some(dst_wallet),
+			&transfers,
+		))
+		.await?;
 
 		Ok(())
 	}
@@ -252,17 +658,69 @@ impl ERC1155Environment for RollupMockup {
 			.await
 			.balance_of(wallet_address, token_address, token_id)
 	}
+
+	async fn erc1155_balance_of_batch<I>(
+		&self,
+		wallet_address: Address,
+		token_address: Address,
+		token_ids: I,
+	) -> Vec<Uint>
+	where
+		I: IntoIdsIter,
+	{
+		self.erc1155_wallet
+			.read()
+			.await
+			.balance_of_batch(wallet_address, token_address, token_ids)
+	}
+}
+
+pub const DEVNET_CHAIN_ID: u64 = 31337;
+
+/// Stand-in for the RANDAO mix of the block of an input, deterministic so tests can reproduce it.
+pub fn prev_randao(input_index: u64) -> Hash {
+	Hash::from(hash::keccak256(input_index.to_be_bytes()))
+}
+
+/// Seconds since the epoch for the input timestamps, from the JS clock in the browser where `SystemTime` panics.
+fn now() -> u64 {
+	#[cfg(all(target_arch = "wasm32", feature = "wasm"))]
+	return (js_sys::Date::now() / 1000.0) as u64;
+	#[cfg(not(all(target_arch = "wasm32", feature = "wasm")))]
+	return std::time::UNIX_EPOCH.elapsed().unwrap().as_secs();
 }
 
 pub struct MockupOptions {
 	pub portal_config: PortalHandlerConfig,
+	pub deposit_decoders: DepositDecoders,
+	pub deposit_reject_policy: DepositRejectPolicy,
+	pub sender_policy: SenderPolicy,
+	pub sender_reports: bool, // Send an `UNAUTHORIZED` report for the inputs rejected by the sender policy
+	pub transactional_wallets: bool,
+	pub chain_id: Option<u64>, // Chain id of the inputs, the local devnet one by default
+	pub output_limits: OutputLimits,
+	pub voucher_dedup: VoucherDedupPolicy,
+	pub legacy_vouchers: bool, // Withdraw ether with the `withdrawEther` vouchers of the rollups node v1, on by default
+	pub pre_processor: Option<PreProcessor>,
+	pub middlewares: Middlewares,
+	pub inspect_cache: Option<usize>,
+	pub balance_notices: bool,
+	pub idempotency_keys: Option<IdempotencyKeys>,
+	pub dedup_window: Option<DedupWindow>,
+	pub deposit_receipts: bool,
+	pub hex_payloads: bool,
+	pub about: bool,
+	pub build_info: Option<BuildInfo>,
+	pub gio_handlers: HashMap<u16, GioHandler>,
+	pub voucher_schedule: Option<VoucherSchedule>,
+	pub state_commitments: Option<StateCommitments>,
+	#[cfg(feature = "memory-tracking")]
+	pub memory_limit: Option<MemoryLimit>,
 }
 
 impl Default for MockupOptions {
 	fn default() -> Self {
-		Self {
-			portal_config: PortalHandlerConfig::default(),
-		}
+		MockupOptionsBuilder::default().build()
 	}
 }
 
@@ -274,12 +732,60 @@ impl MockupOptions {
 
 pub struct MockupOptionsBuilder {
 	portal_config: PortalHandlerConfig,
+	deposit_decoders: DepositDecoders,
+	deposit_reject_policy: DepositRejectPolicy,
+	sender_policy: SenderPolicy,
+	sender_reports: bool,
+	transactional_wallets: bool,
+	chain_id: Option<u64>,
+	output_limits: OutputLimits,
+	voucher_dedup: VoucherDedupPolicy,
+	legacy_vouchers: bool,
+	pre_processor: Option<PreProcessor>,
+	middlewares: Middlewares,
+	inspect_cache: Option<usize>,
+	balance_notices: bool,
+	idempotency_keys: Option<IdempotencyKeys>,
+	dedup_window: Option<DedupWindow>,
+	deposit_receipts: bool,
+	hex_payloads: bool,
+	about: bool,
+	build_info: Option<BuildInfo>,
+	gio_handlers: HashMap<u16, GioHandler>,
+	voucher_schedule: Option<VoucherSchedule>,
+	state_commitments: Option<StateCommitments>,
+	#[cfg(feature = "memory-tracking")]
+	memory_limit: Option<MemoryLimit>,
 }
 
 impl Default for MockupOptionsBuilder {
 	fn default() -> Self {
 		Self {
-			portal_config: PortalHandlerConfig::default(),
+			portal_config: Default::default(),
+			deposit_decoders: Default::default(),
+			deposit_reject_policy: Default::default(),
+			sender_policy: Default::default(),
+			sender_reports: Default::default(),
+			transactional_wallets: Default::default(),
+			chain_id: Default::default(),
+			output_limits: Default::default(),
+			voucher_dedup: Default::default(),
+			legacy_vouchers: true,
+			pre_processor: Default::default(),
+			middlewares: Default::default(),
+			inspect_cache: Default::default(),
+			balance_notices: Default::default(),
+			idempotency_keys: Default::default(),
+			dedup_window: Default::default(),
+			deposit_receipts: Default::default(),
+			hex_payloads: Default::default(),
+			about: Default::default(),
+			build_info: Default::default(),
+			gio_handlers: Default::default(),
+			voucher_schedule: Default::default(),
+			state_commitments: Default::default(),
+			#[cfg(feature = "memory-tracking")]
+			memory_limit: Default::default(),
 		}
 	}
 }
@@ -290,9 +796,156 @@ impl MockupOptionsBuilder {
 		self
 	}
 
+	pub fn deposit_decoder(mut self, decoder: impl DepositDecoder + 'static) -> Self {
+		self.deposit_decoders.register(decoder);
+		self
+	}
+
+	pub fn deposit_reject_policy(mut self, deposit_reject_policy: DepositRejectPolicy) -> Self {
+		self.deposit_reject_policy = deposit_reject_policy;
+		self
+	}
+
+	pub fn sender_policy(mut self, sender_policy: SenderPolicy) -> Self {
+		self.sender_policy = sender_policy;
+		self
+	}
+
+	pub fn sender_reports(mut self, sender_reports: bool) -> Self {
+		self.sender_reports = sender_reports;
+		self
+	}
+
+	pub fn transactional_wallets(mut self, transactional_wallets: bool) -> Self {
+		self.transactional_wallets = transactional_wallets;
+		self
+	}
+
+	pub fn chain_id(mut self, chain_id: u64) -> Self {
+		self.chain_id = Some(chain_id);
+		self
+	}
+
+	pub fn output_limits(mut self, output_limits: OutputLimits) -> Self {
+		self.output_limits = output_limits;
+		self
+	}
+
+	pub fn voucher_dedup(mut self, voucher_dedup: VoucherDedupPolicy) -> Self {
+		self.voucher_dedup = voucher_dedup;
+		self
+	}
+
+	pub fn legacy_vouchers(mut self, legacy_vouchers: bool) -> Self {
+		self.legacy_vouchers = legacy_vouchers;
+		self
+	}
+
+	pub fn pre_processor<F, Fut>(mut self, hook: F) -> Self
+	where
+		F: Fn(Metadata, Vec<u8>) -> Fut + Send + Sync + 'static,
+		Fut: Future<Output = Result<PreProcess, Box<dyn Error>>> + 'static,
+	{
+		self.pre_processor = Some(PreProcessor::new(hook));
+		self
+	}
+
+	pub fn middleware(mut self, middleware: impl Middleware + 'static) -> Self {
+		self.middlewares.register(middleware);
+		self
+	}
+
+	pub fn inspect_cache(mut self, capacity: usize) -> Self {
+		self.inspect_cache = Some(capacity);
+		self
+	}
+
+	pub fn balance_notices(mut self, balance_notices: bool) -> Self {
+		self.balance_notices = balance_notices;
+		self
+	}
+
+	pub fn idempotency_keys(mut self, idempotency_keys: IdempotencyKeys) -> Self {
+		self.idempotency_keys = Some(idempotency_keys);
+		self
+	}
+
+	pub fn dedup_window(mut self, dedup_window: DedupWindow) -> Self {
+		self.dedup_window = Some(dedup_window);
+		self
+	}
+
+	pub fn deposit_receipts(mut self, deposit_receipts: bool) -> Self {
+		self.deposit_receipts = deposit_receipts;
+		self
+	}
+
+	pub fn hex_payloads(mut self, hex_payloads: bool) -> Self {
+		self.hex_payloads = hex_payloads;
+		self
+	}
+
+	pub fn about(mut self, about: bool) -> Self {
+		self.about = about;
+		self
+	}
+
+	pub fn build_info(mut self, build_info: BuildInfo) -> Self {
+		self.build_info = Some(build_info);
+		self
+	}
+
+	pub fn gio_handler<F>(mut self, domain: u16, handler: F) -> Self
+	where
+		F: Fn(&[u8]) -> Result<GioResponse, Box<dyn Error>> + Send + Sync + 'static,
+	{
+		self.gio_handlers.insert(domain, Arc::new(handler));
+		self
+	}
+
+	pub fn voucher_schedule(mut self, voucher_schedule: VoucherSchedule) -> Self {
+		self.voucher_schedule = Some(voucher_schedule);
+		self
+	}
+
+	pub fn state_commitments(mut self, state_commitments: StateCommitments) -> Self {
+		self.state_commitments = Some(state_commitments);
+		self
+	}
+
+	#[cfg(feature = "memory-tracking")]
+	pub fn memory_limit(mut self, memory_limit: MemoryLimit) -> Self {
+		self.memory_limit = Some(memory_limit);
+		self
+	}
+
 	pub fn build(self) -> MockupOptions {
 		MockupOptions {
 			portal_config: self.portal_config,
+			deposit_decoders: self.deposit_decoders,
+			deposit_reject_policy: self.deposit_reject_policy,
+			sender_policy: self.sender_policy,
+			sender_reports: self.sender_reports,
+			transactional_wallets: self.transactional_wallets,
+			chain_id: self.chain_id,
+			output_limits: self.output_limits,
+			voucher_dedup: self.voucher_dedup,
+			legacy_vouchers: self.legacy_vouchers,
+			pre_processor: self.pre_processor,
+			middlewares: self.middlewares,
+			inspect_cache: self.inspect_cache,
+			balance_notices: self.balance_notices,
+			idempotency_keys: self.idempotency_keys,
+			dedup_window: self.dedup_window,
+			deposit_receipts: self.deposit_receipts,
+			hex_payloads: self.hex_payloads,
+			about: self.about,
+			build_info: self.build_info,
+			gio_handlers: self.gio_handlers,
+			voucher_schedule: self.voucher_schedule,
+			state_commitments: self.state_commitments,
+			#[cfg(feature = "memory-tracking")]
+			memory_limit: self.memory_limit,
 		}
 	}
 }
@@ -306,139 +959,481 @@ impl RollupInternalEnvironment for RollupMockup {
 		self.ether_wallet.clone()
 	}
 
+	#[cfg(feature = "erc20")]
 	fn get_erc20_wallet(&self) -> Arc<RwLock<ERC20Wallet>> {
 		self.erc20_wallet.clone()
 	}
 
+	#[cfg(feature = "erc721")]
 	fn get_erc721_wallet(&self) -> Arc<RwLock<ERC721Wallet>> {
 		self.erc721_wallet.clone()
 	}
 
+	#[cfg(feature = "erc1155")]
 	fn get_erc1155_wallet(&self) -> Arc<RwLock<ERC1155Wallet>> {
 		self.erc1155_wallet.clone()
 	}
+
+	fn get_erc4626_wallet(&self) -> Arc<RwLock<ERC4626Wallet>> {
+		self.erc4626_wallet.clone()
+	}
+
+	fn get_activity(&self) -> Arc<RwLock<ActivityCounters>> {
+		self.activity.clone()
+	}
+
+	fn balance_notices(&self) -> bool {
+		self.balance_notices
+	}
 }
 
 pub struct Tester<A> {
 	app: A,
-	env: RollupMockup,
+	env: Arc<RollupMockup>,
+	mockup_options: MockupOptions,
+	inspect_cache: Mutex<Option<InspectCache>>,
+}
+
+impl Tester<()> {
+	pub fn builder() -> TesterBuilder {
+		TesterBuilder::default()
+	}
+}
+
+#[derive(Default)]
+pub struct TesterBuilder {
+	env: Option<Arc<RollupMockup>>,
 	mockup_options: MockupOptions,
 }
 
+impl TesterBuilder {
+	pub fn options(mut self, mockup_options: MockupOptions) -> Self {
+		self.mockup_options = mockup_options;
+		self
+	}
+
+	pub fn with_env(mut self, env: Arc<RollupMockup>) -> Self {
+		self.env = Some(env);
+		self
+	}
+
+	pub fn build<A: Application>(self, app: A) -> Tester<A> {
+		let env = self
+			.env
+			.unwrap_or_else(|| Arc::new(RollupMockup::with_options(&self.mockup_options)));
+
+		Tester {
+			app,
+			env,
+			inspect_cache: Mutex::new(self.mockup_options.inspect_cache.map(InspectCache::new)),
+			mockup_options: self.mockup_options,
+		}
+	}
+}
+
 impl<A> Tester<A>
 where
 	A: Application,
 {
 	pub fn new(app: A, mockup_options: MockupOptions) -> Self {
-		Self {
-			app,
-			env: RollupMockup::new(),
-			mockup_options,
-		}
+		Tester::builder().options(mockup_options).build(app)
+	}
+
+	pub fn env(&self) -> Arc<RollupMockup> {
+		self.env.clone()
 	}
 
 	pub async fn deposit(&self, deposit: Deposit) -> AdvanceResult {
-		let sender = self.env.address_book.address_from_deposit(deposit.clone());
+		let portal = self.env.address_book.address_from_deposit(deposit.clone());
+		self.deposit_from(portal, deposit).await
+	}
+
+	pub async fn deposit_from(&self, portal: Address, deposit: Deposit) -> AdvanceResult {
+		let payload: Vec<u8> = deposit.try_into().expect("Failed to convert deposit to payload");
+		self.deposit_raw(portal, payload).await
+	}
 
-		let metadata = Metadata {
+	pub async fn deposit_raw(&self, portal: Address, payload: impl AsRef<[u8]>) -> AdvanceResult {
+		let sender = portal;
+		let mut metadata = Metadata {
 			input_index: self.env.get_input_index().await,
 			sender,
 			block_number: self.env.get_input_index().await,
-			timestamp: UNIX_EPOCH.elapsed().unwrap().as_secs(),
+			timestamp: now(),
+			app_contract: Some(self.env.app_address),
+			chain_id: Some(self.mockup_options.chain_id.unwrap_or(DEVNET_CHAIN_ID)),
+			prev_randao: Some(prev_randao(self.env.get_input_index().await)),
+			annotations: Default::default(),
 		};
 
-		let (status, error) = match self.mockup_options.portal_config {
+		let wallets_before = self.wallets_snapshot().await;
+		self.reset_memory_peak();
+		if self.mockup_options.transactional_wallets {
+			begin_wallet_transaction(self.env.as_ref()).await;
+		}
+		self.env
+			.activity
+			.write()
+			.await
+			.record_advance(&metadata, InputKind::Deposit);
+
+		let payload = payload.as_ref().to_vec();
+		let (status, error) =
+			match pre_process(self.mockup_options.pre_processor.as_ref(), &mut metadata, payload).await {
+				Ok(PreProcess::Continue { payload, .. }) => self.handle_deposit(metadata.clone(), payload).await,
+				Ok(PreProcess::Finish(status)) => (status, None),
+				Err(e) => (FinishStatus::Reject, Some(e)),
+			};
+
+		let (status, outputs, error) = self.finish_advance(&metadata, status, error).await;
+
+		AdvanceResult {
+			status,
+			outputs,
+			metadata,
+			error,
+			wallets_before,
+		}
+	}
+
+	async fn handle_deposit(&self, metadata: Metadata, payload: Vec<u8>) -> (FinishStatus, Option<Box<dyn Error>>) {
+		match self.mockup_options.portal_config {
 			PortalHandlerConfig::Dispense => (FinishStatus::Accept, None),
 			PortalHandlerConfig::Ignore => {
-				let payload: Vec<u8> = deposit.try_into().expect("Failed to convert deposit to payload");
-				match self
-					.app
-					.advance(&self.env, metadata.clone(), payload.as_slice(), None)
-					.await
-				{
-					Ok(finish_status) => (finish_status, None),
-					Err(e) => (FinishStatus::Reject, Some(e)),
-				}
+				self.advance_app(metadata, &payload, None).await
 			}
 			PortalHandlerConfig::Handle { advance } => {
-				let deposit_payload = handle_portals(
-					&self.env,
-					sender,
-					deposit.try_into().expect("Failed to convert deposit to payload"),
+				let deposit_payload = match handle_portals(
+					self.env.as_ref(),
+					&self.mockup_options.deposit_decoders,
+					metadata.sender,
+					payload.clone(),
 				)
 				.await
-				.expect("Failed to handle deposit payload")
-				.expect("No deposit returned");
+				{
+					Ok(Some(deposit_payload)) => deposit_payload,
+					Ok(None) => return self.advance_app(metadata, &payload, None).await,
+					Err(e) => return (FinishStatus::Reject, Some(e.into())),
+				};
+				let (transactional, policy) = (
+					self.mockup_options.transactional_wallets,
+					self.mockup_options.deposit_reject_policy,
+				);
+				keep_deposit_credit(self.env.as_ref(), transactional, policy).await;
+
+				let policy = self.mockup_options.deposit_reject_policy;
+				if let Some(change) = BalanceChange::from_deposit(&deposit_payload) {
+					if let Err(e) = self.env.notify_balance_change(change).await {
+						let status = settle_rejected_deposit(self.env.as_ref(), policy, &deposit_payload).await;
+						return (status, Some(e.into()));
+					}
+				}
+				if let (true, Some(receipt)) = (
+					self.mockup_options.deposit_receipts,
+					DepositReceipt::from_deposit(&deposit_payload, metadata.input_index),
+				) {
+					if let Err(e) = self.env.send_notice(receipt.to_bytes()).await {
+						let status = settle_rejected_deposit(self.env.as_ref(), policy, &deposit_payload).await;
+						return (status, Some(e.into()));
+					}
+				}
 
 				if advance {
-					match self
-						.app
-						.advance(&self.env, metadata.clone(), &[], Some(deposit_payload))
-						.await
-					{
-						Ok(finish_status) => (finish_status, None),
-						Err(e) => (FinishStatus::Reject, Some(e)),
+					let (status, error) = self.advance_app(metadata, &[], Some(deposit_payload.clone())).await;
+
+					match status {
+						FinishStatus::Reject => (
+							settle_rejected_deposit(self.env.as_ref(), policy, &deposit_payload).await,
+							error,
+						),
+						FinishStatus::Accept => (status, error),
 					}
 				} else {
 					(FinishStatus::Accept, None)
 				}
 			}
+		}
+	}
+
+	pub async fn advance(&self, sender: Address, payload: impl AsRef<[u8]> + Send) -> AdvanceResult {
+		let mut metadata = Metadata {
+			input_index: self.env.get_input_index().await,
+			sender,
+			block_number: self.env.get_input_index().await,
+			timestamp: now(),
+			app_contract: Some(self.env.app_address),
+			chain_id: Some(self.mockup_options.chain_id.unwrap_or(DEVNET_CHAIN_ID)),
+			prev_randao: Some(prev_randao(self.env.get_input_index().await)),
+			annotations: Default::default(),
 		};
 
-		let outputs = match self.env.advance(status).await {
-			Ok(Some(outputs)) => outputs,
-			_ => Vec::new(),
+		let wallets_before = self.wallets_snapshot().await;
+		self.reset_memory_peak();
+		if self.mockup_options.transactional_wallets {
+			begin_wallet_transaction(self.env.as_ref()).await;
+		}
+		self.env
+			.activity
+			.write()
+			.await
+			.record_advance(&metadata, InputKind::Advance);
+
+		let payload = self.unwrap_hex(payload.as_ref().to_vec());
+		let pre_processor = self.mockup_options.pre_processor.as_ref();
+		let (status, error) = match self.permits_sender(&metadata).await {
+			true => match pre_process(pre_processor, &mut metadata, payload).await {
+				Ok(PreProcess::Continue { payload, .. }) => match self.skip_duplicates(&metadata, payload).await {
+					Ok(Some(payload)) => self.advance_app(metadata.clone(), &payload, None).await,
+					Ok(None) => (FinishStatus::Accept, None),
+					Err(e) => (FinishStatus::Reject, Some(e)),
+				},
+				Ok(PreProcess::Finish(status)) => (status, None),
+				Err(e) => (FinishStatus::Reject, Some(e)),
+			},
+			false => (FinishStatus::Reject, None),
 		};
 
+		let (status, outputs, error) = self.finish_advance(&metadata, status, error).await;
+
 		AdvanceResult {
 			status,
 			outputs,
 			metadata,
 			error,
+			wallets_before,
 		}
 	}
 
-	pub async fn advance(&self, sender: Address, payload: impl AsRef<[u8]> + Send) -> AdvanceResult {
-		let metadata = Metadata {
-			input_index: self.env.get_input_index().await,
-			sender,
-			block_number: self.env.get_input_index().await,
-			timestamp: UNIX_EPOCH.elapsed().unwrap().as_secs(),
-		};
+	async fn permits_sender(&self, metadata: &Metadata) -> bool {
+		let (policy, report) = (&self.mockup_options.sender_policy, self.mockup_options.sender_reports);
+		self.env.address_book.is_portal(metadata.sender)
+			|| permits_sender(self.env.as_ref(), policy, report, metadata).await
+	}
 
-		let (status, error) = match self
-			.app
-			.advance(&self.env, metadata.clone(), payload.as_ref(), None)
-			.await
-		{
+	async fn advance_app(
+		&self,
+		metadata: Metadata,
+		payload: &[u8],
+		deposit: Option<Deposit>,
+	) -> (FinishStatus, Option<Box<dyn Error>>) {
+		let middlewares = &self.mockup_options.middlewares;
+		let (status, error) = match middlewares.advance(&self.app, self.env.as_ref(), metadata, payload, deposit).await {
 			Ok(finish_status) => (finish_status, None),
-			Err(e) => (FinishStatus::Reject, Some(e)),
+			Err(e) => {
+				self.app.on_error(self.env.as_ref(), e.as_ref()).await;
+				(FinishStatus::Reject, Some(e))
+			}
 		};
 
-		AdvanceResult {
-			status,
-			outputs: match self.env.advance(status).await {
-				Ok(Some(outputs)) => outputs,
-				_ => Vec::new(),
+		// Checked before the deposit of the input is settled, so the rejection applies the deposit reject policy
+		let (status, error) = match self.env.ether_wallet.read().await.check_supply() {
+			Ok(()) => (status, error),
+			Err(e) => (FinishStatus::Reject, Some(e.into())),
+		};
+		#[cfg(feature = "memory-tracking")]
+		let (status, error) = match self.mockup_options.memory_limit.map(|limit| limit.check()) {
+			Some(Err(e)) => (FinishStatus::Reject, Some(e)),
+			_ => (status, error),
+		};
+		(status, error)
+	}
+
+	fn reset_memory_peak(&self) {
+		#[cfg(feature = "memory-tracking")]
+		if self.mockup_options.memory_limit.is_some() {
+			memory::reset_peak();
+		}
+	}
+
+	async fn finish_advance(
+		&self,
+		metadata: &Metadata,
+		status: FinishStatus,
+		error: Option<Box<dyn Error>>,
+	) -> (FinishStatus, Vec<Output>, Option<Box<dyn Error>>) {
+		if self.mockup_options.transactional_wallets {
+			end_wallet_transaction(self.env.as_ref(), status).await;
+		}
+
+		if let (FinishStatus::Accept, Some(schedule)) = (status, &self.mockup_options.voucher_schedule) {
+			if let Err(e) = schedule.emit_due(self.env.as_ref(), metadata).await {
+				error!("Error emitting the scheduled vouchers: {}", e);
+			}
+		}
+		if let (FinishStatus::Accept, Some(commitments)) = (status, &self.mockup_options.state_commitments) {
+			if let Err(e) = commitments.emit(self.env.as_ref(), metadata.input_index).await {
+				error!("Error emitting the state commitment notice: {}", e);
+			}
+		}
+
+		if let Some(keys) = &self.mockup_options.idempotency_keys {
+			keys.finish(status);
+		}
+		if let Some(window) = &self.mockup_options.dedup_window {
+			window.finish(status);
+		}
+
+		let outputs = match self.env.advance(status).await {
+			Ok(Some(outputs)) => outputs,
+			_ => Vec::new(),
+		};
+		self.invalidate_inspect_cache().await;
+		(status, outputs, error)
+	}
+
+	/// Returns the payload to process, or `None` if the input is skipped by the idempotency keys or dedup window.
+	async fn skip_duplicates(&self, metadata: &Metadata, payload: Vec<u8>) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+		let payload = match &self.mockup_options.idempotency_keys {
+			Some(keys) => match keys.process(self.env.as_ref(), metadata, payload).await? {
+				Some(payload) => payload,
+				None => return Ok(None),
 			},
-			metadata,
-			error,
+			None => payload,
+		};
+		match &self.mockup_options.dedup_window {
+			Some(window) if !window.process(self.env.as_ref(), metadata, &payload).await? => Ok(None),
+			_ => Ok(Some(payload)),
+		}
+	}
+
+	pub async fn advance_str(&self, sender: Address, payload: impl AsRef<str>) -> AdvanceResult {
+		self.advance(sender, payload.as_ref().as_bytes()).await
+	}
+
+	pub async fn advance_json(&self, sender: Address, payload: &impl Serialize) -> AdvanceResult {
+		let payload = serde_json::to_vec(payload).expect("Failed to serialize payload to JSON");
+		self.advance(sender, payload).await
+	}
+
+	pub async fn inspect_str(&self, payload: impl AsRef<str>) -> InspectResult {
+		self.inspect(payload.as_ref().as_bytes()).await
+	}
+
+	pub async fn inspect_json(&self, payload: &impl Serialize) -> InspectResult {
+		let payload = serde_json::to_vec(payload).expect("Failed to serialize payload to JSON");
+		self.inspect(payload).await
+	}
+
+	pub async fn advance_hex(&self, sender: Address, payload: impl AsRef<[u8]> + Send) -> AdvanceResult {
+		self.advance(sender, codec::encode_hex(payload)).await
+	}
+
+	pub async fn inspect_hex(&self, payload: impl AsRef<[u8]> + Send) -> InspectResult {
+		self.inspect(codec::encode_hex(payload)).await
+	}
+
+	fn unwrap_hex(&self, payload: Vec<u8>) -> Vec<u8> {
+		match self.mockup_options.hex_payloads {
+			true => codec::unwrap_hex(payload),
+			false => payload,
+		}
+	}
+
+	pub async fn execute_voucher(&self, index: u64) -> Result<Option<AdvanceResult>, Box<dyn Error>> {
+		let Output::Voucher {
+			destination, payload, ..
+		} = self.env.execute_voucher(index).await?
+		else {
+			unreachable!("only vouchers are recorded as executable");
+		};
+		if destination != self.env.address_book.input_box {
+			return Ok(None);
+		}
+
+		let (dapp, input) = decode_add_input(&payload)?;
+		if dapp != self.env.app_address {
+			return Ok(None);
+		}
+		Ok(Some(self.advance(self.env.app_address, input).await))
+	}
+
+	pub async fn was_executed(&self, index: u64) -> bool {
+		self.env.was_executed(index).await
+	}
+
+	/// Runs the `on_start` hook of the app, like the `Supervisor` before the first input.
+	pub async fn start(&self) -> Result<(), Box<dyn Error>> {
+		self.app.on_start(self.env.as_ref()).await.map_err(Into::into)
+	}
+
+	/// Runs the `on_shutdown` hook of the app, like the `Supervisor` when its loop terminates.
+	pub async fn shutdown(&self) -> Result<(), Box<dyn Error>> {
+		self.app.on_shutdown(self.env.as_ref()).await.map_err(Into::into)
+	}
+
+	pub async fn wallets_snapshot(&self) -> WalletsSnapshot {
+		WalletsSnapshot::capture(self.env.as_ref()).await
+	}
+
+	async fn invalidate_inspect_cache(&self) {
+		if let Some(cache) = self.inspect_cache.lock().await.as_mut() {
+			cache.invalidate();
 		}
 	}
 
 	pub async fn inspect(&self, payload: impl AsRef<[u8]> + Send) -> InspectResult {
-		let (status, error) = match self.app.inspect(&self.env, payload.as_ref()).await {
+		self.env.activity.write().await.record_inspect();
+		let mut inspect_cache = self.inspect_cache.lock().await;
+		if let Some(reports) = inspect_cache.as_ref().and_then(|cache| cache.get(payload.as_ref())) {
+			return InspectResult {
+				status: FinishStatus::Accept,
+				outputs: reports
+					.iter()
+					.map(|payload| Output::Report {
+						payload: payload.clone(),
+					})
+					.collect(),
+				error: None,
+				exception: None,
+				illegal_outputs: Vec::new(),
+			};
+		}
+
+		self.env.begin_inspect().await;
+		let payload = self.unwrap_hex(payload.as_ref().to_vec());
+		let inspected = match self.mockup_options.about && payload == ABOUT_ROUTE {
+			true => self.send_about().await,
+			false => match self.mockup_options.middlewares.inspect(&self.app, self.env.as_ref(), &payload).await {
+				Ok(finish_status) => Ok(finish_status),
+				Err(e) => {
+					self.app.on_error(self.env.as_ref(), e.as_ref()).await;
+					Err(e)
+				}
+			},
+		};
+		let (status, error) = match inspected {
 			Ok(finish_status) => (finish_status, None),
 			Err(e) => (FinishStatus::Reject, Some(e)),
 		};
 
+		let (outputs, illegal_outputs) = self.env.inspect().await;
+		if let (Some(cache), FinishStatus::Accept) = (inspect_cache.as_mut(), status) {
+			let reports = outputs
+				.iter()
+				.filter_map(|output| match output {
+					Output::Report { payload } => Some(payload.clone()),
+					_ => None,
+				})
+				.collect();
+			cache.insert(payload.as_ref(), reports);
+		}
+
+		let exception = error.as_ref().map(|e| e.to_string().into_bytes());
 		InspectResult {
 			status,
-			outputs: self.env.outputs.read().await.clone(),
+			outputs,
 			error,
+			exception,
+			illegal_outputs,
 		}
 	}
 
+	async fn send_about(&self) -> Result<FinishStatus, Box<dyn Error>> {
+		let about = About::new(&self.mockup_options.portal_config, &[Selector::Any]).build(self.env.build_info());
+		self.env.send_report(about.to_bytes()?).await?;
+		Ok(FinishStatus::Accept)
+	}
+
 	pub async fn ether_addresses(&self) -> Vec<Address> {
 		self.env.ether_addresses().await
 	}
@@ -448,7 +1443,7 @@ where
 		source: Address,
 		destination: Address,
 		value: Uint,
-	) -> Result<(), Box<dyn Error>> {
+	) -> Result<(), CrabrollsError> {
 		self.env.ether_transfer(source, destination, value).await
 	}
 
@@ -456,57 +1451,84 @@ where
 		self.env.ether_balance(address).await
 	}
 
+	pub async fn ether_total_supply(&self) -> Uint {
+		self.env.ether_total_supply().await
+	}
+
+	#[cfg(feature = "erc20")]
 	pub async fn erc20_addresses(&self) -> Vec<Address> {
 		self.env.erc20_addresses().await
 	}
 
+	#[cfg(feature = "erc20")]
 	pub async fn erc20_transfer(
 		&self,
 		src_wallet: Address,
 		dst_wallet: Address,
 		token_address: Address,
 		value: Uint,
-	) -> Result<(), Box<dyn Error>> {
+	) -> Result<(), CrabrollsError> {
 		self.env
 			.erc20_transfer(src_wallet, dst_wallet, token_address, value)
 			.await
 	}
 
+	#[cfg(feature = "erc20")]
 	pub async fn erc20_balance(&self, wallet_address: Address, token_address: Address) -> Uint {
 		self.env.erc20_balance(wallet_address, token_address).await
 	}
 
+	#[cfg(feature = "erc721")]
 	pub async fn erc721_addresses(&self) -> Vec<Address> {
 		self.env.erc721_addresses().await
 	}
 
+	#[cfg(feature = "erc721")]
 	pub async fn erc721_transfer(
 		&self,
 		src_wallet: Address,
 		dst_wallet: Address,
 		token_address: Address,
 		token_id: Uint,
-	) -> Result<(), Box<dyn Error>> {
+	) -> Result<(), CrabrollsError> {
 		self.env
 			.erc721_transfer(src_wallet, dst_wallet, token_address, token_id)
 			.await
 	}
 
+	#[cfg(feature = "erc721")]
 	pub async fn erc721_owner_of(&self, token_address: Address, token_id: Uint) -> Option<Address> {
 		self.env.erc721_owner_of(token_address, token_id).await
 	}
 
+	#[cfg(feature = "erc721")]
+	pub async fn erc721_tokens_of(&self, owner: Address) -> Vec<(Address, Uint)> {
+		self.env.erc721_tokens_of(owner).await
+	}
+
+	#[cfg(feature = "erc721")]
+	pub async fn erc721_tokens_of_collection(&self, owner: Address, token_address: Address) -> Vec<Uint> {
+		self.env.erc721_tokens_of_collection(owner, token_address).await
+	}
+
+	#[cfg(feature = "erc721")]
+	pub async fn erc721_metadata_of(&self, token_address: Address, token_id: Uint) -> Option<TokenMetadata> {
+		self.env.erc721_metadata_of(token_address, token_id).await
+	}
+
+	#[cfg(feature = "erc1155")]
 	pub async fn erc1155_addresses(&self) -> Vec<Address> {
 		self.env.erc1155_addresses().await
 	}
 
+	#[cfg(feature = "erc1155")]
 	pub async fn erc1155_transfer<I>(
 		&self,
 		src_wallet: Address,
 		dst_wallet: Address,
 		token_address: Address,
 		transfers: I,
-	) -> Result<(), Box<dyn Error>>
+	) -> Result<(), CrabrollsError>
 	where
 		I: IntoIdsAmountsIter,
 	{
@@ -515,7 +1537,1387 @@ where
 			.await
 	}
 
+	#[cfg(feature = "erc1155")]
 	pub async fn erc1155_balance(&self, wallet_address: Address, token_address: Address, token_id: Uint) -> Uint {
 		self.env.erc1155_balance(wallet_address, token_address, token_id).await
 	}
+
+	#[cfg(feature = "erc1155")]
+	pub async fn erc1155_balance_of_batch<I>(
+		&self,
+		wallet_address: Address,
+		token_address: Address,
+		token_ids: I,
+	) -> Vec<Uint>
+	where
+		I: IntoIdsIter,
+	{
+		self.env
+			.erc1155_balance_of_batch(wallet_address, token_address, token_ids)
+			.await
+	}
+
+	pub async fn erc4626_addresses(&self) -> Vec<Address> {
+		self.env.erc4626_addresses().await
+	}
+
+	pub async fn erc4626_transfer(
+		&self,
+		src_wallet: Address,
+		dst_wallet: Address,
+		vault_address: Address,
+		shares: Uint,
+	) -> Result<(), CrabrollsError> {
+		self.env
+			.erc4626_transfer(src_wallet, dst_wallet, vault_address, shares)
+			.await
+	}
+
+	pub async fn erc4626_balance(&self, wallet_address: Address, vault_address: Address) -> Uint {
+		self.env.erc4626_balance(wallet_address, vault_address).await
+	}
+}
+
+fn decode_add_input(payload: &[u8]) -> Result<(Address, Vec<u8>), Box<dyn Error>> {
+	let selector = &hash::keccak256("addInput(address,bytes)")[..4];
+	let arguments = payload
+		.strip_prefix(selector)
+		.ok_or("voucher to the InputBox is not an addInput call")?;
+	let tokens = ethabi::decode(&[ParamType::Address, ParamType::Bytes], arguments)?;
+	Ok((abi::extract::address(&tokens[0])?, abi::extract::bytes(&tokens[1])?))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::build_info;
+	use crate::core::contracts::erc4626::ERC4626PortalDecoder;
+	use crate::types::balance::{Asset, AssetAmount};
+	use crate::types::report::ErrorReport;
+	use crate::types::testing::ResultUtils;
+
+	struct InspectApp;
+
+	impl Application for InspectApp {
+		type Error = Box<dyn Error>;
+
+		async fn advance(
+			&self,
+			env: &impl Environment,
+			_metadata: Metadata,
+			payload: &[u8],
+			_deposit: Option<Deposit>,
+		) -> Result<FinishStatus, Box<dyn Error>> {
+			env.send_notice(payload).await?;
+			Ok(FinishStatus::Accept)
+		}
+
+		async fn inspect(&self, env: &impl Environment, payload: &[u8]) -> Result<FinishStatus, Box<dyn Error>> {
+			env.send_report(payload).await?;
+			if payload == b"notice" {
+				env.send_notice(payload).await?;
+			}
+			if payload == b"delegate" {
+				env.send_delegate_call_voucher(Address::zero(), payload).await?;
+			}
+			Ok(FinishStatus::Accept)
+		}
+	}
+
+	#[async_std::test]
+	async fn test_inspect_only_emits_reports() {
+		let tester = Tester::new(InspectApp, MockupOptions::default());
+
+		let result = tester.inspect(b"report").await;
+		assert!(result.is_accepted());
+		assert_eq!(result.get_reports(), vec![b"report".to_vec()]);
+		assert!(result.get_exception().is_none());
+		assert!(!result.has_illegal_outputs());
+	}
+
+	#[async_std::test]
+	async fn test_inspect_rejects_delegate_call_vouchers() {
+		let tester = Tester::new(InspectApp, MockupOptions::default());
+
+		let result = tester.inspect(b"delegate").await;
+		assert!(result.is_rejected());
+		assert_eq!(
+			result.get_error().unwrap().to_string(),
+			"can't emit a delegate-call-voucher during inspect"
+		);
+		assert_eq!(
+			result.get_illegal_outputs(),
+			vec![Output::DelegateCallVoucher {
+				destination: Address::zero(),
+				payload: b"delegate".to_vec()
+			}]
+		);
+	}
+
+	#[async_std::test]
+	async fn test_inspect_rejects_notices() {
+		let tester = Tester::new(InspectApp, MockupOptions::default());
+
+		let result = tester.inspect(b"notice").await;
+		assert!(result.is_rejected());
+		assert_eq!(
+			result.get_error().unwrap().to_string(),
+			"can't emit a notice during inspect"
+		);
+		assert_eq!(result.get_reports(), vec![b"notice".to_vec()]);
+		assert_eq!(result.get_exception(), Some(&b"can't emit a notice during inspect"[..]));
+		assert_eq!(
+			result.get_illegal_outputs(),
+			vec![Output::Notice {
+				payload: b"notice".to_vec()
+			}]
+		);
+
+		let result = tester.advance(Address::zero(), b"advance").await;
+		assert_eq!(
+			result.get_outputs(),
+			vec![Output::Notice {
+				payload: b"advance".to_vec()
+			}]
+		);
+	}
+
+	struct RejectApp;
+
+	impl Application for RejectApp {
+		type Error = Box<dyn Error>;
+
+		async fn advance(
+			&self,
+			_env: &impl Environment,
+			_metadata: Metadata,
+			_payload: &[u8],
+			_deposit: Option<Deposit>,
+		) -> Result<FinishStatus, Box<dyn Error>> {
+			Ok(FinishStatus::Reject)
+		}
+	}
+
+	async fn reject_deposit(policy: DepositRejectPolicy) -> (Tester<RejectApp>, AdvanceResult) {
+		let options = MockupOptions::builder().deposit_reject_policy(policy).build();
+		let tester = Tester::new(RejectApp, options);
+		let result = tester
+			.deposit(Deposit::Ether {
+				sender: Address::zero(),
+				amount: Uint::from(100),
+			})
+			.await;
+		(tester, result)
+	}
+
+	#[async_std::test]
+	async fn test_rejected_deposit_is_kept() {
+		let (tester, result) = reject_deposit(DepositRejectPolicy::Keep).await;
+		assert!(result.is_rejected());
+		assert_eq!(tester.ether_balance(Address::zero()).await, Uint::from(100));
+	}
+
+	#[async_std::test]
+	async fn test_rejected_deposit_is_rolled_back() {
+		let (tester, result) = reject_deposit(DepositRejectPolicy::Rollback).await;
+		assert!(result.is_rejected());
+		assert_eq!(tester.ether_balance(Address::zero()).await, Uint::zero());
+		result.assert_state_unchanged(&tester).await;
+		result.assert_no_outputs_except_reports();
+	}
+
+	#[async_std::test]
+	#[should_panic(expected = "Expected wallets to be unchanged by the input")]
+	async fn test_kept_deposit_changes_state() {
+		let (tester, result) = reject_deposit(DepositRejectPolicy::Keep).await;
+		result.assert_state_unchanged(&tester).await;
+	}
+
+	#[async_std::test]
+	async fn test_rejected_deposit_is_refunded() {
+		let (tester, result) = reject_deposit(DepositRejectPolicy::Refund).await;
+		assert!(result.is_accepted());
+		assert_eq!(tester.ether_balance(Address::zero()).await, Uint::zero());
+
+		let outputs = result.get_outputs();
+		assert_eq!(outputs.len(), 1);
+		assert!(matches!(outputs[0], Output::Voucher { .. }));
+	}
+
+	#[async_std::test]
+	async fn test_node_v2_metadata() {
+		let tester = Tester::new(SpendApp, MockupOptions::default());
+		let result = tester.advance(Address::repeat_byte(1), b"").await;
+		assert_eq!(result.metadata.chain_id, Some(DEVNET_CHAIN_ID));
+		assert_eq!(result.metadata.prev_randao, Some(prev_randao(0)));
+
+		let tester = Tester::new(SpendApp, MockupOptions::builder().chain_id(1).build());
+		tester.advance(Address::repeat_byte(1), b"").await;
+		let result = tester.advance(Address::repeat_byte(1), b"").await;
+		assert_eq!(result.metadata.chain_id, Some(1));
+		assert_eq!(result.metadata.prev_randao, Some(prev_randao(1)));
+	}
+
+	struct SpendApp;
+
+	impl Application for SpendApp {
+		type Error = Box<dyn Error>;
+
+		async fn advance(
+			&self,
+			env: &impl Environment,
+			metadata: Metadata,
+			payload: &[u8],
+			deposit: Option<Deposit>,
+		) -> Result<FinishStatus, Box<dyn Error>> {
+			env.ether_transfer(metadata.sender, Address::zero(), Uint::from(30))
+				.await?;
+			env.ether_withdraw(Address::zero(), Uint::from(10)).await?;
+			if deposit.is_some() {
+				return Ok(FinishStatus::Reject);
+			}
+			match payload {
+				b"fail" => Err("spent and failed".into()),
+				b"reject" => Ok(FinishStatus::Reject),
+				_ => Ok(FinishStatus::Accept),
+			}
+		}
+	}
+
+	#[async_std::test]
+	async fn test_transactional_wallets() {
+		let tester = Tester::new(SpendApp, MockupOptions::builder().transactional_wallets(true).build());
+		let alice = Address::repeat_byte(1);
+		let ether_wallet = tester.env.ether_wallet.clone();
+		ether_wallet.write().await.credit(alice, Uint::from(100)).unwrap();
+
+		// With the `Keep` policy, the deposit of a rejected advance stays credited and only the app changes roll back
+		let deposit = Deposit::Ether {
+			sender: alice,
+			amount: Uint::from(50),
+		};
+		tester.deposit(deposit.clone()).await.assert_rejected();
+		assert_eq!(tester.ether_balance(alice).await, Uint::from(150));
+		assert_eq!(tester.ether_balance(Address::zero()).await, Uint::zero());
+
+		for payload in [&b"reject"[..], b"fail"] {
+			let result = tester.advance(alice, payload).await;
+			result.assert_rejected();
+			result.assert_state_unchanged(&tester).await;
+		}
+		assert_eq!(ether_wallet.read().await.total_deposited(), Uint::from(150));
+
+		tester.advance(alice, b"pay").await.assert_accepted();
+		assert_eq!(tester.ether_balance(alice).await, Uint::from(120));
+		assert_eq!(tester.ether_balance(Address::zero()).await, Uint::from(20));
+		assert!(ether_wallet.read().await.check_supply().is_ok());
+
+		let options = MockupOptions::builder()
+			.transactional_wallets(true)
+			.deposit_reject_policy(DepositRejectPolicy::Rollback)
+			.build();
+		let tester = Tester::new(SpendApp, options);
+		tester.env.ether_wallet.write().await.credit(alice, Uint::from(100)).unwrap();
+		let result = tester.deposit(deposit).await;
+		result.assert_rejected().assert_state_unchanged(&tester).await;
+	}
+
+	struct AnnotatedApp;
+
+	impl Application for AnnotatedApp {
+		type Error = Box<dyn Error>;
+
+		async fn advance(
+			&self,
+			env: &impl Environment,
+			metadata: Metadata,
+			payload: &[u8],
+			_deposit: Option<Deposit>,
+		) -> Result<FinishStatus, Box<dyn Error>> {
+			let tag = metadata.annotations.get("tag").cloned().unwrap_or_default();
+			env.send_notice([tag.as_bytes(), payload].concat()).await?;
+			Ok(FinishStatus::Accept)
+		}
+	}
+
+	fn pre_processed_tester() -> Tester<AnnotatedApp> {
+		let options = MockupOptions::builder()
+			.pre_processor(|_metadata, payload| async move {
+				match payload.strip_prefix(b"tag:") {
+					Some(rest) => Ok(PreProcess::Continue {
+						payload: rest.to_vec(),
+						annotations: [("tag".to_string(), "tagged:".to_string())].into(),
+					}),
+					None if payload == b"skip" => Ok(PreProcess::Finish(FinishStatus::Accept)),
+					None if payload == b"fail" => Err("unsupported payload".into()),
+					None => Ok(PreProcess::Continue {
+						payload,
+						annotations: Default::default(),
+					}),
+				}
+			})
+			.build();
+		Tester::new(AnnotatedApp, options)
+	}
+
+	#[async_std::test]
+	async fn test_pre_processor_transforms_and_annotates() {
+		let tester = pre_processed_tester();
+
+		let result = tester.advance(Address::zero(), b"tag:hello").await;
+		assert!(result.is_accepted());
+		assert_eq!(result.get_metadata().annotations["tag"], "tagged:");
+		assert_eq!(
+			result.get_outputs(),
+			vec![Output::Notice {
+				payload: b"tagged:hello".to_vec()
+			}]
+		);
+
+		let result = tester.advance(Address::zero(), b"hello").await;
+		assert_eq!(
+			result.get_outputs(),
+			vec![Output::Notice {
+				payload: b"hello".to_vec()
+			}]
+		);
+	}
+
+	#[async_std::test]
+	async fn test_pre_processor_short_circuits() {
+		let tester = pre_processed_tester();
+
+		let result = tester.advance(Address::zero(), b"skip").await;
+		assert!(result.is_accepted());
+		assert!(result.get_outputs().is_empty());
+
+		let result = tester.advance(Address::zero(), b"fail").await;
+		assert!(result.is_rejected());
+		assert_eq!(result.get_error().unwrap().to_string(), "unsupported payload");
+	}
+
+	#[cfg(not(feature = "erc20"))]
+	#[async_std::test]
+	async fn test_disabled_wallet_portal() {
+		let tester = Tester::new(AnnotatedApp, MockupOptions::default());
+		let result = tester
+			.deposit(Deposit::ERC20 {
+				sender: Address::repeat_byte(0xaa),
+				token: Address::repeat_byte(0xbb),
+				amount: Uint::from(500),
+			})
+			.await;
+		assert!(result.is_rejected());
+		assert!(result.get_error().unwrap().to_string().contains("isn't enabled"));
+	}
+
+	#[cfg(feature = "erc20")]
+	#[async_std::test]
+	async fn test_custom_portal_deposit() {
+		let portal = address!("0x0000000000000000000000000000000000000042");
+		let token = address!("0x0000000000000000000000000000000000000043");
+		let options = MockupOptions::builder()
+			.deposit_decoder(
+				move |sender: Address, payload: &[u8]| -> Result<Option<Deposit>, Box<dyn Error>> {
+					if sender != portal {
+						return Ok(None);
+					}
+					Ok(Some(Deposit::ERC20 {
+						sender: Address::from_slice(&payload[..20]),
+						token,
+						amount: Uint::from_big_endian(&payload[20..52]),
+					}))
+				},
+			)
+			.build();
+		let tester = Tester::new(AnnotatedApp, options);
+
+		let mut payload = Address::repeat_byte(0xaa).as_bytes().to_vec();
+		payload.extend_from_slice(&ethabi::encode(&[ethabi::Token::Uint(ethabi::Uint::from(500))]));
+		let result = tester
+			.deposit(Deposit::Custom {
+				portal,
+				sender: Address::repeat_byte(0xaa),
+				payload,
+			})
+			.await;
+
+		assert!(result.is_accepted());
+		assert_eq!(result.get_metadata().sender, portal);
+		assert_eq!(
+			tester.erc20_balance(Address::repeat_byte(0xaa), token).await,
+			Uint::from(500)
+		);
+	}
+
+	#[async_std::test]
+	async fn test_erc4626_vault_deposit() {
+		let portal = address!("0x0000000000000000000000000000000000000042");
+		let vault = address!("0x0000000000000000000000000000000000000044");
+		let options = MockupOptions::builder()
+			.deposit_decoder(ERC4626PortalDecoder::new(portal))
+			.build();
+		let tester = Tester::new(AnnotatedApp, options);
+
+		let deposit = Deposit::ERC4626 {
+			portal,
+			sender: Address::repeat_byte(0xaa),
+			vault,
+			shares: Uint::from(300),
+		};
+		let result = tester.deposit(deposit.clone()).await;
+
+		assert!(result.is_accepted());
+		assert_eq!(result.get_metadata().sender, portal);
+		assert_eq!(
+			tester.erc4626_balance(Address::repeat_byte(0xaa), vault).await,
+			Uint::from(300)
+		);
+
+		tester
+			.erc4626_transfer(
+				Address::repeat_byte(0xaa),
+				Address::repeat_byte(0xbb),
+				vault,
+				Uint::from(100),
+			)
+			.await
+			.unwrap();
+		assert_eq!(
+			tester.erc4626_balance(Address::repeat_byte(0xbb), vault).await,
+			Uint::from(100)
+		);
+		assert_eq!(tester.wallets_snapshot().await.erc4626.len(), 2);
+	}
+
+	#[async_std::test]
+	async fn test_per_type_output_indices() {
+		let env = RollupMockup::new();
+		assert_eq!(env.send_notice(b"n0").await.unwrap(), Some(0));
+		assert_eq!(env.send_voucher(Address::zero(), b"v0").await.unwrap(), Some(0));
+		assert_eq!(env.send_notice(b"n1").await.unwrap(), Some(1));
+		env.advance(FinishStatus::Accept).await.unwrap();
+
+		assert_eq!(env.send_voucher(Address::zero(), b"v1").await.unwrap(), Some(1));
+		env.advance(FinishStatus::Reject).await.unwrap();
+
+		assert_eq!(env.send_voucher(Address::zero(), b"v1").await.unwrap(), Some(1));
+		assert_eq!(env.send_notice(b"n2").await.unwrap(), Some(2));
+		env.advance(FinishStatus::Accept).await.unwrap();
+
+		assert_eq!(env.output_count("voucher").await, 2);
+		assert_eq!(env.output_count("notice").await, 3);
+		assert_eq!(env.output_count("report").await, 0);
+	}
+
+	#[async_std::test]
+	async fn test_ether_withdraw_vouchers() {
+		let alice = Address::repeat_byte(1);
+		let env = RollupMockup::with_options(&MockupOptions::builder().legacy_vouchers(false).build());
+		env.ether_wallet.write().await.credit(alice, Uint::from(100)).unwrap();
+		env.ether_withdraw(alice, Uint::from(40)).await.unwrap();
+		assert!(env
+			.send_voucher_with_value(alice, Uint::one(), b"deposit()")
+			.await
+			.is_ok());
+		assert_eq!(
+			env.advance(FinishStatus::Accept).await.unwrap().unwrap()[0],
+			Output::Voucher {
+				destination: alice,
+				value: Uint::from(40),
+				payload: Vec::new(),
+			}
+		);
+
+		let legacy = RollupMockup::new();
+		legacy
+			.ether_wallet
+			.write()
+			.await
+			.credit(alice, Uint::from(100))
+			.unwrap();
+		legacy.ether_withdraw(alice, Uint::from(40)).await.unwrap();
+		assert!(legacy
+			.send_voucher_with_value(alice, Uint::one(), b"deposit()")
+			.await
+			.is_err());
+		assert!(legacy.send_delegate_call_voucher(alice, b"").await.is_err());
+		assert_eq!(
+			legacy.advance(FinishStatus::Accept).await.unwrap().unwrap(),
+			vec![Output::Voucher {
+				destination: legacy.app_address,
+				value: Uint::zero(),
+				payload: abi::ether::withdraw(alice, Uint::from(40)).unwrap(),
+			}]
+		);
+	}
+
+	#[async_std::test]
+	async fn test_refused_withdraw_keeps_the_funds() {
+		let (alice, token) = (Address::repeat_byte(1), Address::repeat_byte(2));
+		let limits = OutputLimits {
+			max_voucher_size: 4,
+			..OutputLimits::default()
+		};
+		let env = RollupMockup::with_options(&MockupOptions::builder().output_limits(limits).build());
+		env.ether_wallet.write().await.credit(alice, Uint::from(100)).unwrap();
+		env.erc4626_wallet.write().await.set_balance(alice, token, Uint::from(10));
+
+		assert!(env.ether_withdraw(alice, Uint::from(40)).await.is_err());
+		assert!(env.erc4626_withdraw(alice, token, Uint::from(10)).await.is_err());
+		assert_eq!(env.ether_balance(alice).await, Uint::from(100));
+		assert_eq!(env.ether_total_supply().await, Uint::from(100));
+		assert_eq!(env.erc4626_balance(alice, token).await, Uint::from(10));
+		assert!(env.advance(FinishStatus::Accept).await.unwrap().unwrap().is_empty());
+	}
+
+	struct DehashApp;
+
+	impl Application for DehashApp {
+		type Error = Box<dyn Error>;
+
+		async fn advance(
+			&self,
+			env: &impl Environment,
+			_metadata: Metadata,
+			payload: &[u8],
+			_deposit: Option<Deposit>,
+		) -> Result<FinishStatus, Box<dyn Error>> {
+			let response = env.gio(0x2d, payload).await?;
+			env.send_notice(response.data).await?;
+			Ok(FinishStatus::Accept)
+		}
+	}
+
+	#[async_std::test]
+	async fn test_gio_requests() {
+		let options = MockupOptions::builder()
+			.gio_handler(0x2d, |id| {
+				Ok(GioResponse {
+					code: 0,
+					data: id.iter().rev().copied().collect(),
+				})
+			})
+			.build();
+		let tester = Tester::new(DehashApp, options);
+		tester
+			.advance(Address::zero(), b"abc")
+			.await
+			.assert_accepted()
+			.assert_notice("cba");
+		assert_eq!(
+			tester.env().gio_requests().await,
+			vec![GioRequest {
+				domain: 0x2d,
+				payload: b"abc".to_vec()
+			}]
+		);
+
+		let tester = Tester::new(DehashApp, MockupOptions::default());
+		tester
+			.advance(Address::zero(), b"abc")
+			.await
+			.assert_error_contains("no GIO handler for domain 45");
+	}
+
+	#[async_std::test]
+	async fn test_delegate_call_voucher() {
+		let env = RollupMockup::with_options(&MockupOptions::builder().legacy_vouchers(false).build());
+		assert_eq!(env.send_voucher(Address::zero(), b"v0").await.unwrap(), Some(0));
+		assert_eq!(
+			env.send_delegate_call_voucher(Address::repeat_byte(1), b"d0")
+				.await
+				.unwrap(),
+			Some(0)
+		);
+		env.advance(FinishStatus::Accept).await.unwrap();
+
+		assert_eq!(env.output_count("delegate-call-voucher").await, 1);
+		assert_eq!(env.vouchers().await.len(), 1); // Only regular vouchers can be executed on the mockup
+
+		env.begin_inspect().await;
+		assert!(env.send_delegate_call_voucher(Address::zero(), b"d1").await.is_err());
+	}
+
+	#[async_std::test]
+	async fn test_send_report_str_and_json() {
+		let env = RollupMockup::new();
+		env.send_report_str(Uint::from(1_000)).await.unwrap();
+		env.send_report_json(&serde_json::json!({ "balance": 1_000 }))
+			.await
+			.unwrap();
+
+		assert_eq!(
+			env.advance(FinishStatus::Accept).await.unwrap().unwrap(),
+			vec![
+				Output::Report {
+					payload: b"1000".to_vec()
+				},
+				Output::Report {
+					payload: br#"{"balance":1000}"#.to_vec()
+				},
+			]
+		);
+	}
+
+	#[derive(Default)]
+	struct CountingApp {
+		inspects: std::sync::atomic::AtomicU64,
+	}
+
+	impl Application for CountingApp {
+		type Error = Box<dyn Error>;
+
+		async fn advance(
+			&self,
+			_env: &impl Environment,
+			_metadata: Metadata,
+			_payload: &[u8],
+			_deposit: Option<Deposit>,
+		) -> Result<FinishStatus, Box<dyn Error>> {
+			Ok(FinishStatus::Accept)
+		}
+
+		async fn inspect(&self, env: &impl Environment, _payload: &[u8]) -> Result<FinishStatus, Box<dyn Error>> {
+			let inspects = self.inspects.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1;
+			env.send_report(inspects.to_string()).await?;
+			Ok(FinishStatus::Accept)
+		}
+	}
+
+	#[async_std::test]
+	async fn test_inspect_cache() {
+		let tester = Tester::new(
+			CountingApp::default(),
+			MockupOptions::builder().inspect_cache(16).build(),
+		);
+
+		assert_eq!(tester.inspect(b"total").await.get_reports(), vec![b"1".to_vec()]);
+		assert_eq!(tester.inspect(b"total").await.get_reports(), vec![b"1".to_vec()]);
+		assert_eq!(tester.inspect(b"other").await.get_reports(), vec![b"2".to_vec()]);
+
+		tester.advance(Address::zero(), b"").await;
+		assert_eq!(tester.inspect(b"total").await.get_reports(), vec![b"3".to_vec()]);
+	}
+
+	struct RateLimitedApp;
+
+	impl Application for RateLimitedApp {
+		type Error = Box<dyn Error>;
+
+		async fn advance(
+			&self,
+			env: &impl Environment,
+			metadata: Metadata,
+			_payload: &[u8],
+			_deposit: Option<Deposit>,
+		) -> Result<FinishStatus, Box<dyn Error>> {
+			match env.get_activity().read().await.sender_count(metadata.sender) {
+				count if count > 2 => Ok(FinishStatus::Reject),
+				_ => Ok(FinishStatus::Accept),
+			}
+		}
+
+		async fn inspect(&self, env: &impl Environment, _payload: &[u8]) -> Result<FinishStatus, Box<dyn Error>> {
+			let inspects = env.get_activity().read().await.kind_count(InputKind::Inspect);
+			env.send_report(inspects.to_string()).await?;
+			Ok(FinishStatus::Accept)
+		}
+	}
+
+	#[async_std::test]
+	async fn test_activity_counters() {
+		let tester = Tester::new(RateLimitedApp, MockupOptions::default());
+		let sender = Address::repeat_byte(1);
+
+		assert!(tester.advance(sender, b"").await.is_accepted());
+		assert!(tester.advance(sender, b"").await.is_accepted());
+		assert!(tester.advance(sender, b"").await.is_rejected());
+		assert!(tester.advance(Address::zero(), b"").await.is_accepted());
+		assert_eq!(tester.inspect(b"").await.get_reports(), vec![b"1".to_vec()]);
+	}
+
+	struct PayApp;
+
+	impl Application for PayApp {
+		type Error = Box<dyn Error>;
+
+		async fn advance(
+			&self,
+			env: &impl Environment,
+			metadata: Metadata,
+			_payload: &[u8],
+			deposit: Option<Deposit>,
+		) -> Result<FinishStatus, Box<dyn Error>> {
+			if deposit.is_none() {
+				env.ether_transfer(metadata.sender, Address::zero(), Uint::from(4))
+					.await?;
+			}
+			Ok(FinishStatus::Accept)
+		}
+	}
+
+	fn balance_changes(outputs: Vec<Output>) -> Vec<BalanceChange> {
+		outputs
+			.iter()
+			.filter_map(|output| match output {
+				Output::Notice { payload } => BalanceChange::from_slice(payload).ok(),
+				_ => None,
+			})
+			.collect()
+	}
+
+	#[async_std::test]
+	async fn test_balance_notices() {
+		let tester = Tester::new(PayApp, MockupOptions::builder().balance_notices(true).build());
+		let sender = Address::repeat_byte(1);
+
+		let result = tester
+			.deposit(Deposit::Ether {
+				sender,
+				amount: Uint::from(10),
+			})
+			.await;
+		assert_eq!(
+			balance_changes(result.get_outputs()),
+			vec![BalanceChange::ether(
+				BalanceChangeKind::Deposit,
+				None,
+				Some(sender),
+				Uint::from(10)
+			)]
+		);
+
+		let result = tester.advance(sender, b"").await;
+		assert_eq!(
+			balance_changes(result.get_outputs()),
+			vec![BalanceChange::ether(
+				BalanceChangeKind::Transfer,
+				Some(sender),
+				Some(Address::zero()),
+				Uint::from(4)
+			)]
+		);
+	}
+
+	#[async_std::test]
+	async fn test_deposit_receipts() {
+		let tester = Tester::new(PayApp, MockupOptions::builder().deposit_receipts(true).build());
+		let sender = Address::repeat_byte(1);
+
+		let result = tester
+			.deposit(Deposit::Ether {
+				sender,
+				amount: Uint::from(10),
+			})
+			.await;
+		let receipts: Vec<DepositReceipt> = result
+			.get_outputs()
+			.iter()
+			.filter_map(|output| match output {
+				Output::Notice { payload } => DepositReceipt::from_slice(payload).ok(),
+				_ => None,
+			})
+			.collect();
+		assert_eq!(
+			receipts,
+			vec![DepositReceipt {
+				asset: Asset::Ether,
+				sender,
+				amounts: vec![AssetAmount {
+					id: None,
+					amount: Uint::from(10)
+				}],
+				input_index: result.metadata.input_index,
+			}]
+		);
+		assert!(balance_changes(result.get_outputs()).is_empty());
+		assert!(tester.advance(sender, b"").await.get_outputs().is_empty());
+	}
+
+	/// Only applies payloads of one byte, transferring that many wei from the sender to the zero address.
+	#[derive(Default)]
+	struct ValidatedApp {
+		applied: std::sync::atomic::AtomicU64,
+	}
+
+	impl Application for ValidatedApp {
+		type Error = Box<dyn Error>;
+
+		async fn advance(
+			&self,
+			env: &impl Environment,
+			metadata: Metadata,
+			payload: &[u8],
+			deposit: Option<Deposit>,
+		) -> Result<FinishStatus, Box<dyn Error>> {
+			if deposit.is_none() {
+				self.applied.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+				env.ether_transfer(metadata.sender, Address::zero(), Uint::from(payload[0]))
+					.await?;
+				env.send_notice(payload).await?;
+			}
+			Ok(FinishStatus::Accept)
+		}
+
+		fn validate(&self, _metadata: &Metadata, payload: &[u8]) -> Result<(), Box<dyn Error>> {
+			match payload.len() {
+				1 => Ok(()),
+				_ => Err("expected a single byte".into()),
+			}
+		}
+	}
+
+	#[async_std::test]
+	async fn test_validate_before_advance() {
+		let tester = Tester::new(ValidatedApp::default(), MockupOptions::default());
+		let sender = Address::repeat_byte(1);
+		let deposit = Deposit::Ether {
+			sender,
+			amount: Uint::from(10),
+		};
+		assert!(tester.deposit(deposit).await.is_accepted()); // Deposits aren't validated
+
+		let result = tester.advance(sender, [7]).await;
+		assert!(result.is_accepted());
+		assert_eq!(result.notices(), vec![vec![7]]);
+		assert_eq!(tester.ether_balance(sender).await, Uint::from(3));
+
+		let result = tester.advance(sender, b"invalid").await;
+		assert!(result.is_rejected());
+		assert!(result.get_outputs().is_empty());
+		assert_eq!(result.error.unwrap().to_string(), "expected a single byte");
+		assert_eq!(tester.ether_balance(sender).await, Uint::from(3));
+		assert_eq!(tester.app.applied.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+		let dynamic: Box<dyn crate::core::dynamic::DynApplication> = Box::new(ValidatedApp::default());
+		let tester = Tester::new(dynamic, MockupOptions::default());
+		assert!(tester.advance(sender, b"invalid").await.is_rejected());
+	}
+
+	#[async_std::test]
+	async fn test_idempotency_keys() {
+		let keys = IdempotencyKeys::new(16);
+		let options = MockupOptions::builder().idempotency_keys(keys.clone()).build();
+		let tester = Tester::new(ValidatedApp::default(), options);
+		let sender = Address::repeat_byte(1);
+		let deposit = Deposit::Ether {
+			sender,
+			amount: Uint::from(10),
+		};
+		assert!(tester.deposit(deposit).await.is_accepted());
+
+		let result = tester.advance(sender, b"Idempotency-Key: k1\n\x03").await;
+		assert!(result.is_accepted());
+		assert_eq!(result.notices(), vec![vec![3]]);
+		assert!(keys.contains(sender, "k1"));
+
+		let result = tester.advance(sender, b"Idempotency-Key: k1\n\x03").await;
+		assert!(result.is_accepted());
+		assert!(result.notices().is_empty());
+		let report = ErrorReport::from_slice(&result.reports()[0]).unwrap();
+		assert_eq!(report.code, ErrorReport::DUPLICATE);
+		assert_eq!(tester.ether_balance(sender).await, Uint::from(7));
+
+		// Rejected inputs can be resubmitted with the same key
+		assert!(tester.advance(sender, b"Idempotency-Key: k2\n").await.is_rejected());
+		assert!(tester.advance(sender, b"Idempotency-Key: k2\n\x02").await.is_accepted());
+		assert_eq!(keys.len(), 2);
+	}
+
+	#[async_std::test]
+	async fn test_dedup_window() {
+		let options = MockupOptions::builder().dedup_window(DedupWindow::new(2)).build();
+		let tester = Tester::new(ValidatedApp::default(), options);
+		let sender = Address::repeat_byte(1);
+		let deposit = Deposit::Ether {
+			sender,
+			amount: Uint::from(10),
+		};
+		assert!(tester.deposit(deposit).await.is_accepted());
+
+		assert_eq!(tester.advance(sender, [1]).await.notices(), vec![vec![1]]);
+		let result = tester.advance(sender, [1]).await;
+		assert!(result.is_accepted());
+		assert!(result.notices().is_empty());
+		assert_eq!(
+			ErrorReport::from_slice(&result.reports()[0]).unwrap().code,
+			ErrorReport::DUPLICATE
+		);
+
+		// Out of the window of 2 inputs, the same input is processed again
+		assert!(tester.advance(sender, [2]).await.is_accepted());
+		assert!(tester.advance(sender, [3]).await.is_accepted());
+		assert_eq!(tester.advance(sender, [1]).await.notices(), vec![vec![1]]);
+		assert_eq!(tester.ether_balance(sender).await, Uint::from(3));
+	}
+
+	struct MintApp;
+
+	impl Application for MintApp {
+		type Error = Box<dyn Error>;
+
+		async fn advance(
+			&self,
+			env: &impl Environment,
+			metadata: Metadata,
+			_payload: &[u8],
+			_deposit: Option<Deposit>,
+		) -> Result<FinishStatus, Box<dyn Error>> {
+			env.get_ether_wallet()
+				.write()
+				.await
+				.set_balance(metadata.sender, Uint::from(1));
+			Ok(FinishStatus::Accept)
+		}
+	}
+
+	#[async_std::test]
+	async fn test_ether_supply_invariant() {
+		let tester = Tester::new(MintApp, MockupOptions::default());
+
+		let result = tester.advance(Address::zero(), b"").await;
+		assert!(result.is_rejected());
+		assert_eq!(
+			result.get_error().unwrap().to_string(),
+			"ether total supply 1 exceeds the credited deposits 0"
+		);
+
+		// The rejection goes through the deposit reject policy, rolling back the deposit
+		let options = MockupOptions::builder()
+			.deposit_reject_policy(DepositRejectPolicy::Rollback)
+			.build();
+		let tester = Tester::new(MintApp, options);
+		let alice = Address::repeat_byte(0xa);
+		let deposit = Deposit::Ether {
+			sender: alice,
+			amount: Uint::from(5),
+		};
+		assert!(tester.deposit(deposit).await.is_rejected());
+		assert_eq!(tester.ether_balance(alice).await, Uint::zero());
+	}
+
+	struct InvoiceApp;
+
+	impl Application for InvoiceApp {
+		type Error = Box<dyn Error>;
+
+		async fn advance(
+			&self,
+			env: &impl Environment,
+			metadata: Metadata,
+			payload: &[u8],
+			deposit: Option<Deposit>,
+		) -> Result<FinishStatus, Box<dyn Error>> {
+			if deposit.is_none() {
+				let memo = String::from_utf8(payload.to_vec())?;
+				env.ether_transfer_with_memo(
+					metadata.sender,
+					Address::zero(),
+					Uint::from(3),
+					memo,
+					payload != b"quiet",
+				)
+				.await?;
+			}
+			Ok(FinishStatus::Accept)
+		}
+	}
+
+	#[async_std::test]
+	async fn test_transfer_with_memo() {
+		let tester = Tester::new(InvoiceApp, MockupOptions::default());
+		let sender = Address::repeat_byte(1);
+		tester
+			.deposit(Deposit::Ether {
+				sender,
+				amount: Uint::from(10),
+			})
+			.await;
+
+		let result = tester.advance(sender, b"invoice-42").await;
+		assert_eq!(
+			balance_changes(result.get_outputs()),
+			vec![BalanceChange::ether(
+				BalanceChangeKind::Transfer,
+				Some(sender),
+				Some(Address::zero()),
+				Uint::from(3)
+			)
+			.memo("invoice-42")]
+		);
+
+		let result = tester.advance(sender, b"quiet").await;
+		assert!(result.is_accepted());
+		assert!(result.get_outputs().is_empty());
+		assert_eq!(tester.ether_balance(sender).await, Uint::from(4));
+	}
+
+	#[async_std::test]
+	async fn test_deposit_raw_malformed() {
+		let tester = Tester::new(PayApp, MockupOptions::default());
+		let portal = AddressBook::default().ether_portal;
+		let payload = EtherWallet::deposit_payload(Address::repeat_byte(1), Uint::from(10)).unwrap();
+
+		let result = tester.deposit_raw(portal, &payload[..30]).await;
+		assert!(result.is_rejected());
+		assert!(result.is_errored());
+		result.assert_state_unchanged(&tester).await;
+		result.assert_no_outputs_except_reports();
+
+		assert!(tester.deposit_raw(portal, &payload).await.is_accepted());
+		assert_eq!(tester.ether_balance(Address::repeat_byte(1)).await, Uint::from(10));
+	}
+
+	#[async_std::test]
+	async fn test_str_and_json_payloads() {
+		let tester = Tester::new(InspectApp, MockupOptions::default());
+
+		let result = tester.advance_str(Address::zero(), "hello").await;
+		assert_eq!(
+			result.get_outputs(),
+			vec![Output::Notice {
+				payload: b"hello".to_vec()
+			}]
+		);
+
+		let result = tester
+			.advance_json(Address::zero(), &serde_json::json!({ "kind": "swap" }))
+			.await;
+		assert_eq!(
+			result.get_outputs(),
+			vec![Output::Notice {
+				payload: br#"{"kind":"swap"}"#.to_vec()
+			}]
+		);
+
+		assert_eq!(
+			tester.inspect_str("report").await.get_reports(),
+			vec![b"report".to_vec()]
+		);
+		assert_eq!(
+			tester.inspect_json(&vec![1, 2]).await.get_reports(),
+			vec![b"[1,2]".to_vec()]
+		);
+	}
+
+	#[async_std::test]
+	async fn test_hex_payloads() {
+		let options = MockupOptions::builder().hex_payloads(true).build();
+		let tester = Tester::new(InspectApp, options);
+
+		let result = tester.advance_hex(Address::zero(), br#"{"a":1}"#).await;
+		assert_eq!(
+			result.get_outputs(),
+			vec![Output::Notice {
+				payload: br#"{"a":1}"#.to_vec()
+			}]
+		);
+		assert_eq!(
+			tester.inspect_hex(b"report").await.get_reports(),
+			vec![b"report".to_vec()]
+		);
+
+		let tester = Tester::new(InspectApp, MockupOptions::default());
+		let result = tester.advance_hex(Address::zero(), b"hi").await;
+		assert_eq!(
+			result.get_outputs(),
+			vec![Output::Notice {
+				payload: b"0x6869".to_vec()
+			}]
+		);
+	}
+
+	struct RelayApp;
+
+	impl Application for RelayApp {
+		type Error = Box<dyn Error>;
+
+		async fn advance(
+			&self,
+			env: &impl Environment,
+			metadata: Metadata,
+			payload: &[u8],
+			_deposit: Option<Deposit>,
+		) -> Result<FinishStatus, Box<dyn Error>> {
+			let app_address = address!("0xab7528bb862fb57e8a2bcd567a2e929a0be56a5e");
+			if metadata.sender == app_address {
+				env.send_notice(payload).await?;
+				return Ok(FinishStatus::Accept);
+			}
+
+			let mut call = hash::keccak256("addInput(address,bytes)")[..4].to_vec();
+			call.extend(ethabi::encode(&[
+				ethabi::Token::Address(app_address.into_ethabi()),
+				ethabi::Token::Bytes(payload.to_vec()),
+			]));
+			env.send_voucher(Address::repeat_byte(0xee), b"withdraw").await?;
+			env.send_voucher(AddressBook::default().input_box, call).await?;
+			Ok(FinishStatus::Accept)
+		}
+	}
+
+	#[async_std::test]
+	async fn test_execute_voucher() {
+		let tester = Tester::new(RelayApp, MockupOptions::default());
+		assert!(tester.advance(Address::zero(), b"ping").await.is_accepted());
+		assert_eq!(tester.env().vouchers().await.len(), 2);
+
+		assert!(!tester.was_executed(0).await);
+		assert!(tester.execute_voucher(0).await.unwrap().is_none());
+		assert!(tester.was_executed(0).await);
+		assert_eq!(
+			tester.execute_voucher(0).await.unwrap_err().to_string(),
+			"voucher 0 was already executed"
+		);
+
+		let result = tester.execute_voucher(1).await.unwrap().unwrap();
+		assert_eq!(result.notices(), vec![b"ping".to_vec()]);
+		assert!(tester.execute_voucher(2).await.is_err());
+	}
+
+	#[async_std::test]
+	async fn test_about_route() {
+		let tester = Tester::new(InspectApp, MockupOptions::default());
+		assert_eq!(
+			tester.inspect(ABOUT_ROUTE).await.get_reports(),
+			vec![ABOUT_ROUTE.to_vec()]
+		);
+
+		let tester = Tester::new(InspectApp, MockupOptions::builder().about(true).build());
+		let result = tester.inspect(ABOUT_ROUTE).await;
+		assert!(result.is_accepted());
+		let reports: Vec<serde_json::Value> = result.reports_as().unwrap();
+		assert_eq!(reports[0]["about"]["version"], env!("CARGO_PKG_VERSION"));
+		assert_eq!(reports[0]["about"]["routes"], serde_json::json!(["Any"]));
+		assert!(reports[0]["about"].get("build").is_none());
+
+		let options = MockupOptions::builder().about(true).build_info(build_info!()).build();
+		let tester = Tester::new(InspectApp, options);
+		assert_eq!(tester.env().build_info().unwrap().name, "crabrolls");
+		let reports: Vec<serde_json::Value> = tester.inspect(ABOUT_ROUTE).await.reports_as().unwrap();
+		assert_eq!(reports[0]["about"]["build"]["version"], env!("CARGO_PKG_VERSION"));
+	}
+
+	#[async_std::test]
+	async fn test_default_inspect() {
+		let tester = Tester::new(PayApp, MockupOptions::default());
+		let result = tester.inspect(b"anything").await;
+		assert!(result.is_accepted());
+		assert!(result.get_outputs().is_empty());
+	}
+
+	#[async_std::test]
+	async fn test_shared_env() {
+		let env = Arc::new(RollupMockup::new());
+		let payer = Tester::builder().with_env(env.clone()).build(PayApp);
+		let echo = Tester::builder().with_env(env).build(InspectApp);
+		let sender = Address::repeat_byte(1);
+
+		let result = payer
+			.deposit(Deposit::Ether {
+				sender,
+				amount: Uint::from(10),
+			})
+			.await;
+		assert!(result.is_accepted());
+		assert_eq!(echo.ether_balance(sender).await, Uint::from(10));
+
+		let result = echo.advance(sender, b"hi").await;
+		assert_eq!(result.get_metadata().input_index, 1);
+
+		let result = payer.advance(sender, b"pay").await;
+		assert_eq!(result.get_metadata().input_index, 2);
+		assert_eq!(echo.ether_balance(sender).await, Uint::from(6));
+	}
+
+	#[async_std::test]
+	async fn test_deposit_from_overridden_portal() {
+		let tester = Tester::new(PayApp, MockupOptions::default());
+		let deposit = Deposit::Ether {
+			sender: Address::repeat_byte(1),
+			amount: Uint::from(10),
+		};
+
+		let result = tester
+			.deposit_from(AddressBook::default().erc20_portal, deposit.clone())
+			.await;
+		assert!(result.is_rejected());
+		result.assert_state_unchanged(&tester).await;
+
+		let result = tester.deposit_from(Address::repeat_byte(0x42), deposit).await;
+		assert!(result.is_rejected());
+		assert_eq!(result.get_metadata().sender, Address::repeat_byte(0x42));
+		result.assert_state_unchanged(&tester).await;
+	}
+
+	#[derive(Debug, thiserror::Error)]
+	enum GameError {
+		#[error("unknown move {0}")]
+		UnknownMove(String),
+		#[error(transparent)]
+		Crabrolls(#[from] CrabrollsError),
+	}
+
+	struct GameApp;
+
+	impl Application for GameApp {
+		type Error = GameError;
+
+		async fn advance(
+			&self,
+			env: &impl Environment,
+			_metadata: Metadata,
+			payload: &[u8],
+			_deposit: Option<Deposit>,
+		) -> Result<FinishStatus, GameError> {
+			match payload {
+				b"jump" => {
+					env.send_notice(payload).await?;
+					Ok(FinishStatus::Accept)
+				}
+				_ => Err(GameError::UnknownMove(String::from_utf8_lossy(payload).into_owned())),
+			}
+		}
+
+		async fn inspect(&self, _env: &impl Environment, _payload: &[u8]) -> Result<FinishStatus, GameError> {
+			Err(GameError::Crabrolls(CrabrollsError::other("no state")))
+		}
+	}
+
+	#[async_std::test]
+	async fn test_application_error_type() {
+		let tester = Tester::new(GameApp, MockupOptions::default());
+		tester.advance(Address::zero(), b"jump").await.assert_accepted();
+
+		let result = tester.advance(Address::zero(), b"fly").await;
+		result.assert_rejected().assert_error_contains("unknown move fly");
+		assert!(matches!(result.get_error_as::<GameError>(), Some(GameError::UnknownMove(m)) if m == "fly"));
+
+		let result = tester.inspect(b"state").await;
+		assert!(matches!(
+			result.get_error_as::<GameError>(),
+			Some(GameError::Crabrolls(CrabrollsError::Other(_)))
+		));
+	}
+
+	struct StaticApp;
+
+	impl Application for StaticApp {
+		type Error = Box<dyn Error>;
+
+		const INSPECT_REPORT: Option<&'static [u8]> = Some(b"static v1");
+
+		async fn advance(
+			&self,
+			_env: &impl Environment,
+			_metadata: Metadata,
+			_payload: &[u8],
+			_deposit: Option<Deposit>,
+		) -> Result<FinishStatus, Box<dyn Error>> {
+			Ok(FinishStatus::Accept)
+		}
+	}
+
+	#[async_std::test]
+	async fn test_default_inspect_report() {
+		let tester = Tester::new(StaticApp, MockupOptions::default());
+		let result = tester.inspect(b"anything").await;
+		result.assert_accepted().assert_report(b"static v1").assert_report_count(1);
+
+		let tester = Tester::new(RelayApp, MockupOptions::default());
+		tester.inspect(b"anything").await.assert_accepted().assert_report_count(0);
+
+		let limits = OutputLimits {
+			max_report_size: 4,
+			..OutputLimits::default()
+		};
+		let tester = Tester::new(StaticApp, MockupOptions::builder().output_limits(limits).build());
+		let result = tester.inspect(b"anything").await;
+		result.assert_rejected().assert_report_count(0);
+		assert!(matches!(result.get_error_as::<CrabrollsError>(), Some(CrabrollsError::Other(_))));
+	}
+
+	#[derive(Default)]
+	struct HookApp {
+		events: std::sync::Mutex<Vec<String>>,
+	}
+
+	impl Application for HookApp {
+		type Error = Box<dyn Error>;
+
+		async fn advance(
+			&self,
+			_env: &impl Environment,
+			_metadata: Metadata,
+			payload: &[u8],
+			_deposit: Option<Deposit>,
+		) -> Result<FinishStatus, Box<dyn Error>> {
+			match payload {
+				b"fail" => Err("advance failed".into()),
+				_ => Ok(FinishStatus::Accept),
+			}
+		}
+
+		async fn inspect(&self, _env: &impl Environment, _payload: &[u8]) -> Result<FinishStatus, Box<dyn Error>> {
+			Err("inspect failed".into())
+		}
+
+		fn validate(&self, _metadata: &Metadata, payload: &[u8]) -> Result<(), Box<dyn Error>> {
+			match payload {
+				b"invalid" => Err("invalid input".into()),
+				_ => Ok(()),
+			}
+		}
+
+		async fn on_start(&self, _env: &impl Environment) -> Result<(), Box<dyn Error>> {
+			self.events.lock().unwrap().push("start".to_string());
+			Ok(())
+		}
+
+		async fn on_shutdown(&self, _env: &impl Environment) -> Result<(), Box<dyn Error>> {
+			self.events.lock().unwrap().push("shutdown".to_string());
+			Ok(())
+		}
+
+		async fn on_error(&self, _env: &impl Environment, error: &(dyn Error + 'static)) {
+			self.events.lock().unwrap().push(error.to_string());
+		}
+	}
+
+	#[async_std::test]
+	async fn test_lifecycle_hooks() {
+		let tester = Tester::new(HookApp::default(), MockupOptions::default());
+		tester.start().await.unwrap();
+		tester.advance(Address::zero(), b"ok").await.assert_accepted();
+		tester.advance(Address::zero(), b"invalid").await.assert_rejected();
+		tester.advance(Address::zero(), b"fail").await.assert_rejected();
+		tester.inspect(b"state").await.assert_rejected();
+		tester.shutdown().await.unwrap();
+
+		assert_eq!(
+			*tester.app.events.lock().unwrap(),
+			vec!["start", "invalid input", "advance failed", "inspect failed", "shutdown"]
+		);
+	}
+
+	#[async_std::test]
+	async fn test_sender_policy() {
+		let (alice, bob) = (Address::repeat_byte(0xa1), Address::repeat_byte(0xb0));
+		let options = MockupOptions::builder().sender_policy(SenderPolicy::allow([alice])).build();
+		let tester = Tester::new(InspectApp, options);
+		tester.advance(alice, b"hello").await.assert_accepted().assert_notice(b"hello");
+		let result = tester.advance(bob, b"hello").await;
+		result.assert_rejected().assert_no_error().assert_output_count(0);
+		let deposit = Deposit::Ether {
+			sender: bob,
+			amount: Uint::from(100),
+		};
+		tester.deposit(deposit).await.assert_accepted();
+
+		let options = MockupOptions::builder().sender_policy(SenderPolicy::deny([bob])).build();
+		let tester = Tester::new(InspectApp, options);
+		tester.advance(alice, b"hello").await.assert_accepted();
+		tester.advance(bob, b"hello").await.assert_rejected();
+	}
 }
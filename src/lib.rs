@@ -1,3 +1,4 @@
+#[cfg(feature = "rollup")]
 extern crate pretty_env_logger;
 #[macro_use]
 extern crate log;
@@ -6,22 +7,102 @@ mod core;
 mod types;
 mod utils;
 
+// Used by the code generated by `abigen`
+#[doc(hidden)]
+pub use ethabi;
+#[doc(hidden)]
+pub use types::primitives::EthabiCompat;
+
 use core::{application::Application, environment::Environment};
 use types::machine::{FinishStatus, Metadata};
 
 pub mod prelude {
 	pub use crate::core::{
+		about::{About, BuildInfo, ABOUT_ROUTE},
+		activity::{ActivityCounters, InputKind},
 		application::Application,
-		context::{RunOptions, Supervisor},
+		approvals::{ApprovalGate, ApprovalInput, Approvals, Operation, PendingApproval},
+		calls::{CallHandler, CallRouter},
+		commit_reveal::{CommitReveal, Commitment},
+		commitment::{StateCommitment, StateCommitments},
+		conformance,
+		context::{PreProcessor, Selector, StopCondition},
+		contracts::decoder::{DepositDecoder, DepositDecoders},
+		contracts::erc4626::ERC4626PortalDecoder,
+		dynamic::{DynApplication, DynEnv, DynEnvironment},
 		environment::Environment,
-		testing::{MockupOptions, Tester},
+		handoff::{HandoffOptions, WalletsSnapshot},
+		idempotency::{DedupWindow, IdempotencyKeys},
+		middleware::{Middleware, Middlewares},
+		plugins::PluginRegistry,
+		runtime::{self, block_on},
+		schedule::{Due, ScheduledVoucher, VoucherSchedule},
+		sessions::{Session, SessionGrant, SessionInput, SessionKeys, SessionRouter},
+		streams::{Stream, Streams},
+		testing::{GioHandler, MockupOptions, RollupMockup, Tester, TesterBuilder},
 	};
 
 	pub use crate::types::{
 		address_book::AddressBook,
-		machine::{Deposit, FinishStatus, Metadata, Output, PortalHandlerConfig},
+		balance::{Asset, AssetAmount, BalanceChange, BalanceChangeKind, DepositReceipt, TokenMetadata},
+		error::CrabrollsError,
+		machine::{
+			Advance, Deposit, DepositRejectPolicy, FinishStatus, GioRequest, GioResponse, Input, Inspect, Metadata,
+			Output, OutputLimits, PortalHandlerConfig, PreProcess, SenderPolicy, VoucherDedupPolicy,
+		},
+		report::ErrorReport,
 		testing::{AdvanceResult, InspectResult, ResultUtils},
 	};
 
-	pub use crate::utils::{abi::abi, macros::*, units};
+	pub use crate::utils::{
+		abi::abi,
+		abigen,
+		canonical_json,
+		codec::{self, PayloadCodec},
+		crypto,
+		eip712::{self, Eip712Domain},
+		hash::{self, keccak256},
+		ids::{self, Id},
+		macros::*,
+		math,
+		memo::Memo,
+		merkle::{self, IncrementalMerkleTree},
+		oracles::{self, Oracle, SignedData},
+		units,
+	};
+
+	pub use crate::types::primitives::{Address, Uint};
+
+	#[cfg(feature = "alloy")]
+	pub use crate::types::primitives::{AddressCompat, UintCompat};
+
+	#[cfg(feature = "alloy")]
+	pub use crate::utils::interop::{ToAlloy, ToEthabi};
+
+	#[cfg(feature = "decimal")]
+	pub use crate::utils::decimal::{self, Decimal};
+
+	#[cfg(feature = "rollup")]
+	pub use crate::core::{
+		backend::Backend,
+		context::{RunOptions, Supervisor},
+		playground::{
+			Playground, PlaygroundInput, PlaygroundOutput, PLAYGROUND_BALANCES_ROUTE, PLAYGROUND_INPUTS_ROUTE,
+			PLAYGROUND_OUTPUTS_ROUTE,
+		},
+		retry::RetryPolicy,
+	};
+
+	#[cfg(feature = "erc20")]
+	pub use crate::core::{
+		contracts::erc20::{ERC677PortalDecoder, ERC777PortalDecoder},
+		faucet::{Faucet, FaucetGate, FaucetInput, FaucetSource},
+		governance::{Governance, GovernanceInput, Proposal, ProposalState, Tally, Vote},
+	};
+
+	#[cfg(feature = "orderbook")]
+	pub use crate::core::orderbook::{Fill, Order, OrderBook, Placement, Side};
+
+	#[cfg(feature = "memory-tracking")]
+	pub use crate::core::memory::{self, MemoryLimit, MemoryLimitAction, MemoryUsage, TrackingAllocator};
 }
@@ -1,5 +1,5 @@
 use crate::address;
-use ethabi::Address;
+use crate::types::primitives::*;
 
 use super::machine::Deposit;
 
@@ -15,8 +15,8 @@ pub struct AddressBook {
 	pub input_box: Address,
 }
 
-impl AddressBook {
-	pub fn default() -> Self {
+impl Default for AddressBook {
+	fn default() -> Self {
 		Self {
 			cartesi_app_factory: address!("0x7122cd1221C20892234186facfE8615e6743Ab02"),
 			app_address_relay: address!("0xF5DE34d6BbC0446E2a45719E718efEbaaE179daE"),
@@ -28,7 +28,9 @@ impl AddressBook {
 			input_box: address!("0x59b22D57D4f067708AB0c00552767405926dc768"),
 		}
 	}
+}
 
+impl AddressBook {
 	pub fn is_portal(&self, sender: Address) -> bool {
 		self.ether_portal == sender
 			|| self.erc20_portal == sender
@@ -49,6 +51,7 @@ impl AddressBook {
 					self.erc1155_batch_portal
 				}
 			}
+			Deposit::ERC4626 { portal, .. } | Deposit::Custom { portal, .. } => portal,
 		}
 	}
 }
@@ -0,0 +1,246 @@
+use super::machine::Deposit;
+use crate::types::primitives::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum BalanceChangeKind {
+	Deposit,
+	Transfer,
+	Withdraw,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase", tag = "type")]
+pub enum Asset {
+	Ether,
+	ERC20 { token: Address },
+	ERC721 { token: Address },
+	ERC1155 { token: Address },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenMetadata {
+	Uri(String),
+	Json(serde_json::Value),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AssetAmount {
+	pub id: Option<Uint>,
+	pub amount: Uint,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BalanceChange {
+	pub kind: BalanceChangeKind,
+	pub asset: Asset,
+	pub from: Option<Address>, // None when the funds enter the rollup
+	pub to: Option<Address>,   // None when the funds leave the rollup
+	pub amounts: Vec<AssetAmount>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub memo: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+	balance_change: BalanceChange,
+}
+
+impl BalanceChange {
+	pub fn ether(kind: BalanceChangeKind, from: Option<Address>, to: Option<Address>, amount: Uint) -> Self {
+		Self {
+			kind,
+			asset: Asset::Ether,
+			from,
+			to,
+			amounts: vec![AssetAmount { id: None, amount }],
+			memo: None,
+		}
+	}
+
+	pub fn erc20(
+		kind: BalanceChangeKind,
+		token: Address,
+		from: Option<Address>,
+		to: Option<Address>,
+		amount: Uint,
+	) -> Self {
+		Self {
+			kind,
+			asset: Asset::ERC20 { token },
+			from,
+			to,
+			amounts: vec![AssetAmount { id: None, amount }],
+			memo: None,
+		}
+	}
+
+	pub fn erc721(
+		kind: BalanceChangeKind,
+		token: Address,
+		from: Option<Address>,
+		to: Option<Address>,
+		id: Uint,
+	) -> Self {
+		Self {
+			kind,
+			asset: Asset::ERC721 { token },
+			from,
+			to,
+			amounts: vec![AssetAmount {
+				id: Some(id),
+				amount: Uint::one(),
+			}],
+			memo: None,
+		}
+	}
+
+	pub fn erc1155(
+		kind: BalanceChangeKind,
+		token: Address,
+		from: Option<Address>,
+		to: Option<Address>,
+		ids_amounts: &[(Uint, Uint)],
+	) -> Self {
+		Self {
+			kind,
+			asset: Asset::ERC1155 { token },
+			from,
+			to,
+			amounts: ids_amounts
+				.iter()
+				.map(|(id, amount)| AssetAmount {
+					id: Some(*id),
+					amount: *amount,
+				})
+				.collect(),
+			memo: None,
+		}
+	}
+
+	pub fn memo(mut self, memo: impl Into<String>) -> Self {
+		self.memo = Some(memo.into());
+		self
+	}
+
+	pub fn from_deposit(deposit: &Deposit) -> Option<Self> {
+		let kind = BalanceChangeKind::Deposit;
+		match deposit {
+			Deposit::Ether { sender, amount } => Some(Self::ether(kind, None, Some(*sender), *amount)),
+			Deposit::ERC20 { sender, token, amount } => Some(Self::erc20(kind, *token, None, Some(*sender), *amount)),
+			Deposit::ERC721 { sender, token, id } => Some(Self::erc721(kind, *token, None, Some(*sender), *id)),
+			Deposit::ERC1155 {
+				sender,
+				token,
+				ids_amounts,
+			} => Some(Self::erc1155(kind, *token, None, Some(*sender), ids_amounts)),
+			Deposit::ERC4626 {
+				sender, vault, shares, ..
+			} => Some(Self::erc20(kind, *vault, None, Some(*sender), *shares)), // Vault shares are ERC20 tokens
+			Deposit::Custom { .. } => None,
+		}
+	}
+
+	pub fn to_bytes(&self) -> Vec<u8> {
+		serde_json::to_vec(&Envelope {
+			balance_change: self.clone(),
+		})
+		.expect("Failed to serialize the balance change")
+	}
+
+	pub fn from_slice(payload: &[u8]) -> Result<Self, Box<dyn std::error::Error>> {
+		Ok(serde_json::from_slice::<Envelope>(payload)?.balance_change)
+	}
+}
+
+/// Canonical notice of a credited deposit, the same for every crabrolls dApp so explorers and frontends can display
+/// inbound funds without app-specific decoding.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DepositReceipt {
+	#[serde(flatten)]
+	pub asset: Asset,
+	pub sender: Address,
+	pub amounts: Vec<AssetAmount>,
+	pub input_index: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ReceiptEnvelope {
+	deposit_receipt: DepositReceipt,
+}
+
+impl DepositReceipt {
+	/// Receipt of a portal deposit, `None` for custom deposits since their assets aren't known to the framework.
+	pub fn from_deposit(deposit: &Deposit, input_index: u64) -> Option<Self> {
+		let change = BalanceChange::from_deposit(deposit)?;
+		Some(Self {
+			asset: change.asset,
+			sender: change.to?,
+			amounts: change.amounts,
+			input_index,
+		})
+	}
+
+	pub fn to_bytes(&self) -> Vec<u8> {
+		serde_json::to_vec(&ReceiptEnvelope {
+			deposit_receipt: self.clone(),
+		})
+		.expect("Failed to serialize the deposit receipt")
+	}
+
+	pub fn from_slice(payload: &[u8]) -> Result<Self, Box<dyn std::error::Error>> {
+		Ok(serde_json::from_slice::<ReceiptEnvelope>(payload)?.deposit_receipt)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::uint;
+
+	#[test]
+	fn test_balance_change_envelope() {
+		let change = BalanceChange::from_deposit(&Deposit::Ether {
+			sender: Address::repeat_byte(1),
+			amount: uint!(10),
+		})
+		.unwrap();
+		let payload: serde_json::Value = serde_json::from_slice(&change.to_bytes()).unwrap();
+		assert_eq!(payload["balance_change"]["kind"], "deposit");
+		assert_eq!(payload["balance_change"]["asset"]["type"], "ether");
+		assert_eq!(payload["balance_change"]["from"], serde_json::Value::Null);
+		assert_eq!(BalanceChange::from_slice(&change.to_bytes()).unwrap(), change);
+	}
+
+	#[test]
+	fn test_deposit_receipt_envelope() {
+		let deposit = Deposit::ERC1155 {
+			sender: Address::repeat_byte(1),
+			token: Address::repeat_byte(2),
+			ids_amounts: vec![(uint!(1), uint!(5)), (uint!(2), uint!(7))],
+		};
+		let receipt = DepositReceipt::from_deposit(&deposit, 3).unwrap();
+		let payload: serde_json::Value = serde_json::from_slice(&receipt.to_bytes()).unwrap();
+		assert_eq!(payload["deposit_receipt"]["type"], "erc1155");
+		assert_eq!(
+			payload["deposit_receipt"]["token"],
+			format!("{:?}", Address::repeat_byte(2))
+		);
+		assert_eq!(
+			payload["deposit_receipt"]["sender"],
+			format!("{:?}", Address::repeat_byte(1))
+		);
+		assert_eq!(payload["deposit_receipt"]["amounts"].as_array().unwrap().len(), 2);
+		assert_eq!(payload["deposit_receipt"]["input_index"], 3);
+		assert_eq!(DepositReceipt::from_slice(&receipt.to_bytes()).unwrap(), receipt);
+
+		let custom = Deposit::Custom {
+			portal: Address::repeat_byte(3),
+			sender: Address::repeat_byte(1),
+			payload: vec![],
+		};
+		assert_eq!(DepositReceipt::from_deposit(&custom, 3), None);
+	}
+}
@@ -0,0 +1,95 @@
+use std::error::Error;
+use thiserror::Error;
+
+/// Errors of the environment, the wallets and the ABI helpers, to match on their kind rather than on their message.
+#[derive(Debug, Error)]
+pub enum CrabrollsError {
+	#[error("{0}")]
+	Abi(String), // Payload that doesn't decode, or token of an unexpected type
+	#[error("{0}")]
+	Portal(String), // Deposit of a portal that can't be credited
+	#[error("insufficient funds")]
+	InsufficientFunds,
+	#[error("insufficient shares")]
+	InsufficientShares,
+	#[error("token not owned")]
+	TokenNotOwned, // No wallet owns the token
+	#[error("wallet does not own the token")]
+	NotOwner,
+	#[error("can't transfer to self")]
+	SelfTransfer,
+	#[error("balance overflow")]
+	BalanceOverflow,
+	#[error("{0}")]
+	Wallet(String), // Any other inconsistent wallet operation
+	#[error("{0}")]
+	Transport(String), // Request to the rollup server that failed or got an unexpected response
+	#[error("{0}")]
+	Other(String),
+}
+
+impl CrabrollsError {
+	pub fn abi(message: impl Into<String>) -> Self {
+		Self::Abi(message.into())
+	}
+
+	pub fn portal(message: impl Into<String>) -> Self {
+		Self::Portal(message.into())
+	}
+
+	pub fn wallet(message: impl Into<String>) -> Self {
+		Self::Wallet(message.into())
+	}
+
+	pub fn transport(message: impl Into<String>) -> Self {
+		Self::Transport(message.into())
+	}
+
+	pub fn other(message: impl Into<String>) -> Self {
+		Self::Other(message.into())
+	}
+}
+
+impl From<ethabi::Error> for CrabrollsError {
+	fn from(error: ethabi::Error) -> Self {
+		Self::Abi(error.to_string())
+	}
+}
+
+impl From<std::string::FromUtf8Error> for CrabrollsError {
+	fn from(error: std::string::FromUtf8Error) -> Self {
+		Self::Abi(error.to_string())
+	}
+}
+
+impl From<serde_json::Error> for CrabrollsError {
+	fn from(error: serde_json::Error) -> Self {
+		Self::Other(error.to_string())
+	}
+}
+
+/// Keeps the kind of a boxed `CrabrollsError`, e.g. one returned by an application, and wraps any other error.
+impl From<Box<dyn Error>> for CrabrollsError {
+	fn from(error: Box<dyn Error>) -> Self {
+		match error.downcast::<CrabrollsError>() {
+			Ok(error) => *error,
+			Err(error) => Self::Other(error.to_string()),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_boxed_error_keeps_its_kind() {
+		let error: Box<dyn Error> = CrabrollsError::InsufficientFunds.into();
+		assert!(matches!(CrabrollsError::from(error), CrabrollsError::InsufficientFunds));
+
+		let error: Box<dyn Error> = "boom".into();
+		let error = CrabrollsError::from(error);
+		assert!(matches!(error, CrabrollsError::Other(_)));
+		assert_eq!(error.to_string(), "boom");
+	}
+}
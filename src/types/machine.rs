@@ -1,17 +1,31 @@
 use crate::{
-	core::contracts::{erc1155::ERC1155Wallet, erc20::ERC20Wallet, erc721::ERC721Wallet, ether::EtherWallet},
-	utils::parsers::deserializers::*,
+	core::contracts::{erc4626::ERC4626Wallet, ether::EtherWallet},
+	utils::{abi::abi, parsers::deserializers::*},
 };
-use ethabi::{Address, Uint};
+use crate::types::primitives::*;
+use ethabi::Hash;
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct Metadata {
+	#[serde(alias = "index")]
 	pub input_index: u64,
-	#[serde(alias = "msg_sender")]
+	#[serde(alias = "msg_sender", alias = "msgSender")]
 	pub sender: Address,
+	#[serde(alias = "blockNumber")]
 	pub block_number: u64,
+	#[serde(alias = "block_timestamp", alias = "blockTimestamp")]
 	pub timestamp: u64,
+	#[serde(default, alias = "appContract")]
+	pub app_contract: Option<Address>,
+	#[serde(default, alias = "chainId")]
+	pub chain_id: Option<u64>, // Only delivered by the rollups node v2
+	#[serde(default, alias = "prevRandao")]
+	pub prev_randao: Option<Hash>, // RANDAO mix of the block of the input, only delivered by the rollups node v2
+	#[serde(skip)]
+	pub annotations: HashMap<String, String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
@@ -28,37 +42,157 @@ pub struct Advance {
 	pub payload: Vec<u8>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct Inspect {
 	#[serde(deserialize_with = "deserialize_string_of_bytes")]
 	pub payload: Vec<u8>,
 }
 
-#[derive(Debug)]
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct IndexResponse {
+	pub index: u64,
+}
+
+/// Generic IO request of the rollups node v2, asking the node for data of a `domain` (e.g. the preimage of a hash)
+/// identified by the payload.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct GioRequest {
+	pub domain: u16,
+	#[serde(
+		rename = "id",
+		serialize_with = "serialize_bytes_as_string",
+		deserialize_with = "deserialize_string_of_bytes"
+	)]
+	pub payload: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct GioResponse {
+	#[serde(rename = "response_code")]
+	pub code: u16,
+	#[serde(
+		rename = "response",
+		serialize_with = "serialize_bytes_as_string",
+		deserialize_with = "deserialize_string_of_bytes"
+	)]
+	pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
 pub enum Input {
 	Advance(Advance),
 	Inspect(Inspect),
 }
 
-#[derive(Serialize, Debug, Clone, PartialEq)]
-#[serde(untagged)]
+impl Input {
+	pub fn from_finish_response(value: serde_json::Value) -> Result<Self, Box<dyn Error>> {
+		let request_type = value["request_type"].as_str().ok_or("Invalid request type")?;
+		let data = value["data"].clone();
+
+		match request_type {
+			"advance_state" => Ok(Input::Advance(serde_json::from_value(data)?)),
+			"inspect_state" => Ok(Input::Inspect(serde_json::from_value(data)?)),
+			_ => Err(Box::from("Invalid request type")),
+		}
+	}
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
 pub enum Output {
 	Voucher {
 		destination: Address,
-		#[serde(serialize_with = "serialize_bytes_as_string")]
+		#[serde(
+			default,
+			serialize_with = "serialize_uint_as_string",
+			deserialize_with = "deserialize_string_of_uint"
+		)]
+		value: Uint, // Ether sent with the call, only supported by the rollups node v2
+		#[serde(
+			serialize_with = "serialize_bytes_as_string",
+			deserialize_with = "deserialize_string_of_bytes"
+		)]
+		payload: Vec<u8>,
+	},
+	#[serde(rename = "delegate-call-voucher")]
+	DelegateCallVoucher {
+		destination: Address, // Contract whose code runs in the context of the application contract
+		#[serde(
+			serialize_with = "serialize_bytes_as_string",
+			deserialize_with = "deserialize_string_of_bytes"
+		)]
 		payload: Vec<u8>,
 	},
 	Notice {
-		#[serde(serialize_with = "serialize_bytes_as_string")]
+		#[serde(
+			serialize_with = "serialize_bytes_as_string",
+			deserialize_with = "deserialize_string_of_bytes"
+		)]
 		payload: Vec<u8>,
 	},
 	Report {
-		#[serde(serialize_with = "serialize_bytes_as_string")]
+		#[serde(
+			serialize_with = "serialize_bytes_as_string",
+			deserialize_with = "deserialize_string_of_bytes"
+		)]
 		payload: Vec<u8>,
 	},
 }
 
+impl Output {
+	pub fn kind(&self) -> &'static str {
+		match self {
+			Output::Voucher { .. } => "voucher",
+			Output::DelegateCallVoucher { .. } => "delegate-call-voucher",
+			Output::Notice { .. } => "notice",
+			Output::Report { .. } => "report",
+		}
+	}
+
+	pub fn payload(&self) -> &[u8] {
+		match self {
+			Output::Voucher { payload, .. }
+			| Output::DelegateCallVoucher { payload, .. }
+			| Output::Notice { payload }
+			| Output::Report { payload } => payload,
+		}
+	}
+
+	/// Body posted to the output's own endpoint (`/voucher`, `/delegate-call-voucher`, `/notice` or `/report`), without
+	/// the type tag.
+	pub fn to_request(&self) -> Result<serde_json::Value, Box<dyn Error>> {
+		let mut request = serde_json::to_value(self)?;
+		if let Some(fields) = request.as_object_mut() {
+			fields.remove("type");
+		}
+		Ok(request)
+	}
+
+	/// Body posted to the rollups node v1, whose vouchers have no value and which has no delegate call vouchers.
+	pub fn to_v1_request(&self) -> Result<serde_json::Value, Box<dyn Error>> {
+		self.check_v1()?;
+		let mut request = self.to_request()?;
+		if let Some(fields) = request.as_object_mut() {
+			fields.remove("value");
+		}
+		Ok(request)
+	}
+
+	pub fn check_v1(&self) -> Result<(), Box<dyn Error>> {
+		match self {
+			Output::Voucher { value, .. } if !value.is_zero() => {
+				Err("vouchers with value are only supported by the rollups node v2".into())
+			}
+			Output::DelegateCallVoucher { .. } => {
+				Err("delegate call vouchers are only supported by the rollups node v2".into())
+			}
+			_ => Ok(()),
+		}
+	}
+}
+
 #[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
 pub enum Deposit {
 	Ether {
 		sender: Address,
@@ -79,6 +213,17 @@ pub enum Deposit {
 		token: Address,
 		ids_amounts: Vec<(Uint, Uint)>,
 	},
+	ERC4626 {
+		portal: Address,
+		sender: Address,
+		vault: Address,
+		shares: Uint,
+	},
+	Custom {
+		portal: Address,
+		sender: Address,
+		payload: Vec<u8>,
+	},
 }
 
 impl TryFrom<Deposit> for Vec<u8> {
@@ -87,13 +232,20 @@ impl TryFrom<Deposit> for Vec<u8> {
 	fn try_from(deposit: Deposit) -> Result<Self, Self::Error> {
 		match deposit {
 			Deposit::Ether { sender, amount } => Ok(EtherWallet::deposit_payload(sender, amount)?),
-			Deposit::ERC20 { sender, token, amount } => Ok(ERC20Wallet::deposit_payload(sender, token, amount)?),
-			Deposit::ERC721 { sender, token, id } => Ok(ERC721Wallet::deposit_payload(sender, token, id)?),
+			Deposit::ERC20 { sender, token, amount } => Ok(abi::erc20::deposit_payload(sender, token, amount)?),
+			Deposit::ERC721 { sender, token, id } => Ok(abi::erc721::deposit_payload(sender, token, id)?),
 			Deposit::ERC1155 {
 				sender,
 				token,
 				ids_amounts,
-			} => Ok(ERC1155Wallet::deposit_payload(sender, token, ids_amounts)?),
+			} => match ids_amounts.as_slice() {
+				[(id, amount)] => Ok(abi::erc1155::single_deposit_payload(sender, token, *id, *amount)?),
+				_ => Ok(abi::erc1155::batch_deposit_payload(sender, token, ids_amounts)?),
+			},
+			Deposit::ERC4626 {
+				sender, vault, shares, ..
+			} => Ok(ERC4626Wallet::deposit_payload(sender, vault, shares)?),
+			Deposit::Custom { payload, .. } => Ok(payload),
 		}
 	}
 }
@@ -110,3 +262,343 @@ impl Default for PortalHandlerConfig {
 		Self::Handle { advance: true }
 	}
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum VoucherDedupPolicy {
+	#[default]
+	Allow, // Emit duplicated vouchers without any check
+	Warn,   // Log a warning when a voucher identical to a previously accepted one is emitted
+	Reject, // Fail the send_voucher call for a voucher identical to another one of the same input
+	Report, // Emit the voucher along with a report flagging the duplicate
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PreProcess {
+	Continue {
+		payload: Vec<u8>,                     // Payload passed on to the portal handling and the app
+		annotations: HashMap<String, String>, // Extra context merged into the Metadata received by the app
+	},
+	Finish(FinishStatus), // Skip the portal handling and the app, finishing the input with the given status
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum DepositRejectPolicy {
+	#[default]
+	Keep, // Keep the deposit credited on the wallet even if the app rejects the advance
+	Rollback, // Remove the credited deposit from the wallet when the app rejects the advance
+	Refund,   // Withdraw the deposit back to the sender and accept the input so the voucher is emitted
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum SenderPolicy {
+	#[default]
+	Open, // Any sender can send advance inputs
+	Allow(HashSet<Address>), // Only the given senders can send advance inputs
+	Deny(HashSet<Address>),  // Any sender but the given ones can send advance inputs
+}
+
+impl SenderPolicy {
+	pub fn allow(senders: impl IntoIterator<Item = Address>) -> Self {
+		Self::Allow(senders.into_iter().collect())
+	}
+
+	pub fn deny(senders: impl IntoIterator<Item = Address>) -> Self {
+		Self::Deny(senders.into_iter().collect())
+	}
+
+	pub fn permits(&self, sender: Address) -> bool {
+		match self {
+			Self::Open => true,
+			Self::Allow(senders) => senders.contains(&sender),
+			Self::Deny(senders) => !senders.contains(&sender),
+		}
+	}
+}
+
+pub const DEFAULT_MAX_OUTPUT_SIZE: usize = 2 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OutputLimits {
+	pub max_voucher_size: usize,
+	pub max_notice_size: usize,
+	pub max_report_size: usize,
+	pub chunk_reports: bool, // Split oversized reports into several reports instead of failing
+}
+
+impl Default for OutputLimits {
+	fn default() -> Self {
+		Self {
+			max_voucher_size: DEFAULT_MAX_OUTPUT_SIZE,
+			max_notice_size: DEFAULT_MAX_OUTPUT_SIZE,
+			max_report_size: DEFAULT_MAX_OUTPUT_SIZE,
+			chunk_reports: false,
+		}
+	}
+}
+
+impl OutputLimits {
+	pub fn max_size(&self, output: &Output) -> usize {
+		match output {
+			Output::Voucher { .. } | Output::DelegateCallVoucher { .. } => self.max_voucher_size,
+			Output::Notice { .. } => self.max_notice_size,
+			Output::Report { .. } => self.max_report_size,
+		}
+	}
+
+	pub fn check(&self, output: &Output) -> Result<(), Box<dyn std::error::Error>> {
+		let max_size = self.max_size(output);
+		let size = output.payload().len();
+		if size > max_size {
+			return Err(format!(
+				"{} payload of {} bytes exceeds the limit of {} bytes",
+				output.kind(),
+				size,
+				max_size
+			)
+			.into());
+		}
+		Ok(())
+	}
+
+	pub fn report_chunks<'a>(&self, payload: &'a [u8]) -> Result<Vec<&'a [u8]>, Box<dyn std::error::Error>> {
+		if payload.len() <= self.max_report_size {
+			return Ok(vec![payload]);
+		}
+		if !self.chunk_reports || self.max_report_size == 0 {
+			self.check(&Output::Report {
+				payload: payload.to_vec(),
+			})?;
+		}
+		Ok(payload.chunks(self.max_report_size).collect())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn limits(max_size: usize, chunk_reports: bool) -> OutputLimits {
+		OutputLimits {
+			max_voucher_size: max_size,
+			max_notice_size: max_size,
+			max_report_size: max_size,
+			chunk_reports,
+		}
+	}
+
+	#[test]
+	fn test_output_limits_check() {
+		let limits = limits(4, false);
+		let notice = Output::Notice { payload: vec![0u8; 4] };
+		assert!(limits.check(&notice).is_ok());
+
+		let voucher = Output::Voucher {
+			destination: Address::zero(),
+			value: Uint::zero(),
+			payload: vec![0u8; 5],
+		};
+		assert_eq!(
+			limits.check(&voucher).unwrap_err().to_string(),
+			"voucher payload of 5 bytes exceeds the limit of 4 bytes"
+		);
+	}
+
+	#[test]
+	fn test_output_tagging() {
+		let voucher = Output::Voucher {
+			destination: Address::zero(),
+			value: Uint::from(0x100),
+			payload: vec![0xab],
+		};
+		let delegate_call = Output::DelegateCallVoucher {
+			destination: Address::zero(),
+			payload: vec![0xab],
+		};
+		let notice = Output::Notice { payload: vec![0xab] };
+		let report = Output::Report { payload: vec![0xab] };
+
+		assert_eq!(
+			serde_json::to_string(&notice).unwrap(),
+			r#"{"type":"notice","payload":"0xab"}"#
+		);
+		for output in [&voucher, &delegate_call, &notice, &report] {
+			assert_eq!(serde_json::to_value(output).unwrap()["type"], output.kind());
+		}
+		for output in [voucher.clone(), delegate_call.clone(), notice.clone(), report.clone()] {
+			let encoded = serde_json::to_vec(&output).unwrap();
+			assert_eq!(serde_json::from_slice::<Output>(&encoded).unwrap(), output);
+		}
+
+		assert_eq!(report.to_request().unwrap(), serde_json::json!({ "payload": "0xab" }));
+		assert_eq!(
+			voucher.to_request().unwrap(),
+			serde_json::json!({
+				"destination": "0x0000000000000000000000000000000000000000",
+				"value": "0x0000000000000000000000000000000000000000000000000000000000000100",
+				"payload": "0xab",
+			})
+		);
+	}
+
+	#[test]
+	fn test_v1_requests() {
+		let voucher = Output::Voucher {
+			destination: Address::zero(),
+			value: Uint::zero(),
+			payload: vec![0xab],
+		};
+		assert_eq!(
+			voucher.to_v1_request().unwrap(),
+			serde_json::json!({ "destination": "0x0000000000000000000000000000000000000000", "payload": "0xab" })
+		);
+
+		let v1_voucher: Output = serde_json::from_value(serde_json::json!({
+			"type": "voucher",
+			"destination": "0x0000000000000000000000000000000000000000",
+			"payload": "0xab",
+		}))
+		.unwrap();
+		assert_eq!(v1_voucher, voucher);
+
+		let with_value = Output::Voucher {
+			destination: Address::zero(),
+			value: Uint::one(),
+			payload: vec![],
+		};
+		assert!(with_value.to_v1_request().is_err());
+		let delegate_call = Output::DelegateCallVoucher {
+			destination: Address::zero(),
+			payload: vec![],
+		};
+		assert!(delegate_call.to_v1_request().is_err());
+		assert!(Output::Notice { payload: vec![] }.to_v1_request().is_ok());
+	}
+
+	#[test]
+	fn test_output_limits_report_chunks() {
+		let payload = [1u8, 2, 3, 4, 5];
+		assert_eq!(limits(8, false).report_chunks(&payload).unwrap(), vec![&payload[..]]);
+		assert_eq!(
+			limits(2, true).report_chunks(&payload).unwrap(),
+			vec![&[1u8, 2][..], &[3u8, 4][..], &[5u8][..]]
+		);
+		assert_eq!(
+			limits(2, false).report_chunks(&payload).unwrap_err().to_string(),
+			"report payload of 5 bytes exceeds the limit of 2 bytes"
+		);
+	}
+
+	#[test]
+	fn test_metadata_app_contract() {
+		let metadata: Metadata = serde_json::from_value(serde_json::json!({
+			"input_index": 1,
+			"msg_sender": "0x0000000000000000000000000000000000000001",
+			"block_number": 2,
+			"timestamp": 3,
+		}))
+		.unwrap();
+		assert_eq!(metadata.app_contract, None);
+
+		let metadata: Metadata = serde_json::from_value(serde_json::json!({
+			"input_index": 1,
+			"msg_sender": "0x0000000000000000000000000000000000000001",
+			"block_number": 2,
+			"timestamp": 3,
+			"app_contract": "0xab7528bb862fb57e8a2bcd567a2e929a0be56a5e",
+		}))
+		.unwrap();
+		assert_eq!(
+			metadata.app_contract,
+			Some(crate::address!("0xab7528bb862fb57e8a2bcd567a2e929a0be56a5e"))
+		);
+		assert_eq!((metadata.chain_id, metadata.prev_randao), (None, None));
+	}
+
+	#[test]
+	fn test_node_v2_metadata() {
+		let randao = "0x0f6a0b8e3d6e6c9e4e5b8f3c6f1d2a7b9c0e1f2a3b4c5d6e7f8091a2b3c4d5e6";
+		let response = serde_json::json!({
+			"request_type": "advance_state",
+			"data": {
+				"metadata": {
+					"chain_id": 31337,
+					"app_contract": "0xab7528bb862fb57e8a2bcd567a2e929a0be56a5e",
+					"msg_sender": "0x0000000000000000000000000000000000000001",
+					"block_number": 2,
+					"block_timestamp": 3,
+					"prev_randao": randao,
+					"input_index": 4,
+				},
+				"payload": "0x6869",
+			},
+		});
+		let Input::Advance(advance) = Input::from_finish_response(response).unwrap() else {
+			panic!("expected an advance input");
+		};
+		assert_eq!(advance.metadata.chain_id, Some(31337));
+		assert_eq!(advance.metadata.prev_randao, Some(randao.parse().unwrap()));
+		assert_eq!(advance.metadata.timestamp, 3);
+		assert_eq!(advance.metadata.input_index, 4);
+
+		let metadata: Metadata = serde_json::from_value(serde_json::json!({
+			"chainId": 1,
+			"appContract": "0xab7528bb862fb57e8a2bcd567a2e929a0be56a5e",
+			"msgSender": "0x0000000000000000000000000000000000000001",
+			"blockNumber": 2,
+			"blockTimestamp": 3,
+			"prevRandao": randao,
+			"index": 4,
+		}))
+		.unwrap();
+		assert_eq!(metadata.chain_id, Some(1));
+		assert_eq!(
+			metadata.sender,
+			crate::address!("0x0000000000000000000000000000000000000001")
+		);
+		assert_eq!(metadata.prev_randao, Some(randao.parse().unwrap()));
+	}
+
+	#[test]
+	fn test_gio_serialization() {
+		let request = GioRequest {
+			domain: 0x2d,
+			payload: vec![0xab, 0xcd],
+		};
+		assert_eq!(
+			serde_json::to_value(&request).unwrap(),
+			serde_json::json!({ "domain": 45, "id": "0xabcd" })
+		);
+
+		let response: GioResponse = serde_json::from_str(r#"{"response_code": 42, "response": "0x6869"}"#).unwrap();
+		assert_eq!(
+			response,
+			GioResponse {
+				code: 42,
+				data: b"hi".to_vec()
+			}
+		);
+	}
+
+	#[test]
+	fn test_index_response() {
+		let response: IndexResponse = serde_json::from_str(r#"{"index": 4294967296}"#).unwrap();
+		assert_eq!(response.index, 4_294_967_296);
+
+		assert!(serde_json::from_str::<IndexResponse>(r#"{"error": "bad output"}"#).is_err());
+		assert!(serde_json::from_str::<IndexResponse>(r#"{"index": -1}"#).is_err());
+	}
+
+	#[test]
+	fn test_sender_policy() {
+		let (alice, bob) = (Address::repeat_byte(0xa1), Address::repeat_byte(0xb0));
+		assert!(SenderPolicy::Open.permits(alice));
+
+		let policy = SenderPolicy::allow([alice]);
+		assert!(policy.permits(alice));
+		assert!(!policy.permits(bob));
+
+		let policy = SenderPolicy::deny([alice]);
+		assert!(!policy.permits(alice));
+		assert!(policy.permits(bob));
+	}
+}
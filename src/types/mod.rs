@@ -1,3 +1,7 @@
 pub mod address_book;
+pub mod balance;
+pub mod error;
 pub mod machine;
+pub mod primitives;
+pub mod report;
 pub mod testing;
@@ -0,0 +1,168 @@
+// `Address` and `Uint` of the public API: the `ethabi` types by default, alloy's `Address` and `U256` with the `alloy`
+// feature. The traits below give the alloy types the `ethabi` methods used across the crate, so the same code
+// compiles against both, and apps moving to alloy can keep their `Uint::zero()` or `as_u64()` calls meanwhile.
+
+#[cfg(not(feature = "alloy"))]
+pub use ethabi::{Address, Uint};
+
+#[cfg(feature = "alloy")]
+pub use alloy_primitives::{Address, U256 as Uint};
+
+/// Conversion to and from the `ethabi` type held by the `Token`s, a no-op without the `alloy` feature.
+pub trait EthabiCompat: Sized {
+	type Ethabi;
+
+	fn into_ethabi(self) -> Self::Ethabi;
+
+	fn from_ethabi(value: Self::Ethabi) -> Self;
+}
+
+impl EthabiCompat for Address {
+	type Ethabi = ethabi::Address;
+
+	#[cfg(not(feature = "alloy"))]
+	fn into_ethabi(self) -> Self::Ethabi {
+		self
+	}
+
+	#[cfg(not(feature = "alloy"))]
+	fn from_ethabi(value: Self::Ethabi) -> Self {
+		value
+	}
+
+	#[cfg(feature = "alloy")]
+	fn into_ethabi(self) -> Self::Ethabi {
+		ethabi::Address::from(self.into_array())
+	}
+
+	#[cfg(feature = "alloy")]
+	fn from_ethabi(value: Self::Ethabi) -> Self {
+		Address::from(value.to_fixed_bytes())
+	}
+}
+
+impl EthabiCompat for Uint {
+	type Ethabi = ethabi::Uint;
+
+	#[cfg(not(feature = "alloy"))]
+	fn into_ethabi(self) -> Self::Ethabi {
+		self
+	}
+
+	#[cfg(not(feature = "alloy"))]
+	fn from_ethabi(value: Self::Ethabi) -> Self {
+		value
+	}
+
+	#[cfg(feature = "alloy")]
+	fn into_ethabi(self) -> Self::Ethabi {
+		ethabi::Uint::from_big_endian(&self.to_be_bytes::<32>())
+	}
+
+	#[cfg(feature = "alloy")]
+	fn from_ethabi(value: Self::Ethabi) -> Self {
+		let mut bytes = [0u8; 32];
+		value.to_big_endian(&mut bytes);
+		Uint::from_be_bytes(bytes)
+	}
+}
+
+/// The `ethabi` methods of `Uint` missing from alloy's `U256`. The `as_*` casts panic on overflow like theirs.
+#[cfg(feature = "alloy")]
+pub trait UintCompat: Sized {
+	fn zero() -> Self;
+
+	fn one() -> Self;
+
+	fn exp10(n: usize) -> Self;
+
+	fn from_dec_str(value: &str) -> Result<Self, alloy_primitives::ruint::ParseError>;
+
+	fn from_big_endian(bytes: &[u8]) -> Self;
+
+	fn to_big_endian(&self, bytes: &mut [u8]);
+
+	fn as_u64(&self) -> u64;
+
+	fn as_u128(&self) -> u128;
+
+	fn as_usize(&self) -> usize;
+
+	fn low_u64(&self) -> u64;
+}
+
+#[cfg(feature = "alloy")]
+impl UintCompat for Uint {
+	fn zero() -> Self {
+		Uint::ZERO
+	}
+
+	fn one() -> Self {
+		Uint::ONE
+	}
+
+	fn exp10(n: usize) -> Self {
+		Uint::from(10u64).pow(Uint::from(n))
+	}
+
+	fn from_dec_str(value: &str) -> Result<Self, alloy_primitives::ruint::ParseError> {
+		Uint::from_str_radix(value, 10)
+	}
+
+	fn from_big_endian(bytes: &[u8]) -> Self {
+		Uint::from_be_slice(bytes)
+	}
+
+	fn to_big_endian(&self, bytes: &mut [u8]) {
+		bytes.copy_from_slice(&self.to_be_bytes::<32>());
+	}
+
+	fn as_u64(&self) -> u64 {
+		u64::try_from(*self).expect("Integer overflow when casting to u64")
+	}
+
+	fn as_u128(&self) -> u128 {
+		u128::try_from(*self).expect("Integer overflow when casting to u128")
+	}
+
+	fn as_usize(&self) -> usize {
+		usize::try_from(*self).expect("Integer overflow when casting to usize")
+	}
+
+	fn low_u64(&self) -> u64 {
+		self.as_limbs()[0]
+	}
+}
+
+/// The `ethabi` methods of `Address` missing from alloy's.
+#[cfg(feature = "alloy")]
+pub trait AddressCompat: Sized {
+	fn zero() -> Self;
+
+	fn from_low_u64_be(value: u64) -> Self;
+
+	fn as_bytes(&self) -> &[u8];
+
+	fn to_fixed_bytes(self) -> [u8; 20];
+}
+
+#[cfg(feature = "alloy")]
+impl AddressCompat for Address {
+	fn zero() -> Self {
+		Address::ZERO
+	}
+
+	fn from_low_u64_be(value: u64) -> Self {
+		let mut bytes = [0u8; 20];
+		bytes[12..].copy_from_slice(&value.to_be_bytes());
+		Address::from(bytes)
+	}
+
+	fn as_bytes(&self) -> &[u8] {
+		self.as_slice()
+	}
+
+	fn to_fixed_bytes(self) -> [u8; 20] {
+		self.into_array()
+	}
+}
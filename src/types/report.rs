@@ -0,0 +1,111 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ErrorReport {
+	pub code: String,
+	pub message: String,
+	pub input_index: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+	error: ErrorReport,
+}
+
+impl ErrorReport {
+	pub const INVALID_PAYLOAD: &'static str = "INVALID_PAYLOAD";
+	pub const UNAUTHORIZED: &'static str = "UNAUTHORIZED";
+	pub const NOT_FOUND: &'static str = "NOT_FOUND";
+	pub const NO_ROUTE: &'static str = "NO_ROUTE";
+	pub const DUPLICATE: &'static str = "DUPLICATE";
+	pub const INTERNAL: &'static str = "INTERNAL";
+
+	pub fn new(code: impl Into<String>, message: impl Into<String>) -> Self {
+		Self {
+			code: code.into(),
+			message: message.into(),
+			input_index: None,
+		}
+	}
+
+	pub fn invalid_payload(message: impl Into<String>) -> Self {
+		Self::new(Self::INVALID_PAYLOAD, message)
+	}
+
+	pub fn unauthorized(message: impl Into<String>) -> Self {
+		Self::new(Self::UNAUTHORIZED, message)
+	}
+
+	pub fn not_found(message: impl Into<String>) -> Self {
+		Self::new(Self::NOT_FOUND, message)
+	}
+
+	pub fn no_route() -> Self {
+		Self::new(Self::NO_ROUTE, "no application selected for the input")
+	}
+
+	pub fn duplicate(message: impl Into<String>) -> Self {
+		Self::new(Self::DUPLICATE, message)
+	}
+
+	pub fn internal(message: impl Into<String>) -> Self {
+		Self::new(Self::INTERNAL, message)
+	}
+
+	pub fn input_index(mut self, input_index: u64) -> Self {
+		self.input_index = Some(input_index);
+		self
+	}
+
+	/// Keeps the code of an `ErrorReport` error and reports any other error as `INTERNAL`.
+	pub fn from_error(error: &(dyn Error + 'static)) -> Self {
+		match error.downcast_ref::<ErrorReport>() {
+			Some(report) => report.clone(),
+			None => Self::internal(error.to_string()),
+		}
+	}
+
+	pub fn to_bytes(&self) -> Vec<u8> {
+		serde_json::to_vec(&Envelope { error: self.clone() }).expect("Failed to serialize the error report")
+	}
+
+	pub fn from_slice(payload: &[u8]) -> Result<Self, Box<dyn Error>> {
+		Ok(serde_json::from_slice::<Envelope>(payload)?.error)
+	}
+}
+
+impl fmt::Display for ErrorReport {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}: {}", self.code, self.message)
+	}
+}
+
+impl Error for ErrorReport {}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_error_report_envelope() {
+		let report = ErrorReport::invalid_payload("bad json").input_index(7);
+		assert_eq!(
+			String::from_utf8(report.to_bytes()).unwrap(),
+			r#"{"error":{"code":"INVALID_PAYLOAD","message":"bad json","input_index":7}}"#
+		);
+		assert_eq!(ErrorReport::from_slice(&report.to_bytes()).unwrap(), report);
+	}
+
+	#[test]
+	fn test_error_report_from_error() {
+		let error: Box<dyn Error> = ErrorReport::unauthorized("admin only").into();
+		assert_eq!(ErrorReport::from_error(error.as_ref()).code, ErrorReport::UNAUTHORIZED);
+
+		let error: Box<dyn Error> = "boom".into();
+		let report = ErrorReport::from_error(error.as_ref());
+		assert_eq!(report.code, ErrorReport::INTERNAL);
+		assert_eq!(report.message, "boom");
+	}
+}
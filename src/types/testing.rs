@@ -1,13 +1,359 @@
 use super::machine::Output;
-use crate::{FinishStatus, Metadata};
+use crate::{
+	core::{handoff::WalletsSnapshot, testing::Tester},
+	Application, FinishStatus, Metadata,
+};
+use crate::types::primitives::*;
+use crate::utils::abi::abi;
+use ethabi::Token;
+use serde::{de::DeserializeOwned, Serialize};
 use std::error::Error;
 
 pub trait ResultUtils {
 	fn is_accepted(&self) -> bool;
 	fn is_rejected(&self) -> bool;
 	fn is_errored(&self) -> bool;
-	fn get_error(&self) -> Option<&dyn Error>;
+	fn get_error(&self) -> Option<&(dyn Error + 'static)>;
 	fn get_outputs(&self) -> Vec<Output>;
+
+	fn notices(&self) -> Vec<Vec<u8>> {
+		self.get_outputs()
+			.into_iter()
+			.filter_map(|output| match output {
+				Output::Notice { payload } => Some(payload),
+				_ => None,
+			})
+			.collect()
+	}
+
+	fn reports(&self) -> Vec<Vec<u8>> {
+		self.get_outputs()
+			.into_iter()
+			.filter_map(|output| match output {
+				Output::Report { payload } => Some(payload),
+				_ => None,
+			})
+			.collect()
+	}
+
+	fn vouchers(&self) -> Vec<(Address, Vec<u8>)> {
+		self.get_outputs()
+			.into_iter()
+			.filter_map(|output| match output {
+				Output::Voucher {
+					destination, payload, ..
+				} => Some((destination, payload)),
+				_ => None,
+			})
+			.collect()
+	}
+
+	fn vouchers_to(&self, destination: Address) -> Vec<Vec<u8>> {
+		self.vouchers()
+			.into_iter()
+			.filter(|(voucher_destination, _)| *voucher_destination == destination)
+			.map(|(_, payload)| payload)
+			.collect()
+	}
+
+	/// Vouchers decoded as calls of the function, or of the functions of the JSON ABI, `abi_or_signature`, skipping
+	/// the vouchers that call other functions.
+	fn voucher_calls(&self, abi_or_signature: &str) -> Vec<(Address, String, Vec<Token>)> {
+		self.vouchers()
+			.into_iter()
+			.filter_map(|(destination, payload)| {
+				let (name, args) = abi::decode::function_call(abi_or_signature, &payload).ok()?;
+				Some((destination, name, args))
+			})
+			.collect()
+	}
+
+	fn delegate_call_vouchers(&self) -> Vec<(Address, Vec<u8>)> {
+		self.get_outputs()
+			.into_iter()
+			.filter_map(|output| match output {
+				Output::DelegateCallVoucher { destination, payload } => Some((destination, payload)),
+				_ => None,
+			})
+			.collect()
+	}
+
+	/// Error of the result as the `Error` type of the app, e.g. its own error enum.
+	fn get_error_as<E: Error + 'static>(&self) -> Option<&E> {
+		self.get_error()?.downcast_ref::<E>()
+	}
+
+	fn notices_as<T: DeserializeOwned>(&self) -> Result<Vec<T>, Box<dyn Error>> {
+		decode_payloads(self.notices())
+	}
+
+	fn reports_as<T: DeserializeOwned>(&self) -> Result<Vec<T>, Box<dyn Error>> {
+		decode_payloads(self.reports())
+	}
+
+	#[track_caller]
+	fn assert_accepted(&self) -> &Self {
+		assert!(
+			self.is_accepted(),
+			"Expected the input to be accepted, but it was rejected (error: {}, outputs: {:?})",
+			describe_error(self.get_error()),
+			self.get_outputs()
+		);
+		self
+	}
+
+	#[track_caller]
+	fn assert_rejected(&self) -> &Self {
+		assert!(
+			self.is_rejected(),
+			"Expected the input to be rejected, but it was accepted (outputs: {:?})",
+			self.get_outputs()
+		);
+		self
+	}
+
+	#[track_caller]
+	fn assert_error_contains(&self, message: &str) -> &Self {
+		match self.get_error() {
+			Some(error) => assert!(
+				error.to_string().contains(message),
+				"Expected an error containing {:?}, got {:?}",
+				message,
+				error.to_string()
+			),
+			None => panic!("Expected an error containing {:?}, got no error", message),
+		}
+		self
+	}
+
+	#[track_caller]
+	fn assert_no_error(&self) -> &Self {
+		assert!(
+			!self.is_errored(),
+			"Expected no error, got {}",
+			describe_error(self.get_error())
+		);
+		self
+	}
+
+	#[track_caller]
+	fn assert_output_count(&self, count: usize) -> &Self {
+		let outputs = self.get_outputs();
+		assert_eq!(
+			outputs.len(),
+			count,
+			"Expected {} outputs, got {}: {:?}",
+			count,
+			outputs.len(),
+			outputs
+		);
+		self
+	}
+
+	#[track_caller]
+	fn assert_notice_count(&self, count: usize) -> &Self {
+		let notices = self.notices();
+		assert_eq!(
+			notices.len(),
+			count,
+			"Expected {} notices, got {}: {:?}",
+			count,
+			notices.len(),
+			lossy_payloads(&notices)
+		);
+		self
+	}
+
+	#[track_caller]
+	fn assert_report_count(&self, count: usize) -> &Self {
+		let reports = self.reports();
+		assert_eq!(
+			reports.len(),
+			count,
+			"Expected {} reports, got {}: {:?}",
+			count,
+			reports.len(),
+			lossy_payloads(&reports)
+		);
+		self
+	}
+
+	#[track_caller]
+	fn assert_voucher_count(&self, count: usize) -> &Self {
+		let vouchers = self.vouchers();
+		assert_eq!(
+			vouchers.len(),
+			count,
+			"Expected {} vouchers, got {}: {:?}",
+			count,
+			vouchers.len(),
+			vouchers
+		);
+		self
+	}
+
+	/// Asserts that at least one voucher is sent to `destination`.
+	#[track_caller]
+	fn assert_voucher_to(&self, destination: Address) -> &Self {
+		let destinations: Vec<Address> = self
+			.vouchers()
+			.into_iter()
+			.map(|(destination, _)| destination)
+			.collect();
+		assert!(
+			destinations.contains(&destination),
+			"Expected a voucher to {:?}, got vouchers to {:?}",
+			destination,
+			destinations
+		);
+		self
+	}
+
+	#[track_caller]
+	fn assert_delegate_call_voucher_count(&self, count: usize) -> &Self {
+		let vouchers = self.delegate_call_vouchers();
+		assert_eq!(
+			vouchers.len(),
+			count,
+			"Expected {} delegate call vouchers, got {}: {:?}",
+			count,
+			vouchers.len(),
+			vouchers
+		);
+		self
+	}
+
+	/// Asserts that at least one voucher to `destination` calls `abi_or_signature` with the `expected` arguments, e.g. a
+	/// `safeTransferFrom(address,address,uint256)` of a token to a user.
+	#[track_caller]
+	fn assert_voucher_call(&self, destination: Address, abi_or_signature: &str, expected: &[Token]) -> &Self {
+		let calls: Vec<Vec<Token>> = self
+			.voucher_calls(abi_or_signature)
+			.into_iter()
+			.filter(|(voucher_destination, ..)| *voucher_destination == destination)
+			.map(|(_, _, args)| args)
+			.collect();
+		assert!(
+			calls.iter().any(|args| args == expected),
+			"Expected a voucher to {:?} calling {} with {:?}, got calls with {:?}",
+			destination,
+			abi_or_signature,
+			expected,
+			calls
+		);
+		self
+	}
+
+	/// Asserts that at least one delegate call voucher is sent to `destination`.
+	#[track_caller]
+	fn assert_delegate_call_voucher_to(&self, destination: Address) -> &Self {
+		let destinations: Vec<Address> = self
+			.delegate_call_vouchers()
+			.into_iter()
+			.map(|(destination, _)| destination)
+			.collect();
+		assert!(
+			destinations.contains(&destination),
+			"Expected a delegate call voucher to {:?}, got delegate call vouchers to {:?}",
+			destination,
+			destinations
+		);
+		self
+	}
+
+	/// Asserts that at least one voucher sends `value` wei to `destination`, e.g. an ether withdrawal.
+	#[track_caller]
+	fn assert_voucher_value(&self, destination: Address, value: Uint) -> &Self {
+		let values: Vec<(Address, Uint)> = self
+			.get_outputs()
+			.into_iter()
+			.filter_map(|output| match output {
+				Output::Voucher { destination, value, .. } => Some((destination, value)),
+				_ => None,
+			})
+			.collect();
+		assert!(
+			values.contains(&(destination, value)),
+			"Expected a voucher of {} to {:?}, got vouchers of {:?}",
+			value,
+			destination,
+			values
+		);
+		self
+	}
+
+	/// Asserts that at least one notice has exactly the `expected` payload.
+	#[track_caller]
+	fn assert_notice(&self, expected: impl AsRef<[u8]>) -> &Self {
+		let notices = self.notices();
+		assert!(
+			notices.iter().any(|notice| notice == expected.as_ref()),
+			"Expected a notice with {:?}, got {:?}",
+			String::from_utf8_lossy(expected.as_ref()),
+			lossy_payloads(&notices)
+		);
+		self
+	}
+
+	/// Asserts that at least one report has exactly the `expected` payload.
+	#[track_caller]
+	fn assert_report(&self, expected: impl AsRef<[u8]>) -> &Self {
+		let reports = self.reports();
+		assert!(
+			reports.iter().any(|report| report == expected.as_ref()),
+			"Expected a report with {:?}, got {:?}",
+			String::from_utf8_lossy(expected.as_ref()),
+			lossy_payloads(&reports)
+		);
+		self
+	}
+
+	/// Asserts that at least one notice decodes to the same JSON value as `expected`, ignoring the key order.
+	#[track_caller]
+	fn assert_notice_json(&self, expected: impl Serialize) -> &Self {
+		assert_json_payload("notice", &self.notices(), expected);
+		self
+	}
+
+	/// Asserts that at least one report decodes to the same JSON value as `expected`, ignoring the key order.
+	#[track_caller]
+	fn assert_report_json(&self, expected: impl Serialize) -> &Self {
+		assert_json_payload("report", &self.reports(), expected);
+		self
+	}
+}
+
+fn describe_error(error: Option<&dyn Error>) -> String {
+	error.map_or_else(|| "none".to_string(), |error| error.to_string())
+}
+
+fn lossy_payloads(payloads: &[Vec<u8>]) -> Vec<String> {
+	payloads
+		.iter()
+		.map(|payload| String::from_utf8_lossy(payload).into_owned())
+		.collect()
+}
+
+#[track_caller]
+fn assert_json_payload(kind: &str, payloads: &[Vec<u8>], expected: impl Serialize) {
+	let expected = serde_json::to_value(expected).expect("Failed to serialize the expected value");
+	let found = payloads
+		.iter()
+		.any(|payload| serde_json::from_slice::<serde_json::Value>(payload).is_ok_and(|value| value == expected));
+	assert!(
+		found,
+		"Expected a {} with JSON {}, got {:?}",
+		kind,
+		expected,
+		lossy_payloads(payloads)
+	);
+}
+
+fn decode_payloads<T: DeserializeOwned>(payloads: Vec<Vec<u8>>) -> Result<Vec<T>, Box<dyn Error>> {
+	payloads
+		.iter()
+		.map(|payload| Ok(serde_json::from_slice(payload)?))
+		.collect()
 }
 
 #[derive(Debug)]
@@ -16,12 +362,30 @@ pub struct AdvanceResult {
 	pub metadata: Metadata,
 	pub status: FinishStatus,
 	pub error: Option<Box<dyn Error>>,
+	pub wallets_before: WalletsSnapshot,
 }
 
 impl AdvanceResult {
 	pub fn get_metadata(&self) -> &Metadata {
 		&self.metadata
 	}
+
+	pub async fn assert_state_unchanged<A: Application>(&self, tester: &Tester<A>) {
+		assert_eq!(
+			self.wallets_before,
+			tester.wallets_snapshot().await,
+			"Expected wallets to be unchanged by the input"
+		);
+	}
+
+	pub fn assert_no_outputs_except_reports(&self) {
+		let outputs: Vec<&Output> = self
+			.outputs
+			.iter()
+			.filter(|output| !matches!(output, Output::Report { .. }))
+			.collect();
+		assert!(outputs.is_empty(), "Expected only reports, got {:?}", outputs);
+	}
 }
 
 #[derive(Debug)]
@@ -29,6 +393,26 @@ pub struct InspectResult {
 	pub outputs: Vec<Output>,
 	pub status: FinishStatus,
 	pub error: Option<Box<dyn Error>>,
+	pub exception: Option<Vec<u8>>,
+	pub illegal_outputs: Vec<Output>,
+}
+
+impl InspectResult {
+	pub fn get_reports(&self) -> Vec<Vec<u8>> {
+		self.reports()
+	}
+
+	pub fn get_exception(&self) -> Option<&[u8]> {
+		self.exception.as_deref()
+	}
+
+	pub fn get_illegal_outputs(&self) -> Vec<Output> {
+		self.illegal_outputs.clone()
+	}
+
+	pub fn has_illegal_outputs(&self) -> bool {
+		!self.illegal_outputs.is_empty()
+	}
 }
 
 impl ResultUtils for AdvanceResult {
@@ -44,7 +428,7 @@ impl ResultUtils for AdvanceResult {
 		self.error.is_some()
 	}
 
-	fn get_error(&self) -> Option<&dyn Error> {
+	fn get_error(&self) -> Option<&(dyn Error + 'static)> {
 		self.error.as_deref()
 	}
 
@@ -66,7 +450,7 @@ impl ResultUtils for InspectResult {
 		self.error.is_some()
 	}
 
-	fn get_error(&self) -> Option<&dyn Error> {
+	fn get_error(&self) -> Option<&(dyn Error + 'static)> {
 		self.error.as_deref()
 	}
 
@@ -74,3 +458,129 @@ impl ResultUtils for InspectResult {
 		self.outputs.clone()
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use serde_json::{json, Value};
+
+	fn result(outputs: Vec<Output>) -> InspectResult {
+		InspectResult {
+			outputs,
+			status: FinishStatus::Accept,
+			error: None,
+			exception: None,
+			illegal_outputs: Vec::new(),
+		}
+	}
+
+	#[test]
+	fn test_output_filters() {
+		let result = result(vec![
+			Output::Notice {
+				payload: br#"{"id":1}"#.to_vec(),
+			},
+			Output::Voucher {
+				destination: Address::repeat_byte(1),
+				value: Uint::zero(),
+				payload: b"first".to_vec(),
+			},
+			Output::Report {
+				payload: b"[1,2]".to_vec(),
+			},
+			Output::Voucher {
+				destination: Address::repeat_byte(2),
+				value: Uint::zero(),
+				payload: b"second".to_vec(),
+			},
+		]);
+
+		assert_eq!(result.notices(), vec![br#"{"id":1}"#.to_vec()]);
+		assert_eq!(result.vouchers().len(), 2);
+		assert_eq!(result.vouchers_to(Address::repeat_byte(2)), vec![b"second".to_vec()]);
+		assert_eq!(result.notices_as::<Value>().unwrap(), vec![json!({ "id": 1 })]);
+		assert_eq!(result.reports_as::<Vec<u32>>().unwrap(), vec![vec![1, 2]]);
+		assert!(result.notices_as::<Vec<u32>>().is_err());
+	}
+
+	#[test]
+	fn test_voucher_calls() {
+		let alice = Address::repeat_byte(0xa);
+		let transfer = vec![
+			Token::Address(ethabi::Address::zero()),
+			Token::Address(alice.into_ethabi()),
+			Token::Uint(ethabi::Uint::from(5)),
+		];
+		let result = result(vec![
+			Output::Voucher {
+				destination: Address::repeat_byte(1),
+				value: Uint::zero(),
+				payload: abi::encode::call("safeTransferFrom(address,address,uint256)", transfer.clone()).unwrap(),
+			},
+			Output::Voucher {
+				destination: Address::repeat_byte(2),
+				value: Uint::zero(),
+				payload: b"raw".to_vec(),
+			},
+		]);
+
+		let calls = result.voucher_calls("safeTransferFrom(address,address,uint256)");
+		assert_eq!(calls, vec![(Address::repeat_byte(1), "safeTransferFrom".to_string(), transfer.clone())]);
+		assert!(result.voucher_calls("transfer(address,uint256)").is_empty());
+		result.assert_voucher_call(Address::repeat_byte(1), "safeTransferFrom(address,address,uint256)", &transfer);
+	}
+
+	#[test]
+	#[should_panic(expected = "Expected a voucher to")]
+	fn test_voucher_call_failure() {
+		let result = result(vec![Output::Voucher {
+			destination: Address::repeat_byte(1),
+			value: Uint::zero(),
+			payload: abi::encode::call("burn(uint256)", vec![Token::Uint(ethabi::Uint::from(5))]).unwrap(),
+		}]);
+		result.assert_voucher_call(Address::repeat_byte(1), "burn(uint256)", &[Token::Uint(ethabi::Uint::from(6))]);
+	}
+
+	#[test]
+	fn test_chained_assertions() {
+		let result = result(vec![
+			Output::Notice {
+				payload: br#"{"id":1,"name":"first"}"#.to_vec(),
+			},
+			Output::Voucher {
+				destination: Address::repeat_byte(1),
+				value: Uint::zero(),
+				payload: b"first".to_vec(),
+			},
+			Output::Report {
+				payload: b"done".to_vec(),
+			},
+			Output::DelegateCallVoucher {
+				destination: Address::repeat_byte(3),
+				payload: b"delegated".to_vec(),
+			},
+		]);
+
+		result
+			.assert_accepted()
+			.assert_no_error()
+			.assert_output_count(4)
+			.assert_notice_count(1)
+			.assert_report_count(1)
+			.assert_voucher_count(1)
+			.assert_voucher_to(Address::repeat_byte(1))
+			.assert_voucher_value(Address::repeat_byte(1), Uint::zero())
+			.assert_delegate_call_voucher_count(1)
+			.assert_delegate_call_voucher_to(Address::repeat_byte(3))
+			.assert_notice_json(json!({ "name": "first", "id": 1 }))
+			.assert_report("done");
+	}
+
+	#[test]
+	#[should_panic(expected = "Expected a voucher to 0x0202020202020202020202020202020202020202, got vouchers to []")]
+	fn test_chained_assertion_failure() {
+		result(vec![])
+			.assert_accepted()
+			.assert_voucher_to(Address::repeat_byte(2));
+	}
+}
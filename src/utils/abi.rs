@@ -1,76 +1,82 @@
+#[allow(clippy::module_inception)]
 pub mod abi {
-	use ethabi::{Address, ParamType, Token, Uint};
-	use std::error::Error;
+	use crate::types::error::CrabrollsError;
+	use crate::types::primitives::*;
+	use ethabi::{Function, ParamType, Token};
+	use std::fs;
+	use std::path::Path;
 
 	pub mod extract {
 		use super::*;
 
-		pub fn address(arg: &ethabi::Token) -> Result<Address, Box<dyn Error>> {
+		pub fn address(arg: &ethabi::Token) -> Result<Address, CrabrollsError> {
 			arg.clone()
 				.into_address()
-				.ok_or_else(|| "invalid type for address".into())
+				.map(Address::from_ethabi)
+				.ok_or_else(|| CrabrollsError::abi("invalid type for address"))
 		}
 
-		pub fn uint(arg: &ethabi::Token) -> Result<Uint, Box<dyn Error>> {
-			arg.clone().into_uint().ok_or_else(|| "invalid type for Uint".into())
+		pub fn uint(arg: &ethabi::Token) -> Result<Uint, CrabrollsError> {
+			arg.clone().into_uint().map(Uint::from_ethabi).ok_or_else(|| CrabrollsError::abi("invalid type for Uint"))
 		}
 
-		pub fn int(arg: &ethabi::Token) -> Result<Uint, Box<dyn Error>> {
-			arg.clone().into_int().ok_or_else(|| "invalid type for Int".into())
+		pub fn int(arg: &ethabi::Token) -> Result<Uint, CrabrollsError> {
+			arg.clone().into_int().map(Uint::from_ethabi).ok_or_else(|| CrabrollsError::abi("invalid type for Int"))
 		}
 
-		pub fn bool(arg: &ethabi::Token) -> Result<bool, Box<dyn Error>> {
-			arg.clone().into_bool().ok_or_else(|| "invalid type for bool".into())
+		pub fn bool(arg: &ethabi::Token) -> Result<bool, CrabrollsError> {
+			arg.clone().into_bool().ok_or_else(|| CrabrollsError::abi("invalid type for bool"))
 		}
 
-		pub fn string(arg: &ethabi::Token) -> Result<String, Box<dyn Error>> {
+		pub fn string(arg: &ethabi::Token) -> Result<String, CrabrollsError> {
 			arg.clone()
 				.into_string()
-				.ok_or_else(|| "invalid type for string".into())
+				.ok_or_else(|| CrabrollsError::abi("invalid type for string"))
 		}
 
-		pub fn bytes(arg: &ethabi::Token) -> Result<Vec<u8>, Box<dyn Error>> {
-			arg.clone().into_bytes().ok_or_else(|| "invalid type for bytes".into())
+		pub fn bytes(arg: &ethabi::Token) -> Result<Vec<u8>, CrabrollsError> {
+			arg.clone().into_bytes().ok_or_else(|| CrabrollsError::abi("invalid type for bytes"))
 		}
 
-		pub fn array_of_address(arg: &ethabi::Token) -> Result<Vec<Address>, Box<dyn Error>> {
+		pub fn array_of_address(arg: &ethabi::Token) -> Result<Vec<Address>, CrabrollsError> {
 			arg.clone()
 				.into_array()
-				.ok_or_else(|| "invalid type for array of Address".into())
+				.ok_or_else(|| CrabrollsError::abi("invalid type for array of Address"))
 				.and_then(|array| {
 					array
 						.into_iter()
-						.map(|token| token.into_address().ok_or_else(|| "invalid type for Address".into()))
-						.collect::<Result<Vec<Address>, Box<dyn Error>>>()
+						.map(|token| {
+							let value = token.into_address().map(Address::from_ethabi);
+							value.ok_or_else(|| CrabrollsError::abi("invalid type for Address"))
+						})
+						.collect::<Result<Vec<Address>, CrabrollsError>>()
 				})
 		}
 
-		pub fn array_of_uint(arg: &ethabi::Token) -> Result<Vec<Uint>, Box<dyn Error>> {
+		pub fn array_of_uint(arg: &ethabi::Token) -> Result<Vec<Uint>, CrabrollsError> {
 			arg.clone()
 				.into_array()
-				.ok_or_else(|| "invalid type for array of Uint".into())
+				.ok_or_else(|| CrabrollsError::abi("invalid type for array of Uint"))
 				.and_then(|array| {
 					array
 						.into_iter()
 						.map(|token| {
-							token
-								.into_uint()
-								.ok_or_else(|| "invalid type for Uint".into())
-								.map(Into::into)
+							let value = token.into_uint().map(Uint::from_ethabi);
+							value.ok_or_else(|| CrabrollsError::abi("invalid type for Uint"))
 						})
-						.collect::<Result<Vec<Uint>, Box<dyn Error>>>()
+						.collect::<Result<Vec<Uint>, CrabrollsError>>()
 				})
 		}
 
-		pub fn array_of_bool(arg: &ethabi::Token) -> Result<Vec<bool>, Box<dyn Error>> {
+		pub fn array_of_bool(arg: &ethabi::Token) -> Result<Vec<bool>, CrabrollsError> {
 			arg.clone()
 				.into_array()
-				.ok_or_else(|| "invalid type for array of bool".into())
+				.ok_or_else(|| CrabrollsError::abi("invalid type for array of bool"))
 				.and_then(|array| {
 					array
 						.into_iter()
-						.map(|token| token.into_bool().ok_or_else(|| "invalid type for bool".into()))
-						.collect::<Result<Vec<bool>, Box<dyn Error>>>()
+						.map(|token| token.into_bool().ok_or_else(|| CrabrollsError::abi("invalid type for bool")))
+						.collect::<Result<Vec<bool>, CrabrollsError>>()
 				})
 		}
 	}
@@ -96,33 +102,73 @@ pub mod abi {
 	}
 
 	pub mod encode {
-		use ethabi::{encode, Function, Token};
-		use serde_json::from_str;
-		use std::error::Error;
+		use super::{Contract, CrabrollsError};
+		use crate::utils::hash::keccak256;
+		use ethabi::param_type::{ParamType, Reader, Writer};
+		use ethabi::{encode, Token};
+
+		/// Calldata of a function given its human-readable signature, e.g. `transfer(address,uint256)`, without the
+		/// JSON ABI of the contract.
+		pub fn call(signature: &str, params: Vec<Token>) -> Result<Vec<u8>, CrabrollsError> {
+			let (name, types) = parse_signature(signature)?;
+			if !Token::types_check(&params, &types) {
+				return Err(CrabrollsError::abi(format!(
+					"arguments don't match the parameters of {}",
+					signature
+				)));
+			}
+
+			let mut call = function_selector(&name, &types).to_vec();
+			call.extend(encode(&params));
+			Ok(call)
+		}
+
+		/// The 4 bytes selector of a function signature, from its canonical form without spaces or type aliases.
+		pub fn selector(signature: &str) -> Result<[u8; 4], CrabrollsError> {
+			let (name, types) = parse_signature(signature)?;
+			Ok(function_selector(&name, &types))
+		}
+
+		pub(crate) fn parse_signature(signature: &str) -> Result<(String, Vec<ParamType>), CrabrollsError> {
+			let invalid = || CrabrollsError::abi(format!("invalid function signature {}", signature));
+			let (name, params) = signature
+				.split_once('(')
+				.filter(|(name, params)| !name.trim().is_empty() && params.ends_with(')'))
+				.ok_or_else(invalid)?;
+			let params = params[..params.len() - 1].trim();
+			if params.is_empty() {
+				return Ok((name.trim().to_string(), Vec::new()));
+			}
+			match Reader::read(&format!("({})", params.replace(' ', ""))) {
+				Ok(ParamType::Tuple(types)) => Ok((name.trim().to_string(), types)),
+				_ => Err(invalid()),
+			}
+		}
+
+		pub(crate) fn function_selector(name: &str, types: &[ParamType]) -> [u8; 4] {
+			let types: Vec<String> = types.iter().map(Writer::write).collect();
+			let hash = keccak256(format!("{}({})", name, types.join(",")));
+			[hash[0], hash[1], hash[2], hash[3]]
+		}
 
 		pub fn function_call(
 			abi_json: &str,
 			function_name: &str,
 			params: Vec<Token>,
-		) -> Result<Vec<u8>, Box<dyn Error>> {
-			let parsed_json: Vec<Function> = from_str(abi_json)?;
-			let func = parsed_json
-				.iter()
-				.find(|&f| f.name == function_name)
-				.ok_or("Function not found in ABI")?;
-			Ok(func.encode_input(&params)?)
+		) -> Result<Vec<u8>, CrabrollsError> {
+			Contract::from_json(abi_json)?.encode(function_name, params)
 		}
 
-		pub fn abi(tokens: &[Token]) -> Result<Vec<u8>, Box<dyn Error>> {
+		pub fn abi(tokens: &[Token]) -> Result<Vec<u8>, CrabrollsError> {
 			Ok(encode(tokens))
 		}
 
-		pub fn pack(tokens: &[Token]) -> Result<Vec<u8>, Box<dyn Error>> {
+		pub fn pack(tokens: &[Token]) -> Result<Vec<u8>, CrabrollsError> {
 			let mut payload = Vec::new();
 
 			for token in tokens {
 				match token {
-					Token::Address(address) => payload.extend_from_slice(&address.as_bytes()[..]),
+					Token::Address(address) => payload.extend_from_slice(address.as_bytes()),
 					Token::Uint(value) | Token::Int(value) => {
 						let mut buf = [0u8; 32];
 						value.to_big_endian(&mut buf);
@@ -149,7 +195,7 @@ pub mod abi {
 					}
 					Token::Array(array) | Token::FixedArray(array) | Token::Tuple(array) => {
 						for token in array {
-							payload.extend_from_slice(&pack(&[token.clone()])?);
+							payload.extend_from_slice(&pack(std::slice::from_ref(token))?);
 						}
 					}
 				}
@@ -160,43 +206,174 @@ pub mod abi {
 	}
 
 	pub mod decode {
-		use ethabi::{decode, ParamType, Token};
-		use std::error::Error;
+		use ethabi::param_type::Reader;
+		use ethabi::{decode, Event, EventParam, Hash, ParamType, RawLog, Token};
 
+		use super::encode::{function_selector, parse_signature};
 		use super::*;
 
-		pub fn abi(params: &[ParamType], payload: &[u8]) -> Result<Vec<Token>, Box<dyn Error>> {
+		type PartialDecode<'a> = Result<(Vec<Token>, &'a [u8]), CrabrollsError>;
+
+		pub fn abi(params: &[ParamType], payload: &[u8]) -> Result<Vec<Token>, CrabrollsError> {
 			Ok(decode(params, payload)?)
 		}
 
+		/// Decodes the calldata of a function call, e.g. the payload of a voucher, into the function name and its
+		/// arguments. The function is given either by its human-readable signature, like `transfer(address,uint256)`,
+		/// or by the JSON ABI of the contract, where it is found by its selector.
+		pub fn function_call(abi_or_signature: &str, calldata: &[u8]) -> Result<(String, Vec<Token>), CrabrollsError> {
+			if abi_or_signature.trim_start().starts_with('[') {
+				return Contract::from_json(abi_or_signature)?.decode_input(calldata);
+			}
+			if calldata.len() < 4 {
+				return Err(CrabrollsError::abi("calldata is shorter than a function selector"));
+			}
+			let (selector, args) = calldata.split_at(4);
+
+			let (name, types) = parse_signature(abi_or_signature)?;
+			if function_selector(&name, &types)[..] != *selector {
+				return Err(CrabrollsError::abi(format!("calldata is not a call to {}", abi_or_signature)));
+			}
+			Ok((name, decode(&types, args)?))
+		}
+
+		/// Decodes the parameters of an event log in their declaration order, the indexed ones from the topics and the
+		/// others from the data. The event is given either by its human-readable signature, like
+		/// `Transfer(address indexed from, address indexed to, uint256 value)`, by its JSON ABI, or by the JSON ABI of
+		/// the contract, where it is found by the first topic. Indexed parameters of dynamic types are decoded as the
+		/// `FixedBytes` of their hash, since only the hash is logged.
+		pub fn event(topics: &[[u8; 32]], data: &[u8], event_abi: &str) -> Result<Vec<Token>, CrabrollsError> {
+			let topics: Vec<Hash> = topics.iter().map(Hash::from).collect();
+			let event = match event_abi.trim_start().chars().next() {
+				Some('[') => {
+					let contract = Contract::from_json(event_abi)?;
+					let signature = topics.first().ok_or_else(|| CrabrollsError::abi("log has no topics"))?;
+					contract
+						.abi()
+						.events()
+						.find(|event| !event.anonymous && event.signature() == *signature)
+						.cloned()
+						.ok_or_else(|| CrabrollsError::abi(format!("event {:?} not found in ABI", signature)))?
+				}
+				Some('{') => serde_json::from_str(event_abi).map_err(|e| CrabrollsError::abi(e.to_string()))?,
+				_ => parse_event_signature(event_abi)?,
+			};
+
+			// The log params are matched by name, so they are renamed by position to allow unnamed ones
+			let event = Event {
+				inputs: (event.inputs.into_iter().enumerate())
+					.map(|(index, param)| EventParam {
+						name: index.to_string(),
+						..param
+					})
+					.collect(),
+				..event
+			};
+			let log = event.parse_log(RawLog {
+				topics,
+				data: data.to_vec(),
+			})?;
+			Ok(log.params.into_iter().map(|param| param.value).collect())
+		}
+
+		fn parse_event_signature(signature: &str) -> Result<Event, CrabrollsError> {
+			let invalid = || CrabrollsError::abi(format!("invalid event signature {}", signature));
+			let signature = signature.trim();
+			let signature = signature.strip_prefix("event ").unwrap_or(signature);
+			let (name, params) = signature
+				.split_once('(')
+				.filter(|(name, params)| !name.trim().is_empty() && params.ends_with(')'))
+				.ok_or_else(invalid)?;
+
+			let mut inputs = Vec::new();
+			for param in split_params(&params[..params.len() - 1]) {
+				let (kind, rest) = split_param_type(param).ok_or_else(invalid)?;
+				inputs.push(EventParam {
+					name: String::new(),
+					kind: Reader::read(&kind).map_err(|_| invalid())?,
+					indexed: rest.split_whitespace().any(|word| word == "indexed"),
+				});
+			}
+			Ok(Event {
+				name: name.trim().to_string(),
+				inputs,
+				anonymous: false,
+			})
+		}
+
+		/// Splits a parameter into its canonical type, without the names of the tuple components, and the rest of it,
+		/// e.g. `(address maker, uint256 amount)[] indexed orders` into `(address,uint256)[]` and ` indexed orders`.
+		fn split_param_type(param: &str) -> Option<(String, &str)> {
+			let Some(tuple) = param.strip_prefix('(') else {
+				let end = param.find(' ').unwrap_or(param.len());
+				return Some((param[..end].to_string(), &param[end..]));
+			};
+
+			let mut depth = 1;
+			let close = tuple.char_indices().find_map(|(index, char)| {
+				match char {
+					'(' => depth += 1,
+					')' => depth -= 1,
+					_ => {}
+				}
+				(depth == 0).then_some(index)
+			})?;
+			let components = split_params(&tuple[..close])
+				.into_iter()
+				.map(|component| split_param_type(component).map(|(kind, _)| kind))
+				.collect::<Option<Vec<_>>>()?;
+			let rest = &tuple[close + 1..];
+			let end = rest.find(' ').unwrap_or(rest.len());
+			Some((format!("({}){}", components.join(","), &rest[..end]), &rest[end..]))
+		}
+
+		/// Splits the parameters of a signature on the commas outside of tuples.
+		fn split_params(params: &str) -> Vec<&str> {
+			let mut split = Vec::new();
+			let (mut depth, mut start) = (0, 0);
+			for (index, char) in params.char_indices() {
+				match char {
+					'(' => depth += 1,
+					')' => depth -= 1,
+					',' if depth == 0 => {
+						split.push(params[start..index].trim());
+						start = index + 1;
+					}
+					_ => {}
+				}
+			}
+			split.push(params[start..].trim());
+			split.into_iter().filter(|param| !param.is_empty()).collect()
+		}
+
 		pub fn pack<'a>(
 			params: &'a [ParamType],
 			mut payload: &'a [u8],
-		) -> Result<(Vec<Token>, Vec<u8>), Box<dyn Error>> {
+		) -> Result<(Vec<Token>, Vec<u8>), CrabrollsError> {
 			let mut tokens = Vec::new();
 
 			for param in params {
 				match param {
 					ParamType::Address => {
-						ensure_payload_length(&payload, 20, "Address")?;
-						tokens.push(Token::Address(Address::from_slice(&payload[..20])));
+						ensure_payload_length(payload, 20, "Address")?;
+						tokens.push(Token::Address(ethabi::Address::from_slice(&payload[..20])));
 						payload = &payload[20..];
 					}
 					ParamType::Uint(size) | ParamType::Int(size) => {
 						let byte_size = size / 8;
-						ensure_payload_length(&payload, byte_size, &format!("Uint/Int of size {}", size))?;
+						ensure_payload_length(payload, byte_size, &format!("Uint/Int of size {}", size))?;
 						tokens.push(Token::Uint(payload[..byte_size].into()));
 						payload = &payload[byte_size..];
 					}
 					ParamType::FixedBytes(size) => {
-						ensure_payload_length(&payload, *size, &format!("FixedBytes of size {}", size))?;
+						ensure_payload_length(payload, *size, &format!("FixedBytes of size {}", size))?;
 						tokens.push(Token::FixedBytes(payload[..*size].to_vec()));
 						payload = &payload[*size..];
 					}
 					ParamType::Bytes | ParamType::String => {
-						ensure_payload_length(&payload, 32, "Bytes/String size")?;
-						let size = Uint::from(&payload[..32]).as_usize();
-						ensure_payload_length(&payload, 32 + size, "Bytes/String")?;
+						ensure_payload_length(payload, 32, "Bytes/String size")?;
+						let size = ethabi::Uint::from(&payload[..32]).as_usize();
+						ensure_payload_length(payload, 32 + size, "Bytes/String")?;
 						if let ParamType::Bytes = param {
 							tokens.push(Token::Bytes(payload[32..32 + size].to_vec()));
 						} else {
@@ -205,13 +382,13 @@ pub mod abi {
 						payload = &payload[32 + size..];
 					}
 					ParamType::Bool => {
-						ensure_payload_length(&payload, 1, "Bool")?;
+						ensure_payload_length(payload, 1, "Bool")?;
 						tokens.push(Token::Bool(payload[0] != 0));
 						payload = &payload[1..];
 					}
 					ParamType::Array(param) => {
-						ensure_payload_length(&payload, 32, "Array size")?;
-						let size = Uint::from(&payload[..32]).as_usize();
+						ensure_payload_length(payload, 32, "Array size")?;
+						let size = ethabi::Uint::from(&payload[..32]).as_usize();
 						payload = &payload[32..];
 						let array = parse_array(param, size, payload)?;
 						tokens.push(Token::Array(array.0));
@@ -233,49 +410,38 @@ pub mod abi {
 			Ok((tokens, payload.to_vec()))
 		}
 
-		fn ensure_payload_length(payload: &[u8], required_len: usize, type_desc: &str) -> Result<(), Box<dyn Error>> {
+		fn ensure_payload_length(payload: &[u8], required_len: usize, type_desc: &str) -> Result<(), CrabrollsError> {
 			if payload.len() < required_len {
-				Err(format!("Insufficient payload length for {}", type_desc).into())
+				Err(CrabrollsError::abi(format!("Insufficient payload length for {}", type_desc)))
 			} else {
 				Ok(())
 			}
 		}
 
-		fn parse_array<'a>(
-			param: &'a ParamType,
-			size: usize,
-			mut payload: &'a [u8],
-		) -> Result<(Vec<Token>, &'a [u8]), Box<dyn Error>> {
+		fn parse_array<'a>(param: &'a ParamType, size: usize, mut payload: &'a [u8]) -> PartialDecode<'a> {
 			let mut array = Vec::new();
 			for _ in 0..size {
-				let token = pack(&[param.clone()], payload)?;
+				let token = pack(std::slice::from_ref(param), payload)?;
 				array.push(token.0[0].clone());
 				payload = &payload[utils::size_of_packed_token(&token.0[0])..];
 			}
 			Ok((array, payload))
 		}
 
-		fn parse_fixed_array<'a>(
-			param: &'a ParamType,
-			size: usize,
-			mut payload: &'a [u8],
-		) -> Result<(Vec<Token>, &'a [u8]), Box<dyn Error>> {
+		fn parse_fixed_array<'a>(param: &'a ParamType, size: usize, mut payload: &'a [u8]) -> PartialDecode<'a> {
 			let mut array = Vec::new();
 			for _ in 0..size {
-				let token = pack(&[param.clone()], payload)?;
+				let token = pack(std::slice::from_ref(param), payload)?;
 				array.push(token.0[0].clone());
 				payload = &payload[utils::size_of_packed_token(&token.0[0])..];
 			}
 			Ok((array, payload))
 		}
 
-		fn parse_tuple<'a>(
-			params: &'a [ParamType],
-			mut payload: &'a [u8],
-		) -> Result<(Vec<Token>, &'a [u8]), Box<dyn Error>> {
+		fn parse_tuple<'a>(params: &'a [ParamType], mut payload: &'a [u8]) -> PartialDecode<'a> {
 			let mut tuple = Vec::new();
 			for param in params {
-				let token = pack(&[param.clone()], payload)?;
+				let token = pack(std::slice::from_ref(param), payload)?;
 				tuple.push(token.0[0].clone());
 				payload = &payload[utils::size_of_packed_token(&token.0[0])..];
 			}
@@ -283,22 +449,152 @@ pub mod abi {
 		}
 	}
 
+	/// Encoding and decoding with the dynamic ABI types of alloy, for apps moving off `ethabi`. Types are given as
+	/// Solidity strings, e.g. `transfer(address,uint256)` or `address,uint256`.
+	/// ABI of a contract parsed once, to encode its calls and decode their outputs without parsing the JSON on every
+	/// call like `encode::function_call` does, e.g. in hot paths or for the vouchers of an app.
+	#[derive(Debug, Clone, PartialEq)]
+	pub struct Contract {
+		abi: ethabi::Contract,
+	}
+
+	impl Contract {
+		/// Parses a JSON ABI, or a compiler artifact with the ABI in its `abi` field, like the ones of Hardhat and
+		/// Foundry.
+		pub fn from_json(json: &str) -> Result<Self, CrabrollsError> {
+			let invalid = |e: serde_json::Error| CrabrollsError::abi(format!("invalid JSON ABI: {}", e));
+			let abi = match serde_json::from_str(json).map_err(invalid)? {
+				serde_json::Value::Object(mut artifact) => artifact
+					.remove("abi")
+					.ok_or_else(|| CrabrollsError::abi("JSON artifact has no abi field"))?,
+				abi => abi,
+			};
+			Ok(Self {
+				abi: serde_json::from_value(abi).map_err(invalid)?,
+			})
+		}
+
+		pub fn from_file(path: impl AsRef<Path>) -> Result<Self, CrabrollsError> {
+			let path = path.as_ref();
+			let json = fs::read_to_string(path)
+				.map_err(|e| CrabrollsError::abi(format!("can't read the ABI file {}: {}", path.display(), e)))?;
+			Self::from_json(&json)
+		}
+
+		/// Calldata of a call to `method`, choosing among its overloads the one whose parameters match `params`.
+		pub fn encode(&self, method: &str, params: Vec<Token>) -> Result<Vec<u8>, CrabrollsError> {
+			let function = self
+				.overloads(method)?
+				.iter()
+				.find(|function| {
+					let types: Vec<ParamType> = function.inputs.iter().map(|input| input.kind.clone()).collect();
+					Token::types_check(&params, &types)
+				})
+				.ok_or_else(|| CrabrollsError::abi(format!("arguments don't match the parameters of {}", method)))?;
+			Ok(function.encode_input(&params)?)
+		}
+
+		/// Decodes the data returned by a call to `method`, with the first of its overloads that can decode it.
+		pub fn decode_output(&self, method: &str, data: &[u8]) -> Result<Vec<Token>, CrabrollsError> {
+			let mut result = Err(CrabrollsError::abi(format!("function {} not found in ABI", method)));
+			for function in self.overloads(method)? {
+				result = function.decode_output(data).map_err(Into::into);
+				if result.is_ok() {
+					break;
+				}
+			}
+			result
+		}
+
+		/// Decodes calldata into the name of the function with its selector and the arguments.
+		pub fn decode_input(&self, calldata: &[u8]) -> Result<(String, Vec<Token>), CrabrollsError> {
+			if calldata.len() < 4 {
+				return Err(CrabrollsError::abi("calldata is shorter than a function selector"));
+			}
+			let (selector, args) = calldata.split_at(4);
+			let function = self
+				.abi
+				.functions()
+				.find(|function| function.short_signature()[..] == *selector)
+				.ok_or_else(|| CrabrollsError::abi(format!("selector 0x{} not found in ABI", hex::encode(selector))))?;
+			Ok((function.name.clone(), function.decode_input(args)?))
+		}
+
+		/// The parsed ABI, e.g. to look up its events and errors.
+		pub fn abi(&self) -> &ethabi::Contract {
+			&self.abi
+		}
+
+		fn overloads(&self, method: &str) -> Result<&Vec<Function>, CrabrollsError> {
+			self.abi
+				.functions
+				.get(method)
+				.ok_or_else(|| CrabrollsError::abi(format!("function {} not found in ABI", method)))
+		}
+	}
+
+	#[cfg(feature = "alloy")]
+	pub mod alloy {
+		use crate::utils::hash::keccak256;
+		use super::CrabrollsError;
+		use alloy_dyn_abi::{DynSolType, DynSolValue};
+
+		/// Parameter types of a comma separated list, as a tuple type.
+		pub fn param_types(types: &str) -> Result<DynSolType, CrabrollsError> {
+			DynSolType::parse(&format!("({})", types)).map_err(|e| CrabrollsError::abi(e.to_string()))
+		}
+
+		pub fn function_call(signature: &str, args: Vec<DynSolValue>) -> Result<Vec<u8>, CrabrollsError> {
+			let (name, params) = signature
+				.split_once('(')
+				.filter(|(name, params)| !name.trim().is_empty() && params.ends_with(')'))
+				.ok_or_else(|| CrabrollsError::abi(format!("invalid function signature {}", signature)))?;
+			let types = param_types(&params[..params.len() - 1])?;
+			let args = DynSolValue::Tuple(args);
+			if !types.matches(&args) {
+				return Err(CrabrollsError::abi(format!(
+					"arguments don't match the parameters of {}",
+					signature
+				)));
+			}
+
+			let selector = keccak256(format!("{}{}", name.trim(), types.sol_type_name()));
+			let mut call = selector[..4].to_vec();
+			call.extend(args.abi_encode_params());
+			Ok(call)
+		}
+
+		pub fn encode(args: Vec<DynSolValue>) -> Vec<u8> {
+			DynSolValue::Tuple(args).abi_encode_params()
+		}
+
+		pub fn decode(types: &str, payload: &[u8]) -> Result<Vec<DynSolValue>, CrabrollsError> {
+			let values = param_types(types)?
+				.abi_decode_params(payload)
+				.map_err(|e| CrabrollsError::abi(e.to_string()))?;
+			match values {
+				DynSolValue::Tuple(values) => Ok(values),
+				value => Ok(vec![value]),
+			}
+		}
+	}
+
 	pub mod ether {
 		use super::*;
 
-		pub fn deposit(payload: Vec<u8>) -> Result<Vec<Token>, Box<dyn Error>> {
+		pub fn deposit(payload: Vec<u8>) -> Result<Vec<Token>, CrabrollsError> {
 			let params = [ParamType::Address, ParamType::Uint(256)];
 
 			decode::pack(&params, payload.as_ref()).map(|(tokens, _)| tokens)
 		}
 
-		pub fn deposit_payload(address: Address, value: Uint) -> Result<Vec<u8>, Box<dyn Error>> {
-			let tokens = vec![Token::Address(address), Token::Uint(value)];
+		pub fn deposit_payload(address: Address, value: Uint) -> Result<Vec<u8>, CrabrollsError> {
+			let tokens = vec![Token::Address(address.into_ethabi()), Token::Uint(value.into_ethabi())];
 
 			encode::pack(&tokens)
 		}
 
-		pub fn withdraw(address: Address, value: Uint) -> Result<Vec<u8>, Box<dyn Error>> {
+		pub fn withdraw(address: Address, value: Uint) -> Result<Vec<u8>, CrabrollsError> {
 			let abi_json = r#"
 			[
 				{
@@ -320,7 +616,7 @@ pub mod abi {
 				}
 			]"#;
 
-			let params = vec![Token::Address(address), Token::Uint(value)];
+			let params = vec![Token::Address(address.into_ethabi()), Token::Uint(value.into_ethabi())];
 
 			encode::function_call(abi_json, "withdrawEther", params)
 		}
@@ -329,7 +625,7 @@ pub mod abi {
 	pub mod erc20 {
 		use super::*;
 
-		pub fn deposit(payload: Vec<u8>) -> Result<Vec<Token>, Box<dyn Error>> {
+		pub fn deposit(payload: Vec<u8>) -> Result<Vec<Token>, CrabrollsError> {
 			let params = [
 				ParamType::Bool,
 				ParamType::Address,
@@ -344,18 +640,18 @@ pub mod abi {
 			wallet_address: Address,
 			token_address: Address,
 			value: Uint,
-		) -> Result<Vec<u8>, Box<dyn Error>> {
+		) -> Result<Vec<u8>, CrabrollsError> {
 			let tokens = vec![
 				Token::Bool(true),
-				Token::Address(token_address),
-				Token::Address(wallet_address),
-				Token::Uint(value),
+				Token::Address(token_address.into_ethabi()),
+				Token::Address(wallet_address.into_ethabi()),
+				Token::Uint(value.into_ethabi()),
 			];
 
 			encode::pack(&tokens)
 		}
 
-		pub fn withdraw(address: Address, value: Uint) -> Result<Vec<u8>, Box<dyn Error>> {
+		pub fn withdraw(address: Address, value: Uint) -> Result<Vec<u8>, CrabrollsError> {
 			let abi_json = r#"
 			[
 				{
@@ -377,16 +673,112 @@ pub mod abi {
 				}
 			]"#;
 
-			let params = vec![Token::Address(address), Token::Uint(value)];
+			let params = vec![Token::Address(address.into_ethabi()), Token::Uint(value.into_ethabi())];
 
 			encode::function_call(abi_json, "transfer", params)
 		}
 	}
 
+	pub mod erc777 {
+		use super::*;
+
+		/// The token followed by the `tokensReceived(operator, from, to, amount, userData, operatorData)` hook arguments.
+		pub fn deposit(payload: Vec<u8>) -> Result<Vec<Token>, CrabrollsError> {
+			let params = [
+				ParamType::Address,
+				ParamType::Address,
+				ParamType::Address,
+				ParamType::Address,
+				ParamType::Uint(256),
+				ParamType::Bytes,
+				ParamType::Bytes,
+			];
+
+			decode::abi(&params, payload.as_ref())
+		}
+
+		pub fn deposit_payload(
+			token_address: Address,
+			operator: Address,
+			wallet_address: Address,
+			recipient: Address,
+			value: Uint,
+			user_data: Vec<u8>,
+		) -> Result<Vec<u8>, CrabrollsError> {
+			let tokens = vec![
+				Token::Address(token_address.into_ethabi()),
+				Token::Address(operator.into_ethabi()),
+				Token::Address(wallet_address.into_ethabi()),
+				Token::Address(recipient.into_ethabi()),
+				Token::Uint(value.into_ethabi()),
+				Token::Bytes(user_data),
+				Token::Bytes(vec![]),
+			];
+
+			encode::abi(&tokens)
+		}
+	}
+
+	pub mod erc677 {
+		use super::*;
+
+		/// The token followed by the `onTokenTransfer(from, amount, data)` callback arguments.
+		pub fn deposit(payload: Vec<u8>) -> Result<Vec<Token>, CrabrollsError> {
+			let params = [
+				ParamType::Address,
+				ParamType::Address,
+				ParamType::Uint(256),
+				ParamType::Bytes,
+			];
+
+			decode::abi(&params, payload.as_ref())
+		}
+
+		pub fn deposit_payload(
+			token_address: Address,
+			wallet_address: Address,
+			value: Uint,
+			data: Vec<u8>,
+		) -> Result<Vec<u8>, CrabrollsError> {
+			let tokens = vec![
+				Token::Address(token_address.into_ethabi()),
+				Token::Address(wallet_address.into_ethabi()),
+				Token::Uint(value.into_ethabi()),
+				Token::Bytes(data),
+			];
+
+			encode::abi(&tokens)
+		}
+	}
+
+	pub mod erc4626 {
+		use super::*;
+
+		pub fn deposit(payload: Vec<u8>) -> Result<Vec<Token>, CrabrollsError> {
+			let params = [ParamType::Address, ParamType::Address, ParamType::Uint(256)];
+
+			decode::pack(&params, payload.as_ref()).map(|(tokens, _)| tokens)
+		}
+
+		pub fn deposit_payload(
+			wallet_address: Address,
+			vault_address: Address,
+			shares: Uint,
+		) -> Result<Vec<u8>, CrabrollsError> {
+			let tokens = vec![
+				Token::Address(vault_address.into_ethabi()),
+				Token::Address(wallet_address.into_ethabi()),
+				Token::Uint(shares.into_ethabi()),
+			];
+
+			encode::pack(&tokens)
+		}
+	}
+
 	pub mod erc721 {
 		use super::*;
 
-		pub fn deposit(payload: Vec<u8>) -> Result<Vec<Token>, Box<dyn Error>> {
+		pub fn deposit(payload: Vec<u8>) -> Result<Vec<Token>, CrabrollsError> {
 			let params = [ParamType::Address, ParamType::Address, ParamType::Uint(256)];
 
 			decode::pack(&params, payload.as_ref()).map(|(tokens, _)| tokens)
@@ -396,17 +788,17 @@ pub mod abi {
 			wallet_address: Address,
 			token_address: Address,
 			token_id: Uint,
-		) -> Result<Vec<u8>, Box<dyn Error>> {
+		) -> Result<Vec<u8>, CrabrollsError> {
 			let tokens = vec![
-				Token::Address(token_address),
-				Token::Address(wallet_address),
-				Token::Uint(token_id),
+				Token::Address(token_address.into_ethabi()),
+				Token::Address(wallet_address.into_ethabi()),
+				Token::Uint(token_id.into_ethabi()),
 			];
 
 			encode::pack(&tokens)
 		}
 
-		pub fn withdraw(dapp_address: Address, address: Address, token_id: Uint) -> Result<Vec<u8>, Box<dyn Error>> {
+		pub fn withdraw(dapp_address: Address, address: Address, token_id: Uint) -> Result<Vec<u8>, CrabrollsError> {
 			let abi_json = r#"
 			[
 				{
@@ -434,9 +826,9 @@ pub mod abi {
 			]"#;
 
 			let params = vec![
-				Token::Address(dapp_address),
-				Token::Address(address),
-				Token::Uint(token_id),
+				Token::Address(dapp_address.into_ethabi()),
+				Token::Address(address.into_ethabi()),
+				Token::Uint(token_id.into_ethabi()),
 			];
 
 			encode::function_call(abi_json, "safeTransferFrom", params)
@@ -446,7 +838,7 @@ pub mod abi {
 	pub mod erc1155 {
 		use super::*;
 
-		pub fn single_deposit(payload: Vec<u8>) -> Result<Vec<Token>, Box<dyn Error>> {
+		pub fn single_deposit(payload: Vec<u8>) -> Result<Vec<Token>, CrabrollsError> {
 			let params = [
 				ParamType::Address,
 				ParamType::Address,
@@ -457,7 +849,7 @@ pub mod abi {
 			decode::pack(&params, payload.as_ref()).map(|(tokens, _)| tokens)
 		}
 
-		pub fn batch_deposit(payload: Vec<u8>) -> Result<Vec<Token>, Box<dyn Error>> {
+		pub fn batch_deposit(payload: Vec<u8>) -> Result<Vec<Token>, CrabrollsError> {
 			let params = [ParamType::Address, ParamType::Address];
 
 			let (addresses_tokens, payload) = decode::pack(&params, payload.as_ref())?;
@@ -477,12 +869,12 @@ pub mod abi {
 			token_address: Address,
 			token_id: Uint,
 			amount: Uint,
-		) -> Result<Vec<u8>, Box<dyn Error>> {
+		) -> Result<Vec<u8>, CrabrollsError> {
 			let tokens = vec![
-				Token::Address(token_address),
-				Token::Address(wallet_address),
-				Token::Uint(token_id),
-				Token::Uint(amount),
+				Token::Address(token_address.into_ethabi()),
+				Token::Address(wallet_address.into_ethabi()),
+				Token::Uint(token_id.into_ethabi()),
+				Token::Uint(amount.into_ethabi()),
 			];
 
 			encode::pack(&tokens)
@@ -492,16 +884,19 @@ pub mod abi {
 			wallet_address: Address,
 			token_address: Address,
 			ids_amounts: Vec<(Uint, Uint)>,
-		) -> Result<Vec<u8>, Box<dyn Error>> {
-			let ids = ids_amounts.iter().map(|(id, _)| Token::Uint(id.clone())).collect();
+		) -> Result<Vec<u8>, CrabrollsError> {
+			let ids = ids_amounts.iter().map(|(id, _)| Token::Uint(id.into_ethabi())).collect();
 			let amounts = ids_amounts
 				.iter()
-				.map(|(_, amount)| ethabi::Token::Uint(amount.clone()))
+				.map(|(_, amount)| ethabi::Token::Uint(amount.into_ethabi()))
 				.collect();
 
 			let ids_amounts_bytes = encode::abi(&[Token::Array(ids), Token::Array(amounts)])?;
 
-			let tokens = vec![Token::Address(token_address), Token::Address(wallet_address)];
+			let tokens = vec![
+				Token::Address(token_address.into_ethabi()),
+				Token::Address(wallet_address.into_ethabi()),
+			];
 
 			Ok(encode::pack(&tokens)?
 				.iter()
@@ -516,7 +911,7 @@ pub mod abi {
 			token_id: Uint,
 			amount: Uint,
 			data: Vec<u8>,
-		) -> Result<Vec<u8>, Box<dyn Error>> {
+		) -> Result<Vec<u8>, CrabrollsError> {
 			let abi_json = r#"
 			[
 				{
@@ -554,10 +949,10 @@ pub mod abi {
 			]"#;
 
 			let params = vec![
-				Token::Address(dapp_address),
-				Token::Address(address),
-				Token::Uint(token_id),
-				Token::Uint(amount),
+				Token::Address(dapp_address.into_ethabi()),
+				Token::Address(address.into_ethabi()),
+				Token::Uint(token_id.into_ethabi()),
+				Token::Uint(amount.into_ethabi()),
 				Token::Bytes(data),
 			];
 
@@ -569,7 +964,7 @@ pub mod abi {
 			address: Address,
 			withdrawals: Vec<(Uint, Uint)>,
 			data: Vec<u8>,
-		) -> Result<Vec<u8>, Box<dyn Error>> {
+		) -> Result<Vec<u8>, CrabrollsError> {
 			let abi_json = r#"
 			[
 				{
@@ -607,20 +1002,10 @@ pub mod abi {
 			]"#;
 
 			let params = vec![
-				Token::Address(dapp_address),
-				Token::Address(address),
-				Token::Array(
-					withdrawals
-						.iter()
-						.map(|(id, _amount)| Token::Uint(id.clone()))
-						.collect(),
-				),
-				Token::Array(
-					withdrawals
-						.iter()
-						.map(|(_id, amount)| Token::Uint(amount.clone()))
-						.collect(),
-				),
+				Token::Address(dapp_address.into_ethabi()),
+				Token::Address(address.into_ethabi()),
+				Token::Array(withdrawals.iter().map(|(id, _amount)| Token::Uint(id.into_ethabi())).collect()),
+				Token::Array(withdrawals.iter().map(|(_id, amount)| Token::Uint(amount.into_ethabi())).collect()),
 				Token::Bytes(data),
 			];
 
@@ -632,8 +1017,194 @@ pub mod abi {
 #[cfg(test)]
 mod tests {
 	use super::abi;
+	use crate::types::primitives::*;
+	use crate::utils::hash::keccak256;
 	use crate::{address, uint};
-	use ethabi::{Address, Token, Uint};
+	use ethabi::Token;
+
+	#[cfg(feature = "alloy")]
+	#[test]
+	fn test_alloy_function_call() {
+		use alloy_dyn_abi::DynSolValue;
+
+		let recipient = address!("0x70997970c51812dc3a010c7d01b50e0d17dc79c8");
+		let args = vec![
+			DynSolValue::Address(recipient),
+			DynSolValue::Uint(uint!(100), 256),
+		];
+		let call = abi::alloy::function_call("transfer(address,uint)", args.clone()).unwrap();
+		assert_eq!(
+			call,
+			abi::encode::function_call(
+				r#"[{"name":"transfer","inputs":[{"name":"to","type":"address"},{"name":"value","type":"uint256"}],"outputs":[],"type":"function"}]"#,
+				"transfer",
+				vec![Token::Address(recipient.into_ethabi()), Token::Uint(ethabi::Uint::from(100))]
+			)
+			.unwrap()
+		);
+		assert_eq!(abi::alloy::decode("address,uint256", &call[4..]).unwrap(), args);
+		assert_eq!(abi::alloy::encode(args.clone()), call[4..].to_vec());
+
+		assert!(abi::alloy::function_call("transfer(address,bool)", args.clone()).is_err());
+		assert!(abi::alloy::function_call("(address,uint256)", args).is_err());
+	}
+
+	#[test]
+	fn test_encode_call() {
+		let recipient = address!("0x70997970c51812dc3a010c7d01b50e0d17dc79c8");
+		let params = vec![Token::Address(recipient.into_ethabi()), Token::Uint(ethabi::Uint::from(100))];
+		let call = abi::encode::call("transfer(address, uint)", params.clone()).unwrap();
+		assert_eq!(hex::encode(&call[..4]), "a9059cbb");
+		assert_eq!(
+			call,
+			abi::encode::function_call(
+				r#"[{"name":"transfer","inputs":[{"name":"to","type":"address"},{"name":"value","type":"uint256"}],"outputs":[],"type":"function"}]"#,
+				"transfer",
+				params.clone()
+			)
+			.unwrap()
+		);
+
+		assert_eq!(abi::encode::selector("totalSupply()").unwrap(), [0x18, 0x16, 0x0d, 0xdd]);
+		assert_eq!(abi::encode::call("totalSupply()", vec![]).unwrap(), vec![0x18, 0x16, 0x0d, 0xdd]);
+		assert!(abi::encode::call("transfer(address,bool)", params.clone()).is_err());
+		assert!(abi::encode::call("(address,uint256)", params.clone()).is_err());
+		assert!(abi::encode::call("transfer(address,uint256", params).is_err());
+	}
+
+	#[test]
+	fn test_decode_function_call() {
+		let alice = address!("0x70997970c51812dc3a010c7d01b50e0d17dc79c8");
+		let owner = address!("0xf39fd6e51aad88f6f4ce6ab8827279cfffb92266");
+		let params = vec![
+			Token::Address(owner.into_ethabi()),
+			Token::Address(alice.into_ethabi()),
+			Token::Uint(ethabi::Uint::from(5)),
+		];
+		let call = abi::encode::call("safeTransferFrom(address,address,uint256)", params.clone()).unwrap();
+
+		let (name, tokens) = abi::decode::function_call("safeTransferFrom(address, address, uint)", &call).unwrap();
+		assert_eq!(name, "safeTransferFrom");
+		assert_eq!(tokens, params);
+
+		let abi_json = r#"[
+			{"type":"event","name":"Transfer","inputs":[{"name":"from","type":"address","indexed":true},{"name":"to","type":"address","indexed":true},{"name":"tokenId","type":"uint256","indexed":true}],"anonymous":false},
+			{"type":"function","name":"safeTransferFrom","inputs":[{"name":"from","type":"address"},{"name":"to","type":"address"},{"name":"tokenId","type":"uint256"}],"outputs":[],"stateMutability":"nonpayable"},
+			{"type":"function","name":"safeTransferFrom","inputs":[{"name":"from","type":"address"},{"name":"to","type":"address"},{"name":"tokenId","type":"uint256"},{"name":"data","type":"bytes"}],"outputs":[],"stateMutability":"nonpayable"}
+		]"#;
+		let (name, tokens) = abi::decode::function_call(abi_json, &call).unwrap();
+		assert_eq!(name, "safeTransferFrom");
+		assert_eq!(tokens, params);
+
+		assert!(abi::decode::function_call("transferFrom(address,address,uint256)", &call).is_err());
+		assert!(abi::decode::function_call("safeTransferFrom(address,address,uint256)", &call[..3]).is_err());
+		assert!(abi::decode::function_call("[]", &call).is_err());
+	}
+
+	const ERC721_ABI: &str = r#"[
+		{"type":"function","name":"balanceOf","inputs":[{"name":"owner","type":"address"}],"outputs":[{"name":"","type":"uint256"}],"stateMutability":"view"},
+		{"type":"function","name":"safeTransferFrom","inputs":[{"name":"from","type":"address"},{"name":"to","type":"address"},{"name":"tokenId","type":"uint256"}],"outputs":[],"stateMutability":"nonpayable"},
+		{"type":"function","name":"safeTransferFrom","inputs":[{"name":"from","type":"address"},{"name":"to","type":"address"},{"name":"tokenId","type":"uint256"},{"name":"data","type":"bytes"}],"outputs":[],"stateMutability":"nonpayable"}
+	]"#;
+
+	#[test]
+	fn test_contract() {
+		let contract = abi::Contract::from_json(ERC721_ABI).unwrap();
+		let alice = address!("0x70997970c51812dc3a010c7d01b50e0d17dc79c8");
+		let transfer = vec![
+			Token::Address(ethabi::Address::zero()),
+			Token::Address(alice.into_ethabi()),
+			Token::Uint(ethabi::Uint::from(5)),
+		];
+		let with_data = [transfer.clone(), vec![Token::Bytes(b"hi".to_vec())]].concat();
+
+		let call = contract.encode("safeTransferFrom", transfer.clone()).unwrap();
+		assert_eq!(
+			call,
+			abi::encode::call("safeTransferFrom(address,address,uint256)", transfer.clone()).unwrap()
+		);
+		let call_with_data = contract.encode("safeTransferFrom", with_data.clone()).unwrap();
+		assert_eq!(hex::encode(&call_with_data[..4]), "b88d4fde");
+		assert_eq!(contract.decode_input(&call).unwrap(), ("safeTransferFrom".to_string(), transfer));
+		assert_eq!(contract.decode_input(&call_with_data).unwrap().1, with_data);
+
+		let output = abi::encode::abi(&[Token::Uint(ethabi::Uint::from(3))]).unwrap();
+		assert_eq!(contract.decode_output("balanceOf", &output).unwrap(), vec![Token::Uint(ethabi::Uint::from(3))]);
+		assert_eq!(contract.abi().functions().count(), 3);
+
+		assert!(contract.encode("burn", vec![]).is_err());
+		assert!(contract.encode("balanceOf", vec![Token::Bool(true)]).is_err());
+		assert!(contract.decode_output("balanceOf", &[1]).is_err());
+		assert!(contract.decode_input(&[0xde, 0xad, 0xbe, 0xef]).is_err());
+	}
+
+	#[test]
+	fn test_contract_from_file() {
+		let path = std::env::temp_dir().join(format!("crabrolls-abi-{}.json", std::process::id()));
+		std::fs::write(&path, format!(r#"{{"contractName":"Nft","abi":{}}}"#, ERC721_ABI)).unwrap();
+		let contract = abi::Contract::from_file(&path);
+		std::fs::remove_file(&path).unwrap();
+
+		assert_eq!(contract.unwrap(), abi::Contract::from_json(ERC721_ABI).unwrap());
+		assert!(abi::Contract::from_file(&path).is_err());
+		assert!(abi::Contract::from_json(r#"{"contractName":"Nft"}"#).is_err());
+	}
+
+	#[test]
+	fn test_decode_event() {
+		let from = address!("0xf39fd6e51aad88f6f4ce6ab8827279cfffb92266");
+		let to = address!("0x70997970c51812dc3a010c7d01b50e0d17dc79c8");
+		let word = |address: Address| {
+			let mut word = [0u8; 32];
+			word[12..].copy_from_slice(address.as_bytes());
+			word
+		};
+		let transfer = keccak256("Transfer(address,address,uint256)");
+		let topics = [transfer, word(from), word(to)];
+		let data = abi::encode::abi(&[Token::Uint(ethabi::Uint::from(100))]).unwrap();
+		let expected = vec![
+			Token::Address(from.into_ethabi()),
+			Token::Address(to.into_ethabi()),
+			Token::Uint(ethabi::Uint::from(100)),
+		];
+
+		let signature = "event Transfer(address indexed from, address indexed to, uint256 value)";
+		assert_eq!(abi::decode::event(&topics, &data, signature).unwrap(), expected);
+		assert_eq!(abi::decode::event(&topics, &data, "Transfer(address indexed,address indexed,uint)").unwrap(), expected);
+
+		let event_json = r#"{"type":"event","name":"Transfer","inputs":[{"name":"from","type":"address","indexed":true},{"name":"to","type":"address","indexed":true},{"name":"value","type":"uint256","indexed":false}],"anonymous":false}"#;
+		assert_eq!(abi::decode::event(&topics, &data, event_json).unwrap(), expected);
+		let abi_json = format!(
+			r#"[{{"type":"event","name":"Approval","inputs":[],"anonymous":false}},{}]"#,
+			event_json
+		);
+		assert_eq!(abi::decode::event(&topics, &data, &abi_json).unwrap(), expected);
+
+		// More indexed params than topics, unknown event and invalid signature
+		let signature = "Transfer(address indexed from, address indexed to, uint256 indexed value)";
+		assert!(abi::decode::event(&topics, &data, signature).is_err());
+		assert!(abi::decode::event(&[keccak256("Other()")], &[], &abi_json).is_err());
+		assert!(abi::decode::event(&topics, &data, "Transfer(address indexed from").is_err());
+	}
+
+	#[test]
+	fn test_decode_event_tuples() {
+		let signature = "Order(uint256 indexed id, (address maker, uint256[] amounts)[] legs, string indexed note)";
+		let legs = Token::Array(vec![Token::Tuple(vec![
+			Token::Address(ethabi::Address::zero()),
+			Token::Array(vec![Token::Uint(ethabi::Uint::from(1)), Token::Uint(ethabi::Uint::from(2))]),
+		])]);
+		let topics = [
+			keccak256("Order(uint256,(address,uint256[])[],string)"),
+			abi::encode::abi(&[Token::Uint(ethabi::Uint::from(7))]).unwrap().try_into().unwrap(),
+			keccak256("note"),
+		];
+		let data = abi::encode::abi(std::slice::from_ref(&legs)).unwrap();
+
+		let tokens = abi::decode::event(&topics, &data, signature).unwrap();
+		let note = Token::FixedBytes(keccak256("note").to_vec());
+		assert_eq!(tokens, vec![Token::Uint(ethabi::Uint::from(7)), legs, note]);
+	}
 
 	#[test]
 	fn test_ether_withdraw() {
@@ -658,13 +1229,13 @@ mod tests {
 		assert_eq!(tokens.len(), 2);
 
 		if let Token::Address(address) = &tokens[0] {
-			assert_eq!(address, &address!("0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266"));
+			assert_eq!(address, &address!("0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266").into_ethabi());
 		} else {
 			panic!("invalid token type");
 		}
 
 		if let Token::Uint(value) = &tokens[1] {
-			assert_eq!(value, &uint!(100));
+			assert_eq!(value, &ethabi::Uint::from(100));
 		} else {
 			panic!("invalid token type");
 		}
@@ -679,13 +1250,13 @@ mod tests {
 		assert_eq!(tokens.len(), 4);
 
 		if let Token::Address(dapp_address) = &tokens[0] {
-			assert_eq!(dapp_address, &address!("0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266"));
+			assert_eq!(dapp_address, &address!("0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266").into_ethabi());
 		} else {
 			panic!("invalid token type");
 		}
 
 		if let Token::Address(address) = &tokens[1] {
-			assert_eq!(address, &address!("0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266"));
+			assert_eq!(address, &address!("0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266").into_ethabi());
 		} else {
 			panic!("invalid token type");
 		}
@@ -696,7 +1267,7 @@ mod tests {
 
 				for (j, token) in array.iter().enumerate() {
 					if let Token::Uint(value) = token {
-						assert_eq!(value, &Uint::from((i * 3 + j + 1) as u128));
+						assert_eq!(value, &ethabi::Uint::from((i * 3 + j + 1) as u128));
 					} else {
 						panic!("invalid token type");
 					}
@@ -734,7 +1305,7 @@ mod tests {
 		let function_name = "transfer";
 		let address = address!("0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266");
 		let value = uint!(1000);
-		let params = vec![Token::Address(address), Token::Uint(value)];
+		let params = vec![Token::Address(address.into_ethabi()), Token::Uint(value.into_ethabi())];
 
 		let encoded = abi::encode::function_call(abi_json, function_name, params).expect("encoding failed");
 		let expected = hex::decode("a9059cbb000000000000000000000000f39fd6e51aad88f6f4ce6ab8827279cfffb9226600000000000000000000000000000000000000000000000000000000000003e8").expect("decoding failed");
@@ -0,0 +1,198 @@
+use super::abi::abi::Contract;
+use crate::types::error::CrabrollsError;
+use ethabi::{Function, ParamType};
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const KEYWORDS: &[&str] = &[
+	"as", "async", "await", "box", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern", "false", "fn",
+	"for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return", "self", "static",
+	"struct", "super", "trait", "true", "type", "unsafe", "use", "where", "while", "yield",
+];
+
+/// Generates the voucher constructors of a contract from a build script, reading its JSON ABI (or compiler artifact)
+/// from `abi_path` and writing them to `$OUT_DIR/{name}.rs`, to be included with `abigen!(mod name)`.
+pub fn build(name: &str, abi_path: impl AsRef<Path>) -> Result<(), CrabrollsError> {
+	let abi_path = abi_path.as_ref();
+	println!("cargo:rerun-if-changed={}", abi_path.display());
+
+	let source = generate(&Contract::from_file(abi_path)?)?;
+	let out_dir = env::var("OUT_DIR").map_err(|_| CrabrollsError::other("OUT_DIR is only set for build scripts"))?;
+	let path = PathBuf::from(out_dir).join(format!("{}.rs", name));
+	fs::write(&path, source).map_err(|e| CrabrollsError::other(format!("can't write {}: {}", path.display(), e)))
+}
+
+/// Source of a typed function per function of the contract, returning the calldata of a call to it, e.g.
+/// `pub fn transfer(to: Address, value: Uint) -> Vec<u8>`. Names are converted to snake case, and the overloads
+/// after the one with fewer parameters get the names of their extra parameters, e.g. `safe_transfer_from_with_data`.
+/// Tuples are taken as `Token`s.
+pub fn generate(contract: &Contract) -> Result<String, CrabrollsError> {
+	let mut source = String::from("// Generated by crabrolls from the ABI of the contract, do not edit\n");
+	let mut names = Vec::new();
+
+	for overloads in contract.abi().functions.values() {
+		let mut overloads: Vec<&Function> = overloads.iter().collect();
+		overloads.sort_by_key(|function| function.inputs.len());
+		let base = overloads[0];
+
+		for function in overloads {
+			let mut name = snake_case(&function.name);
+			if function.inputs.len() > base.inputs.len() {
+				let extra: Vec<String> = (function.inputs.iter().enumerate())
+					.skip(base.inputs.len())
+					.map(|(index, input)| param_name(&input.name, index))
+					.collect();
+				name = format!("{}_with_{}", name, extra.join("_and_"));
+			}
+			let name = escape_keyword(name);
+			if names.contains(&name) {
+				return Err(CrabrollsError::abi(format!("overloads of {} generate the same name", function.name)));
+			}
+
+			let params: Vec<(String, &ParamType)> = (function.inputs.iter().enumerate())
+				.map(|(index, input)| (param_name(&input.name, index), &input.kind))
+				.collect();
+			let args: Vec<String> = params
+				.iter()
+				.map(|(param, kind)| format!("{}: {}", param, rust_type(kind)))
+				.collect();
+			let tokens: Vec<String> = params.iter().map(|(param, kind)| token(kind, param)).collect();
+			let selector: Vec<String> = function
+				.short_signature()
+				.iter()
+				.map(|byte| format!("0x{:02x}", byte))
+				.collect();
+
+			let signature = function.signature();
+			let signature = signature.split(':').next().unwrap_or_default();
+			let _ = write!(
+				source,
+				"\n/// Calldata of `{}`.\npub fn {}({}) -> Vec<u8> {{\n\tlet mut call = vec![{}];\n\tcall.extend(::crabrolls::ethabi::encode(&[{}]));\n\tcall\n}}\n",
+				signature,
+				name,
+				args.join(", "),
+				selector.join(", "),
+				tokens.join(", ")
+			);
+			names.push(name);
+		}
+	}
+	Ok(source)
+}
+
+fn rust_type(kind: &ParamType) -> String {
+	match kind {
+		ParamType::Address => "::crabrolls::prelude::Address".to_string(),
+		ParamType::Uint(_) | ParamType::Int(_) => "::crabrolls::prelude::Uint".to_string(),
+		ParamType::Bool => "bool".to_string(),
+		ParamType::String => "String".to_string(),
+		ParamType::Bytes => "Vec<u8>".to_string(),
+		ParamType::FixedBytes(size) => format!("[u8; {}]", size),
+		ParamType::Array(kind) => format!("Vec<{}>", rust_type(kind)),
+		ParamType::FixedArray(kind, size) => format!("[{}; {}]", rust_type(kind), size),
+		ParamType::Tuple(_) => "::crabrolls::ethabi::Token".to_string(),
+	}
+}
+
+/// Expression converting the Rust value `value` of `kind` to its `Token`. The addresses and integers go through
+/// `EthabiCompat`, so the same code builds whether or not the app enables the `alloy` feature.
+fn token(kind: &ParamType, value: &str) -> String {
+	let items = |kind: &ParamType| format!("{}.into_iter().map(|item| {}).collect()", value, token(kind, "item"));
+	let ethabi = |value: &str| format!("::crabrolls::EthabiCompat::into_ethabi({})", value);
+	match kind {
+		ParamType::Address => format!("::crabrolls::ethabi::Token::Address({})", ethabi(value)),
+		ParamType::Uint(_) => format!("::crabrolls::ethabi::Token::Uint({})", ethabi(value)),
+		ParamType::Int(_) => format!("::crabrolls::ethabi::Token::Int({})", ethabi(value)),
+		ParamType::Bool => format!("::crabrolls::ethabi::Token::Bool({})", value),
+		ParamType::String => format!("::crabrolls::ethabi::Token::String({})", value),
+		ParamType::Bytes => format!("::crabrolls::ethabi::Token::Bytes({})", value),
+		ParamType::FixedBytes(_) => format!("::crabrolls::ethabi::Token::FixedBytes({}.to_vec())", value),
+		ParamType::Array(kind) => format!("::crabrolls::ethabi::Token::Array({})", items(kind)),
+		ParamType::FixedArray(kind, _) => format!("::crabrolls::ethabi::Token::FixedArray({})", items(kind)),
+		ParamType::Tuple(_) => value.to_string(),
+	}
+}
+
+fn param_name(name: &str, index: usize) -> String {
+	match snake_case(name.trim_start_matches('_')) {
+		name if name.is_empty() => format!("arg{}", index),
+		name => escape_keyword(name),
+	}
+}
+
+/// Converts a Solidity name to snake case, keeping acronyms together, e.g. `tokenURI` to `token_uri`.
+fn snake_case(name: &str) -> String {
+	let chars: Vec<char> = name.chars().collect();
+	let mut snake = String::new();
+	for (index, char) in chars.iter().enumerate() {
+		if char.is_uppercase() && index > 0 {
+			let previous = chars[index - 1];
+			let next_is_lower = chars.get(index + 1).is_some_and(|next| next.is_lowercase());
+			if previous.is_lowercase() || previous.is_ascii_digit() || (previous.is_uppercase() && next_is_lower) {
+				snake.push('_');
+			}
+		}
+		snake.extend(char.to_lowercase());
+	}
+	snake
+}
+
+fn escape_keyword(name: String) -> String {
+	match KEYWORDS.contains(&name.as_str()) {
+		true => format!("{}_", name),
+		false => name,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_snake_case() {
+		assert_eq!(snake_case("safeTransferFrom"), "safe_transfer_from");
+		assert_eq!(snake_case("tokenURI"), "token_uri");
+		assert_eq!(snake_case("ERC20Transfer"), "erc20_transfer");
+		assert_eq!(snake_case("withdraw"), "withdraw");
+		assert_eq!(param_name("_to", 0), "to");
+		assert_eq!(param_name("", 2), "arg2");
+		assert_eq!(param_name("type", 0), "type_");
+	}
+
+	#[test]
+	fn test_generate() {
+		let contract = Contract::from_json(
+			r#"[
+			{"type":"event","name":"Transfer","inputs":[],"anonymous":false},
+			{"type":"function","name":"transfer","inputs":[{"name":"_to","type":"address"},{"name":"_value","type":"uint256"}],"outputs":[{"name":"","type":"bool"}],"stateMutability":"nonpayable"},
+			{"type":"function","name":"safeTransferFrom","inputs":[{"name":"from","type":"address"},{"name":"to","type":"address"},{"name":"tokenId","type":"uint256"},{"name":"data","type":"bytes"}],"outputs":[],"stateMutability":"nonpayable"},
+			{"type":"function","name":"safeTransferFrom","inputs":[{"name":"from","type":"address"},{"name":"to","type":"address"},{"name":"tokenId","type":"uint256"}],"outputs":[],"stateMutability":"nonpayable"},
+			{"type":"function","name":"setRoots","inputs":[{"name":"","type":"bytes32[]"},{"name":"order","type":"tuple","components":[{"name":"id","type":"uint256"}]}],"outputs":[],"stateMutability":"nonpayable"}
+		]"#,
+		)
+		.unwrap();
+		let source = generate(&contract).unwrap();
+
+		assert!(source.contains(concat!(
+			"/// Calldata of `transfer(address,uint256)`.\n",
+			"pub fn transfer(to: ::crabrolls::prelude::Address, value: ::crabrolls::prelude::Uint) -> Vec<u8> {\n",
+			"\tlet mut call = vec![0xa9, 0x05, 0x9c, 0xbb];\n",
+			"\tcall.extend(::crabrolls::ethabi::encode(&[",
+			"::crabrolls::ethabi::Token::Address(::crabrolls::EthabiCompat::into_ethabi(to)), ",
+			"::crabrolls::ethabi::Token::Uint(::crabrolls::EthabiCompat::into_ethabi(value))]));\n",
+			"\tcall\n}\n"
+		)));
+		assert!(source.contains("pub fn safe_transfer_from(from: "));
+		assert!(source.contains("pub fn safe_transfer_from_with_data(from: "));
+		assert!(source.contains(concat!(
+			"pub fn set_roots(arg0: Vec<[u8; 32]>, order: ::crabrolls::ethabi::Token) -> Vec<u8> {\n",
+			"\tlet mut call = vec![0x",
+		)));
+		assert!(source.contains(
+			"::crabrolls::ethabi::Token::Array(arg0.into_iter().map(|item| ::crabrolls::ethabi::Token::FixedBytes(item.to_vec())).collect()), order"
+		));
+		assert!(!source.contains("Transfer("));
+	}
+}
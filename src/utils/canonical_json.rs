@@ -0,0 +1,125 @@
+use super::hash::keccak256;
+use serde::Serialize;
+use serde_json::Value;
+use std::error::Error;
+
+/// Serializes the value as compact JSON with the object keys sorted (by their bytes), rejecting floats so the same
+/// value always produces the same bytes.
+pub fn to_vec(value: &impl Serialize) -> Result<Vec<u8>, Box<dyn Error>> {
+	let mut output = Vec::new();
+	write_value(&mut output, &serde_json::to_value(value)?)?;
+	Ok(output)
+}
+
+pub fn to_string(value: &impl Serialize) -> Result<String, Box<dyn Error>> {
+	Ok(String::from_utf8(to_vec(value)?)?)
+}
+
+/// Keccak-256 of the canonical bytes of the value.
+pub fn hash(value: &impl Serialize) -> Result<[u8; 32], Box<dyn Error>> {
+	Ok(keccak256(to_vec(value)?))
+}
+
+/// Whether the bytes are valid JSON already in canonical form.
+pub fn is_canonical(bytes: &[u8]) -> bool {
+	serde_json::from_slice::<Value>(bytes)
+		.ok()
+		.and_then(|value| to_vec(&value).ok())
+		.is_some_and(|canonical| canonical == bytes)
+}
+
+fn write_value(output: &mut Vec<u8>, value: &Value) -> Result<(), Box<dyn Error>> {
+	match value {
+		Value::Number(number) if number.is_f64() => {
+			return Err(format!("canonical JSON doesn't allow floats, found {}", number).into())
+		}
+		Value::Array(items) => {
+			output.push(b'[');
+			for (i, item) in items.iter().enumerate() {
+				if i > 0 {
+					output.push(b',');
+				}
+				write_value(output, item)?;
+			}
+			output.push(b']');
+		}
+		Value::Object(map) => {
+			let mut entries: Vec<(&String, &Value)> = map.iter().collect();
+			entries.sort_by(|(a, _), (b, _)| a.as_bytes().cmp(b.as_bytes()));
+			output.push(b'{');
+			for (i, (key, item)) in entries.into_iter().enumerate() {
+				if i > 0 {
+					output.push(b',');
+				}
+				serde_json::to_writer(&mut *output, key)?;
+				output.push(b':');
+				write_value(output, item)?;
+			}
+			output.push(b'}');
+		}
+		_ => serde_json::to_writer(&mut *output, value)?,
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::types::primitives::*;
+	use serde_json::json;
+	use std::collections::HashMap;
+
+	#[test]
+	fn test_sorted_compact_output() {
+		let value = json!({ "b": [1, {"z": null, "a": true}], "a": "x\"y", "é": -5, "B": {} });
+		assert_eq!(
+			to_string(&value).unwrap(),
+			r#"{"B":{},"a":"x\"y","b":[1,{"a":true,"z":null}],"é":-5}"#
+		);
+	}
+
+	#[test]
+	fn test_map_order_independence() {
+		let first: HashMap<String, u64> = (0..50).map(|i| (format!("key{}", i), i)).collect();
+		let mut second: HashMap<String, u64> = HashMap::with_capacity(1000);
+		second.extend((0..50).rev().map(|i| (format!("key{}", i), i)));
+		assert_eq!(to_vec(&first).unwrap(), to_vec(&second).unwrap());
+		assert_eq!(hash(&first).unwrap(), hash(&second).unwrap());
+	}
+
+	#[test]
+	fn test_rejects_floats() {
+		assert!(to_vec(&json!({ "price": 1.5 })).is_err());
+		assert!(to_vec(&[0.0]).is_err());
+		assert_eq!(
+			to_string(&(u64::MAX, i64::MIN)).unwrap(),
+			"[18446744073709551615,-9223372036854775808]"
+		);
+	}
+
+	#[test]
+	fn test_ethabi_types() {
+		#[derive(Serialize)]
+		struct Balance {
+			owner: Address,
+			amount: Uint,
+		}
+		let balance = Balance {
+			owner: Address::repeat_byte(0xab),
+			amount: Uint::from(255),
+		};
+		assert_eq!(
+			to_string(&balance).unwrap(),
+			r#"{"amount":"0xff","owner":"0xabababababababababababababababababababab"}"#
+		);
+	}
+
+	#[test]
+	fn test_is_canonical() {
+		assert!(is_canonical(br#"{"a":1,"b":[true]}"#));
+		assert!(!is_canonical(br#"{"b":1,"a":1}"#));
+		assert!(!is_canonical(br#"{"a": 1}"#));
+		assert!(!is_canonical(br#"{"a":1.0}"#));
+		assert!(!is_canonical(b"{"));
+	}
+}
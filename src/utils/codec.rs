@@ -0,0 +1,87 @@
+use std::error::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PayloadCodec {
+	Json, // UTF-8 JSON object or array, as sent by frontends
+	Hex,  // `0x`-prefixed hex string, e.g. ABI calldata generated by wallets
+	Raw,  // Any other binary payload
+}
+
+impl PayloadCodec {
+	pub fn detect(payload: &[u8]) -> Self {
+		if is_hex(payload) {
+			Self::Hex
+		} else if is_json(payload) {
+			Self::Json
+		} else {
+			Self::Raw
+		}
+	}
+
+	pub fn matches(&self, payload: &[u8]) -> bool {
+		Self::detect(payload) == *self
+	}
+}
+
+pub fn is_hex(payload: &[u8]) -> bool {
+	match payload.strip_prefix(b"0x") {
+		Some(digits) => digits.len() % 2 == 0 && digits.iter().all(u8::is_ascii_hexdigit),
+		None => false,
+	}
+}
+
+pub fn is_json(payload: &[u8]) -> bool {
+	match payload.trim_ascii_start().first() {
+		Some(b'{') | Some(b'[') => serde_json::from_slice::<serde::de::IgnoredAny>(payload).is_ok(),
+		_ => false,
+	}
+}
+
+pub fn decode_hex(payload: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+	match payload.strip_prefix(b"0x") {
+		Some(digits) => Ok(hex::decode(digits)?),
+		None => Err("payload is not a 0x-prefixed hex string".into()),
+	}
+}
+
+pub fn encode_hex(payload: impl AsRef<[u8]>) -> Vec<u8> {
+	format!("0x{}", hex::encode(payload)).into_bytes()
+}
+
+pub fn unwrap_hex(payload: Vec<u8>) -> Vec<u8> {
+	match is_hex(&payload) {
+		true => decode_hex(&payload).unwrap_or(payload),
+		false => payload,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_detect() {
+		assert_eq!(PayloadCodec::detect(br#"{"kind": "swap"}"#), PayloadCodec::Json);
+		assert_eq!(PayloadCodec::detect(b" [1, 2]"), PayloadCodec::Json);
+		assert_eq!(PayloadCodec::detect(b"0xa9059cbb"), PayloadCodec::Hex);
+		assert_eq!(PayloadCodec::detect(b"0x"), PayloadCodec::Hex);
+		assert_eq!(PayloadCodec::detect(b"0xa9059cb"), PayloadCodec::Raw);
+		assert_eq!(PayloadCodec::detect(b"{not json"), PayloadCodec::Raw);
+		assert_eq!(PayloadCodec::detect(&[0xa9, 0x05, 0x9c, 0xbb]), PayloadCodec::Raw);
+	}
+
+	#[test]
+	fn test_decode_hex() {
+		assert_eq!(decode_hex(b"0xa9059cbb").unwrap(), vec![0xa9, 0x05, 0x9c, 0xbb]);
+		assert!(decode_hex(b"a9059cbb").is_err());
+		assert!(decode_hex(b"0xzz").is_err());
+	}
+
+	#[test]
+	fn test_unwrap_hex() {
+		let json = br#"{"kind":"swap"}"#.to_vec();
+		assert_eq!(unwrap_hex(encode_hex(&json)), json);
+		assert_eq!(unwrap_hex(json.clone()), json);
+		assert_eq!(unwrap_hex(b"0xzz".to_vec()), b"0xzz".to_vec());
+	}
+}
@@ -0,0 +1,115 @@
+use super::hash::keccak256;
+use crate::types::error::CrabrollsError;
+use crate::types::primitives::*;
+use k256::ecdsa::{RecoveryId, Signature, VerifyingKey};
+
+/// Size of an Ethereum signature, packed as `r (32 bytes) | s (32 bytes) | v (1 byte)`.
+pub const SIGNATURE_SIZE: usize = 65;
+
+/// The EIP-191 (`personal_sign`) hash of a message, as signed by wallets.
+pub fn personal_message_hash(message: &[u8]) -> [u8; 32] {
+	let mut prefixed = format!("\x19Ethereum Signed Message:\n{}", message.len()).into_bytes();
+	prefixed.extend_from_slice(message);
+	keccak256(prefixed)
+}
+
+/// Recovers the address that signed a 32 bytes hash, like the `ecrecover` precompile. `v` can be either 0/1 or
+/// 27/28, any other recovery id is rejected, and so are signatures with a high `s`, as malleable.
+pub fn ecrecover(hash: &[u8; 32], signature: &[u8]) -> Result<Address, CrabrollsError> {
+	if signature.len() != SIGNATURE_SIZE {
+		return Err(CrabrollsError::abi("signature must have 65 bytes"));
+	}
+	let parsed = Signature::from_slice(&signature[..64]).map_err(|error| CrabrollsError::abi(error.to_string()))?;
+	if parsed.normalize_s().is_some() {
+		return Err(CrabrollsError::abi("non-canonical signature"));
+	}
+	let recovery_id = match signature[64] {
+		v @ (0 | 1) => RecoveryId::from_byte(v),
+		v @ (27 | 28) => RecoveryId::from_byte(v - 27),
+		_ => None,
+	}
+	.ok_or_else(|| CrabrollsError::abi("invalid recovery id"))?;
+
+	let key = VerifyingKey::recover_from_prehash(hash, &parsed, recovery_id)
+		.map_err(|error| CrabrollsError::other(error.to_string()))?;
+	Ok(public_key_address(&key))
+}
+
+pub fn public_key_address(key: &VerifyingKey) -> Address {
+	let public_key = key.to_encoded_point(false);
+	Address::from_slice(&keccak256(&public_key.as_bytes()[1..])[12..])
+}
+
+/// Recovers the signer of a message signed with `personal_sign`.
+pub fn recover_personal_signer(message: &[u8], signature: &[u8]) -> Result<Address, CrabrollsError> {
+	ecrecover(&personal_message_hash(message), signature)
+}
+
+pub fn verify_personal_message(message: &[u8], signature: &[u8], signer: Address) -> bool {
+	recover_personal_signer(message, signature).is_ok_and(|recovered| recovered == signer)
+}
+
+/// Splits a relayed payload packed as `message | signature (65 bytes)` into the `personal_sign` signer of the message,
+/// the real sender of a meta-transaction, and the message. Replays must be prevented by the app, e.g. with a nonce
+/// in the message.
+pub fn recover_signed_payload(payload: &[u8]) -> Result<(Address, &[u8]), CrabrollsError> {
+	if payload.len() < SIGNATURE_SIZE {
+		return Err(CrabrollsError::abi("signed payload is too short"));
+	}
+	let (message, signature) = payload.split_at(payload.len() - SIGNATURE_SIZE);
+	Ok((recover_personal_signer(message, signature)?, message))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use k256::ecdsa::SigningKey;
+
+	fn sign(key: &SigningKey, message: &[u8]) -> Vec<u8> {
+		let (signature, recovery_id) = key.sign_prehash_recoverable(&personal_message_hash(message)).unwrap();
+		let mut signature = signature.to_vec();
+		signature.push(27 + recovery_id.to_byte());
+		signature
+	}
+
+	#[test]
+	fn test_ecrecover() {
+		// Private key 0x...01 belongs to a well known address
+		let key = SigningKey::from_slice(&[[0u8; 31].as_slice(), &[1u8]].concat()).unwrap();
+		let signer = public_key_address(key.verifying_key());
+		assert_eq!(format!("{:?}", signer), "0x7e5f4552091a69125d5dfcb7b8c2659029395bdf");
+
+		let mut signature = sign(&key, b"hello");
+		assert_eq!(ecrecover(&personal_message_hash(b"hello"), &signature).unwrap(), signer);
+		signature[64] -= 27;
+		assert_eq!(recover_personal_signer(b"hello", &signature).unwrap(), signer);
+
+		assert!(ecrecover(&personal_message_hash(b"hello"), &signature[..64]).is_err());
+		for v in [2, 3, 5, 29, 30] {
+			signature[64] = v;
+			assert!(recover_personal_signer(b"hello", &signature).is_err());
+		}
+	}
+
+	#[test]
+	fn test_verify_personal_message() {
+		let key = SigningKey::from_slice(&[7u8; 32]).unwrap();
+		let signer = public_key_address(key.verifying_key());
+		let signature = sign(&key, b"transfer 10 to bob");
+
+		assert!(verify_personal_message(b"transfer 10 to bob", &signature, signer));
+		assert!(!verify_personal_message(b"transfer 99 to bob", &signature, signer));
+		assert!(!verify_personal_message(b"transfer 10 to bob", &signature, Address::zero()));
+	}
+
+	#[test]
+	fn test_recover_signed_payload() {
+		let key = SigningKey::from_slice(&[7u8; 32]).unwrap();
+		let payload = [b"nonce:1;buy 10".as_slice(), &sign(&key, b"nonce:1;buy 10")].concat();
+
+		let (signer, message) = recover_signed_payload(&payload).unwrap();
+		assert_eq!(signer, public_key_address(key.verifying_key()));
+		assert_eq!(message, b"nonce:1;buy 10");
+		assert!(recover_signed_payload(&payload[..SIGNATURE_SIZE - 1]).is_err());
+	}
+}
@@ -0,0 +1,74 @@
+use crate::types::primitives::*;
+pub use rust_decimal::Decimal;
+use std::error::Error;
+
+/// Converts a token amount with `scale` decimals (e.g. 18 for ether) into a `Decimal`, failing if it doesn't fit.
+pub fn from_uint(amount: Uint, scale: u32) -> Result<Decimal, Box<dyn Error>> {
+	if amount > Uint::from(u128::MAX >> 1) {
+		return Err("amount doesn't fit in a decimal".into());
+	}
+	Decimal::try_from_i128_with_scale(amount.as_u128() as i128, scale)
+		.map_err(|_| "amount doesn't fit in a decimal".into())
+}
+
+/// Converts a `Decimal` back into a token amount with `scale` decimals, failing instead of dropping precision.
+pub fn to_uint(value: Decimal, scale: u32) -> Result<Uint, Box<dyn Error>> {
+	if value.is_sign_negative() && !value.is_zero() {
+		return Err("negative decimal can't be a token amount".into());
+	}
+
+	let value = if value.scale() > scale {
+		value.normalize()
+	} else {
+		value
+	};
+	if value.scale() > scale {
+		return Err(format!("decimal has more than {} fractional digits", scale).into());
+	}
+
+	let factor = Uint::from(10u64)
+		.checked_pow(Uint::from(scale - value.scale()))
+		.ok_or("token amount overflow")?;
+	Uint::from(value.mantissa().unsigned_abs())
+		.checked_mul(factor)
+		.ok_or_else(|| "token amount overflow".into())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::str::FromStr;
+
+	#[test]
+	fn test_roundtrip() {
+		let amount = Uint::from(1_500_000_000_000_000_000u64);
+		let value = from_uint(amount, 18).unwrap();
+		assert_eq!(value, Decimal::from_str("1.5").unwrap());
+		assert_eq!(to_uint(value, 18).unwrap(), amount);
+		assert_eq!(
+			to_uint(Decimal::from_str("1.50").unwrap(), 6).unwrap(),
+			Uint::from(1_500_000u64)
+		);
+	}
+
+	#[test]
+	fn test_lossy_conversions_fail() {
+		assert_eq!(
+			to_uint(Decimal::from_str("0.0000001").unwrap(), 6)
+				.unwrap_err()
+				.to_string(),
+			"decimal has more than 6 fractional digits"
+		);
+		assert!(to_uint(Decimal::from_str("-1").unwrap(), 18).is_err());
+		assert!(from_uint(Uint::MAX, 18).is_err());
+		assert!(from_uint(Uint::from(u128::MAX), 0).is_err());
+	}
+
+	#[test]
+	fn test_interest_accrual() {
+		let principal = from_uint(Uint::from(1_000_000u64), 6).unwrap();
+		let rate = Decimal::from_str("0.05").unwrap();
+		let accrued = (principal * (Decimal::ONE + rate)).round_dp(6);
+		assert_eq!(to_uint(accrued, 6).unwrap(), Uint::from(1_050_000u64));
+	}
+}
@@ -0,0 +1,174 @@
+use super::crypto::ecrecover;
+use super::hash::keccak256;
+use crate::types::error::CrabrollsError;
+use crate::types::primitives::*;
+use ethabi::Token;
+
+/// Domain of the EIP-712 signatures of a dApp, only the set fields are part of the `EIP712Domain` type.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Eip712Domain {
+	pub name: Option<String>,
+	pub version: Option<String>,
+	pub chain_id: Option<Uint>,
+	pub verifying_contract: Option<Address>,
+	pub salt: Option<[u8; 32]>,
+}
+
+impl Eip712Domain {
+	pub fn new(name: impl Into<String>, version: impl Into<String>) -> Self {
+		Self {
+			name: Some(name.into()),
+			version: Some(version.into()),
+			..Self::default()
+		}
+	}
+
+	pub fn chain_id(mut self, chain_id: u64) -> Self {
+		self.chain_id = Some(Uint::from(chain_id));
+		self
+	}
+
+	pub fn verifying_contract(mut self, verifying_contract: Address) -> Self {
+		self.verifying_contract = Some(verifying_contract);
+		self
+	}
+
+	pub fn salt(mut self, salt: [u8; 32]) -> Self {
+		self.salt = Some(salt);
+		self
+	}
+
+	pub fn separator(&self) -> [u8; 32] {
+		let mut fields = Vec::new();
+		let mut values = Vec::new();
+		if let Some(name) = &self.name {
+			fields.push("string name");
+			values.push(Token::String(name.clone()));
+		}
+		if let Some(version) = &self.version {
+			fields.push("string version");
+			values.push(Token::String(version.clone()));
+		}
+		if let Some(chain_id) = self.chain_id {
+			fields.push("uint256 chainId");
+			values.push(Token::Uint(chain_id.into_ethabi()));
+		}
+		if let Some(verifying_contract) = self.verifying_contract {
+			fields.push("address verifyingContract");
+			values.push(Token::Address(verifying_contract.into_ethabi()));
+		}
+		if let Some(salt) = self.salt {
+			fields.push("bytes32 salt");
+			values.push(Token::FixedBytes(salt.to_vec()));
+		}
+		hash_struct(&format!("EIP712Domain({})", fields.join(",")), &values)
+	}
+
+	/// The digest signed by `eth_signTypedData` for a struct of this domain, `keccak256(0x1901 | separator | hash)`.
+	pub fn typed_data_hash(&self, struct_hash: &[u8; 32]) -> [u8; 32] {
+		let mut data = vec![0x19, 0x01];
+		data.extend_from_slice(&self.separator());
+		data.extend_from_slice(struct_hash);
+		keccak256(data)
+	}
+
+	pub fn recover_signer(&self, struct_hash: &[u8; 32], signature: &[u8]) -> Result<Address, CrabrollsError> {
+		ecrecover(&self.typed_data_hash(struct_hash), signature)
+	}
+}
+
+/// Hashes a struct given its full type, e.g. `Mail(Person from,string contents)Person(string name,address wallet)`,
+/// and its field values in order. `String` and `Bytes` values are hashed, `Array` values are hashed from their
+/// encoded items, and nested structs must be passed as the `FixedBytes` of their own `hash_struct`.
+pub fn hash_struct(encoded_type: &str, values: &[Token]) -> [u8; 32] {
+	let mut data = keccak256(encoded_type).to_vec();
+	for value in values {
+		data.extend_from_slice(&encode_value(value));
+	}
+	keccak256(data)
+}
+
+fn encode_value(value: &Token) -> [u8; 32] {
+	match value {
+		Token::String(value) => keccak256(value),
+		Token::Bytes(value) => keccak256(value),
+		Token::Array(items) | Token::FixedArray(items) => {
+			keccak256(items.iter().flat_map(encode_value).collect::<Vec<_>>())
+		}
+		Token::FixedBytes(value) => {
+			let mut word = [0u8; 32];
+			word[..value.len().min(32)].copy_from_slice(&value[..value.len().min(32)]);
+			word
+		}
+		value => {
+			let mut word = [0u8; 32];
+			word.copy_from_slice(&ethabi::encode(std::slice::from_ref(value))[..32]);
+			word
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::address;
+
+	const MAIL_TYPE: &str = "Mail(Person from,Person to,string contents)Person(string name,address wallet)";
+	const PERSON_TYPE: &str = "Person(string name,address wallet)";
+
+	fn person(name: &str, wallet: Address) -> Token {
+		let hash = hash_struct(PERSON_TYPE, &[Token::String(name.to_string()), Token::Address(wallet.into_ethabi())]);
+		Token::FixedBytes(hash.to_vec())
+	}
+
+	// The `Mail` example of the EIP-712 specification
+	#[test]
+	fn test_mail_example() {
+		let domain = Eip712Domain::new("Ether Mail", "1")
+			.chain_id(1)
+			.verifying_contract(address!("0xCcCCccccCCCCcCCCCCCcCcCccCcCCCcCcccccccC"));
+		assert_eq!(
+			hex::encode(domain.separator()),
+			"f2cee375fa42b42143804025fc449deafd50cc031ca257e0b194a650a912090f"
+		);
+
+		let cow = address!("0xCD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826");
+		let mail = hash_struct(
+			MAIL_TYPE,
+			&[
+				person("Cow", cow),
+				person("Bob", address!("0xbBbBBBBbbBBBbbbBbbBbbbbBBbBbbbbBbBbbBBbB")),
+				Token::String("Hello, Bob!".to_string()),
+			],
+		);
+		assert_eq!(
+			hex::encode(mail),
+			"c52c0ee5d84264471806290a3f2c4cecfc5490626bf912d01f240d7a274b371e"
+		);
+		assert_eq!(
+			hex::encode(domain.typed_data_hash(&mail)),
+			"be609aee343fb3c4b28e1df9e632fca64fcfaede20f02e86244efddf30957bd2"
+		);
+
+		let signature = hex::decode(concat!(
+			"4355c47d63924e8a72e509b65029052eb6c299d53a04e167c5775fd466751c9d",
+			"07299936d304c153f6443dfa05f40ff007d72911b6f72307f996231605b91562",
+			"1c"
+		))
+		.unwrap();
+		assert_eq!(domain.recover_signer(&mail, &signature).unwrap(), cow);
+		assert_ne!(domain.chain_id(5).recover_signer(&mail, &signature).unwrap(), cow);
+	}
+
+	#[test]
+	fn test_encode_values() {
+		assert_eq!(encode_value(&Token::Bool(true))[31], 1);
+		assert_eq!(encode_value(&Token::Uint(ethabi::Uint::from(258)))[30..], [1, 2]);
+		assert_eq!(encode_value(&Token::FixedBytes(vec![0xab]))[..2], [0xab, 0]);
+		assert_eq!(encode_value(&Token::Bytes(b"hi".to_vec())), keccak256(b"hi"));
+
+		let items = vec![Token::Uint(ethabi::Uint::from(1)), Token::Uint(ethabi::Uint::from(2))];
+		let expected = keccak256(ethabi::encode(&items));
+		assert_eq!(encode_value(&Token::Array(items)), expected);
+	}
+}
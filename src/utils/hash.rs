@@ -0,0 +1,23 @@
+use sha3::{Digest, Keccak256};
+
+pub fn keccak256(data: impl AsRef<[u8]>) -> [u8; 32] {
+	Keccak256::digest(data.as_ref()).into()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_keccak256_empty() {
+		assert_eq!(
+			hex::encode(keccak256([])),
+			"c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"
+		);
+	}
+
+	#[test]
+	fn test_keccak256_signature() {
+		assert_eq!(hex::encode(&keccak256("transfer(address,uint256)")[..4]), "a9059cbb");
+	}
+}
@@ -0,0 +1,122 @@
+use super::hash::keccak256;
+use crate::types::primitives::*;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+const CROCKFORD: &[u8; 32] = b"0123456789abcdefghjkmnpqrstvwxyz";
+const COMPACT_BYTES: usize = 10; // 80 bits, 16 base32 characters
+
+/// Stable 32-byte entity id, derived with keccak256 so it can be recomputed from the same inputs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Id([u8; 32]);
+
+impl Id {
+	pub fn as_bytes(&self) -> &[u8; 32] {
+		&self.0
+	}
+
+	pub fn to_hex(&self) -> String {
+		format!("0x{}", hex::encode(self.0))
+	}
+
+	/// Short base32 (Crockford, lowercase) encoding of the first 80 bits, for logs and user-facing references.
+	pub fn compact(&self) -> String {
+		let mut encoded = String::new();
+		let mut buffer: u32 = 0;
+		let mut bits = 0;
+		for byte in &self.0[..COMPACT_BYTES] {
+			buffer = (buffer << 8) | *byte as u32;
+			bits += 8;
+			while bits >= 5 {
+				bits -= 5;
+				encoded.push(CROCKFORD[((buffer >> bits) & 0x1f) as usize] as char);
+			}
+		}
+		encoded
+	}
+}
+
+impl From<[u8; 32]> for Id {
+	fn from(bytes: [u8; 32]) -> Self {
+		Self(bytes)
+	}
+}
+
+impl fmt::Display for Id {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}", self.to_hex())
+	}
+}
+
+impl FromStr for Id {
+	type Err = Box<dyn Error>;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let bytes = hex::decode(s.strip_prefix("0x").unwrap_or(s))?;
+		let bytes: [u8; 32] = bytes.try_into().map_err(|_| "id must have 32 bytes")?;
+		Ok(Self(bytes))
+	}
+}
+
+impl Serialize for Id {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.serialize_str(&self.to_hex())
+	}
+}
+
+impl<'de> Deserialize<'de> for Id {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		let s = String::deserialize(deserializer)?;
+		s.parse()
+			.map_err(|e: Box<dyn Error>| serde::de::Error::custom(e.to_string()))
+	}
+}
+
+pub fn from_bytes(data: impl AsRef<[u8]>) -> Id {
+	Id(keccak256(data))
+}
+
+pub fn from_input(input_index: u64, sender: Address, nonce: u64) -> Id {
+	let mut data = Vec::with_capacity(36);
+	data.extend_from_slice(&input_index.to_be_bytes());
+	data.extend_from_slice(sender.as_bytes());
+	data.extend_from_slice(&nonce.to_be_bytes());
+	from_bytes(data)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_from_input_is_deterministic() {
+		let sender = Address::repeat_byte(0xaa);
+		assert_eq!(from_input(1, sender, 0), from_input(1, sender, 0));
+		assert_ne!(from_input(1, sender, 0), from_input(1, sender, 1));
+		assert_ne!(from_input(1, sender, 0), from_input(2, sender, 0));
+		assert_ne!(from_input(1, sender, 0), from_input(1, Address::zero(), 0));
+	}
+
+	#[test]
+	fn test_encodings() {
+		let id = from_bytes([]);
+		assert_eq!(
+			id.to_hex(),
+			"0xc5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"
+		);
+		assert_eq!(id.compact().len(), 16);
+		assert_eq!(id.to_string().parse::<Id>().unwrap(), id);
+
+		let json = serde_json::to_string(&id).unwrap();
+		assert_eq!(serde_json::from_str::<Id>(&json).unwrap(), id);
+		assert!("0x1234".parse::<Id>().is_err());
+	}
+
+	#[test]
+	fn test_compact_alphabet() {
+		assert_eq!(Id([0u8; 32]).compact(), "0000000000000000");
+		assert_eq!(Id([0xff; 32]).compact(), "zzzzzzzzzzzzzzzz");
+	}
+}
@@ -0,0 +1,219 @@
+// With the `alloy` feature, `Address` and `Uint` are alloy's types across the API. The shims below convert the
+// `ethabi` types of the code not migrated yet, which are also the `H160`, `U256` and `Token` of ethers-rs, and
+// let the wallet methods and `uint!` take them as they are.
+#[cfg(feature = "erc1155")]
+use crate::core::contracts::erc1155::{IntoIdsAmountsIter, IntoIdsIter};
+use crate::types::primitives::*;
+use crate::utils::macros::IntoUint;
+use alloy_dyn_abi::DynSolValue;
+use alloy_primitives::{FixedBytes, Function, I256};
+use ethabi::Token;
+
+pub trait ToAlloy {
+	type Output;
+	fn to_alloy(self) -> Self::Output;
+}
+
+pub trait ToEthabi {
+	type Output;
+	fn to_ethabi(self) -> Self::Output;
+}
+
+impl ToAlloy for ethabi::Address {
+	type Output = Address;
+
+	fn to_alloy(self) -> Self::Output {
+		Address::from_ethabi(self)
+	}
+}
+
+impl ToEthabi for Address {
+	type Output = ethabi::Address;
+
+	fn to_ethabi(self) -> Self::Output {
+		self.into_ethabi()
+	}
+}
+
+impl ToAlloy for ethabi::Uint {
+	type Output = Uint;
+
+	fn to_alloy(self) -> Self::Output {
+		Uint::from_ethabi(self)
+	}
+}
+
+impl ToEthabi for Uint {
+	type Output = ethabi::Uint;
+
+	fn to_ethabi(self) -> Self::Output {
+		self.into_ethabi()
+	}
+}
+
+impl ToEthabi for I256 {
+	type Output = ethabi::Uint;
+
+	fn to_ethabi(self) -> Self::Output {
+		self.into_raw().to_ethabi()
+	}
+}
+
+impl ToAlloy for Token {
+	type Output = DynSolValue;
+
+	fn to_alloy(self) -> Self::Output {
+		match self {
+			Token::Address(address) => DynSolValue::Address(address.to_alloy()),
+			// Solidity has no `bytesN` past 32, so longer values can only be carried as dynamic bytes.
+			Token::FixedBytes(bytes) if bytes.len() > 32 => DynSolValue::Bytes(bytes),
+			Token::FixedBytes(bytes) => {
+				let size = bytes.len();
+				let mut word = [0u8; 32];
+				word[..size].copy_from_slice(&bytes);
+				DynSolValue::FixedBytes(FixedBytes(word), size)
+			}
+			Token::Bytes(bytes) => DynSolValue::Bytes(bytes),
+			Token::Int(value) => DynSolValue::Int(I256::from_raw(value.to_alloy()), 256),
+			Token::Uint(value) => DynSolValue::Uint(value.to_alloy(), 256),
+			Token::Bool(value) => DynSolValue::Bool(value),
+			Token::String(value) => DynSolValue::String(value),
+			Token::FixedArray(tokens) => DynSolValue::FixedArray(tokens.into_iter().map(ToAlloy::to_alloy).collect()),
+			Token::Array(tokens) => DynSolValue::Array(tokens.into_iter().map(ToAlloy::to_alloy).collect()),
+			Token::Tuple(tokens) => DynSolValue::Tuple(tokens.into_iter().map(ToAlloy::to_alloy).collect()),
+		}
+	}
+}
+
+impl ToEthabi for DynSolValue {
+	type Output = Token;
+
+	fn to_ethabi(self) -> Self::Output {
+		match self {
+			DynSolValue::Address(address) => Token::Address(address.to_ethabi()),
+			DynSolValue::Function(Function(bytes)) => Token::FixedBytes(bytes.to_vec()),
+			DynSolValue::FixedBytes(word, size) => Token::FixedBytes(word[..size].to_vec()),
+			DynSolValue::Bytes(bytes) => Token::Bytes(bytes),
+			DynSolValue::Int(value, _) => Token::Int(value.to_ethabi()),
+			DynSolValue::Uint(value, _) => Token::Uint(value.to_ethabi()),
+			DynSolValue::Bool(value) => Token::Bool(value),
+			DynSolValue::String(value) => Token::String(value),
+			DynSolValue::FixedArray(values) => Token::FixedArray(values.into_iter().map(ToEthabi::to_ethabi).collect()),
+			DynSolValue::Array(values) => Token::Array(values.into_iter().map(ToEthabi::to_ethabi).collect()),
+			DynSolValue::Tuple(values) => Token::Tuple(values.into_iter().map(ToEthabi::to_ethabi).collect()),
+		}
+	}
+}
+
+#[cfg(feature = "erc1155")]
+impl IntoIdsAmountsIter for (ethabi::Uint, ethabi::Uint) {
+	fn into_inner_iter(self) -> Box<dyn Iterator<Item = (Uint, Uint)>> {
+		Box::new(std::iter::once((self.0.to_alloy(), self.1.to_alloy())))
+	}
+}
+
+#[cfg(feature = "erc1155")]
+impl IntoIdsAmountsIter for Vec<(ethabi::Uint, ethabi::Uint)> {
+	fn into_inner_iter(self) -> Box<dyn Iterator<Item = (Uint, Uint)>> {
+		Box::new(self.into_iter().map(|(id, amount)| (id.to_alloy(), amount.to_alloy())))
+	}
+}
+
+#[cfg(feature = "erc1155")]
+impl IntoIdsIter for ethabi::Uint {
+	fn into_inner_iter(self) -> Box<dyn Iterator<Item = Uint>> {
+		Box::new(std::iter::once(self.to_alloy()))
+	}
+}
+
+#[cfg(feature = "erc1155")]
+impl IntoIdsIter for Vec<ethabi::Uint> {
+	fn into_inner_iter(self) -> Box<dyn Iterator<Item = Uint>> {
+		Box::new(self.into_iter().map(ToAlloy::to_alloy))
+	}
+}
+
+impl IntoUint for ethabi::Uint {
+	fn into_uint(self) -> Uint {
+		self.to_alloy()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{address, uint};
+
+	#[test]
+	fn test_address_round_trip() {
+		let address: ethabi::Address = "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266".parse().unwrap();
+		let converted = address.to_alloy();
+		assert_eq!(converted, address!("0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266"));
+		assert_eq!(converted.to_ethabi(), address);
+	}
+
+	#[test]
+	fn test_uint_round_trip() {
+		let value = ethabi::Uint::exp10(24);
+		let converted = value.to_alloy();
+		assert_eq!(converted, uint!("1_000_000_000_000_000_000_000_000"));
+		assert_eq!(converted.to_ethabi(), value);
+		assert_eq!(ethabi::Uint::MAX.to_alloy(), Uint::MAX);
+	}
+
+	#[test]
+	fn test_token_round_trip() {
+		let token = Token::Tuple(vec![
+			Token::Address(ethabi::Address::from_low_u64_be(1)),
+			Token::Uint(ethabi::Uint::from(100)),
+			Token::Int(ethabi::Uint::MAX),
+			Token::FixedBytes(vec![1, 2, 3, 4]),
+			Token::Bytes(vec![5, 6]),
+			Token::Bool(true),
+			Token::String("crabrolls".into()),
+			Token::Array(vec![Token::Uint(ethabi::Uint::from(1)), Token::Uint(ethabi::Uint::from(2))]),
+		]);
+		let converted = token.clone().to_alloy();
+		assert_eq!(converted.abi_encode(), ethabi::encode(std::slice::from_ref(&token)));
+		assert_eq!(converted.to_ethabi(), token);
+	}
+
+	#[test]
+	fn test_long_fixed_bytes_conversion() {
+		let token = Token::FixedBytes(vec![7; 40]);
+		assert_eq!(token.to_alloy(), DynSolValue::Bytes(vec![7; 40]));
+	}
+
+	#[test]
+	fn test_uint_macro_takes_ethabi_values() {
+		assert_eq!(uint!(ethabi::Uint::exp10(18)), Uint::from(10u64).pow(Uint::from(18u64)));
+	}
+
+	#[async_std::test]
+	async fn test_wallets_with_ethabi_values() {
+		use crate::core::contracts::erc1155::ERC1155Environment;
+		use crate::core::contracts::ether::EtherEnvironment;
+		use crate::core::environment::RollupInternalEnvironment;
+		use crate::core::testing::RollupMockup;
+
+		let env = RollupMockup::new();
+		let (alice, bob) = (ethabi::Address::from_low_u64_be(1), ethabi::Address::from_low_u64_be(2));
+		env.get_ether_wallet().write().await.set_balance(alice.to_alloy(), uint!(100));
+
+		let value = ethabi::Uint::from(40);
+		env.ether_transfer(alice.to_alloy(), bob.to_alloy(), value.to_alloy()).await.unwrap();
+		assert_eq!(env.ether_balance(bob.to_alloy()).await.to_ethabi(), ethabi::Uint::from(40));
+
+		let token = address!("0x0000000000000000000000000000000000000003");
+		(env.get_erc1155_wallet().write().await).set_balance(alice.to_alloy(), token, uint!(1), uint!(10));
+		let transfer = (ethabi::Uint::from(1), ethabi::Uint::from(4));
+		env.erc1155_transfer(alice.to_alloy(), bob.to_alloy(), token, transfer).await.unwrap();
+		assert_eq!(env.erc1155_balance(bob.to_alloy(), token, uint!(1)).await, uint!(4));
+	}
+
+	#[test]
+	fn test_negative_int_conversion() {
+		let value = DynSolValue::Int(I256::MINUS_ONE, 256);
+		assert_eq!(value.to_ethabi(), Token::Int(ethabi::Uint::MAX));
+	}
+}
@@ -1,20 +1,116 @@
-use ethabi::{Address, Uint};
+use crate::types::primitives::*;
+
+pub trait IntoUint {
+	fn into_uint(self) -> Uint;
+}
+
+macro_rules! impl_into_uint {
+	($($t:ty),*) => {
+		$(
+			impl IntoUint for $t {
+				fn into_uint(self) -> Uint {
+					Uint::from(self)
+				}
+			}
+		)*
+	};
+}
+
+impl_into_uint!(u8, u16, u32, u64, u128, usize, Uint);
+
+// Unsuffixed literals such as `uint!(100)` fall back to `i32`, so the signed types are kept but reject negatives.
+macro_rules! impl_into_uint_signed {
+	($($t:ty),*) => {
+		$(
+			impl IntoUint for $t {
+				fn into_uint(self) -> Uint {
+					let value = u64::try_from(self).expect("Invalid uint format: negative value");
+					Uint::from(value)
+				}
+			}
+		)*
+	};
+}
+
+impl_into_uint_signed!(i8, i16, i32, i64, isize);
+
+impl IntoUint for &str {
+	fn into_uint(self) -> Uint {
+		let digits = self.replace('_', "");
+		let parsed = match digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+			_ if digits.is_empty() => None,
+			Some("") => None,
+			Some(hex) => Uint::from_str_radix(hex, 16).ok(),
+			None => Uint::from_dec_str(&digits).ok(),
+		};
+		parsed.expect("Invalid uint format")
+	}
+}
+
+impl IntoUint for &String {
+	fn into_uint(self) -> Uint {
+		self.as_str().into_uint()
+	}
+}
+
+impl IntoUint for String {
+	fn into_uint(self) -> Uint {
+		self.as_str().into_uint()
+	}
+}
 
 #[macro_export]
 macro_rules! address {
-	($address:expr) => {
-		(|| -> Address { $address.parse().expect("Invalid address format") })()
-	};
+	($address:expr) => {{
+		let address: $crate::prelude::Address = $address.parse().expect("Invalid address format");
+		address
+	}};
 }
 
 #[macro_export]
 macro_rules! uint {
 	($value:expr) => {
-		(|| -> Uint { Uint::from($value) })()
+		$crate::prelude::IntoUint::into_uint($value)
+	};
+}
+
+/// Includes the voucher constructors generated by `abigen::build` in the build script as a module of the same name,
+/// e.g. `abigen!(pub mod erc20)` for `abigen::build("erc20", "abi/ERC20.json")`.
+#[macro_export]
+macro_rules! abigen {
+	($vis:vis mod $name:ident) => {
+		$vis mod $name {
+			include!(concat!(env!("OUT_DIR"), "/", stringify!($name), ".rs"));
+		}
+	};
+}
+
+/// Captures the build info of the calling crate. The git SHA and build timestamp are read from the `GIT_SHA` and
+/// `SOURCE_DATE_EPOCH` environment variables at compile time, and the listed features are kept if they're enabled.
+#[macro_export]
+macro_rules! build_info {
+	($($feature:literal),* $(,)?) => {
+		$crate::prelude::BuildInfo {
+			name: env!("CARGO_PKG_NAME").to_string(),
+			version: env!("CARGO_PKG_VERSION").to_string(),
+			git_sha: option_env!("GIT_SHA").map(|sha| sha.to_string()),
+			build_timestamp: option_env!("SOURCE_DATE_EPOCH").and_then(|timestamp| timestamp.parse().ok()),
+			features: {
+				#[allow(unused_mut)]
+				let mut features: Vec<String> = Vec::new();
+				$(
+					if cfg!(feature = $feature) {
+						features.push($feature.to_string());
+					}
+				)*
+				features
+			},
+		}
 	};
 }
 
 pub use address;
+pub use build_info;
 pub use uint;
 
 #[cfg(test)]
@@ -24,7 +120,6 @@ mod tests {
 	#[test]
 	fn test_address_macro() {
 		let address = address!("0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266");
-
 		assert_eq!(
 			address,
 			Address::from([
@@ -34,10 +129,71 @@ mod tests {
 		);
 	}
 
+	#[test]
+	fn test_address_macro_ignores_local_address_type() {
+		#[allow(dead_code)]
+		struct Address;
+
+		let address: crate::prelude::Address = address!("0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266");
+		assert_eq!(format!("{:?}", address), "0xf39fd6e51aad88f6f4ce6ab8827279cfffb92266");
+	}
+
 	#[test]
 	fn test_uint_macro() {
 		let value = uint!(100);
-
 		assert_eq!(value, Uint::from(100u64));
 	}
+
+	#[test]
+	fn test_uint_macro_decimal_string() {
+		assert_eq!(uint!("100"), Uint::from(100u64));
+		assert_eq!(uint!("1_000_000"), Uint::from(1_000_000u64));
+	}
+
+	#[test]
+	fn test_uint_macro_hex_string() {
+		assert_eq!(uint!("0xff"), Uint::from(255u64));
+		assert_eq!(uint!("0x1_00"), Uint::from(256u64));
+	}
+
+	#[test]
+	fn test_uint_macro_beyond_u128() {
+		let value = uint!("1_000_000_000_000_000_000_000_000");
+		assert_eq!(value, Uint::exp10(24));
+		assert_eq!(
+			uint!("0xffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff"),
+			Uint::MAX
+		);
+	}
+
+	#[test]
+	fn test_uint_macro_from_uint() {
+		let value = Uint::exp10(24);
+		assert_eq!(uint!(value), Uint::exp10(24));
+	}
+
+	#[test]
+	#[should_panic(expected = "Invalid uint format")]
+	fn test_uint_macro_negative_value() {
+		let value: i64 = -1;
+		uint!(value);
+	}
+
+	#[test]
+	#[should_panic(expected = "Invalid uint format")]
+	fn test_uint_macro_invalid_string() {
+		uint!("12abc");
+	}
+
+	#[test]
+	#[should_panic(expected = "Invalid uint format")]
+	fn test_uint_macro_empty_string() {
+		uint!("");
+	}
+
+	#[test]
+	#[should_panic(expected = "Invalid uint format")]
+	fn test_uint_macro_empty_hex_string() {
+		uint!("0x_");
+	}
 }
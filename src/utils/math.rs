@@ -0,0 +1,152 @@
+use crate::types::primitives::*;
+use std::error::Error;
+
+pub const PERCENT: u64 = 100;
+pub const BASIS_POINTS: u64 = 10_000;
+
+#[cfg(not(feature = "alloy"))]
+fn full_mul_div(a: Uint, b: Uint, denominator: Uint, round_up: bool) -> Result<Uint, Box<dyn Error>> {
+	use ethabi::ethereum_types::U512;
+
+	if denominator.is_zero() {
+		return Err("division by zero".into());
+	}
+
+	let product = a.full_mul(b);
+	let denominator = U512::from(denominator);
+	let (mut quotient, remainder) = product.div_mod(denominator);
+	if round_up && !remainder.is_zero() {
+		quotient += U512::one();
+	}
+
+	Uint::try_from(quotient).map_err(|_| "mul_div overflow".into())
+}
+
+#[cfg(feature = "alloy")]
+fn full_mul_div(a: Uint, b: Uint, denominator: Uint, round_up: bool) -> Result<Uint, Box<dyn Error>> {
+	use alloy_primitives::U512;
+
+	if denominator.is_zero() {
+		return Err("division by zero".into());
+	}
+
+	let product: U512 = a.widening_mul(b);
+	let (mut quotient, remainder) = product.div_rem(U512::from(denominator));
+	if round_up && !remainder.is_zero() {
+		quotient += U512::from(1u64);
+	}
+
+	Uint::checked_from_limbs_slice(quotient.as_limbs()).ok_or_else(|| "mul_div overflow".into())
+}
+
+pub fn mul_div(a: Uint, b: Uint, denominator: Uint) -> Result<Uint, Box<dyn Error>> {
+	full_mul_div(a, b, denominator, false)
+}
+
+pub fn mul_div_up(a: Uint, b: Uint, denominator: Uint) -> Result<Uint, Box<dyn Error>> {
+	full_mul_div(a, b, denominator, true)
+}
+
+pub fn percent_of(value: Uint, percent: u64) -> Result<Uint, Box<dyn Error>> {
+	mul_div(value, Uint::from(percent), Uint::from(PERCENT))
+}
+
+pub fn bps_of(value: Uint, bps: u64) -> Result<Uint, Box<dyn Error>> {
+	mul_div(value, Uint::from(bps), Uint::from(BASIS_POINTS))
+}
+
+pub fn bps_of_up(value: Uint, bps: u64) -> Result<Uint, Box<dyn Error>> {
+	mul_div_up(value, Uint::from(bps), Uint::from(BASIS_POINTS))
+}
+
+pub fn to_bps(part: Uint, total: Uint) -> Result<Uint, Box<dyn Error>> {
+	mul_div(part, Uint::from(BASIS_POINTS), total)
+}
+
+pub fn saturating_add(a: Uint, b: Uint) -> Uint {
+	a.saturating_add(b)
+}
+
+pub fn saturating_sub(a: Uint, b: Uint) -> Uint {
+	a.saturating_sub(b)
+}
+
+pub fn saturating_mul(a: Uint, b: Uint) -> Uint {
+	a.saturating_mul(b)
+}
+
+pub fn min(values: impl IntoIterator<Item = Uint>) -> Option<Uint> {
+	values.into_iter().min()
+}
+
+pub fn max(values: impl IntoIterator<Item = Uint>) -> Option<Uint> {
+	values.into_iter().max()
+}
+
+pub fn sum(values: impl IntoIterator<Item = Uint>) -> Result<Uint, Box<dyn Error>> {
+	values.into_iter().try_fold(Uint::zero(), |acc, value| {
+		acc.checked_add(value).ok_or_else(|| "sum overflow".into())
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::uint;
+
+	#[test]
+	fn test_mul_div() {
+		assert_eq!(mul_div(uint!(10), uint!(3), uint!(4)).unwrap(), uint!(7));
+		assert_eq!(mul_div_up(uint!(10), uint!(3), uint!(4)).unwrap(), uint!(8));
+		assert_eq!(mul_div_up(uint!(10), uint!(4), uint!(4)).unwrap(), uint!(10));
+	}
+
+	#[test]
+	fn test_mul_div_full_precision() {
+		// The intermediate product overflows 256 bits but the result fits.
+		let result = mul_div(Uint::MAX, Uint::MAX, Uint::MAX).unwrap();
+		assert_eq!(result, Uint::MAX);
+
+		let result = mul_div(Uint::MAX, uint!(2), uint!(4)).unwrap();
+		assert_eq!(result, Uint::MAX / Uint::from(2));
+	}
+
+	#[test]
+	fn test_mul_div_errors() {
+		assert_eq!(
+			mul_div(uint!(1), uint!(1), Uint::zero()).unwrap_err().to_string(),
+			"division by zero"
+		);
+		assert_eq!(
+			mul_div(Uint::MAX, uint!(2), uint!(1)).unwrap_err().to_string(),
+			"mul_div overflow"
+		);
+	}
+
+	#[test]
+	fn test_percent_and_bps() {
+		let amount = uint!("1_000_000_000_000_000_000");
+		assert_eq!(percent_of(amount, 5).unwrap(), uint!("50_000_000_000_000_000"));
+		assert_eq!(bps_of(amount, 30).unwrap(), uint!("3_000_000_000_000_000"));
+		assert_eq!(bps_of(uint!(1), 30).unwrap(), Uint::zero());
+		assert_eq!(bps_of_up(uint!(1), 30).unwrap(), uint!(1));
+		assert_eq!(to_bps(uint!(25), uint!(100)).unwrap(), uint!(2_500));
+	}
+
+	#[test]
+	fn test_saturating() {
+		assert_eq!(saturating_add(Uint::MAX, uint!(1)), Uint::MAX);
+		assert_eq!(saturating_sub(uint!(1), uint!(2)), Uint::zero());
+		assert_eq!(saturating_mul(Uint::MAX, uint!(2)), Uint::MAX);
+	}
+
+	#[test]
+	fn test_min_max_sum() {
+		let values = vec![uint!(3), uint!(1), uint!(2)];
+		assert_eq!(min(values.clone()), Some(uint!(1)));
+		assert_eq!(max(values.clone()), Some(uint!(3)));
+		assert_eq!(sum(values).unwrap(), uint!(6));
+		assert_eq!(min(Vec::new()), None);
+		assert_eq!(sum(vec![Uint::MAX, uint!(1)]).unwrap_err().to_string(), "sum overflow");
+	}
+}
@@ -0,0 +1,90 @@
+use std::sync::Mutex;
+
+/// Caches a derived value until the input index advances or `invalidate` is called.
+#[derive(Debug)]
+pub struct Memo<T> {
+	cached: Mutex<Option<(u64, T)>>,
+}
+
+impl<T: Clone> Memo<T> {
+	pub fn new() -> Self {
+		Self {
+			cached: Mutex::new(None),
+		}
+	}
+
+	pub fn get_or_compute(&self, input_index: u64, compute: impl FnOnce() -> T) -> T {
+		let mut cached = self.cached.lock().expect("Memo lock poisoned");
+		match cached.as_ref() {
+			Some((index, value)) if *index == input_index => value.clone(),
+			_ => {
+				let value = compute();
+				*cached = Some((input_index, value.clone()));
+				value
+			}
+		}
+	}
+
+	pub fn get(&self, input_index: u64) -> Option<T> {
+		match self.cached.lock().expect("Memo lock poisoned").as_ref() {
+			Some((index, value)) if *index == input_index => Some(value.clone()),
+			_ => None,
+		}
+	}
+
+	pub fn invalidate(&self) {
+		*self.cached.lock().expect("Memo lock poisoned") = None;
+	}
+}
+
+impl<T: Clone> Default for Memo<T> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_memo_invalidation() {
+		let memo = Memo::new();
+		let mut computed = 0;
+
+		assert_eq!(memo.get(0), None);
+		assert_eq!(
+			memo.get_or_compute(0, || {
+				computed += 1;
+				computed
+			}),
+			1
+		);
+		assert_eq!(
+			memo.get_or_compute(0, || {
+				computed += 1;
+				computed
+			}),
+			1
+		);
+		assert_eq!(memo.get(0), Some(1));
+
+		assert_eq!(
+			memo.get_or_compute(1, || {
+				computed += 1;
+				computed
+			}),
+			2
+		);
+		assert_eq!(memo.get(0), None);
+
+		memo.invalidate();
+		assert_eq!(
+			memo.get_or_compute(1, || {
+				computed += 1;
+				computed
+			}),
+			3
+		);
+	}
+}
@@ -0,0 +1,200 @@
+use super::hash::keccak256;
+use std::error::Error;
+
+pub const MAX_DEPTH: usize = 63;
+
+fn hash_pair(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+	let mut preimage = [0u8; 64];
+	preimage[..32].copy_from_slice(left);
+	preimage[32..].copy_from_slice(right);
+	keccak256(preimage)
+}
+
+/// Append-only Merkle tree of fixed depth, with keccak256 nodes and zero-hash padding for the empty leaves. The nodes
+/// are cached per level, so appending a leaf and computing the root or a proof take `depth` hashes at most.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IncrementalMerkleTree {
+	depth: usize,
+	zeros: Vec<[u8; 32]>,      // Root of an empty subtree at each level
+	nodes: Vec<Vec<[u8; 32]>>, // Nodes of the filled subtrees at each level, the last of each being the frontier
+}
+
+impl IncrementalMerkleTree {
+	pub fn new(depth: usize) -> Result<Self, Box<dyn Error>> {
+		if depth == 0 || depth > MAX_DEPTH {
+			return Err(format!("merkle tree depth must be between 1 and {}", MAX_DEPTH).into());
+		}
+		let mut zeros = vec![[0u8; 32]];
+		for level in 0..depth {
+			zeros.push(hash_pair(&zeros[level], &zeros[level]));
+		}
+		Ok(Self {
+			depth,
+			zeros,
+			nodes: vec![Vec::new(); depth],
+		})
+	}
+
+	pub fn depth(&self) -> usize {
+		self.depth
+	}
+
+	pub fn len(&self) -> u64 {
+		self.nodes[0].len() as u64
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.nodes[0].is_empty()
+	}
+
+	pub fn capacity(&self) -> u64 {
+		1 << self.depth
+	}
+
+	pub fn leaf(&self, index: u64) -> Option<[u8; 32]> {
+		self.nodes[0].get(index as usize).cloned()
+	}
+
+	/// Appends the leaf (usually a hash of the committed entry), returning its index.
+	pub fn append(&mut self, leaf: [u8; 32]) -> Result<u64, Box<dyn Error>> {
+		let index = self.len();
+		if index >= self.capacity() {
+			return Err(format!("merkle tree of depth {} is full", self.depth).into());
+		}
+
+		self.nodes[0].push(leaf);
+		let mut position = index as usize;
+		let mut node = leaf;
+		for level in 1..self.depth {
+			if position.is_multiple_of(2) {
+				break; // The parent is still incomplete, so it's computed on demand with the zero hashes
+			}
+			node = hash_pair(&self.nodes[level - 1][position - 1], &node);
+			position /= 2;
+			self.nodes[level].push(node);
+		}
+		Ok(index)
+	}
+
+	/// Node at the level (0 for the leaves) and position, padding the missing leaves with zeros.
+	fn node(&self, level: usize, position: usize) -> [u8; 32] {
+		if let Some(node) = self.nodes[level].get(position) {
+			return *node;
+		}
+		if level == 0 || (position as u64) << level >= self.len() {
+			return self.zeros[level];
+		}
+		hash_pair(
+			&self.node(level - 1, position * 2),
+			&self.node(level - 1, position * 2 + 1),
+		)
+	}
+
+	pub fn root(&self) -> [u8; 32] {
+		hash_pair(&self.node(self.depth - 1, 0), &self.node(self.depth - 1, 1))
+	}
+
+	/// Sibling hashes from the leaf up to the root.
+	pub fn proof(&self, index: u64) -> Result<Vec<[u8; 32]>, Box<dyn Error>> {
+		if index >= self.len() {
+			return Err(format!("leaf {} is not in the merkle tree", index).into());
+		}
+		let mut position = index as usize;
+		let mut proof = Vec::with_capacity(self.depth);
+		for level in 0..self.depth {
+			proof.push(self.node(level, position ^ 1));
+			position /= 2;
+		}
+		Ok(proof)
+	}
+}
+
+/// Checks the proof of the leaf at the index against the root, e.g. on the client side.
+pub fn verify(root: [u8; 32], leaf: [u8; 32], index: u64, proof: &[[u8; 32]]) -> bool {
+	if proof.len() > MAX_DEPTH || index >> proof.len() != 0 {
+		return false;
+	}
+	let mut node = leaf;
+	for (level, sibling) in proof.iter().enumerate() {
+		node = match (index >> level) & 1 {
+			0 => hash_pair(&node, sibling),
+			_ => hash_pair(sibling, &node),
+		};
+	}
+	node == root
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn leaf(i: u64) -> [u8; 32] {
+		keccak256(i.to_be_bytes())
+	}
+
+	/// Root of the full tree, hashing every level.
+	fn naive_root(depth: usize, leaves: &[[u8; 32]]) -> [u8; 32] {
+		let mut level: Vec<[u8; 32]> = leaves.to_vec();
+		level.resize(1 << depth, [0u8; 32]);
+		while level.len() > 1 {
+			level = level.chunks(2).map(|pair| hash_pair(&pair[0], &pair[1])).collect();
+		}
+		level[0]
+	}
+
+	#[test]
+	fn test_invalid_depth() {
+		assert!(IncrementalMerkleTree::new(0).is_err());
+		assert!(IncrementalMerkleTree::new(MAX_DEPTH + 1).is_err());
+	}
+
+	#[test]
+	fn test_roots_match_the_full_tree() {
+		let mut tree = IncrementalMerkleTree::new(4).unwrap();
+		assert_eq!(tree.root(), naive_root(4, &[]));
+
+		let mut leaves = Vec::new();
+		for i in 0..16 {
+			assert_eq!(tree.append(leaf(i)).unwrap(), i);
+			leaves.push(leaf(i));
+			assert_eq!(tree.root(), naive_root(4, &leaves), "root after {} leaves", i + 1);
+		}
+		assert!(tree.append(leaf(16)).is_err());
+		assert_eq!(tree.len(), tree.capacity());
+	}
+
+	#[test]
+	fn test_proofs() {
+		let mut tree = IncrementalMerkleTree::new(5).unwrap();
+		for i in 0..11 {
+			tree.append(leaf(i)).unwrap();
+		}
+		let root = tree.root();
+		for i in 0..11 {
+			let proof = tree.proof(i).unwrap();
+			assert_eq!(proof.len(), 5);
+			assert!(verify(root, leaf(i), i, &proof));
+			assert!(!verify(root, leaf(i + 1), i, &proof));
+			assert!(!verify(root, leaf(i), i ^ 1, &proof));
+		}
+		assert!(tree.proof(11).is_err());
+
+		// Proofs stay valid for the root at the time they were built, not the later ones
+		let proof = tree.proof(3).unwrap();
+		tree.append(leaf(11)).unwrap();
+		assert!(!verify(tree.root(), leaf(3), 3, &proof));
+		assert!(verify(tree.root(), leaf(3), 3, &tree.proof(3).unwrap()));
+	}
+
+	#[test]
+	fn test_deep_tree() {
+		let mut tree = IncrementalMerkleTree::new(32).unwrap();
+		let empty = tree.root();
+		for i in 0..1000 {
+			tree.append(leaf(i)).unwrap();
+		}
+		assert_ne!(tree.root(), empty);
+		assert!(verify(tree.root(), leaf(777), 777, &tree.proof(777).unwrap()));
+		assert!(!verify(tree.root(), leaf(777), 1 << 33, &tree.proof(777).unwrap()));
+	}
+}
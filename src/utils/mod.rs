@@ -1,5 +1,21 @@
 pub mod abi;
+pub mod abigen;
+pub mod canonical_json;
+pub mod codec;
+pub mod crypto;
+#[cfg(feature = "decimal")]
+pub mod decimal;
+pub mod eip712;
+pub mod hash;
+pub mod ids;
+#[cfg(feature = "alloy")]
+pub mod interop;
 pub mod macros;
+pub mod math;
+pub mod memo;
+pub mod merkle;
+pub mod oracles;
 pub mod parsers;
+#[cfg(feature = "rollup")]
 pub mod requests;
 pub mod units;
@@ -0,0 +1,184 @@
+use super::crypto::{ecrecover, personal_message_hash, SIGNATURE_SIZE};
+use crate::types::primitives::*;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::sync::Mutex;
+
+const TIMESTAMP_SIZE: usize = 8;
+
+/// Off-chain data signed by a reporter, packed as `timestamp (8 bytes) | data | signature (65 bytes)`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SignedData {
+	pub timestamp: u64,
+	pub data: Vec<u8>,
+	pub signature: Vec<u8>,
+}
+
+impl SignedData {
+	pub fn decode(payload: &[u8]) -> Result<Self, Box<dyn Error>> {
+		if payload.len() < TIMESTAMP_SIZE + SIGNATURE_SIZE {
+			return Err("signed data payload is too short".into());
+		}
+		let (timestamp, rest) = payload.split_at(TIMESTAMP_SIZE);
+		let (data, signature) = rest.split_at(rest.len() - SIGNATURE_SIZE);
+		Ok(Self {
+			timestamp: u64::from_be_bytes(timestamp.try_into()?),
+			data: data.to_vec(),
+			signature: signature.to_vec(),
+		})
+	}
+
+	pub fn encode(&self) -> Vec<u8> {
+		let mut payload = self.timestamp.to_be_bytes().to_vec();
+		payload.extend_from_slice(&self.data);
+		payload.extend_from_slice(&self.signature);
+		payload
+	}
+
+	/// The EIP-191 (`personal_sign`) hash of `timestamp | data`, as signed by the reporter.
+	pub fn message_hash(&self) -> [u8; 32] {
+		let mut message = self.timestamp.to_be_bytes().to_vec();
+		message.extend_from_slice(&self.data);
+		personal_message_hash(&message)
+	}
+
+	pub fn signer(&self) -> Result<Address, Box<dyn Error>> {
+		Ok(ecrecover(&self.message_hash(), &self.signature)?)
+	}
+}
+
+/// Verifies signed data feeds against a fixed set of trusted reporters, independently of the input sender.
+#[derive(Debug)]
+pub struct Oracle {
+	signers: HashSet<Address>,
+	max_age: Option<u64>,
+	last_timestamps: Mutex<HashMap<Address, u64>>,
+}
+
+impl Oracle {
+	pub fn new(signers: impl IntoIterator<Item = Address>) -> Self {
+		Self {
+			signers: signers.into_iter().collect(),
+			max_age: None,
+			last_timestamps: Mutex::new(HashMap::new()),
+		}
+	}
+
+	/// Rejects data signed more than `seconds` before the input timestamp.
+	pub fn max_age(mut self, seconds: u64) -> Self {
+		self.max_age = Some(seconds);
+		self
+	}
+
+	pub fn is_signer(&self, address: Address) -> bool {
+		self.signers.contains(&address)
+	}
+
+	/// Checks the signature, freshness and ordering of `signed` at the input timestamp `now`, returning the reporter.
+	pub fn verify(&self, signed: &SignedData, now: u64) -> Result<Address, Box<dyn Error>> {
+		let signer = signed.signer()?;
+		if !self.is_signer(signer) {
+			return Err(format!("{:?} is not a trusted signer", signer).into());
+		}
+		if signed.timestamp > now {
+			return Err("signed data is from the future".into());
+		}
+		if let Some(max_age) = self.max_age {
+			if now - signed.timestamp > max_age {
+				return Err("signed data is stale".into());
+			}
+		}
+
+		let mut last_timestamps = self.last_timestamps.lock().expect("Oracle lock poisoned");
+		if let Some(last) = last_timestamps.get(&signer) {
+			if signed.timestamp <= *last {
+				return Err("signed data was already superseded".into());
+			}
+		}
+		last_timestamps.insert(signer, signed.timestamp);
+		Ok(signer)
+	}
+
+	pub fn verify_payload(&self, payload: &[u8], now: u64) -> Result<(Address, Vec<u8>), Box<dyn Error>> {
+		let signed = SignedData::decode(payload)?;
+		let signer = self.verify(&signed, now)?;
+		Ok((signer, signed.data))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::utils::crypto::public_key_address;
+	use k256::ecdsa::SigningKey;
+
+	fn reporter(seed: u8) -> SigningKey {
+		SigningKey::from_slice(&[seed; 32]).unwrap()
+	}
+
+	fn address_of(key: &SigningKey) -> Address {
+		public_key_address(key.verifying_key())
+	}
+
+	fn sign(key: &SigningKey, timestamp: u64, data: &[u8]) -> SignedData {
+		let mut signed = SignedData {
+			timestamp,
+			data: data.to_vec(),
+			signature: Vec::new(),
+		};
+		let (signature, recovery_id) = key.sign_prehash_recoverable(&signed.message_hash()).unwrap();
+		signed.signature = signature.to_vec();
+		signed.signature.push(27 + recovery_id.to_byte());
+		signed
+	}
+
+	#[test]
+	fn test_recover_known_signer() {
+		// Private key 0x...01 belongs to a well known address
+		let key = SigningKey::from_slice(&[[0u8; 31].as_slice(), &[1u8]].concat()).unwrap();
+		assert_eq!(
+			format!("{:?}", address_of(&key)),
+			"0x7e5f4552091a69125d5dfcb7b8c2659029395bdf"
+		);
+		let signed = sign(&key, 1, b"price");
+		assert_eq!(signed.signer().unwrap(), address_of(&key));
+	}
+
+	#[test]
+	fn test_verify_payload() {
+		let key = reporter(7);
+		let oracle = Oracle::new([address_of(&key)]).max_age(60);
+
+		let payload = sign(&key, 100, b"ETH/USD 3000").encode();
+		let (signer, data) = oracle.verify_payload(&payload, 120).unwrap();
+		assert_eq!(signer, address_of(&key));
+		assert_eq!(data, b"ETH/USD 3000");
+
+		assert_eq!(
+			oracle.verify_payload(&payload, 130).unwrap_err().to_string(),
+			"signed data was already superseded"
+		);
+		assert_eq!(
+			oracle
+				.verify_payload(&sign(&key, 200, b"late").encode(), 300)
+				.unwrap_err()
+				.to_string(),
+			"signed data is stale"
+		);
+		assert!(oracle.verify_payload(&sign(&key, 500, b"early").encode(), 300).is_err());
+	}
+
+	#[test]
+	fn test_untrusted_or_tampered_data() {
+		let key = reporter(7);
+		let oracle = Oracle::new([address_of(&key)]);
+
+		assert!(oracle.verify(&sign(&reporter(8), 1, b"price"), 1).is_err());
+
+		let mut tampered = sign(&key, 1, b"price");
+		tampered.data = b"prize".to_vec();
+		assert!(oracle.verify(&tampered, 1).is_err());
+
+		assert!(SignedData::decode(&[0u8; 10]).is_err());
+	}
+}
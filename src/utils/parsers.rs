@@ -1,4 +1,5 @@
 pub mod deserializers {
+	use crate::types::primitives::*;
 	use hex;
 	use serde::{Deserialize, Deserializer};
 
@@ -16,4 +17,25 @@ pub mod deserializers {
 	{
 		serializer.serialize_str(&format!("0x{}", hex::encode(bytes)))
 	}
+
+	pub fn deserialize_string_of_uint<'de, D>(deserializer: D) -> Result<Uint, D::Error>
+	where
+		D: Deserializer<'de>,
+	{
+		let bytes = deserialize_string_of_bytes(deserializer)?;
+		if bytes.len() > 32 {
+			return Err(serde::de::Error::custom("uint is longer than 32 bytes"));
+		}
+		Ok(Uint::from_big_endian(&bytes))
+	}
+
+	/// Serializes the value as 32 big endian bytes, like the `value` of the vouchers of the rollups node v2.
+	pub fn serialize_uint_as_string<S>(value: &Uint, serializer: S) -> Result<S::Ok, S::Error>
+	where
+		S: serde::Serializer,
+	{
+		let mut bytes = [0u8; 32];
+		value.to_big_endian(&mut bytes);
+		serialize_bytes_as_string(&bytes, serializer)
+	}
 }
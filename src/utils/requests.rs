@@ -1,25 +1,439 @@
-use serde::Serialize;
-use std::error::Error;
+use crate::core::context::RunOptions;
+use crate::core::retry::{self, RetryPolicy};
+use crate::types::error::CrabrollsError;
+use crate::types::machine::{GioRequest, GioResponse};
+use async_io::{Async, Timer};
+use async_lock::Mutex;
+use futures::future::{self, Either};
+use futures::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use futures::task::noop_waker_ref;
+use http_types::{Method, Request, Url};
+use serde::{de::DeserializeOwned, Serialize};
 use std::fmt::Debug;
-use ureq;
+use std::future::Future;
+use std::io::{self, ErrorKind};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::pin::{pin, Pin};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::task::{Context, Poll};
+use std::time::Duration;
 
+trait Stream: AsyncRead + AsyncWrite + Send + Sync + Unpin {}
+
+impl<S: AsyncRead + AsyncWrite + Send + Sync + Unpin> Stream for S {}
+
+/// Connection to the rollup server, shared with the HTTP/1.1 codec while it reads a response.
+type Connection = async_dup::Arc<async_dup::Mutex<Box<dyn Stream>>>;
+
+/// Response of the rollup server, with its body read in full.
+pub struct Response {
+	status: u16,
+	body: Vec<u8>,
+}
+
+impl Response {
+	pub fn status(&self) -> u16 {
+		self.status
+	}
+}
+
+/// Asynchronous HTTP client of the rollup server, so awaiting a request, e.g. the long poll of `/finish`, doesn't
+/// stall the executor. It works with any executor, since the sockets are driven by the reactor of `async-io`.
 pub struct ClientWrapper {
 	base_url: String,
+	idle: Mutex<Option<Connection>>, // Connection kept alive for the next request
+	keep_alive: bool,
+	connect_timeout: Option<Duration>,
+	read_timeout: Option<Duration>,
+	request_timeout: Option<Duration>,
+	retry_policy: RetryPolicy,
+	#[cfg(feature = "tls")]
+	tls: futures_rustls::TlsConnector,
 }
 
 impl ClientWrapper {
-	pub fn new(base_url: String) -> Self {
-		Self { base_url }
+	pub fn new(options: &RunOptions) -> Self {
+		Self {
+			base_url: options.rollup_url.clone(),
+			idle: Mutex::new(None),
+			keep_alive: options.keep_alive,
+			connect_timeout: options.connect_timeout,
+			read_timeout: options.read_timeout,
+			request_timeout: options.request_timeout,
+			retry_policy: options.retry_policy,
+			#[cfg(feature = "tls")]
+			tls: tls_connector(),
+		}
 	}
 
-	pub async fn post<T: Serialize + Debug>(&self, route: &str, request: &T) -> Result<ureq::Response, Box<dyn Error>> {
+	pub async fn post<T: Serialize + Debug>(&self, route: &str, request: &T) -> Result<Response, CrabrollsError> {
 		let url = format!("{}/{}", self.base_url, route);
-		let response = ureq::post(&url).send_json(serde_json::to_value(request)?)?;
+		if cfg!(not(feature = "tls")) && url.starts_with("https://") {
+			return Err(CrabrollsError::transport("https rollup URLs require the `tls` feature"));
+		}
+		let url = Url::parse(&url).map_err(|e| CrabrollsError::transport(format!("invalid URL {}: {}", url, e)))?;
+		let body = serde_json::to_vec(request)?;
+		let mut attempt = 1;
+		let response = loop {
+			// Boxed since the futures of the HTTP/1.1 codec would make the ones of every caller large
+			let sent = AtomicBool::new(false);
+			let exchange = Box::pin(self.exchange(&url, &body, &sent));
+			match timeout(self.request_timeout, exchange).await.and_then(|result| result) {
+				// Only the requests that never reached the server in full are retried, e.g. on connection errors, since
+				// the server may have handled the others even without answering them in time
+				Err(e) if !sent.load(Ordering::Relaxed) && attempt < self.retry_policy.max_attempts => {
+					let backoff = self.retry_policy.backoff(attempt);
+					warn!("Request to {} failed, retrying in {:?}: {}", url, backoff, e);
+					retry::sleep(backoff).await;
+					attempt += 1;
+				}
+				result => break result.map_err(|e| CrabrollsError::transport(format!("{}: {}", url, e)))?,
+			}
+		};
+		match response.status {
+			400.. => Err(CrabrollsError::transport(format!("{}: status code {}", url, response.status))),
+			_ => Ok(response),
+		}
+	}
+
+	/// Sends the request on the kept-alive connection if the server didn't close it, and on a new one otherwise. The
+	/// request is only sent again on a new connection when it couldn't be written to the kept-alive one, since the
+	/// server may have handled a request it received, e.g. emitting an output twice.
+	async fn exchange(&self, url: &Url, body: &[u8], sent: &AtomicBool) -> io::Result<Response> {
+		let idle = self.idle.lock().await.take().filter(|connection| !is_closed(connection));
+		if let Some(connection) = idle {
+			match self.send(connection, url, body, sent).await {
+				Err(e) if !sent.load(Ordering::Relaxed) && e.kind() != ErrorKind::TimedOut => {
+					debug!("Reconnecting to the rollup server: {}", e)
+				}
+				result => return result,
+			}
+		}
+		let connection = self.connect(url).await?;
+		self.send(connection, url, body, sent).await
+	}
+
+	/// Sends the request on the connection, setting `sent` once it's written in full.
+	async fn send(&self, connection: Connection, url: &Url, body: &[u8], sent: &AtomicBool) -> io::Result<Response> {
+		let mut request = Request::new(Method::Post, url.clone());
+		request.insert_header("Content-Type", "application/json");
+		if !self.keep_alive {
+			request.insert_header("Connection", "close");
+		}
+		request.set_body(body);
+
+		let mut writer = connection.clone();
+		let write = async {
+			futures::io::copy(async_h1::client::Encoder::new(request), &mut writer).await?;
+			writer.flush().await
+		};
+		timeout(self.read_timeout, write).await??;
+		sent.store(true, Ordering::Relaxed);
+
+		let mut response = timeout(self.read_timeout, async_h1::client::decode(connection.clone()))
+			.await?
+			.map_err(|e| io::Error::other(e.to_string()))?;
+		let body = timeout(self.read_timeout, response.body_bytes())
+			.await?
+			.map_err(|e| io::Error::other(e.to_string()))?;
+
+		let closed = (response.header("Connection")).is_some_and(|value| value.as_str().eq_ignore_ascii_case("close"));
+		if self.keep_alive && !closed {
+			*self.idle.lock().await = Some(connection);
+		}
+		Ok(Response {
+			status: response.status().into(),
+			body,
+		})
+	}
+
+	async fn connect(&self, url: &Url) -> io::Result<Connection> {
+		let host = url.host_str().unwrap_or_default().to_string();
+		let port = url.port_or_known_default().unwrap_or(80);
+		let mut error = io::Error::new(ErrorKind::NotFound, format!("{} doesn't resolve to any address", host));
+		for address in resolve(&host, port).await? {
+			match timeout(self.connect_timeout, Async::<TcpStream>::connect(address)).await? {
+				Ok(stream) => {
+					stream.get_ref().set_nodelay(true)?;
+					let stream: Box<dyn Stream> = match url.scheme() {
+						#[cfg(feature = "tls")]
+						"https" => Box::new(timeout(self.connect_timeout, self.tls_handshake(&host, stream)).await??),
+						_ => Box::new(stream),
+					};
+					return Ok(async_dup::Arc::new(async_dup::Mutex::new(stream)));
+				}
+				Err(e) => error = e,
+			}
+		}
+		Err(error)
+	}
+
+	#[cfg(feature = "tls")]
+	async fn tls_handshake(
+		&self,
+		host: &str,
+		stream: Async<TcpStream>,
+	) -> io::Result<futures_rustls::client::TlsStream<Async<TcpStream>>> {
+		let host = host.trim_start_matches('[').trim_end_matches(']');
+		let server_name = futures_rustls::pki_types::ServerName::try_from(host.to_string())
+			.map_err(|e| io::Error::new(ErrorKind::InvalidInput, e))?;
+		self.tls.connect(server_name, stream).await
+	}
+
+	pub async fn gio(&self, request: &GioRequest) -> Result<GioResponse, CrabrollsError> {
+		let response = self.post("gio", request).await?;
+		let response = self
+			.parse_response(response)
+			.map_err(|e| CrabrollsError::transport(format!("invalid gio response: {}", e)))?;
 		Ok(response)
 	}
 
-	pub async fn parse_response(&self, response: ureq::Response) -> Result<serde_json::Value, Box<dyn Error>> {
-		let response_json: serde_json::Value = response.into_json()?;
-		Ok(response_json)
+	pub fn parse_response<T: DeserializeOwned>(&self, response: Response) -> Result<T, CrabrollsError> {
+		serde_json::from_slice(&response.body).map_err(|e| CrabrollsError::transport(e.to_string()))
+	}
+}
+
+/// Whether the server closed the idle connection, e.g. after its keep-alive timeout, polled once without waiting.
+fn is_closed(connection: &Connection) -> bool {
+	let mut reader = connection.clone();
+	let mut context = Context::from_waker(noop_waker_ref());
+	!matches!(Pin::new(&mut reader).poll_read(&mut context, &mut [0; 1]), Poll::Pending)
+}
+
+/// Resolves the address of a host on the blocking thread pool, since the resolver of the system blocks.
+pub(crate) async fn resolve(host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+	let target = (host.trim_start_matches('[').trim_end_matches(']').to_string(), port);
+	blocking::unblock(move || target.to_socket_addrs().map(Iterator::collect)).await
+}
+
+/// Fails with a `TimedOut` error if the future doesn't complete within the duration, if any.
+pub(crate) async fn timeout<T>(duration: Option<Duration>, future: impl Future<Output = T>) -> io::Result<T> {
+	let Some(duration) = duration else {
+		return Ok(future.await);
+	};
+	match future::select(pin!(future), Timer::after(duration)).await {
+		Either::Left((output, _)) => Ok(output),
+		Either::Right(_) => Err(io::Error::new(ErrorKind::TimedOut, format!("timed out after {:?}", duration))),
+	}
+}
+
+#[cfg(feature = "tls")]
+fn tls_connector() -> futures_rustls::TlsConnector {
+	use futures_rustls::rustls::{ClientConfig, RootCertStore};
+
+	let roots = RootCertStore {
+		roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+	};
+	let config = ClientConfig::builder()
+		.with_root_certificates(roots)
+		.with_no_client_auth();
+	futures_rustls::TlsConnector::from(std::sync::Arc::new(config))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::io::{BufRead, BufReader, Read, Write};
+	use std::net::TcpListener;
+	use std::time::{Duration, Instant};
+
+	fn client_with(url: &str, retry_policy: RetryPolicy) -> ClientWrapper {
+		ClientWrapper::new(&RunOptions::builder().rollup_url(url).retry_policy(retry_policy).build())
+	}
+
+	/// Reads a request with its body, returning false once the client closed the connection.
+	fn read_request(reader: &mut BufReader<TcpStream>) -> bool {
+		let (mut line, mut length) = (String::new(), 0);
+		loop {
+			line.clear();
+			if reader.read_line(&mut line).unwrap_or(0) == 0 {
+				return false;
+			}
+			if let Some((name, value)) = line.split_once(':') {
+				if name.eq_ignore_ascii_case("content-length") {
+					length = value.trim().parse().unwrap();
+				}
+			}
+			if line == "\r\n" {
+				break;
+			}
+		}
+		reader.read_exact(&mut vec![0; length]).unwrap();
+		true
+	}
+
+	const OK: &[u8] = b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\n{}";
+
+	#[async_std::test]
+	async fn test_reset_after_the_request_is_not_resent() {
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let url = format!("http://{}", listener.local_addr().unwrap());
+		let requests = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+		let server_requests = requests.clone();
+		std::thread::spawn(move || {
+			let (stream, _) = listener.accept().unwrap();
+			let mut reader = BufReader::new(stream.try_clone().unwrap());
+			read_request(&mut reader);
+			server_requests.fetch_add(1, Ordering::SeqCst);
+			(&stream).write_all(OK).unwrap();
+			read_request(&mut reader);
+			server_requests.fetch_add(1, Ordering::SeqCst);
+			drop((reader, stream)); // Handled, but the connection drops before the response
+
+			for stream in listener.incoming() {
+				let stream = stream.unwrap();
+				read_request(&mut BufReader::new(stream.try_clone().unwrap()));
+				server_requests.fetch_add(1, Ordering::SeqCst);
+				(&stream).write_all(OK).unwrap();
+			}
+		});
+
+		let client = client_with(&url, RetryPolicy::none());
+		let notice = serde_json::json!({ "payload": "0x" });
+		assert_eq!(client.post("notice", &notice).await.unwrap().status(), 200);
+		assert!(client.post("notice", &notice).await.is_err());
+		assert_eq!(requests.load(Ordering::SeqCst), 2);
+	}
+
+	#[async_std::test]
+	async fn test_closed_idle_connections_are_replaced() {
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let url = format!("http://{}", listener.local_addr().unwrap());
+		std::thread::spawn(move || {
+			for stream in listener.incoming().take(2) {
+				let stream = stream.unwrap();
+				read_request(&mut BufReader::new(stream.try_clone().unwrap()));
+				(&stream).write_all(OK).unwrap(); // Closed after the response, like an expired keep-alive
+			}
+		});
+
+		let client = client_with(&url, RetryPolicy::none());
+		let notice = serde_json::json!({ "payload": "0x" });
+		assert_eq!(client.post("notice", &notice).await.unwrap().status(), 200);
+		async_std::task::sleep(Duration::from_millis(50)).await;
+		assert_eq!(client.post("notice", &notice).await.unwrap().status(), 200);
+	}
+
+	#[async_std::test]
+	async fn test_post_does_not_block_the_executor() {
+		let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+		let url = format!("http://{}", listener.local_addr().unwrap());
+		std::thread::spawn(move || {
+			let (stream, _) = listener.accept().unwrap();
+			let mut reader = BufReader::new(stream.try_clone().unwrap());
+			let mut line = String::new();
+			while reader.read_line(&mut line).unwrap() > 2 {
+				line.clear();
+			}
+			std::thread::sleep(Duration::from_millis(300)); // Long poll without pending inputs
+			let mut stream = stream;
+			stream
+				.write_all(b"HTTP/1.1 202 Accepted\r\nContent-Length: 0\r\n\r\n")
+				.unwrap();
+		});
+
+		let client = client_with(&url, RetryPolicy::none());
+		let finish = async {
+			let response = client.post("finish", &serde_json::json!({ "status": "accept" })).await;
+			assert_eq!(response.unwrap().status(), 202);
+			Instant::now()
+		};
+		let tick = async {
+			async_std::task::sleep(Duration::from_millis(20)).await;
+			Instant::now()
+		};
+		let (finished_at, ticked_at) = futures::future::join(finish, tick).await;
+		assert!(ticked_at < finished_at);
+	}
+
+	#[async_std::test]
+	async fn test_request_timeout() {
+		let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+		let url = format!("http://{}", listener.local_addr().unwrap());
+		std::thread::spawn(move || {
+			let (_stream, _) = listener.accept().unwrap();
+			std::thread::sleep(Duration::from_secs(2)); // Hung dispatcher
+		});
+
+		let options = RunOptions::builder()
+			.rollup_url(url)
+			.request_timeout(Duration::from_millis(100))
+			.build();
+		let started_at = Instant::now();
+		let response = ClientWrapper::new(&options)
+			.post("finish", &serde_json::json!({ "status": "accept" }))
+			.await;
+		assert!(response.is_err());
+		assert!(started_at.elapsed() < Duration::from_secs(1));
+	}
+
+	#[async_std::test]
+	async fn test_stalled_requests_are_not_retried() {
+		let policy = RetryPolicy {
+			max_attempts: 5,
+			initial_backoff: Duration::from_millis(10),
+			..RetryPolicy::default()
+		};
+		for builder in [
+			RunOptions::builder().read_timeout(Duration::from_millis(100)),
+			RunOptions::builder().request_timeout(Duration::from_millis(100)),
+		] {
+			let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+			let url = format!("http://{}", listener.local_addr().unwrap());
+			let requests = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+			let server_requests = requests.clone();
+			std::thread::spawn(move || {
+				let mut streams = Vec::new();
+				for stream in listener.incoming() {
+					let stream = stream.unwrap();
+					read_request(&mut BufReader::new(stream.try_clone().unwrap()));
+					server_requests.fetch_add(1, Ordering::SeqCst);
+					streams.push(stream); // Applied, but the response stalls
+				}
+			});
+
+			let client = ClientWrapper::new(&builder.rollup_url(url).retry_policy(policy).build());
+			assert!(client.post("finish", &serde_json::json!({ "status": "accept" })).await.is_err());
+			assert_eq!(requests.load(Ordering::SeqCst), 1);
+		}
+	}
+
+	#[async_std::test]
+	async fn test_post_retries_connection_errors() {
+		let address = std::net::TcpListener::bind("127.0.0.1:0")
+			.unwrap()
+			.local_addr()
+			.unwrap();
+		let url = format!("http://{}", address);
+		let client = client_with(&url, RetryPolicy::none());
+		assert!(client
+			.post("report", &serde_json::json!({ "payload": "0x" }))
+			.await
+			.is_err());
+
+		let server = std::thread::spawn(move || {
+			std::thread::sleep(Duration::from_millis(150)); // The node restarts
+			let listener = std::net::TcpListener::bind(address).unwrap();
+			let (stream, _) = listener.accept().unwrap();
+			let mut reader = BufReader::new(stream.try_clone().unwrap());
+			let mut line = String::new();
+			while reader.read_line(&mut line).unwrap() > 2 {
+				line.clear();
+			}
+			let mut stream = stream;
+			stream
+				.write_all(b"HTTP/1.1 202 Accepted\r\nContent-Length: 0\r\n\r\n")
+				.unwrap();
+		});
+		let policy = RetryPolicy {
+			max_attempts: 20,
+			initial_backoff: Duration::from_millis(20),
+			max_backoff: Duration::from_millis(50),
+			..RetryPolicy::default()
+		};
+		let client = client_with(&url, policy);
+		let response = client.post("report", &serde_json::json!({ "payload": "0x" })).await;
+		assert_eq!(response.unwrap().status(), 202);
+		server.join().unwrap();
 	}
 }
@@ -1,5 +1,5 @@
 pub mod wei {
-	use ethabi::Uint;
+	use crate::types::primitives::*;
 
 	pub fn to_ether(wei: Uint) -> f64 {
 		wei.as_u128() as f64 / 1_000_000_000_000_000_000.0
@@ -21,7 +21,6 @@ pub mod wei {
 mod tests {
 	use super::*;
 	use crate::uint;
-	use ethabi::Uint;
 
 	#[test]
 	fn test_to_ether() {